@@ -124,6 +124,7 @@ async fn register_source_resources(fs: &FeatureStore) -> Result<Graph, Box<dyn E
         value_type: FeatureValueType::String,
         entity_id: refers_to.resource_id(),
         transformation_id: None,
+        data_source_id: None,
         description: None,
         tags: HashMap::new(),
         owners: Vec::new(),
@@ -165,9 +166,13 @@ async fn extract_features(
     // calling build_transformation is optional, which gives a named transformation. otherwise an anonymous transformation is created
     // tc.as_ref().borrow_mut().build_transformation("demo_topo_fields_trans", None);
     let transform_graph = graph.transform(&tc, &fs.registry).await?;
-    let user_product_user = transform_graph.cypher_to_graph(
+    let upu_graph = transform_graph.cypher_to_graph(
         "MATCH (p: Product)-[:belongsTo]->(cat: Category {name: \" Books\"}) MATCH (r1: Review)-[:rates]->(p)<-[:rates]-(r2: Review) MATCH (r1: Review)-[:isWrittenBy]->(u1: Reviewer) MATCH (r2: Review)-[:isWrittenBy]->(u2: Reviewer) RETURN u1, collect(u2)",
-    ).export_topology("upu");
+    );
+    let user_product_user = upu_graph.export_topology("upu");
+    fs.registry
+        .register_resources(&upu_graph.derived_edge_entities().iter().collect::<Vec<_>>())
+        .await?;
     let rating_counts = transform_graph
         .cypher_to_dataframe(
             "MATCH (r:Review)-[:isWrittenBy]->(u:Reviewer)
@@ -176,13 +181,21 @@ async fn extract_features(
             UNWIND cnts as number
             WITH u, number/total as ratio, number
             RETURN u.reviewerID as user, collect(number)+collect(ratio) as dims",
-        )
+            HashMap::new(),
+            &HashMap::from([
+                ("user".to_string(), FeatureValueType::String),
+                (
+                    "dims".to_string(),
+                    FeatureValueType::Array(Box::new(FeatureValueType::Float)),
+                ),
+            ]),
+        )?
         .select(
             vec!["user".to_string()]
                 .into_iter()
                 .chain((0..10).map(|i| format!("dims.str[{}]", i)))
                 .collect(),
-        )
+        )?
         .export();
 
     finalize_transformation(
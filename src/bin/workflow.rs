@@ -32,6 +32,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         last_updated_timestamp: None,
         tags: HashMap::new(),
         owners: Vec::new(),
+        data_source_ids: Vec::new(),
     };
 
     let field_1 = Field {
@@ -40,6 +41,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         value_type: FeatureValueType::Float,
         entity_id: entity_1.resource_id(),
         transformation_id: Some("t_1".to_string()),
+        data_source_id: None,
         description: None,
         tags: HashMap::new(),
         owners: Vec::new(),
@@ -80,6 +82,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         value_type: FeatureValueType::Float,
         entity_id: entity_1.resource_id(),
         transformation_id: Some("t_2".to_string()),
+        data_source_id: None,
         description: None,
         tags: HashMap::new(),
         owners: Vec::new(),
@@ -107,6 +110,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         value_type: FeatureValueType::Float,
         entity_id: entity_1.resource_id(),
         transformation_id: Some(transformation_1.resource_id()),
+        data_source_id: None,
         description: Some("Sum of feature 1 and feature 2".to_string()),
         tags: HashMap::new(),
         owners: Vec::new(),
@@ -12,6 +12,9 @@ pub struct Cli {
 pub enum Commands {
     Apply {},
 
+    /// Shows the feature views and graph constraints/indexes that apply would change
+    Plan {},
+
     #[clap(arg_required_else_help = true)]
     Materialize {
         time: String,
@@ -19,5 +22,66 @@ pub enum Commands {
 
     Serve {},
 
-    Clean {},
+    /// Removes what the project created. Without scope flags, all scopes are cleaned.
+    Clean {
+        /// Lists what would be removed without removing anything
+        #[clap(long)]
+        dry_run: bool,
+        /// Skips the confirmation asked before deleting graph data or registry resources
+        #[clap(long)]
+        yes: bool,
+        /// Removes the vertices and edges ingested from the project data sources
+        #[clap(long)]
+        graph: bool,
+        /// Removes the online tables of the applied feature views
+        #[clap(long)]
+        online_store: bool,
+        /// Removes the resources the project registered
+        #[clap(long)]
+        registry: bool,
+        /// Number of graph elements deleted per transaction
+        #[clap(long, default_value_t = 10000, value_parser = parse_batch_size)]
+        batch_size: usize,
+    },
+
+    /// Generates entities and fields from the vertex and edge types found in the graph database,
+    /// and registers them or writes them to a file
+    ImportSchema {
+        /// Writes the definitions to this JSON file instead of registering them
+        #[clap(long)]
+        output: Option<String>,
+        /// Number of vertices or edges of each type read to find properties and their types
+        #[clap(long, default_value_t = 1000)]
+        sample_size: usize,
+    },
+
+    /// Compares the registered entities and fields with sampled data in the graph database
+    Validate {
+        /// Number of vertices or edges of each entity read
+        #[clap(long, default_value_t = 1000)]
+        sample_size: usize,
+        /// Reports fields whose property is null in a larger fraction of the samples
+        #[clap(long, default_value_t = 1.0)]
+        max_null_rate: f64,
+    },
+
+    /// Shows how a registered transformation runs: each data with its operation, inputs, output
+    /// schema, and whether it is pushed down to the graph database with its Cypher query
+    #[clap(arg_required_else_help = true)]
+    Explain {
+        /// Resource id of the transformation, such as Transformation/name/
+        transformation_id: String,
+        /// Prints the plan as JSON
+        #[clap(long)]
+        json: bool,
+    },
+}
+
+/// Parses a positive number of elements per transaction
+fn parse_batch_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("the batch size must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
 }
@@ -1,10 +1,14 @@
-use neo4rs::*;
+use etcd_rs::{Client, ClientConfig};
+use gfs::{
+    CleanScope, EtcdStorage, FeatureRegistry, FeatureValue, FeatureView, GDBProvider,
+    GraphClientConfig, GraphDataHandler, GraphDatabaseCredentials, InfraProvider, OnlineStore,
+};
 use rusqlite::Connection;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::sync::Arc;
 
 fn load_json(path: &str) -> std::result::Result<Value, Box<dyn std::error::Error>> {
     match File::open(path) {
@@ -33,64 +37,267 @@ pub async fn apply() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let path = "feature_store.json";
     let json = load_json(path)?;
     println!("{:#?}", json);
+    let graph_data_handler = load_graph_data_handler(&json)?;
 
     match json["data_sources"][0]["type"].as_str().unwrap() {
         "cypher" => {
             println!("processing cypher source");
             let cypher = load_cypher(json["data_sources"][0]["path"].as_str().unwrap())?;
-            let uri = json["gdb"]["uri"].as_str().unwrap();
-            let user = json["gdb"]["user"].as_str().unwrap();
-            let pass = json["gdb"]["password"].as_str().unwrap();
-
-            let graph = Arc::new(Graph::new(uri, user, pass).await.unwrap());
-
-            let txn = graph.start_txn().await.unwrap();
-            txn.run(query(&cypher)).await.unwrap();
-            txn.commit().await.unwrap();
+            graph_data_handler
+                .database
+                .run_query(&cypher, HashMap::new())
+                .await?;
         }
         _ => {
             println!("Unknown data source type");
         }
     }
 
+    if let Some(registry) = load_registry(&json).await? {
+        let provider = GDBProvider::new(graph_data_handler, load_online_store(&json))?;
+        let (views_to_delete, views_to_keep) = plan_views(&registry, &provider).await?;
+        println!(
+            "updating infra: {} views to keep, {} views to delete",
+            views_to_keep.len(),
+            views_to_delete.len()
+        );
+        provider
+            .update_infra(&registry, views_to_delete, views_to_keep)
+            .await?;
+    }
+
     Ok(())
 }
 
-pub async fn clean() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    println!("Clean");
+pub async fn plan() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let path = "feature_store.json";
     let json = load_json(path)?;
-    // println!("{:#?}", json);
-    let cypher = "MATCH (n) DETACH DELETE n";
-    let uri = json["gdb"]["uri"].as_str().unwrap();
-    let user = json["gdb"]["user"].as_str().unwrap();
-    let pass = json["gdb"]["password"].as_str().unwrap();
+    let registry = load_registry(&json)
+        .await?
+        .ok_or("no registry configured in feature_store.json")?;
+    let provider = GDBProvider::new(load_graph_data_handler(&json)?, load_online_store(&json))?;
+
+    let (views_to_delete, views_to_keep) = plan_views(&registry, &provider).await?;
+    for view in &views_to_keep {
+        println!("= {}", view.resource_id());
+    }
+    for view in &views_to_delete {
+        println!("- {}", view.resource_id());
+    }
+    print!("{}", provider.plan_schema(&registry, &views_to_keep).await?);
+    Ok(())
+}
 
-    let graph = Arc::new(Graph::new(uri, user, pass).await.unwrap());
+async fn load_registry(
+    json: &Value,
+) -> std::result::Result<Option<FeatureRegistry>, Box<dyn std::error::Error>> {
+    match json["registry"]["endpoints"].as_array() {
+        Some(endpoints) => Ok(Some(FeatureRegistry {
+            storage: EtcdStorage {
+                client: Client::connect(ClientConfig::new(
+                    endpoints
+                        .iter()
+                        .filter_map(|e| e.as_str())
+                        .map(|e| e.into())
+                        .collect::<Vec<_>>(),
+                ))
+                .await?,
+            },
+        })),
+        None => Ok(None),
+    }
+}
 
-    let txn = graph.start_txn().await.unwrap();
-    txn.run(query(cypher)).await.unwrap();
-    txn.commit().await.unwrap();
+fn load_graph_data_handler(
+    json: &Value,
+) -> std::result::Result<GraphDataHandler, Box<dyn std::error::Error>> {
+    GraphDataHandler::neo4j(
+        &GraphDatabaseCredentials {
+            uri: json["gdb"]["uri"].as_str().unwrap().to_string(),
+            user: json["gdb"]["user"].as_str().unwrap().to_string(),
+            pass: json["gdb"]["password"].as_str().unwrap().to_string(),
+        },
+        serde_json::from_value::<GraphClientConfig>(json["gdb"].clone())?,
+    )
+}
+
+fn load_online_store(json: &Value) -> OnlineStore {
+    OnlineStore {
+        path: json["online_store"][0]["path"]
+            .as_str()
+            .unwrap()
+            .to_string(),
+    }
+}
+
+/// Returns the feature views to delete and to keep: all registered views are kept, and views
+/// applied before but no longer registered are deleted
+async fn plan_views(
+    registry: &FeatureRegistry,
+    provider: &GDBProvider,
+) -> std::result::Result<(Vec<FeatureView>, Vec<FeatureView>), Box<dyn std::error::Error>> {
+    let views_to_keep = registry.get_feature_views().await?;
+    let views_to_delete = provider
+        .online_store
+        .applied_views()?
+        .into_iter()
+        .filter(|applied| {
+            !views_to_keep
+                .iter()
+                .any(|v| v.resource_id() == applied.resource_id())
+        })
+        .collect();
+    Ok((views_to_delete, views_to_keep))
+}
+
+pub async fn clean(
+    dry_run: bool,
+    yes: bool,
+    graph: bool,
+    online_store: bool,
+    registry: bool,
+    batch_size: usize,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let path = "feature_store.json";
+    let json = load_json(path)?;
+    let feature_registry = load_registry(&json)
+        .await?
+        .ok_or("no registry configured in feature_store.json")?;
+    let provider = GDBProvider::new(load_graph_data_handler(&json)?, load_online_store(&json))?;
+
+    let mut scopes: Vec<CleanScope> = [
+        (graph, CleanScope::GraphData),
+        (online_store, CleanScope::OnlineStore),
+        (registry, CleanScope::Registry),
+    ]
+    .into_iter()
+    .filter(|(selected, _)| *selected)
+    .map(|(_, scope)| scope)
+    .collect();
+    if scopes.is_empty() {
+        scopes = CleanScope::ALL.to_vec();
+    }
+    let data_source_names: Vec<String> = json["data_sources"]
+        .as_array()
+        .map(|sources| {
+            sources
+                .iter()
+                .filter_map(|s| s["name"].as_str())
+                .map(|s| s.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let plan = provider
+        .plan_clean(&feature_registry, &data_source_names, &scopes)
+        .await?;
+    print!("{}", plan);
+    if dry_run || plan.is_empty() {
+        return Ok(());
+    }
+
+    if plan.is_destructive() && !yes {
+        print!(
+            "Delete the above from project {}? [y/N] ",
+            json["project_name"].as_str().unwrap_or_default()
+        );
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Err("aborted".into());
+        }
+    }
+    provider.clean(&feature_registry, &plan, batch_size).await?;
 
     Ok(())
 }
 
-pub async fn materialize() -> std::result::Result<(), Box<dyn std::error::Error>> {
+pub async fn import_schema(
+    output: Option<String>,
+    sample_size: usize,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
     let path = "feature_store.json";
     let json = load_json(path)?;
+    let graph_data_handler = load_graph_data_handler(&json)?;
+    let schema = gfs::import_schema(graph_data_handler.database.as_ref(), sample_size).await?;
+    println!(
+        "found {} entities and {} fields",
+        schema.entities.len(),
+        schema.fields.len()
+    );
+
+    match output {
+        Some(output) => {
+            let mut file = File::create(&output)?;
+            file.write_all(schema.to_json()?.as_bytes())?;
+            println!("written to {}", output);
+        }
+        None => {
+            let registry = load_registry(&json)
+                .await?
+                .ok_or("no registry configured in feature_store.json")?;
+            schema.register(&registry).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn validate(
+    sample_size: usize,
+    max_null_rate: f64,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let path = "feature_store.json";
+    let json = load_json(path)?;
+    let registry = load_registry(&json)
+        .await?
+        .ok_or("no registry configured in feature_store.json")?;
+    let graph_data_handler = load_graph_data_handler(&json)?;
+    let report = gfs::validate_registry(
+        graph_data_handler.database.as_ref(),
+        &registry,
+        sample_size,
+        max_null_rate,
+    )
+    .await?;
+    print!("{}", report);
+    if report.is_clean() {
+        Ok(())
+    } else {
+        Err(format!("found {} schema drift issues", report.issues.len()).into())
+    }
+}
 
-    let uri = json["gdb"]["uri"].as_str().unwrap();
-    let user = json["gdb"]["user"].as_str().unwrap();
-    let pass = json["gdb"]["password"].as_str().unwrap();
+pub async fn explain(
+    transformation_id: &str,
+    json: bool,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let path = "feature_store.json";
+    let json_config = load_json(path)?;
+    let registry = load_registry(&json_config)
+        .await?
+        .ok_or("no registry configured in feature_store.json")?;
+    let transformation = registry
+        .get_transformation(&transformation_id.to_string())
+        .await?;
+    let explanation = gfs::TransformationExecutor::new(&transformation)?.explain()?;
+    if json {
+        println!("{}", explanation.to_json()?);
+    } else {
+        print!("{}", explanation);
+    }
+    Ok(())
+}
 
-    let graph = Arc::new(Graph::new(uri, user, pass).await.unwrap());
+pub async fn materialize() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let path = "feature_store.json";
+    let json = load_json(path)?;
+    let graph_data_handler = load_graph_data_handler(&json)?;
 
     let feature_view_json = load_json(json["feature_views"][0].as_str().unwrap())?;
-    let q = format!(
-        "MATCH (n:{}) RETURN n",
-        feature_view_json["node_type"].as_str().unwrap()
-    );
-    let mut result = graph.execute(query(&q)).await.unwrap();
+    let nodes = graph_data_handler
+        .database
+        .scan_vertices(feature_view_json["node_type"].as_str().unwrap())
+        .await?;
 
     let conn = Connection::open(json["online_store"][0]["path"].as_str().unwrap())?;
     conn.execute(
@@ -115,21 +322,23 @@ pub async fn materialize() -> std::result::Result<(), Box<dyn std::error::Error>
         [],
     )?;
 
-    while let Ok(Some(row)) = result.next().await {
-        let node: Node = row.get("n").unwrap();
-        // println!("{:#?}", node.get::<String>("title").unwrap());
+    for node in &nodes {
+        let get_string = |key: &str| match node.properties.get(key) {
+            Some(FeatureValue::String(s)) => s.clone(),
+            _ => String::new(),
+        };
         conn.execute(
             format!(
                 "INSERT INTO {} (id, title, released, tagline) VALUES ({}, ?, {}, ?)",
                 feature_view_json["name"].as_str().unwrap(),
-                node.id(),
-                node.get::<i64>("released").unwrap_or(0),
+                node.id,
+                match node.properties.get("released") {
+                    Some(FeatureValue::Int(released)) => *released,
+                    _ => 0,
+                },
             )
             .as_str(),
-            [
-                node.get::<String>("title").unwrap_or_default(),
-                node.get::<String>("tagline").unwrap_or_default(),
-            ],
+            [get_string("title"), get_string("tagline")],
         )?;
     }
 
@@ -138,6 +347,9 @@ pub async fn materialize() -> std::result::Result<(), Box<dyn std::error::Error>
 
 #[tokio::test]
 async fn test_neo4rs() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use neo4rs::*;
+    use std::sync::Arc;
+
     let uri = "localhost:7687";
     let user = "neo4j";
     let password = "neo4j";
@@ -3,32 +3,558 @@
 /// - Data Sources
 ///     - Primary (transformation: None)
 ///     - Transformation (transformation: SourceTransformation)
+use crate::{FeatureValue, FeatureValueType, ResourceId, ResourceOp};
+use chrono::serde::ts_seconds_option;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DataSource {
     pub name: String,
+    pub variant: Option<String>,
+    /// File path for offline sources, or topic name for online sources
     pub path: String,
     pub data_source_type: DataSourceType,
     pub transformation: Option<SourceTransformation>,
+    pub description: Option<String>,
+    #[serde(with = "ts_seconds_option")]
+    pub created_at: Option<DateTime<Utc>>,
+    pub tags: HashMap<String, String>,
+    pub owners: Vec<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum DataSourceType {
     OfflineDataSourceType(OfflineDataSourceType),
     OnlineDataSourceType(OnlineDataSourceType),
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum OfflineDataSourceType {
-    CsvSource,
+    CsvSource { delimiter: char },
     CypherSource,
     ParquetSource,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum OnlineDataSourceType {
-    KafkaSource,
-    PulsarSource,
+    KafkaSource {
+        brokers: Vec<String>,
+        group_id: Option<String>,
+    },
+    PulsarSource {
+        service_url: String,
+        subscription: String,
+    },
+}
+
+impl ResourceOp for DataSource {
+    fn resource_id(&self) -> ResourceId {
+        format!(
+            "{}/{}/{}",
+            "DataSource",
+            &self.name,
+            &self.variant.as_ref().unwrap_or(&"".to_string())
+        )
+    }
+}
+
+impl DataSource {
+    pub fn new(
+        name: &str,
+        variant: Option<String>,
+        path: &str,
+        data_source_type: DataSourceType,
+        transformation: Option<SourceTransformation>,
+    ) -> Self {
+        DataSource {
+            name: name.to_string(),
+            variant,
+            path: path.to_string(),
+            data_source_type,
+            transformation,
+            description: None,
+            created_at: Some(Utc::now()),
+            tags: HashMap::new(),
+            owners: Vec::new(),
+        }
+    }
+
+    pub fn new_csv_source(
+        name: &str,
+        variant: Option<String>,
+        path: &str,
+        transformation: Option<SourceTransformation>,
+    ) -> Self {
+        DataSource::new(
+            name,
+            variant,
+            path,
+            DataSourceType::OfflineDataSourceType(OfflineDataSourceType::CsvSource {
+                delimiter: ',',
+            }),
+            transformation,
+        )
+    }
 }
 
 /// from source to graph
-pub enum SourceTransformation {}
+///
+/// The steps are applied to the rows of a tabular source in order, and the resulting rows are then
+/// split into vertices and edges by the mappings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SourceTransformation {
+    pub steps: Vec<SourceTransformationStep>,
+    pub mappings: Vec<GraphMapping>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SourceTransformationStep {
+    /// Renames a column
+    Rename { from: String, to: String },
+    /// Casts the values of a column to the given type
+    Cast {
+        column: String,
+        value_type: FeatureValueType,
+    },
+    /// Adds a string column by joining the values of other columns, e.g. a composite key
+    DeriveKey {
+        column: String,
+        from: Vec<String>,
+        separator: String,
+    },
+    /// Keeps only the rows whose column value satisfies the predicate
+    Filter {
+        column: String,
+        predicate: Predicate,
+    },
+    /// Keeps only the first row of each distinct combination of the given columns
+    Dedup { columns: Vec<String> },
+}
+
+/// A predicate on a single column value. Operands are cast to the type of the column value.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Predicate {
+    Eq(String),
+    Ne(String),
+    Lt(String),
+    Le(String),
+    Gt(String),
+    Ge(String),
+    IsNull,
+    IsNotNull,
+}
+
+/// Maps each row of a table to a graph element. One table can be mapped to a vertex type plus any
+/// number of edge types.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum GraphMapping {
+    Vertex {
+        tlabel: String,
+        /// The column used as the vertex primary key
+        key: String,
+        properties: Vec<String>,
+    },
+    Edge {
+        tlabel: String,
+        src: VertexKeyMapping,
+        dst: VertexKeyMapping,
+        properties: Vec<String>,
+    },
+}
+
+/// Identifies the endpoint of an edge by the vertex type and the column holding its primary key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VertexKeyMapping {
+    pub tlabel: String,
+    pub primary_key: String,
+    pub column: String,
+}
+
+/// Raw rows read from a tabular source
+#[derive(Debug, Clone, Default)]
+pub struct SourceTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<FeatureValue>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexKey {
+    pub tlabel: String,
+    pub primary_key: String,
+    pub value: FeatureValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct VertexRecord {
+    pub key: VertexKey,
+    pub properties: HashMap<String, FeatureValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EdgeRecord {
+    pub tlabel: String,
+    pub src: VertexKey,
+    pub dst: VertexKey,
+    pub properties: HashMap<String, FeatureValue>,
+}
+
+/// Graph data produced from a source, ready to be upserted into the graph database
+#[derive(Debug, Clone, Default)]
+pub struct GraphRecords {
+    pub vertices: Vec<VertexRecord>,
+    pub edges: Vec<EdgeRecord>,
+}
+
+impl DataSource {
+    /// Returns the vertex and edge tlabels the source is ingested into. Cypher and online sources
+    /// have no mappings and return none.
+    pub fn ingested_tlabels(&self) -> (Vec<String>, Vec<String>) {
+        let mut vertex_tlabels = Vec::new();
+        let mut edge_tlabels = Vec::new();
+        match &self.transformation {
+            Some(transformation) => {
+                for mapping in &transformation.mappings {
+                    match mapping {
+                        GraphMapping::Vertex { tlabel, .. } => vertex_tlabels.push(tlabel.clone()),
+                        GraphMapping::Edge { tlabel, .. } => edge_tlabels.push(tlabel.clone()),
+                    }
+                }
+            }
+            None => {
+                if let DataSourceType::OfflineDataSourceType(OfflineDataSourceType::CsvSource {
+                    ..
+                }) = &self.data_source_type
+                {
+                    vertex_tlabels.push(self.name.clone());
+                }
+            }
+        }
+        (vertex_tlabels, edge_tlabels)
+    }
+
+    /// Reads the source table and applies the source transformation. Without a transformation,
+    /// each row becomes a vertex labeled by the source name and keyed by the first column.
+    pub fn load_graph_records(&self) -> Result<GraphRecords, Box<dyn Error>> {
+        let table = match &self.data_source_type {
+            DataSourceType::OfflineDataSourceType(OfflineDataSourceType::CsvSource {
+                delimiter,
+            }) => SourceTable::from_csv(&fs::read_to_string(&self.path)?, *delimiter)?,
+            _ => return Err(format!("source {} is not tabular", self.name).into()),
+        };
+        match &self.transformation {
+            Some(transformation) => transformation.apply(table),
+            None => {
+                let key = table
+                    .columns
+                    .first()
+                    .ok_or(format!("source {} has no columns", self.name))?
+                    .clone();
+                SourceTransformation {
+                    steps: Vec::new(),
+                    mappings: vec![GraphMapping::Vertex {
+                        tlabel: self.name.clone(),
+                        key,
+                        properties: table.columns.clone(),
+                    }],
+                }
+                .apply(table)
+            }
+        }
+    }
+}
+
+impl SourceTable {
+    /// Parses CSV text with a header line. Quoted fields may contain delimiters, escaped quotes
+    /// and line breaks. All values are read as strings.
+    pub fn from_csv(text: &str, delimiter: char) -> Result<Self, Box<dyn Error>> {
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                ('"', true) => quoted = false,
+                ('"', false) if field.is_empty() => quoted = true,
+                (c, false) if c == delimiter => record.push(std::mem::take(&mut field)),
+                ('\r', false) => {}
+                ('\n', false) => {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                _ => field.push(c),
+            }
+        }
+        if quoted {
+            return Err("unterminated quoted field in csv".into());
+        }
+        if !field.is_empty() || !record.is_empty() {
+            record.push(field);
+            records.push(record);
+        }
+        let mut records = records.into_iter().filter(|r| r != &[""]);
+        let columns = records.next().ok_or("empty csv")?;
+        let rows = records
+            .enumerate()
+            .map(|(i, r)| {
+                if r.len() != columns.len() {
+                    return Err(format!(
+                        "csv row {} has {} fields, expected {}",
+                        i + 1,
+                        r.len(),
+                        columns.len()
+                    )
+                    .into());
+                }
+                Ok(r.into_iter().map(FeatureValue::String).collect())
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        Ok(SourceTable { columns, rows })
+    }
+
+    fn column_index(&self, column: &str) -> Result<usize, Box<dyn Error>> {
+        self.columns
+            .iter()
+            .position(|c| c == column)
+            .ok_or_else(|| format!("unknown column {}", column).into())
+    }
+}
+
+impl Predicate {
+    fn eval(&self, value: &FeatureValue) -> Result<bool, Box<dyn Error>> {
+        use std::cmp::Ordering::*;
+        let (operand, accepted): (&str, &[std::cmp::Ordering]) = match self {
+            Predicate::IsNull => return Ok(value.is_null()),
+            Predicate::IsNotNull => return Ok(!value.is_null()),
+            Predicate::Eq(v) => (v, &[Equal]),
+            Predicate::Ne(v) => (v, &[Less, Greater]),
+            Predicate::Lt(v) => (v, &[Less]),
+            Predicate::Le(v) => (v, &[Less, Equal]),
+            Predicate::Gt(v) => (v, &[Greater]),
+            Predicate::Ge(v) => (v, &[Greater, Equal]),
+        };
+        let operand = match value {
+            FeatureValue::Null => return Ok(false),
+            FeatureValue::String(_) => FeatureValue::String(operand.to_string()),
+            FeatureValue::Int(_) => FeatureValue::parse(operand, &FeatureValueType::Float)?,
+            v => FeatureValue::String(operand.to_string()).cast(&value_type_of(v))?,
+        };
+        Ok(matches!(value.compare(&operand), Some(ord) if accepted.contains(&ord)))
+    }
+}
+
+fn value_type_of(value: &FeatureValue) -> FeatureValueType {
+    match value {
+        FeatureValue::Int(_) => FeatureValueType::Int,
+        FeatureValue::Float(_) => FeatureValueType::Float,
+        FeatureValue::Boolean(_) => FeatureValueType::Boolean,
+        FeatureValue::Date(_) => FeatureValueType::Date,
+        FeatureValue::Time(_) => FeatureValueType::Time,
+        FeatureValue::DateTime(_) => FeatureValueType::DateTime,
+        FeatureValue::Duration(_) => FeatureValueType::Duration,
+        _ => FeatureValueType::String,
+    }
+}
+
+impl SourceTransformation {
+    pub fn apply(&self, mut table: SourceTable) -> Result<GraphRecords, Box<dyn Error>> {
+        self.check_endpoint_keys()?;
+        for step in &self.steps {
+            step.apply(&mut table)?;
+        }
+        let mut res = GraphRecords::default();
+        let mut seen_vertices = HashSet::new();
+        for mapping in &self.mappings {
+            match mapping {
+                GraphMapping::Vertex {
+                    tlabel,
+                    key,
+                    properties,
+                } => {
+                    let key_idx = table.column_index(key)?;
+                    let prop_idx = property_indices(&table, properties)?;
+                    for row in &table.rows {
+                        let value = &row[key_idx];
+                        // the same vertex may appear in many rows when a table is split
+                        if value.is_null()
+                            || !seen_vertices.insert((tlabel.clone(), value.to_string()))
+                        {
+                            continue;
+                        }
+                        let mut props = row_properties(row, &prop_idx);
+                        props.insert(key.clone(), value.clone());
+                        res.vertices.push(VertexRecord {
+                            key: VertexKey {
+                                tlabel: tlabel.clone(),
+                                primary_key: key.clone(),
+                                value: value.clone(),
+                            },
+                            properties: props,
+                        });
+                    }
+                }
+                GraphMapping::Edge {
+                    tlabel,
+                    src,
+                    dst,
+                    properties,
+                } => {
+                    let src_idx = table.column_index(&src.column)?;
+                    let dst_idx = table.column_index(&dst.column)?;
+                    let prop_idx = property_indices(&table, properties)?;
+                    for row in &table.rows {
+                        if row[src_idx].is_null() || row[dst_idx].is_null() {
+                            continue;
+                        }
+                        res.edges.push(EdgeRecord {
+                            tlabel: tlabel.clone(),
+                            src: src.key(&row[src_idx]),
+                            dst: dst.key(&row[dst_idx]),
+                            properties: row_properties(row, &prop_idx),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Checks that edges refer to the vertices mapped here by the key they are mapped with, as
+    /// vertices are only found by their key. Vertex types mapped by other sources are not checked.
+    fn check_endpoint_keys(&self) -> Result<(), Box<dyn Error>> {
+        let vertex_keys: Vec<(&String, &String)> = self
+            .mappings
+            .iter()
+            .filter_map(|m| match m {
+                GraphMapping::Vertex { tlabel, key, .. } => Some((tlabel, key)),
+                GraphMapping::Edge { .. } => None,
+            })
+            .collect();
+        for mapping in &self.mappings {
+            if let GraphMapping::Edge {
+                tlabel, src, dst, ..
+            } = mapping
+            {
+                for endpoint in [src, dst] {
+                    let keys: Vec<&String> = vertex_keys
+                        .iter()
+                        .filter(|(t, _)| **t == endpoint.tlabel)
+                        .map(|(_, key)| *key)
+                        .collect();
+                    if !keys.is_empty() && !keys.contains(&&endpoint.primary_key) {
+                        return Err(format!(
+                            "edge {} refers to {} vertices by {}, but they are mapped with key {}",
+                            tlabel, endpoint.tlabel, endpoint.primary_key, keys[0]
+                        )
+                        .into());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SourceTransformationStep {
+    fn apply(&self, table: &mut SourceTable) -> Result<(), Box<dyn Error>> {
+        match self {
+            SourceTransformationStep::Rename { from, to } => {
+                let idx = table.column_index(from)?;
+                table.columns[idx] = to.clone();
+            }
+            SourceTransformationStep::Cast { column, value_type } => {
+                let idx = table.column_index(column)?;
+                for row in table.rows.iter_mut() {
+                    row[idx] = row[idx].cast(value_type)?;
+                }
+            }
+            SourceTransformationStep::DeriveKey {
+                column,
+                from,
+                separator,
+            } => {
+                let indices = property_indices(table, from)?;
+                for row in table.rows.iter_mut() {
+                    let value = if indices.iter().any(|(_, idx)| row[*idx].is_null()) {
+                        FeatureValue::Null
+                    } else {
+                        FeatureValue::String(
+                            indices
+                                .iter()
+                                .map(|(_, idx)| row[*idx].to_string())
+                                .collect::<Vec<_>>()
+                                .join(separator),
+                        )
+                    };
+                    row.push(value);
+                }
+                table.columns.push(column.clone());
+            }
+            SourceTransformationStep::Filter { column, predicate } => {
+                let idx = table.column_index(column)?;
+                let mut rows = Vec::with_capacity(table.rows.len());
+                for row in table.rows.drain(..) {
+                    if predicate.eval(&row[idx])? {
+                        rows.push(row);
+                    }
+                }
+                table.rows = rows;
+            }
+            SourceTransformationStep::Dedup { columns } => {
+                let indices = property_indices(table, columns)?;
+                let mut seen = HashSet::new();
+                table.rows.retain(|row| {
+                    seen.insert(
+                        indices
+                            .iter()
+                            .map(|(_, idx)| row[*idx].to_string())
+                            .collect::<Vec<_>>(),
+                    )
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VertexKeyMapping {
+    fn key(&self, value: &FeatureValue) -> VertexKey {
+        VertexKey {
+            tlabel: self.tlabel.clone(),
+            primary_key: self.primary_key.clone(),
+            value: value.clone(),
+        }
+    }
+}
+
+fn property_indices<'a>(
+    table: &SourceTable,
+    columns: &'a [String],
+) -> Result<Vec<(&'a String, usize)>, Box<dyn Error>> {
+    columns
+        .iter()
+        .map(|c| Ok((c, table.column_index(c)?)))
+        .collect()
+}
+
+fn row_properties(
+    row: &[FeatureValue],
+    indices: &[(&String, usize)],
+) -> HashMap<String, FeatureValue> {
+    indices
+        .iter()
+        .filter(|(_, idx)| !row[*idx].is_null())
+        .map(|(name, idx)| ((*name).clone(), row[*idx].clone()))
+        .collect()
+}
 
 // pub trait OfflineSourceIngestion {
 //     fn ingest(&self, graph_data_handler: &GraphDataHandler) -> Result<(), Box<dyn Error>>;
@@ -53,3 +579,110 @@ pub enum SourceTransformation {}
 //         unimplemented!()
 //     }
 // }
+
+#[test]
+fn split_review_table_into_graph() {
+    let table = SourceTable::from_csv(
+        "review,reviewer,product,overall\n\
+         r1,u1,p1,5\n\
+         r2,u1,p2,2\n\
+         r2,u1,p2,2\n\
+         r3,u2,p1,\"4\"\n",
+        ',',
+    )
+    .unwrap();
+    let transformation = SourceTransformation {
+        steps: vec![
+            SourceTransformationStep::Dedup {
+                columns: vec!["review".to_string()],
+            },
+            SourceTransformationStep::Cast {
+                column: "overall".to_string(),
+                value_type: FeatureValueType::Int,
+            },
+            SourceTransformationStep::Filter {
+                column: "overall".to_string(),
+                predicate: Predicate::Ge("3".to_string()),
+            },
+            SourceTransformationStep::Rename {
+                from: "review".to_string(),
+                to: "id".to_string(),
+            },
+        ],
+        mappings: vec![
+            GraphMapping::Vertex {
+                tlabel: "Review".to_string(),
+                key: "id".to_string(),
+                properties: vec!["overall".to_string()],
+            },
+            GraphMapping::Vertex {
+                tlabel: "Reviewer".to_string(),
+                key: "reviewer".to_string(),
+                properties: vec![],
+            },
+            GraphMapping::Edge {
+                tlabel: "isWrittenBy".to_string(),
+                src: VertexKeyMapping {
+                    tlabel: "Review".to_string(),
+                    primary_key: "id".to_string(),
+                    column: "id".to_string(),
+                },
+                dst: VertexKeyMapping {
+                    tlabel: "Reviewer".to_string(),
+                    primary_key: "reviewer".to_string(),
+                    column: "reviewer".to_string(),
+                },
+                properties: vec![],
+            },
+        ],
+    };
+    let mut mistyped = transformation.clone();
+    if let GraphMapping::Edge { dst, .. } = &mut mistyped.mappings[2] {
+        dst.primary_key = "reviewerId".to_string();
+    }
+    assert!(mistyped.apply(table.clone()).is_err());
+    let records = transformation.apply(table).unwrap();
+    assert_eq!(records.vertices.len(), 4);
+    assert_eq!(records.edges.len(), 2);
+    assert_eq!(
+        records.vertices[1].properties.get("overall"),
+        Some(&FeatureValue::Int(4))
+    );
+    assert_eq!(
+        records.edges[1].dst.value,
+        FeatureValue::String("u2".to_string())
+    );
+}
+
+#[test]
+fn serialize_data_source() {
+    let data_source = DataSource::new_csv_source(
+        "reviews",
+        Some("v1".to_string()),
+        "data/reviews.csv",
+        Some(SourceTransformation {
+            steps: vec![SourceTransformationStep::Cast {
+                column: "overall".to_string(),
+                value_type: FeatureValueType::Int,
+            }],
+            mappings: vec![GraphMapping::Vertex {
+                tlabel: "Review".to_string(),
+                key: "id".to_string(),
+                properties: vec!["overall".to_string()],
+            }],
+        }),
+    );
+    let serialized = serde_json::to_string(&data_source).unwrap();
+    let deserialized: DataSource = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(deserialized.resource_id(), "DataSource/reviews/v1");
+    assert_eq!(serde_json::to_string(&deserialized).unwrap(), serialized);
+    assert_eq!(
+        deserialized.ingested_tlabels(),
+        (vec!["Review".to_string()], vec![])
+    );
+    // the creation time is kept to the second
+    assert_eq!(
+        deserialized.created_at.map(|t| t.timestamp()),
+        data_source.created_at.map(|t| t.timestamp())
+    );
+}
@@ -3,6 +3,7 @@ mod feature_view;
 mod field;
 mod graph;
 mod transformation;
+mod value;
 
 use std::fmt::Debug;
 
@@ -13,6 +14,7 @@ pub use feature_view::{FeatureView, TableFeatureView, TopologyFeatureView, Topol
 pub use field::Field;
 pub use graph::{Graph, Topology};
 pub use transformation::{Transformation, TransformationType};
+pub use value::FeatureValue;
 
 pub type ResourceId = String;
 
@@ -23,7 +25,7 @@ pub trait ResourceOp: Serialize + DeserializeOwned + Debug + Clone {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum FeatureValueType {
     String,
     Int,
@@ -20,6 +20,9 @@ pub struct Entity {
     pub last_updated_timestamp: Option<DateTime<Utc>>,
     pub tags: HashMap<String, String>,
     pub owners: Vec<String>,
+    /// The data sources that populate this entity
+    #[serde(default)]
+    pub data_source_ids: Vec<ResourceId>,
 }
 
 /// per node/edge type
@@ -57,6 +60,7 @@ impl Entity {
             last_updated_timestamp: None,
             tags: HashMap::new(),
             owners: Vec::new(),
+            data_source_ids: Vec::new(),
         }
     }
 
@@ -76,6 +80,24 @@ impl Entity {
         )
     }
 
+    pub fn tlabel(&self) -> &str {
+        match &self.entity_type {
+            EntityType::NodeEntity { tlabel } => tlabel,
+            EntityType::EdgeEntity { tlabel } => tlabel,
+        }
+    }
+
+    /// Returns the source and destination entity ids of an edge entity
+    pub fn edge_endpoint_ids(&self) -> Option<(ResourceId, ResourceId)> {
+        match &self.entity_type {
+            EntityType::NodeEntity { .. } => None,
+            EntityType::EdgeEntity { .. } => self
+                .primary_key
+                .split_once('|')
+                .map(|(src, dst)| (src.to_string(), dst.to_string())),
+        }
+    }
+
     // TODO: The edge primary key is now set as `{src_entity_id}|{dst_entity_id}`
     pub fn new_edge_entity(
         name: &str,
@@ -12,6 +12,22 @@ pub enum FeatureView {
     TopologyFeatureView(TopologyFeatureView),
 }
 
+impl FeatureView {
+    pub fn resource_id(&self) -> ResourceId {
+        match self {
+            FeatureView::TableFeatureView(view) => view.resource_id(),
+            FeatureView::TopologyFeatureView(view) => view.resource_id(),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        match self {
+            FeatureView::TableFeatureView(view) => view.online,
+            FeatureView::TopologyFeatureView(view) => view.online,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TableFeatureView {
     pub name: String,
@@ -15,6 +15,9 @@ pub struct Field {
     pub value_type: FeatureValueType,
     pub entity_id: String,
     pub transformation_id: Option<String>,
+    /// The data source the field is ingested from, if it is not derived by a transformation
+    #[serde(default)]
+    pub data_source_id: Option<ResourceId>,
     pub description: Option<String>,
     pub tags: HashMap<String, String>,
     pub owners: Vec<String>,
@@ -51,10 +54,54 @@ impl Field {
                 value_type: name_type.1.clone(),
                 entity_id: entity.resource_id(),
                 transformation_id: None,
+                data_source_id: None,
                 description: None,
                 tags: HashMap::new(),
                 owners: Vec::new(),
             })
             .collect()
     }
+
+    /// Returns the ids of the raw data sources the field is derived from: the source it is
+    /// ingested from, or else the sources that populate its entity, which is needed then
+    pub fn data_source_ids(&self, entity: Option<&Entity>) -> Vec<ResourceId> {
+        match (&self.data_source_id, entity) {
+            (Some(id), _) => vec![id.clone()],
+            (None, Some(entity)) => entity.data_source_ids.clone(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+#[test]
+fn resolve_field_data_sources() {
+    let mut movie = Entity::new_node_entity("movie", None, "Movie", "title");
+    movie.data_source_ids = vec![
+        "DataSource/movies/".to_string(),
+        "DataSource/imdb/".to_string(),
+    ];
+    let mut fields = Field::new_fields(
+        vec![
+            ("year", FeatureValueType::Int),
+            ("rating", FeatureValueType::Float),
+        ],
+        &movie,
+        None,
+    );
+    fields[1].data_source_id = Some("DataSource/ratings/".to_string());
+
+    // a field without a source of its own falls back to the sources of its entity
+    assert_eq!(
+        fields[0].data_source_ids(Some(&movie)),
+        movie.data_source_ids
+    );
+    assert!(fields[0].data_source_ids(None).is_empty());
+    assert_eq!(
+        fields[1].data_source_ids(Some(&movie)),
+        vec!["DataSource/ratings/".to_string()]
+    );
+    assert_eq!(
+        fields[1].data_source_ids(None),
+        vec!["DataSource/ratings/".to_string()]
+    );
 }
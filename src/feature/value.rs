@@ -0,0 +1,211 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::Display;
+
+use super::FeatureValueType;
+
+/// A single typed feature value. Durations are kept in seconds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FeatureValue {
+    Null,
+    String(String),
+    Int(i64),
+    Float(f64),
+    Boolean(bool),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    DateTime(DateTime<Utc>),
+    Duration(i64),
+    Array(Vec<FeatureValue>),
+}
+
+impl FeatureValue {
+    /// Parses a raw string read from a source into a value of the given type. Empty strings are
+    /// parsed as `Null` for all types but `String`.
+    pub fn parse(raw: &str, value_type: &FeatureValueType) -> Result<Self, Box<dyn Error>> {
+        let s = raw.trim();
+        if s.is_empty() && !matches!(value_type, FeatureValueType::String) {
+            return Ok(FeatureValue::Null);
+        }
+        let res = match value_type {
+            FeatureValueType::String => FeatureValue::String(raw.to_string()),
+            FeatureValueType::Int => FeatureValue::Int(s.parse()?),
+            FeatureValueType::Float => FeatureValue::Float(s.parse()?),
+            FeatureValueType::Boolean => match s.to_lowercase().as_str() {
+                "true" | "1" => FeatureValue::Boolean(true),
+                "false" | "0" => FeatureValue::Boolean(false),
+                _ => return Err(format!("cannot parse {} as Boolean", s).into()),
+            },
+            FeatureValueType::Date => FeatureValue::Date(NaiveDate::parse_from_str(s, "%Y-%m-%d")?),
+            FeatureValueType::Time => FeatureValue::Time(NaiveTime::parse_from_str(s, "%H:%M:%S")?),
+            FeatureValueType::DateTime => match s.parse::<i64>() {
+                // unix timestamps in seconds are common in raw data
+                Ok(secs) => FeatureValue::DateTime(
+                    Utc.timestamp_opt(secs, 0)
+                        .single()
+                        .ok_or_else(|| format!("timestamp {} is out of range", secs))?,
+                ),
+                Err(_) => {
+                    FeatureValue::DateTime(DateTime::parse_from_rfc3339(s)?.with_timezone(&Utc))
+                }
+            },
+            FeatureValueType::Duration => FeatureValue::Duration(s.parse()?),
+            FeatureValueType::Array(_) => {
+                FeatureValue::from_json(&serde_json::from_str(s)?, value_type)?
+            }
+            FeatureValueType::Topology => {
+                return Err("Topology values cannot be parsed from a string".into())
+            }
+        };
+        Ok(res)
+    }
+
+    /// Converts a JSON value into a value of the given type
+    pub fn from_json(
+        json: &serde_json::Value,
+        value_type: &FeatureValueType,
+    ) -> Result<Self, Box<dyn Error>> {
+        use serde_json::Value;
+        match (json, value_type) {
+            (Value::Null, _) => Ok(FeatureValue::Null),
+            (Value::Array(values), FeatureValueType::Array(element_type)) => {
+                Ok(FeatureValue::Array(
+                    values
+                        .iter()
+                        .map(|v| FeatureValue::from_json(v, element_type))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ))
+            }
+            (Value::String(s), _) => FeatureValue::parse(s, value_type),
+            (v, _) => FeatureValue::parse(&v.to_string(), value_type),
+        }
+    }
+
+    /// Converts the value to the given type, going through its string representation if there is
+    /// no direct conversion
+    pub fn cast(&self, value_type: &FeatureValueType) -> Result<Self, Box<dyn Error>> {
+        match (self, value_type) {
+            (FeatureValue::Null, _) => Ok(FeatureValue::Null),
+            (FeatureValue::Int(v), FeatureValueType::Float) => Ok(FeatureValue::Float(*v as f64)),
+            (FeatureValue::Float(v), FeatureValueType::Int) => Ok(FeatureValue::Int(*v as i64)),
+            (FeatureValue::Array(values), FeatureValueType::Array(element_type)) => {
+                Ok(FeatureValue::Array(
+                    values
+                        .iter()
+                        .map(|v| v.cast(element_type))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ))
+            }
+            (FeatureValue::String(s), _) => FeatureValue::parse(s, value_type),
+            (v, _) => FeatureValue::parse(&v.to_string(), value_type),
+        }
+    }
+
+    /// Converts a JSON value into a value of the type it looks like: integers are Int, other
+    /// numbers Float, and objects are kept as their JSON string
+    pub fn infer_from_json(json: &serde_json::Value) -> Self {
+        use serde_json::Value;
+        match json {
+            Value::Null => FeatureValue::Null,
+            Value::Bool(v) => FeatureValue::Boolean(*v),
+            Value::Number(v) => match v.as_i64() {
+                Some(v) => FeatureValue::Int(v),
+                None => FeatureValue::Float(v.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(v) => FeatureValue::String(v.clone()),
+            Value::Array(values) => {
+                FeatureValue::Array(values.iter().map(FeatureValue::infer_from_json).collect())
+            }
+            Value::Object(_) => FeatureValue::String(json.to_string()),
+        }
+    }
+
+    /// Returns the type of the value, None for Null. Arrays take the type of their first non-null
+    /// element, String if there is none.
+    pub fn value_type(&self) -> Option<FeatureValueType> {
+        let res = match self {
+            FeatureValue::Null => return None,
+            FeatureValue::String(_) => FeatureValueType::String,
+            FeatureValue::Int(_) => FeatureValueType::Int,
+            FeatureValue::Float(_) => FeatureValueType::Float,
+            FeatureValue::Boolean(_) => FeatureValueType::Boolean,
+            FeatureValue::Date(_) => FeatureValueType::Date,
+            FeatureValue::Time(_) => FeatureValueType::Time,
+            FeatureValue::DateTime(_) => FeatureValueType::DateTime,
+            FeatureValue::Duration(_) => FeatureValueType::Duration,
+            FeatureValue::Array(values) => FeatureValueType::Array(Box::new(
+                values
+                    .iter()
+                    .find_map(|v| v.value_type())
+                    .unwrap_or(FeatureValueType::String),
+            )),
+        };
+        Some(res)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, FeatureValue::Null)
+    }
+
+    /// Compares two values of the same type, with Int and Float compared numerically. Returns None
+    /// for incomparable values, including Null.
+    pub fn compare(&self, other: &FeatureValue) -> Option<Ordering> {
+        match (self, other) {
+            (FeatureValue::String(a), FeatureValue::String(b)) => a.partial_cmp(b),
+            (FeatureValue::Int(a), FeatureValue::Int(b)) => a.partial_cmp(b),
+            (FeatureValue::Float(a), FeatureValue::Float(b)) => a.partial_cmp(b),
+            (FeatureValue::Int(a), FeatureValue::Float(b)) => (*a as f64).partial_cmp(b),
+            (FeatureValue::Float(a), FeatureValue::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (FeatureValue::Boolean(a), FeatureValue::Boolean(b)) => a.partial_cmp(b),
+            (FeatureValue::Date(a), FeatureValue::Date(b)) => a.partial_cmp(b),
+            (FeatureValue::Time(a), FeatureValue::Time(b)) => a.partial_cmp(b),
+            (FeatureValue::DateTime(a), FeatureValue::DateTime(b)) => a.partial_cmp(b),
+            (FeatureValue::Duration(a), FeatureValue::Duration(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl Display for FeatureValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FeatureValue::Null => Ok(()),
+            FeatureValue::String(v) => f.write_str(v),
+            FeatureValue::Int(v) => write!(f, "{}", v),
+            FeatureValue::Float(v) => write!(f, "{}", v),
+            FeatureValue::Boolean(v) => write!(f, "{}", v),
+            FeatureValue::Date(v) => write!(f, "{}", v.format("%Y-%m-%d")),
+            FeatureValue::Time(v) => write!(f, "{}", v.format("%H:%M:%S")),
+            FeatureValue::DateTime(v) => f.write_str(&v.to_rfc3339()),
+            FeatureValue::Duration(v) => write!(f, "{}", v),
+            FeatureValue::Array(values) => {
+                f.write_str("[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    match v {
+                        FeatureValue::String(_)
+                        | FeatureValue::Date(_)
+                        | FeatureValue::Time(_)
+                        | FeatureValue::DateTime(_) => write!(f, "{:?}", v.to_string())?,
+                        FeatureValue::Null => f.write_str("null")?,
+                        _ => write!(f, "{}", v)?,
+                    }
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+#[test]
+fn parse_timestamps() {
+    assert_eq!(
+        FeatureValue::parse("86400", &FeatureValueType::DateTime).unwrap(),
+        FeatureValue::parse("1970-01-02T00:00:00Z", &FeatureValueType::DateTime).unwrap()
+    );
+    assert!(FeatureValue::parse(&i64::MAX.to_string(), &FeatureValueType::DateTime).is_err());
+}
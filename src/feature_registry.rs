@@ -7,6 +7,18 @@ pub struct FeatureRegistry {
     pub storage: EtcdStorage,
 }
 
+/// The kinds of registered resources, which prefix their ids
+pub const RESOURCE_KINDS: [&str; 8] = [
+    "Entity",
+    "Field",
+    "DataSource",
+    "Transformation",
+    "TableFeatureView",
+    "TopologyFeatureView",
+    "Topology",
+    "Graph",
+];
+
 impl FeatureRegistry {
     pub async fn register_resource(
         &self,
@@ -30,8 +42,15 @@ impl FeatureRegistry {
     }
 
     // TODO(tatiana): TBD, provide range getter interface
-    pub async fn get_entities(&self) -> Result<Vec<String>, Box<dyn Error>> {
-        self.storage.get_all("Entity").await
+    pub async fn get_entities(&self) -> Result<Vec<Entity>, Box<dyn Error>> {
+        let values: Result<Vec<Entity>, serde_json::Error> = self
+            .storage
+            .get_all("Entity/")
+            .await?
+            .iter()
+            .map(|jstr| serde_json::from_str::<Entity>(jstr))
+            .collect();
+        Ok(values?)
     }
 
     pub async fn get_entity_fields(&self, entity_name: &str) -> Result<Vec<Field>, Box<dyn Error>> {
@@ -66,6 +85,57 @@ impl FeatureRegistry {
         Ok(table_feature_view)
     }
 
+    pub async fn get_topology(&self, topology_id: &ResourceId) -> Result<Topology, Box<dyn Error>> {
+        let value = self.storage.get(topology_id).await?;
+        let topology = serde_json::from_str::<Topology>(&value)?;
+        Ok(topology)
+    }
+
+    /// Returns all registered table and topology feature views
+    pub async fn get_feature_views(&self) -> Result<Vec<FeatureView>, Box<dyn Error>> {
+        let mut res = Vec::new();
+        for jstr in self.storage.get_all("TableFeatureView/").await? {
+            res.push(FeatureView::TableFeatureView(serde_json::from_str(&jstr)?));
+        }
+        for jstr in self.storage.get_all("TopologyFeatureView/").await? {
+            res.push(FeatureView::TopologyFeatureView(serde_json::from_str(
+                &jstr,
+            )?));
+        }
+        Ok(res)
+    }
+
+    /// Returns the entities whose data is served by the feature view, including the endpoint
+    /// entities of the edges in a topology feature view
+    pub async fn get_feature_view_entities(
+        &self,
+        feature_view: &FeatureView,
+    ) -> Result<Vec<Entity>, Box<dyn Error>> {
+        let mut entity_ids = Vec::new();
+        match feature_view {
+            FeatureView::TableFeatureView(view) => entity_ids.push(view.entity_id.clone()),
+            FeatureView::TopologyFeatureView(view) => {
+                for topology_id in &view.topology_ids {
+                    let topology = self.get_topology(topology_id).await?;
+                    entity_ids.extend(topology.edge_entity_ids);
+                }
+            }
+        }
+        let mut res: Vec<Entity> = Vec::new();
+        while let Some(id) = entity_ids.pop() {
+            if res.iter().any(|e| e.resource_id() == id) {
+                continue;
+            }
+            let entity = self.get_entity(&id).await?;
+            if let Some((src, dst)) = entity.edge_endpoint_ids() {
+                entity_ids.push(src);
+                entity_ids.push(dst);
+            }
+            res.push(entity);
+        }
+        Ok(res)
+    }
+
     pub async fn get_transformation(
         &self,
         transformation_id: &ResourceId,
@@ -75,6 +145,57 @@ impl FeatureRegistry {
         Ok(transformation)
     }
 
+    pub async fn get_data_source(
+        &self,
+        data_source_id: &ResourceId,
+    ) -> Result<DataSource, Box<dyn Error>> {
+        let value = self.storage.get(data_source_id).await?;
+        let data_source = serde_json::from_str::<DataSource>(&value)?;
+        Ok(data_source)
+    }
+
+    pub async fn get_data_sources(&self) -> Result<Vec<DataSource>, Box<dyn Error>> {
+        let values: Result<Vec<DataSource>, serde_json::Error> = self
+            .storage
+            .get_all("DataSource/")
+            .await?
+            .iter()
+            .map(|jstr| serde_json::from_str::<DataSource>(jstr))
+            .collect();
+        Ok(values?)
+    }
+
+    /// Returns the raw data sources a field is derived from. A field ingested directly refers to
+    /// its source, otherwise the sources that populate its entity are returned.
+    pub async fn get_field_data_sources(
+        &self,
+        field: &Field,
+    ) -> Result<Vec<DataSource>, Box<dyn Error>> {
+        let entity = match &field.data_source_id {
+            Some(_) => None,
+            None => Some(self.get_entity(&field.entity_id).await?),
+        };
+        let mut res = Vec::new();
+        for id in &field.data_source_ids(entity.as_ref()) {
+            res.push(self.get_data_source(id).await?);
+        }
+        Ok(res)
+    }
+
+    /// Returns the ids of all registered resources
+    pub async fn get_resource_ids(&self) -> Result<Vec<ResourceId>, Box<dyn Error>> {
+        let mut res = Vec::new();
+        for kind in RESOURCE_KINDS {
+            res.extend(self.storage.keys(&format!("{}/", kind)).await?);
+        }
+        Ok(res)
+    }
+
+    pub async fn delete_resource(&self, resource_id: &ResourceId) -> Result<(), Box<dyn Error>> {
+        info!("Deleting resource: {}", resource_id);
+        self.storage.delete(resource_id).await
+    }
+
     pub async fn get_graph(&self, graph_id: &ResourceId) -> Result<Graph, Box<dyn Error>> {
         let value = self.storage.get(graph_id).await?;
         let graph = serde_json::from_str::<Graph>(&value)?;
@@ -0,0 +1,169 @@
+use crate::{EdgeRecord, FeatureValue, GraphRecords, GraphSchemaItem, SchemaPlan, VertexRecord};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::error::Error;
+
+mod client;
+mod introspection;
+mod memory;
+mod neo4j;
+mod property_graph;
+#[cfg(test)]
+mod scripted;
+mod validation;
+
+pub use client::*;
+pub use introspection::*;
+pub use memory::*;
+pub use neo4j::*;
+pub use property_graph::*;
+#[cfg(test)]
+pub use scripted::*;
+pub use validation::*;
+
+/// A vertex read from the graph database
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphNode {
+    pub id: i64,
+    pub labels: Vec<String>,
+    pub properties: HashMap<String, FeatureValue>,
+}
+
+/// An edge read from the graph database
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphRelationship {
+    pub id: i64,
+    pub tlabel: String,
+    pub src_id: i64,
+    pub dst_id: i64,
+    pub properties: HashMap<String, FeatureValue>,
+}
+
+/// A path read from the graph database: its vertices in the order they are visited, and the
+/// edges between consecutive vertices
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphPath {
+    pub nodes: Vec<GraphNode>,
+    pub relationships: Vec<GraphRelationship>,
+}
+
+/// A value in a row returned by a query
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphValue {
+    Value(FeatureValue),
+    Node(GraphNode),
+    Relationship(GraphRelationship),
+    Path(GraphPath),
+    /// A list holding vertices, edges or paths. Lists of other values are `Value`s.
+    List(Vec<GraphValue>),
+}
+
+/// The columns and rows returned by a query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GraphRows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<GraphValue>>,
+}
+
+impl GraphRows {
+    pub fn get(&self, row: usize, column: &str) -> Option<&GraphValue> {
+        let i = self.columns.iter().position(|c| c == column)?;
+        self.rows.get(row)?.get(i)
+    }
+}
+
+/// An edge type and the tlabels of the vertices it connects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphEdgeType {
+    pub tlabel: String,
+    /// (source tlabel, destination tlabel) pairs
+    pub endpoints: Vec<(String, String)>,
+}
+
+#[async_trait(?Send)]
+pub trait GraphRowStream {
+    fn columns(&self) -> &[String];
+    /// Returns the next row, or None once all rows are read
+    async fn next(&mut self) -> Result<Option<Vec<GraphValue>>, Box<dyn Error>>;
+}
+
+/// A property graph backend. Vertices are identified by their `VertexKey`: a tlabel and the value
+/// of its primary key property.
+#[async_trait(?Send)]
+pub trait GraphDatabase {
+    /// Runs a query in the query language of the backend and collects its rows
+    async fn run_query(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<GraphRows, Box<dyn Error>>;
+
+    /// Runs a query in the query language of the backend, reading its rows lazily
+    async fn stream_rows(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<Box<dyn GraphRowStream>, Box<dyn Error>>;
+
+    /// Creates the vertices, or updates the properties of the ones with the same key
+    async fn upsert_vertices(&self, vertices: &[VertexRecord]) -> Result<(), Box<dyn Error>>;
+
+    /// Creates the edges, or updates the properties of the ones with the same tlabel between the
+    /// same vertices. Edges whose endpoints do not exist are skipped.
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<(), Box<dyn Error>>;
+
+    async fn scan_vertices(&self, tlabel: &str) -> Result<Vec<GraphNode>, Box<dyn Error>>;
+
+    async fn scan_edges(&self, tlabel: &str) -> Result<Vec<GraphRelationship>, Box<dyn Error>>;
+
+    /// Returns up to `limit` vertices of the tlabel
+    async fn sample_vertices(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphNode>, Box<dyn Error>>;
+
+    /// Returns up to `limit` edges of the tlabel
+    async fn sample_edges(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphRelationship>, Box<dyn Error>>;
+
+    /// Returns the tlabels of the vertices in the graph
+    async fn vertex_types(&self) -> Result<Vec<String>, Box<dyn Error>>;
+
+    /// Returns the edge types in the graph, with the endpoint tlabels found in up to
+    /// `sample_size` edges of each type
+    async fn edge_types(&self, sample_size: usize) -> Result<Vec<GraphEdgeType>, Box<dyn Error>>;
+
+    /// Returns the constraints and indexes
+    async fn schema(&self) -> Result<Vec<GraphSchemaItem>, Box<dyn Error>>;
+
+    async fn apply_schema(&self, plan: &SchemaPlan) -> Result<(), Box<dyn Error>>;
+
+    async fn count_vertices(&self, tlabel: &str) -> Result<usize, Box<dyn Error>>;
+
+    async fn count_edges(&self, tlabel: &str) -> Result<usize, Box<dyn Error>>;
+
+    /// Deletes the vertices of the tlabel with their edges, committing every `batch_size`
+    /// vertices. Returns the number of deleted vertices.
+    async fn delete_vertices(
+        &self,
+        tlabel: &str,
+        batch_size: usize,
+    ) -> Result<usize, Box<dyn Error>>;
+
+    /// Deletes the edges of the tlabel, committing every `batch_size` edges. Returns the number
+    /// of deleted edges.
+    async fn delete_edges(&self, tlabel: &str, batch_size: usize) -> Result<usize, Box<dyn Error>>;
+
+    /// Deletes all vertices and edges, keeping the schema
+    async fn clear(&self) -> Result<(), Box<dyn Error>>;
+
+    /// Upserts the vertices first so that the edges find their endpoints
+    async fn upsert_records(&self, records: &GraphRecords) -> Result<(), Box<dyn Error>> {
+        self.upsert_vertices(&records.vertices).await?;
+        self.upsert_edges(&records.edges).await
+    }
+}
@@ -0,0 +1,503 @@
+use async_trait::async_trait;
+use bolt_client::bolt_proto::message::{Failure, Success};
+use bolt_client::bolt_proto::version::{V4_0, V4_1, V4_2, V4_3};
+use bolt_client::bolt_proto::{Message, ServerState, Value};
+use bolt_client::{Metadata, Params, Stream};
+use deadpool::managed::{self, Object, PoolConfig, PoolError, RecycleResult, Timeouts};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Display;
+use std::time::Duration;
+use tokio::io::BufStream;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+use crate::GraphDatabaseCredentials;
+
+type BoltClient = bolt_client::Client<Compat<BufStream<Stream>>>;
+
+/// Settings of the graph database client, read from the `gdb` section of `feature_store.json`.
+/// Durations are in milliseconds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GraphClientConfig {
+    pub max_connections: usize,
+    pub connect_timeout_ms: u64,
+    /// How long to wait for a free connection when all of them are in use
+    pub acquire_timeout_ms: u64,
+    pub query_timeout_ms: u64,
+    /// Retries of a request failing with a transient error, the wait doubling from
+    /// `retry_backoff_ms` after each attempt
+    pub max_retries: u32,
+    pub retry_backoff_ms: u64,
+    /// Server name to verify the certificate against. TLS is enabled when set, or when the uri
+    /// scheme ends with `+s` (e.g. `neo4j+s://`), in which case the uri host is used.
+    pub tls_domain: Option<String>,
+    /// Database to run queries against, the server default if None
+    pub database: Option<String>,
+}
+
+impl Default for GraphClientConfig {
+    fn default() -> Self {
+        GraphClientConfig {
+            max_connections: 16,
+            connect_timeout_ms: 5_000,
+            acquire_timeout_ms: 30_000,
+            query_timeout_ms: 300_000,
+            max_retries: 3,
+            retry_backoff_ms: 200,
+            tls_domain: None,
+            database: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GraphClientError {
+    /// The connection could not be established or was lost
+    Connection(String),
+    /// The server rejected the request, e.g. with code `Neo.ClientError.Statement.SyntaxError`
+    Server {
+        code: String,
+        message: String,
+    },
+    /// The server answered with a message the client did not expect
+    Protocol(String),
+    Timeout(String),
+}
+
+impl GraphClientError {
+    /// Whether the request may succeed if sent again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            GraphClientError::Connection(_) => true,
+            GraphClientError::Server { code, .. } => code.starts_with("Neo.TransientError."),
+            _ => false,
+        }
+    }
+
+    fn from_failure(failure: &Failure) -> Self {
+        let get = |key: &str| match failure.metadata().get(key) {
+            Some(Value::String(s)) => s.clone(),
+            _ => String::new(),
+        };
+        GraphClientError::Server {
+            code: get("code"),
+            message: get("message"),
+        }
+    }
+}
+
+impl Display for GraphClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphClientError::Connection(msg) => {
+                write!(f, "graph database connection error: {}", msg)
+            }
+            GraphClientError::Server { code, message } => {
+                write!(f, "graph database error {}: {}", code, message)
+            }
+            GraphClientError::Protocol(msg) => write!(f, "graph database protocol error: {}", msg),
+            GraphClientError::Timeout(msg) => write!(f, "graph database timeout: {}", msg),
+        }
+    }
+}
+
+impl Error for GraphClientError {}
+
+impl From<bolt_client::error::ConnectionError> for GraphClientError {
+    fn from(e: bolt_client::error::ConnectionError) -> Self {
+        GraphClientError::Connection(e.to_string())
+    }
+}
+
+impl From<bolt_client::error::CommunicationError> for GraphClientError {
+    fn from(e: bolt_client::error::CommunicationError) -> Self {
+        match e {
+            bolt_client::error::CommunicationError::IoError(e) => {
+                GraphClientError::Connection(e.to_string())
+            }
+            e => GraphClientError::Protocol(e.to_string()),
+        }
+    }
+}
+
+fn expect_success(response: Message) -> Result<Success, GraphClientError> {
+    match response {
+        Message::Success(success) => Ok(success),
+        Message::Failure(failure) => Err(GraphClientError::from_failure(&failure)),
+        other => Err(GraphClientError::Protocol(format!(
+            "unexpected response {:?}",
+            other
+        ))),
+    }
+}
+
+/// A query and its parameters
+#[derive(Debug, Clone)]
+pub struct GraphStatement {
+    pub query: String,
+    pub params: HashMap<String, Value>,
+}
+
+impl GraphStatement {
+    pub fn new(query: &str) -> Self {
+        GraphStatement {
+            query: query.to_string(),
+            params: HashMap::new(),
+        }
+    }
+
+    pub fn param(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// The columns and rows returned by a query
+#[derive(Debug, Clone, Default)]
+pub struct BoltQueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl BoltQueryResult {
+    pub fn get(&self, row: usize, column: &str) -> Option<&Value> {
+        let i = self.columns.iter().position(|c| c == column)?;
+        self.rows.get(row)?.get(i)
+    }
+}
+
+struct BoltManager {
+    address: String,
+    domain: Option<String>,
+    user: String,
+    pass: String,
+    connect_timeout: Duration,
+}
+
+#[async_trait]
+impl managed::Manager<BoltClient, GraphClientError> for BoltManager {
+    async fn create(&self) -> Result<BoltClient, GraphClientError> {
+        let stream = tokio::time::timeout(
+            self.connect_timeout,
+            Stream::connect(self.address.as_str(), self.domain.as_deref()),
+        )
+        .await
+        .map_err(|_| {
+            GraphClientError::Connection(format!("timed out connecting to {}", self.address))
+        })?
+        .map_err(|e| GraphClientError::Connection(e.to_string()))?;
+        let mut client =
+            BoltClient::new(BufStream::new(stream).compat(), &[V4_3, V4_2, V4_1, V4_0]).await?;
+        expect_success(
+            client
+                .hello(Metadata::from_iter(vec![
+                    ("user_agent", "gfs/0.1"),
+                    ("scheme", "basic"),
+                    ("principal", &self.user),
+                    ("credentials", &self.pass),
+                ]))
+                .await?,
+        )?;
+        Ok(client)
+    }
+
+    async fn recycle(&self, client: &mut BoltClient) -> RecycleResult<GraphClientError> {
+        // a failed request leaves the connection in the Failed state, which RESET clears
+        if client.server_state() != ServerState::Ready {
+            expect_success(client.reset().await.map_err(GraphClientError::from)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// A pooled async client of a bolt graph database. Clones share the same pool.
+#[derive(Clone)]
+pub struct GraphClient {
+    pool: managed::Pool<BoltClient, GraphClientError>,
+    config: GraphClientConfig,
+}
+
+impl GraphClient {
+    /// Creates the pool. Connections are opened lazily, on first use.
+    pub fn new(
+        credentials: &GraphDatabaseCredentials,
+        config: GraphClientConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (scheme, address) = match credentials.uri.split_once("://") {
+            Some((scheme, address)) => (scheme, address.trim_end_matches('/')),
+            None => ("bolt", credentials.uri.as_str()),
+        };
+        let host = address
+            .rsplit_once(':')
+            .map_or(address, |(host, _port)| host);
+        let address = match address.contains(':') {
+            true => address.to_string(),
+            false => format!("{}:7687", address),
+        };
+        let domain = match (&config.tls_domain, scheme.ends_with("+s")) {
+            (Some(domain), _) => Some(domain.clone()),
+            (None, true) => Some(host.to_string()),
+            (None, false) => None,
+        };
+        if config.max_connections == 0 {
+            return Err("max_connections must be positive".into());
+        }
+        let manager = BoltManager {
+            address,
+            domain,
+            user: credentials.user.clone(),
+            pass: credentials.pass.clone(),
+            connect_timeout: Duration::from_millis(config.connect_timeout_ms),
+        };
+        let pool = managed::Pool::from_config(
+            manager,
+            PoolConfig {
+                max_size: config.max_connections,
+                timeouts: Timeouts {
+                    wait: Some(Duration::from_millis(config.acquire_timeout_ms)),
+                    create: None,
+                    recycle: Some(Duration::from_millis(config.connect_timeout_ms)),
+                },
+            },
+        );
+        Ok(GraphClient { pool, config })
+    }
+
+    pub fn config(&self) -> &GraphClientConfig {
+        &self.config
+    }
+
+    pub async fn execute(
+        &self,
+        statement: GraphStatement,
+    ) -> Result<BoltQueryResult, Box<dyn Error>> {
+        let mut results = self.with_retries(&[statement], false).await?;
+        Ok(results.remove(0))
+    }
+
+    /// Runs the statements in a single transaction, committed only if all of them succeed
+    pub async fn execute_in_transaction(
+        &self,
+        statements: &[GraphStatement],
+    ) -> Result<Vec<BoltQueryResult>, Box<dyn Error>> {
+        Ok(self.with_retries(statements, true).await?)
+    }
+
+    /// Runs the statement and returns a stream pulling its rows `fetch_size` at a time. The
+    /// connection is held by the stream until all rows are pulled or the stream is dropped.
+    pub async fn stream(
+        &self,
+        statement: GraphStatement,
+        fetch_size: usize,
+    ) -> Result<BoltRowStream, Box<dyn Error>> {
+        let mut backoff = Duration::from_millis(self.config.retry_backoff_ms);
+        let mut retries = 0;
+        loop {
+            let mut client = self.acquire().await?;
+            let res = client
+                .run(
+                    statement.query.clone(),
+                    Some(Params::from(statement.params.clone())),
+                    self.database_metadata(),
+                )
+                .await
+                .map_err(GraphClientError::from)
+                .and_then(expect_success);
+            match res {
+                Ok(success) => {
+                    return Ok(BoltRowStream {
+                        columns: columns(&success),
+                        client: Some(client),
+                        buffer: VecDeque::new(),
+                        fetch_size: fetch_size.max(1) as i64,
+                    })
+                }
+                Err(e) if e.is_transient() && retries < self.config.max_retries => {
+                    if let GraphClientError::Connection(_) = e {
+                        Object::take(client);
+                    }
+                    warn!("{}, retrying in {:?}", e, backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    async fn acquire(&self) -> Result<Object<BoltClient, GraphClientError>, GraphClientError> {
+        self.pool.get().await.map_err(|e| match e {
+            PoolError::Timeout(t) => {
+                GraphClientError::Timeout(format!("{:?} while acquiring a connection", t))
+            }
+            PoolError::Backend(e) => e,
+        })
+    }
+
+    fn database_metadata(&self) -> Option<Metadata> {
+        self.config
+            .database
+            .as_ref()
+            .map(|db| Metadata::from_iter(vec![("db", db.as_str())]))
+    }
+
+    async fn with_retries(
+        &self,
+        statements: &[GraphStatement],
+        transaction: bool,
+    ) -> Result<Vec<BoltQueryResult>, GraphClientError> {
+        let mut backoff = Duration::from_millis(self.config.retry_backoff_ms);
+        let mut retries = 0;
+        loop {
+            match self.attempt(statements, transaction).await {
+                Err(e) if e.is_transient() && retries < self.config.max_retries => {
+                    warn!("{}, retrying in {:?}", e, backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    async fn attempt(
+        &self,
+        statements: &[GraphStatement],
+        transaction: bool,
+    ) -> Result<Vec<BoltQueryResult>, GraphClientError> {
+        let mut client = self.acquire().await?;
+        let res = tokio::time::timeout(
+            Duration::from_millis(self.config.query_timeout_ms),
+            self.run_statements(&mut client, statements, transaction),
+        )
+        .await;
+        match res {
+            Ok(Err(e @ GraphClientError::Connection(_))) => {
+                // the connection is broken, keep it out of the pool
+                Object::take(client);
+                Err(e)
+            }
+            Ok(res) => res,
+            Err(_) => {
+                // a response may still be on its way, so the connection cannot be reused
+                Object::take(client);
+                Err(GraphClientError::Timeout(format!(
+                    "query did not complete within {}ms",
+                    self.config.query_timeout_ms
+                )))
+            }
+        }
+    }
+
+    async fn run_statements(
+        &self,
+        client: &mut BoltClient,
+        statements: &[GraphStatement],
+        transaction: bool,
+    ) -> Result<Vec<BoltQueryResult>, GraphClientError> {
+        let database = self.database_metadata();
+        if transaction {
+            expect_success(client.begin(database.clone()).await?)?;
+        }
+        let mut results = Vec::new();
+        for statement in statements {
+            let success = expect_success(
+                client
+                    .run(
+                        statement.query.clone(),
+                        Some(Params::from(statement.params.clone())),
+                        if transaction { None } else { database.clone() },
+                    )
+                    .await?,
+            )?;
+            let columns = columns(&success);
+            let (records, response) = client
+                .pull(Some(Metadata::from_iter(vec![("n", -1)])))
+                .await?;
+            expect_success(response)?;
+            results.push(BoltQueryResult {
+                columns,
+                rows: records.iter().map(|r| r.fields().to_vec()).collect(),
+            });
+        }
+        if transaction {
+            expect_success(client.commit().await?)?;
+        }
+        Ok(results)
+    }
+}
+
+/// Rows of a query pulled lazily from the server
+pub struct BoltRowStream {
+    pub columns: Vec<String>,
+    client: Option<Object<BoltClient, GraphClientError>>,
+    buffer: VecDeque<Vec<Value>>,
+    fetch_size: i64,
+}
+
+impl BoltRowStream {
+    pub async fn next(&mut self) -> Result<Option<Vec<Value>>, Box<dyn Error>> {
+        while self.buffer.is_empty() {
+            let client = match self.client.as_mut() {
+                Some(client) => client,
+                None => break,
+            };
+            let res = client
+                .pull(Some(Metadata::from_iter(vec![("n", self.fetch_size)])))
+                .await
+                .map_err(GraphClientError::from)
+                .and_then(|(records, response)| Ok((records, expect_success(response)?)));
+            let (records, success) = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    if let (GraphClientError::Connection(_), Some(client)) =
+                        (&e, self.client.take())
+                    {
+                        Object::take(client);
+                    }
+                    return Err(e.into());
+                }
+            };
+            self.buffer
+                .extend(records.iter().map(|r| r.fields().to_vec()));
+            if !matches!(
+                success.metadata().get("has_more"),
+                Some(Value::Boolean(true))
+            ) {
+                // all rows are pulled, give the connection back to the pool
+                self.client = None;
+            }
+        }
+        Ok(self.buffer.pop_front())
+    }
+}
+
+fn columns(success: &Success) -> Vec<String> {
+    match success.metadata().get("fields") {
+        Some(Value::List(fields)) => fields
+            .iter()
+            .map(|f| match f {
+                Value::String(s) => s.clone(),
+                f => format!("{:?}", f),
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[test]
+fn transient_errors() {
+    let failure = |code: &str| GraphClientError::Server {
+        code: code.to_string(),
+        message: String::new(),
+    };
+    assert!(failure("Neo.TransientError.General.DatabaseUnavailable").is_transient());
+    assert!(!failure("Neo.ClientError.Statement.SyntaxError").is_transient());
+    assert!(GraphClientError::Connection("reset by peer".to_string()).is_transient());
+    assert!(!GraphClientError::Timeout("query".to_string()).is_transient());
+}
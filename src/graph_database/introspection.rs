@@ -0,0 +1,195 @@
+use super::*;
+use crate::{
+    Entity, FeatureRegistry, FeatureValueType, Field, GraphElementKind, GraphSchemaItemKind,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Entities and fields generated from the data found in a graph database
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ImportedSchema {
+    pub entities: Vec<Entity>,
+    pub fields: Vec<Field>,
+}
+
+impl ImportedSchema {
+    pub async fn register(&self, registry: &FeatureRegistry) -> Result<(), Box<dyn Error>> {
+        registry
+            .register_resources(&self.entities.iter().collect())
+            .await?;
+        registry
+            .register_resources(&self.fields.iter().collect())
+            .await
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Returns a type that holds values of both types: Int and Float give Float, and other
+/// different types give String
+pub fn merge_value_types(a: &FeatureValueType, b: &FeatureValueType) -> FeatureValueType {
+    use FeatureValueType::*;
+    match (a, b) {
+        (a, b) if a == b => a.clone(),
+        (Int, Float) | (Float, Int) => Float,
+        (Array(a), Array(b)) => Array(Box::new(merge_value_types(a, b))),
+        _ => String,
+    }
+}
+
+/// Returns the type of each property found in the samples. Properties that are always null are
+/// typed as String.
+pub fn observed_types<'a>(
+    samples: impl Iterator<Item = &'a HashMap<String, FeatureValue>>,
+) -> BTreeMap<String, FeatureValueType> {
+    let mut res: BTreeMap<String, Option<FeatureValueType>> = BTreeMap::new();
+    for properties in samples {
+        for (k, v) in properties {
+            let observed = res.entry(k.clone()).or_default();
+            *observed = match (observed.take(), v.value_type()) {
+                (Some(a), Some(b)) => Some(merge_value_types(&a, &b)),
+                (a, b) => a.or(b),
+            };
+        }
+    }
+    res.into_iter()
+        .map(|(k, t)| (k, t.unwrap_or(FeatureValueType::String)))
+        .collect()
+}
+
+fn fields_of(entity: &Entity, types: &BTreeMap<String, FeatureValueType>) -> Vec<Field> {
+    Field::new_fields(
+        types.iter().map(|(k, t)| (k.as_str(), t.clone())).collect(),
+        entity,
+        None,
+    )
+}
+
+/// Generates an entity per vertex tlabel and per (edge tlabel, endpoint tlabels), with a field per
+/// property found in up to `sample_size` elements of each tlabel. The primary key of a vertex
+/// entity is taken from a uniqueness constraint, or else is the `id` property or the first
+/// property. Edge entities of a tlabel with several endpoint pairs are named
+/// `{src}_{tlabel}_{dst}`.
+pub async fn import_schema(
+    database: &dyn GraphDatabase,
+    sample_size: usize,
+) -> Result<ImportedSchema, Box<dyn Error>> {
+    let constraints = database.schema().await?;
+    let mut res = ImportedSchema::default();
+    let mut vertex_entities = HashMap::new();
+    for tlabel in database.vertex_types().await? {
+        let samples = database.sample_vertices(&tlabel, sample_size).await?;
+        let types = observed_types(samples.iter().map(|n| &n.properties));
+        let primary_key = constraints
+            .iter()
+            .find(|c| {
+                c.kind == GraphSchemaItemKind::UniqueConstraint
+                    && c.element == GraphElementKind::Node
+                    && c.tlabel == tlabel
+            })
+            .map(|c| c.properties.join(","))
+            .or_else(|| types.contains_key("id").then(|| "id".to_string()))
+            .or_else(|| types.keys().next().cloned())
+            .unwrap_or_default();
+        info!(
+            "Importing vertex type {} with {} properties keyed by {}",
+            tlabel,
+            types.len(),
+            primary_key
+        );
+        let entity = Entity::new_node_entity(&tlabel, None, &tlabel, &primary_key);
+        res.fields.extend(fields_of(&entity, &types));
+        vertex_entities.insert(tlabel, res.entities.len());
+        res.entities.push(entity);
+    }
+    for edge_type in database.edge_types(sample_size).await? {
+        let samples = database
+            .sample_edges(&edge_type.tlabel, sample_size)
+            .await?;
+        let types = observed_types(samples.iter().map(|e| &e.properties));
+        for (src, dst) in &edge_type.endpoints {
+            let (src, dst) = match (vertex_entities.get(src), vertex_entities.get(dst)) {
+                (Some(src), Some(dst)) => (&res.entities[*src], &res.entities[*dst]),
+                _ => continue,
+            };
+            let name = if edge_type.endpoints.len() == 1 {
+                edge_type.tlabel.clone()
+            } else {
+                format!("{}_{}_{}", src.name, edge_type.tlabel, dst.name)
+            };
+            info!(
+                "Importing edge type {} with {} properties",
+                name,
+                types.len()
+            );
+            let entity = Entity::new_edge_entity(&name, None, &edge_type.tlabel, src, dst);
+            res.fields.extend(fields_of(&entity, &types));
+            res.entities.push(entity);
+        }
+    }
+    Ok(res)
+}
+
+#[tokio::test]
+async fn import_schema_from_memory_graph() {
+    use crate::ResourceOp;
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30, "nick": null}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41.5}},
+                {"tlabel": "Company", "id": "c1", "properties": {"tags": ["a", "b"]}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"],
+                 "properties": {"since": 2019}},
+                {"tlabel": "likes", "src": ["Person", "p1"], "dst": ["Person", "p2"]},
+                {"tlabel": "likes", "src": ["Person", "p2"], "dst": ["Company", "c1"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let schema = import_schema(&MemoryGraphDatabase::from_graph(graph), 100)
+        .await
+        .unwrap();
+    let ids: Vec<_> = schema.entities.iter().map(|e| e.resource_id()).collect();
+    assert_eq!(
+        ids,
+        vec![
+            "Entity/Company/",
+            "Entity/Person/",
+            "Entity/knows/",
+            "Entity/Person_likes_Person/",
+            "Entity/Person_likes_Company/",
+        ]
+    );
+    assert_eq!(schema.entities[1].primary_key, "id");
+    assert_eq!(
+        schema.entities[2].edge_endpoint_ids(),
+        Some(("Entity/Person/".to_string(), "Entity/Person/".to_string()))
+    );
+    let fields: Vec<_> = schema
+        .fields
+        .iter()
+        .map(|f| (f.resource_id(), f.value_type.clone()))
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            ("Field/Company/id/".to_string(), FeatureValueType::String),
+            (
+                "Field/Company/tags/".to_string(),
+                FeatureValueType::Array(Box::new(FeatureValueType::String))
+            ),
+            // the property graph widens the Int ages to Float when 41.5 is stored
+            ("Field/Person/age/".to_string(), FeatureValueType::Float),
+            ("Field/Person/id/".to_string(), FeatureValueType::String),
+            ("Field/Person/nick/".to_string(), FeatureValueType::String),
+            ("Field/knows/since/".to_string(), FeatureValueType::Int),
+        ]
+    );
+}
@@ -0,0 +1,363 @@
+use super::*;
+use std::cell::{Ref, RefCell};
+
+mod query;
+
+use query::MemoryQuery;
+
+/// An embedded property graph kept in memory, for tests and local development without a
+/// graph database server. It runs the `MATCH` queries over one vertex or edge pattern described
+/// by `MemoryQuery`, and fails on the others.
+#[derive(Default)]
+pub struct MemoryGraphDatabase {
+    graph: RefCell<PropertyGraph>,
+    schema: RefCell<Vec<GraphSchemaItem>>,
+}
+
+impl MemoryGraphDatabase {
+    pub fn new() -> Self {
+        MemoryGraphDatabase::default()
+    }
+
+    pub fn from_graph(graph: PropertyGraph) -> Self {
+        MemoryGraphDatabase {
+            graph: RefCell::new(graph),
+            schema: RefCell::default(),
+        }
+    }
+
+    pub fn graph(&self) -> Ref<'_, PropertyGraph> {
+        self.graph.borrow()
+    }
+}
+
+/// Streams rows collected in memory
+pub(crate) struct CollectedRowStream {
+    columns: Vec<String>,
+    rows: std::vec::IntoIter<Vec<GraphValue>>,
+}
+
+impl CollectedRowStream {
+    pub(crate) fn new(rows: GraphRows) -> Self {
+        CollectedRowStream {
+            columns: rows.columns,
+            rows: rows.rows.into_iter(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl GraphRowStream for CollectedRowStream {
+    fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    async fn next(&mut self) -> Result<Option<Vec<GraphValue>>, Box<dyn Error>> {
+        Ok(self.rows.next())
+    }
+}
+
+#[async_trait(?Send)]
+impl GraphDatabase for MemoryGraphDatabase {
+    async fn run_query(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<GraphRows, Box<dyn Error>> {
+        MemoryQuery::parse(query)?.run(&self.graph.borrow(), &params)
+    }
+
+    async fn stream_rows(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<Box<dyn GraphRowStream>, Box<dyn Error>> {
+        let rows = self.run_query(query, params).await?;
+        Ok(Box::new(CollectedRowStream::new(rows)))
+    }
+
+    async fn upsert_vertices(&self, vertices: &[VertexRecord]) -> Result<(), Box<dyn Error>> {
+        let mut graph = self.graph.borrow_mut();
+        for vertex in vertices {
+            graph.upsert_vertex(vertex)?;
+        }
+        Ok(())
+    }
+
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<(), Box<dyn Error>> {
+        let mut graph = self.graph.borrow_mut();
+        for edge in edges {
+            graph.upsert_edge(edge)?;
+        }
+        Ok(())
+    }
+
+    async fn scan_vertices(&self, tlabel: &str) -> Result<Vec<GraphNode>, Box<dyn Error>> {
+        self.sample_vertices(tlabel, usize::MAX).await
+    }
+
+    async fn scan_edges(&self, tlabel: &str) -> Result<Vec<GraphRelationship>, Box<dyn Error>> {
+        self.sample_edges(tlabel, usize::MAX).await
+    }
+
+    async fn sample_vertices(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphNode>, Box<dyn Error>> {
+        let graph = self.graph.borrow();
+        Ok(graph
+            .vertex_types()
+            .get(tlabel)
+            .map(|t| t.ids.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .take(limit)
+            .map(|id| {
+                let vertex = graph.vertex(*id);
+                GraphNode {
+                    id: *id as i64,
+                    labels: vec![vertex.tlabel.clone()],
+                    properties: vertex.properties.clone(),
+                }
+            })
+            .collect())
+    }
+
+    async fn sample_edges(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphRelationship>, Box<dyn Error>> {
+        let graph = self.graph.borrow();
+        Ok(graph
+            .edge_types()
+            .get(tlabel)
+            .map(|t| t.ids.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .take(limit)
+            .map(|id| {
+                let edge = graph.edge(*id);
+                GraphRelationship {
+                    id: *id as i64,
+                    tlabel: edge.tlabel.clone(),
+                    src_id: edge.src as i64,
+                    dst_id: edge.dst as i64,
+                    properties: edge.properties.clone(),
+                }
+            })
+            .collect())
+    }
+
+    async fn vertex_types(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self
+            .graph
+            .borrow()
+            .vertex_types()
+            .iter()
+            .filter(|(_, t)| !t.ids.is_empty())
+            .map(|(tlabel, _)| tlabel.clone())
+            .collect())
+    }
+
+    async fn edge_types(&self, sample_size: usize) -> Result<Vec<GraphEdgeType>, Box<dyn Error>> {
+        let graph = self.graph.borrow();
+        Ok(graph
+            .edge_types()
+            .iter()
+            .filter(|(_, t)| !t.ids.is_empty())
+            .map(|(tlabel, t)| {
+                let mut endpoints = Vec::new();
+                for id in t.ids.iter().take(sample_size) {
+                    let edge = graph.edge(*id);
+                    let pair = (
+                        graph.vertex(edge.src).tlabel.clone(),
+                        graph.vertex(edge.dst).tlabel.clone(),
+                    );
+                    if !endpoints.contains(&pair) {
+                        endpoints.push(pair);
+                    }
+                }
+                GraphEdgeType {
+                    tlabel: tlabel.clone(),
+                    endpoints,
+                }
+            })
+            .collect())
+    }
+
+    async fn schema(&self) -> Result<Vec<GraphSchemaItem>, Box<dyn Error>> {
+        Ok(self.schema.borrow().clone())
+    }
+
+    async fn apply_schema(&self, plan: &SchemaPlan) -> Result<(), Box<dyn Error>> {
+        let mut schema = self.schema.borrow_mut();
+        schema.retain(|item| !plan.to_drop.contains(item));
+        schema.extend(plan.to_create.iter().cloned());
+        Ok(())
+    }
+
+    async fn count_vertices(&self, tlabel: &str) -> Result<usize, Box<dyn Error>> {
+        Ok(self
+            .graph
+            .borrow()
+            .vertex_types()
+            .get(tlabel)
+            .map_or(0, |t| t.ids.len()))
+    }
+
+    async fn count_edges(&self, tlabel: &str) -> Result<usize, Box<dyn Error>> {
+        Ok(self
+            .graph
+            .borrow()
+            .edge_types()
+            .get(tlabel)
+            .map_or(0, |t| t.ids.len()))
+    }
+
+    async fn delete_vertices(
+        &self,
+        tlabel: &str,
+        _batch_size: usize,
+    ) -> Result<usize, Box<dyn Error>> {
+        Ok(self.graph.borrow_mut().remove_vertices(tlabel))
+    }
+
+    async fn delete_edges(
+        &self,
+        tlabel: &str,
+        _batch_size: usize,
+    ) -> Result<usize, Box<dyn Error>> {
+        Ok(self.graph.borrow_mut().remove_edges(tlabel))
+    }
+
+    async fn clear(&self) -> Result<(), Box<dyn Error>> {
+        self.graph.borrow_mut().clear();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn upsert_into_memory_graph() {
+    use crate::VertexKey;
+
+    let key = |tlabel: &str, primary_key: &str, value: &str| VertexKey {
+        tlabel: tlabel.to_string(),
+        primary_key: primary_key.to_string(),
+        value: FeatureValue::String(value.to_string()),
+    };
+    let vertex = |key: VertexKey, overall: i64| VertexRecord {
+        properties: HashMap::from([("overall".to_string(), FeatureValue::Int(overall))]),
+        key,
+    };
+    let edge = |src: VertexKey, dst: VertexKey| EdgeRecord {
+        tlabel: "isWrittenBy".to_string(),
+        src,
+        dst,
+        properties: HashMap::new(),
+    };
+    let db = MemoryGraphDatabase::new();
+    db.upsert_records(&GraphRecords {
+        vertices: vec![
+            vertex(key("Review", "id", "r1"), 5),
+            vertex(key("Reviewer", "reviewerId", "u1"), 0),
+        ],
+        edges: vec![
+            edge(
+                key("Review", "id", "r1"),
+                key("Reviewer", "reviewerId", "u1"),
+            ),
+            // unknown endpoint
+            edge(
+                key("Review", "id", "r2"),
+                key("Reviewer", "reviewerId", "u1"),
+            ),
+        ],
+    })
+    .await
+    .unwrap();
+    // upserting again updates in place
+    db.upsert_records(&GraphRecords {
+        vertices: vec![vertex(key("Review", "id", "r1"), 4)],
+        edges: vec![edge(
+            key("Review", "id", "r1"),
+            key("Reviewer", "reviewerId", "u1"),
+        )],
+    })
+    .await
+    .unwrap();
+
+    let reviews = db.scan_vertices("Review").await.unwrap();
+    assert_eq!(reviews.len(), 1);
+    assert_eq!(reviews[0].properties["overall"], FeatureValue::Int(4));
+    assert_eq!(db.scan_edges("isWrittenBy").await.unwrap().len(), 1);
+
+    db.clear().await.unwrap();
+    assert!(db.scan_vertices("Review").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn query_memory_graph() {
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"name": "ann", "age": 30}},
+                {"tlabel": "Person", "id": "p2", "properties": {"name": "bob", "age": 41}},
+                {"tlabel": "City", "id": "c1", "properties": {"name": "rome"}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"],
+                 "properties": {"since": 2010}},
+                {"tlabel": "livesIn", "src": ["Person", "p2"], "dst": ["City", "c1"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let db = MemoryGraphDatabase::from_graph(graph);
+    let value = |v: FeatureValue| GraphValue::Value(v);
+    let string = |s: &str| value(FeatureValue::String(s.to_string()));
+
+    let rows = db
+        .run_query(
+            "MATCH (n:Person) WHERE n.age > $age RETURN n.name AS name, id(n)",
+            HashMap::from([("age".to_string(), FeatureValue::Int(35))]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(rows.columns, vec!["name", "id(n)"]);
+    assert_eq!(
+        rows.rows,
+        vec![vec![string("bob"), value(FeatureValue::Int(1))]]
+    );
+
+    let mut stream = db
+        .stream_rows(
+            "MATCH (c:`City`)<-[:livesIn]-(p) WHERE p.name <> 'ann' RETURN p.name, c.name",
+            HashMap::new(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(stream.columns(), ["p.name", "c.name"]);
+    assert_eq!(
+        stream.next().await.unwrap(),
+        Some(vec![string("bob"), string("rome")])
+    );
+    assert_eq!(stream.next().await.unwrap(), None);
+
+    let rows = db
+        .run_query("MATCH (a)-[k:knows]->(b) RETURN k LIMIT 1", HashMap::new())
+        .await
+        .unwrap();
+    match &rows.rows[..] {
+        [row] => match &row[..] {
+            [GraphValue::Relationship(k)] => assert_eq!((k.src_id, k.dst_id), (0, 1)),
+            row => panic!("unexpected row {:?}", row),
+        },
+        rows => panic!("unexpected rows {:?}", rows),
+    }
+    assert!(db
+        .run_query("MATCH (n) DETACH DELETE n", HashMap::new())
+        .await
+        .is_err());
+}
@@ -0,0 +1,489 @@
+use crate::{FeatureValue, GraphNode, GraphRelationship, GraphRows, GraphValue, PropertyGraph};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// A query the in-memory graph database runs: one vertex or edge pattern, comparisons of
+/// properties joined by `AND`, and the variables, properties and ids it returns, as in
+/// `MATCH (a:Person)-[k:knows]->(b) WHERE a.age > $age AND b.name <> 'bob' RETURN a.name, id(b)
+/// AS friend LIMIT 10`
+#[derive(Debug, PartialEq)]
+pub(crate) struct MemoryQuery {
+    src: NodePattern,
+    /// The edge and the vertex at its other end, if the pattern goes through an edge
+    edge: Option<(EdgePattern, NodePattern)>,
+    conditions: Vec<Condition>,
+    items: Vec<(Item, String)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+struct NodePattern {
+    var: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct EdgePattern {
+    var: Option<String>,
+    tlabel: Option<String>,
+    /// Whether the edge goes from the second vertex to the first, as in `(a)<-[e]-(b)`
+    reversed: bool,
+}
+
+/// A comparison of the property of a variable with a literal or a parameter
+#[derive(Debug, PartialEq)]
+struct Condition {
+    var: String,
+    property: String,
+    op: String,
+    value: Operand,
+}
+
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Literal(FeatureValue),
+    Param(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Item {
+    Variable(String),
+    Property(String, String),
+    Id(String),
+}
+
+/// A vertex or an edge bound to a variable
+#[derive(Clone, Copy)]
+enum Element {
+    Vertex(usize),
+    Edge(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// A name between backquotes
+    Quoted(String),
+    Str(String),
+    Number(FeatureValue),
+    Param(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 14] = [
+    "<>", "<=", ">=", "->", "<-", "(", ")", "[", "]", ":", ",", ".", "-", "=",
+];
+
+fn tokenize(query: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut res = Vec::new();
+    let mut i = 0;
+    let take_while = |i: usize, f: &dyn Fn(char) -> bool| {
+        (i..chars.len())
+            .find(|j| !f(chars[*j]))
+            .unwrap_or(chars.len())
+    };
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        if c.is_whitespace() || c == ';' {
+            i += 1;
+        } else if c == '`' || c == '\'' || c == '"' {
+            // a quote is escaped by doubling it, or by a backslash in strings
+            let mut text = String::new();
+            let mut j = i + 1;
+            loop {
+                match chars.get(j) {
+                    None => return Err(format!("unterminated quote in {}", query).into()),
+                    Some('\\') if c != '`' => {
+                        text.extend(chars.get(j + 1));
+                        j += 2;
+                    }
+                    Some(q) if *q == c && chars.get(j + 1) == Some(&c) => {
+                        text.push(c);
+                        j += 2;
+                    }
+                    Some(q) if *q == c => break,
+                    Some(q) => {
+                        text.push(*q);
+                        j += 1;
+                    }
+                }
+            }
+            res.push(match c {
+                '`' => Token::Quoted(text),
+                _ => Token::Str(text),
+            });
+            i = j + 1;
+        } else if c == '$' {
+            let end = take_while(i + 1, &is_ident);
+            res.push(Token::Param(chars[i + 1..end].iter().collect()));
+            i = end;
+        } else if c.is_ascii_digit() {
+            let end = take_while(i, &|c| c.is_ascii_digit() || c == '.');
+            let text: String = chars[i..end].iter().collect();
+            res.push(Token::Number(match text.parse::<i64>() {
+                Ok(v) => FeatureValue::Int(v),
+                Err(_) => FeatureValue::Float(text.parse()?),
+            }));
+            i = end;
+        } else if is_ident(c) {
+            let end = take_while(i, &is_ident);
+            res.push(Token::Word(chars[i..end].iter().collect()));
+            i = end;
+        } else if let Some(s) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            res.push(Token::Symbol(s));
+            i += s.chars().count();
+        } else if c == '<' || c == '>' {
+            res.push(Token::Symbol(if c == '<' { "<" } else { ">" }));
+            i += 1;
+        } else {
+            return Err(format!("unexpected {:?} in {}", c, query).into());
+        }
+    }
+    Ok(res)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let res = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        res
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), Box<dyn Error>> {
+        match self.is_keyword(keyword) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(format!("expected {} but found {:?}", keyword, self.peek()).into()),
+        }
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Result<(), Box<dyn Error>> {
+        match self.is_symbol(symbol) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(format!("expected {} but found {:?}", symbol, self.peek()).into()),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Box<dyn Error>> {
+        match self.next() {
+            Some(Token::Word(name)) | Some(Token::Quoted(name)) => Ok(name),
+            token => Err(format!("expected a name but found {:?}", token).into()),
+        }
+    }
+
+    /// The variable and the label of a `(var:Label)` or `[var:Label]` pattern, up to `end`
+    fn pattern(&mut self, end: &str) -> Result<(Option<String>, Option<String>), Box<dyn Error>> {
+        let var = match self.peek() {
+            Some(Token::Word(_)) | Some(Token::Quoted(_)) => Some(self.name()?),
+            _ => None,
+        };
+        let label = match self.is_symbol(":") {
+            true => {
+                self.pos += 1;
+                Some(self.name()?)
+            }
+            false => None,
+        };
+        self.symbol(end)?;
+        Ok((var, label))
+    }
+
+    fn node(&mut self) -> Result<NodePattern, Box<dyn Error>> {
+        self.symbol("(")?;
+        let (var, label) = self.pattern(")")?;
+        Ok(NodePattern { var, label })
+    }
+
+    fn edge(&mut self) -> Result<EdgePattern, Box<dyn Error>> {
+        let reversed = self.is_symbol("<-");
+        self.symbol(if reversed { "<-" } else { "-" })?;
+        self.symbol("[")?;
+        let (var, tlabel) = self.pattern("]")?;
+        self.symbol(if reversed { "-" } else { "->" })?;
+        Ok(EdgePattern {
+            var,
+            tlabel,
+            reversed,
+        })
+    }
+
+    fn condition(&mut self) -> Result<Condition, Box<dyn Error>> {
+        let var = self.name()?;
+        self.symbol(".")?;
+        let property = self.name()?;
+        let op = match self.next() {
+            Some(Token::Symbol(op)) if ["=", "<>", "<", "<=", ">", ">="].contains(&op) => op,
+            token => return Err(format!("expected a comparison but found {:?}", token).into()),
+        };
+        let value = match self.next() {
+            Some(Token::Param(name)) => Operand::Param(name),
+            Some(Token::Str(s)) => Operand::Literal(FeatureValue::String(s)),
+            Some(Token::Number(v)) => Operand::Literal(v),
+            Some(Token::Symbol("-")) => match self.next() {
+                Some(Token::Number(FeatureValue::Int(v))) => {
+                    Operand::Literal(FeatureValue::Int(-v))
+                }
+                Some(Token::Number(FeatureValue::Float(v))) => {
+                    Operand::Literal(FeatureValue::Float(-v))
+                }
+                token => return Err(format!("expected a number but found {:?}", token).into()),
+            },
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("true") => {
+                Operand::Literal(FeatureValue::Boolean(true))
+            }
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("false") => {
+                Operand::Literal(FeatureValue::Boolean(false))
+            }
+            token => return Err(format!("expected a value but found {:?}", token).into()),
+        };
+        Ok(Condition {
+            var,
+            property,
+            op: op.to_string(),
+            value,
+        })
+    }
+
+    /// A returned item and the name of its column
+    fn item(&mut self) -> Result<(Item, String), Box<dyn Error>> {
+        let name = self.name()?;
+        let (item, column) = if name.eq_ignore_ascii_case("id") && self.is_symbol("(") {
+            self.pos += 1;
+            let var = self.name()?;
+            self.symbol(")")?;
+            let column = format!("id({})", var);
+            (Item::Id(var), column)
+        } else if self.is_symbol(".") {
+            self.pos += 1;
+            let property = self.name()?;
+            let column = format!("{}.{}", name, property);
+            (Item::Property(name, property), column)
+        } else {
+            (Item::Variable(name.clone()), name)
+        };
+        match self.is_keyword("AS") {
+            true => {
+                self.pos += 1;
+                Ok((item, self.name()?))
+            }
+            false => Ok((item, column)),
+        }
+    }
+}
+
+impl MemoryQuery {
+    pub(crate) fn parse(query: &str) -> Result<MemoryQuery, Box<dyn Error>> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            pos: 0,
+        };
+        let res = MemoryQuery::parse_tokens(&mut parser).and_then(|res| match parser.peek() {
+            None => Ok(res),
+            Some(token) => Err(format!("unexpected {:?}", token).into()),
+        });
+        res.map_err(|e| {
+            format!(
+                "the in-memory graph database only runs MATCH queries over one vertex or edge \
+                 pattern, but {}: {}",
+                query, e
+            )
+            .into()
+        })
+    }
+
+    fn parse_tokens(parser: &mut Parser) -> Result<MemoryQuery, Box<dyn Error>> {
+        parser.keyword("MATCH")?;
+        let src = parser.node()?;
+        let edge = match parser.is_symbol("-") || parser.is_symbol("<-") {
+            true => Some((parser.edge()?, parser.node()?)),
+            false => None,
+        };
+        let mut conditions = Vec::new();
+        if parser.is_keyword("WHERE") {
+            parser.pos += 1;
+            conditions.push(parser.condition()?);
+            while parser.is_keyword("AND") {
+                parser.pos += 1;
+                conditions.push(parser.condition()?);
+            }
+        }
+        parser.keyword("RETURN")?;
+        let mut items = vec![parser.item()?];
+        while parser.is_symbol(",") {
+            parser.pos += 1;
+            items.push(parser.item()?);
+        }
+        let mut limit = None;
+        if parser.is_keyword("LIMIT") {
+            parser.pos += 1;
+            limit = match parser.next() {
+                Some(Token::Number(FeatureValue::Int(n))) if n >= 0 => Some(n as usize),
+                token => return Err(format!("expected a limit but found {:?}", token).into()),
+            };
+        }
+        Ok(MemoryQuery {
+            src,
+            edge,
+            conditions,
+            items,
+            limit,
+        })
+    }
+
+    /// Runs the query on the graph, with the given parameters
+    pub(crate) fn run(
+        &self,
+        graph: &PropertyGraph,
+        params: &HashMap<String, FeatureValue>,
+    ) -> Result<GraphRows, Box<dyn Error>> {
+        let ids = |types: &std::collections::BTreeMap<String, crate::ElementType>,
+                   tlabel: &Option<String>| {
+            types
+                .iter()
+                .filter(|(t, _)| tlabel.iter().all(|l| l == *t))
+                .flat_map(|(_, t)| t.ids.iter().copied())
+                .collect::<Vec<usize>>()
+        };
+        let vertex_ids = ids(graph.vertex_types(), &self.src.label);
+        let mut matches: Vec<HashMap<String, Element>> = Vec::new();
+        let mut bind = |pairs: &[(&Option<String>, Element)]| {
+            let mut binding = HashMap::new();
+            for (var, element) in pairs {
+                if let Some(var) = var {
+                    binding.insert(var.clone(), *element);
+                }
+            }
+            matches.push(binding);
+        };
+        match &self.edge {
+            None => vertex_ids
+                .into_iter()
+                .for_each(|v| bind(&[(&self.src.var, Element::Vertex(v))])),
+            Some((edge, dst)) => {
+                let has_label = |v: usize, label: &Option<String>| {
+                    label.iter().all(|l| &graph.vertex(v).tlabel == l)
+                };
+                for e in ids(graph.edge_types(), &edge.tlabel) {
+                    let record = graph.edge(e);
+                    let (a, b) = match edge.reversed {
+                        false => (record.src, record.dst),
+                        true => (record.dst, record.src),
+                    };
+                    if has_label(a, &self.src.label) && has_label(b, &dst.label) {
+                        bind(&[
+                            (&self.src.var, Element::Vertex(a)),
+                            (&edge.var, Element::Edge(e)),
+                            (&dst.var, Element::Vertex(b)),
+                        ]);
+                    }
+                }
+            }
+        }
+
+        let element = |binding: &HashMap<String, Element>, var: &str| {
+            binding
+                .get(var)
+                .copied()
+                .ok_or_else(|| format!("unknown variable {}", var))
+        };
+        let properties = |element: Element| match element {
+            Element::Vertex(v) => &graph.vertex(v).properties,
+            Element::Edge(e) => &graph.edge(e).properties,
+        };
+        let mut rows = Vec::new();
+        for binding in matches {
+            if rows.len() >= self.limit.unwrap_or(usize::MAX) {
+                break;
+            }
+            let mut selected = true;
+            for condition in &self.conditions {
+                let value = match &condition.value {
+                    Operand::Literal(value) => value,
+                    Operand::Param(name) => params
+                        .get(name)
+                        .ok_or_else(|| format!("missing parameter {}", name))?,
+                };
+                let property =
+                    properties(element(&binding, &condition.var)?).get(&condition.property);
+                // comparisons with null or of values of different types are not true
+                let ordering = property.and_then(|p| p.compare(value));
+                selected &= match (condition.op.as_str(), ordering) {
+                    (_, None) => false,
+                    ("=", Some(o)) => o == Ordering::Equal,
+                    ("<>", Some(o)) => o != Ordering::Equal,
+                    ("<", Some(o)) => o == Ordering::Less,
+                    ("<=", Some(o)) => o != Ordering::Greater,
+                    (">", Some(o)) => o == Ordering::Greater,
+                    (_, Some(o)) => o != Ordering::Less,
+                };
+            }
+            if !selected {
+                continue;
+            }
+            let mut row = Vec::new();
+            for (item, _) in &self.items {
+                row.push(match item {
+                    Item::Variable(var) => match element(&binding, var)? {
+                        Element::Vertex(v) => GraphValue::Node(GraphNode {
+                            id: v as i64,
+                            labels: vec![graph.vertex(v).tlabel.clone()],
+                            properties: graph.vertex(v).properties.clone(),
+                        }),
+                        Element::Edge(e) => {
+                            let edge = graph.edge(e);
+                            GraphValue::Relationship(GraphRelationship {
+                                id: e as i64,
+                                tlabel: edge.tlabel.clone(),
+                                src_id: edge.src as i64,
+                                dst_id: edge.dst as i64,
+                                properties: edge.properties.clone(),
+                            })
+                        }
+                    },
+                    Item::Property(var, property) => GraphValue::Value(
+                        properties(element(&binding, var)?)
+                            .get(property)
+                            .cloned()
+                            .unwrap_or(FeatureValue::Null),
+                    ),
+                    Item::Id(var) => {
+                        GraphValue::Value(FeatureValue::Int(match element(&binding, var)? {
+                            Element::Vertex(id) | Element::Edge(id) => id as i64,
+                        }))
+                    }
+                });
+            }
+            rows.push(row);
+        }
+        Ok(GraphRows {
+            columns: self.items.iter().map(|(_, c)| c.clone()).collect(),
+            rows,
+        })
+    }
+}
@@ -0,0 +1,566 @@
+use super::*;
+use crate::transformation::quote_identifier;
+use crate::{GraphClientConfig, GraphDatabaseCredentials, GraphElementKind, GraphSchemaItemKind};
+use bolt_client::bolt_proto::{
+    value::{Duration, Node, Path},
+    Value,
+};
+use chrono::{TimeZone, Utc};
+use log::info;
+
+/// Number of records sent to the server per upsert query
+const UPSERT_BATCH_SIZE: usize = 1000;
+/// Number of rows pulled at a time by row streams
+const FETCH_SIZE: usize = 1000;
+
+/// A neo4j server reached over bolt
+pub struct Neo4jGraphDatabase {
+    pub client: GraphClient,
+}
+
+impl Neo4jGraphDatabase {
+    pub fn new(
+        credentials: &GraphDatabaseCredentials,
+        config: GraphClientConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Neo4jGraphDatabase {
+            client: GraphClient::new(credentials, config)?,
+        })
+    }
+
+    async fn scan(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<Vec<GraphValue>, Box<dyn Error>> {
+        let mut stream = self.stream_rows(query, params).await?;
+        let mut res = Vec::new();
+        while let Some(mut row) = stream.next().await? {
+            res.push(row.remove(0));
+        }
+        Ok(res)
+    }
+}
+
+impl Neo4jGraphDatabase {
+    /// Runs a query returning a single count
+    async fn count(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<usize, Box<dyn Error>> {
+        match self
+            .run_query(query, params)
+            .await?
+            .rows
+            .first()
+            .map(|r| &r[0])
+        {
+            Some(GraphValue::Value(FeatureValue::Int(n))) => Ok(*n as usize),
+            v => Err(format!("unexpected count {:?}", v).into()),
+        }
+    }
+
+    /// Runs a delete query limited to `$limit` elements until it deletes nothing, so that each
+    /// batch is committed in its own transaction. A batch of no elements would delete nothing.
+    async fn delete_in_batches(
+        &self,
+        query: &str,
+        batch_size: usize,
+    ) -> Result<usize, Box<dyn Error>> {
+        if batch_size == 0 {
+            return Err("the batch size must be at least 1".into());
+        }
+        let mut res = 0;
+        loop {
+            let deleted = self.count(query, limit_param(batch_size)).await?;
+            if deleted == 0 {
+                return Ok(res);
+            }
+            res += deleted;
+            info!("Deleted {} elements", res);
+        }
+    }
+}
+
+fn statement(query: &str, params: HashMap<String, FeatureValue>) -> GraphStatement {
+    GraphStatement {
+        query: query.to_string(),
+        params: params
+            .iter()
+            .map(|(k, v)| (k.clone(), to_bolt_value(v)))
+            .collect(),
+    }
+}
+
+pub fn to_bolt_value(value: &FeatureValue) -> Value {
+    match value {
+        FeatureValue::Null => Value::Null,
+        FeatureValue::String(v) => Value::String(v.clone()),
+        FeatureValue::Int(v) => Value::Integer(*v),
+        FeatureValue::Float(v) => Value::Float(*v),
+        FeatureValue::Boolean(v) => Value::Boolean(*v),
+        FeatureValue::Date(v) => Value::Date(*v),
+        FeatureValue::Time(v) => Value::LocalTime(*v),
+        FeatureValue::DateTime(v) => Value::from(*v),
+        FeatureValue::Duration(v) => Value::Duration(Duration::new(0, 0, *v, 0)),
+        FeatureValue::Array(values) => Value::List(values.iter().map(to_bolt_value).collect()),
+    }
+}
+
+/// Converts a bolt value into a feature value. Durations are approximated with 30-day months, and
+/// values with no feature value counterpart (maps, points, bytes) are kept as their debug string.
+pub fn from_bolt_value(value: &Value) -> FeatureValue {
+    match value {
+        Value::Null => FeatureValue::Null,
+        Value::String(v) => FeatureValue::String(v.clone()),
+        Value::Integer(v) => FeatureValue::Int(*v),
+        Value::Float(v) => FeatureValue::Float(*v),
+        Value::Boolean(v) => FeatureValue::Boolean(*v),
+        Value::Date(v) => FeatureValue::Date(*v),
+        Value::Time(v, _) | Value::LocalTime(v) => FeatureValue::Time(*v),
+        Value::DateTimeOffset(v) => FeatureValue::DateTime(v.with_timezone(&Utc)),
+        Value::DateTimeZoned(v) => FeatureValue::DateTime(v.with_timezone(&Utc)),
+        Value::LocalDateTime(v) => FeatureValue::DateTime(Utc.from_utc_datetime(v)),
+        Value::Duration(v) => {
+            FeatureValue::Duration((v.months() * 30 + v.days()) * 86_400 + v.seconds())
+        }
+        Value::List(values) => FeatureValue::Array(values.iter().map(from_bolt_value).collect()),
+        v => FeatureValue::String(format!("{:?}", v)),
+    }
+}
+
+fn from_bolt_properties(properties: &HashMap<String, Value>) -> HashMap<String, FeatureValue> {
+    properties
+        .iter()
+        .map(|(k, v)| (k.clone(), from_bolt_value(v)))
+        .collect()
+}
+
+fn from_bolt(value: &Value) -> GraphValue {
+    match value {
+        Value::Node(node) => GraphValue::Node(GraphNode {
+            id: node.node_identity(),
+            labels: node.labels().to_vec(),
+            properties: from_bolt_properties(node.properties()),
+        }),
+        Value::Relationship(rel) => GraphValue::Relationship(GraphRelationship {
+            id: rel.rel_identity(),
+            tlabel: rel.rel_type().to_string(),
+            src_id: rel.start_node_identity(),
+            dst_id: rel.end_node_identity(),
+            properties: from_bolt_properties(rel.properties()),
+        }),
+        Value::Path(path) => GraphValue::Path(from_bolt_path(path)),
+        Value::List(values)
+            if values
+                .iter()
+                .any(|v| matches!(v, Value::Node(_) | Value::Relationship(_) | Value::Path(_))) =>
+        {
+            GraphValue::List(values.iter().map(from_bolt).collect())
+        }
+        v => GraphValue::Value(from_bolt_value(v)),
+    }
+}
+
+/// Follows the sequence of a bolt path: pairs of a 1-based relationship index, negative when the
+/// relationship is traversed backwards, and the index of the next node
+fn from_bolt_path(path: &Path) -> GraphPath {
+    let node = |node: &Node| GraphNode {
+        id: node.node_identity(),
+        labels: node.labels().to_vec(),
+        properties: from_bolt_properties(node.properties()),
+    };
+    let mut nodes: Vec<GraphNode> = path.nodes().iter().take(1).map(node).collect();
+    let mut relationships = Vec::new();
+    for step in path.sequence().chunks(2) {
+        let (rel, next) = match step {
+            [rel, next] => (*rel, *next as usize),
+            _ => break,
+        };
+        let (Some(prev), Some(next), Some(r)) = (
+            nodes.last().map(|n| n.id),
+            path.nodes().get(next),
+            path.relationships().get(rel.unsigned_abs() as usize - 1),
+        ) else {
+            break;
+        };
+        let next = node(next);
+        let (src_id, dst_id) = if rel > 0 {
+            (prev, next.id)
+        } else {
+            (next.id, prev)
+        };
+        relationships.push(GraphRelationship {
+            id: r.rel_identity(),
+            tlabel: r.rel_type().to_string(),
+            src_id,
+            dst_id,
+            properties: from_bolt_properties(r.properties()),
+        });
+        nodes.push(next);
+    }
+    GraphPath {
+        nodes,
+        relationships,
+    }
+}
+
+fn limit_param(limit: usize) -> HashMap<String, FeatureValue> {
+    HashMap::from([("limit".to_string(), FeatureValue::Int(limit as i64))])
+}
+
+fn nodes(values: Vec<GraphValue>) -> Vec<GraphNode> {
+    values
+        .into_iter()
+        .filter_map(|v| match v {
+            GraphValue::Node(node) => Some(node),
+            _ => None,
+        })
+        .collect()
+}
+
+fn relationships(values: Vec<GraphValue>) -> Vec<GraphRelationship> {
+    values
+        .into_iter()
+        .filter_map(|v| match v {
+            GraphValue::Relationship(rel) => Some(rel),
+            _ => None,
+        })
+        .collect()
+}
+
+fn strings(values: Vec<GraphValue>) -> Vec<String> {
+    values
+        .into_iter()
+        .filter_map(|v| match v {
+            GraphValue::Value(FeatureValue::String(s)) => Some(s),
+            _ => None,
+        })
+        .collect()
+}
+
+fn properties_value(properties: &HashMap<String, FeatureValue>) -> Value {
+    Value::Map(
+        properties
+            .iter()
+            .map(|(k, v)| (k.clone(), to_bolt_value(v)))
+            .collect(),
+    )
+}
+
+struct Neo4jRowStream {
+    stream: BoltRowStream,
+}
+
+#[async_trait(?Send)]
+impl GraphRowStream for Neo4jRowStream {
+    fn columns(&self) -> &[String] {
+        &self.stream.columns
+    }
+
+    async fn next(&mut self) -> Result<Option<Vec<GraphValue>>, Box<dyn Error>> {
+        Ok(self
+            .stream
+            .next()
+            .await?
+            .map(|row| row.iter().map(from_bolt).collect()))
+    }
+}
+
+#[async_trait(?Send)]
+impl GraphDatabase for Neo4jGraphDatabase {
+    async fn run_query(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<GraphRows, Box<dyn Error>> {
+        let res = self.client.execute(statement(query, params)).await?;
+        Ok(GraphRows {
+            columns: res.columns,
+            rows: res
+                .rows
+                .iter()
+                .map(|row| row.iter().map(from_bolt).collect())
+                .collect(),
+        })
+    }
+
+    async fn stream_rows(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<Box<dyn GraphRowStream>, Box<dyn Error>> {
+        let stream = self
+            .client
+            .stream(statement(query, params), FETCH_SIZE)
+            .await?;
+        Ok(Box::new(Neo4jRowStream { stream }))
+    }
+
+    async fn upsert_vertices(&self, vertices: &[VertexRecord]) -> Result<(), Box<dyn Error>> {
+        let mut groups: HashMap<(&str, &str), Vec<Value>> = HashMap::new();
+        for vertex in vertices {
+            groups
+                .entry((&vertex.key.tlabel, &vertex.key.primary_key))
+                .or_default()
+                .push(Value::from(HashMap::from([
+                    ("key", to_bolt_value(&vertex.key.value)),
+                    ("properties", properties_value(&vertex.properties)),
+                ])));
+        }
+        for ((tlabel, primary_key), rows) in groups {
+            info!("Upserting {} {} vertices", rows.len(), tlabel);
+            let query = format!(
+                "UNWIND $rows AS row MERGE (n:{} {{{}: row.key}}) SET n += row.properties",
+                quote_identifier(tlabel),
+                quote_identifier(primary_key)
+            );
+            for batch in rows.chunks(UPSERT_BATCH_SIZE) {
+                self.client
+                    .execute(GraphStatement::new(&query).param("rows", batch.to_vec()))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<(), Box<dyn Error>> {
+        let mut groups: HashMap<_, Vec<Value>> = HashMap::new();
+        for edge in edges {
+            groups
+                .entry((
+                    &edge.tlabel,
+                    (&edge.src.tlabel, &edge.src.primary_key),
+                    (&edge.dst.tlabel, &edge.dst.primary_key),
+                ))
+                .or_default()
+                .push(Value::from(HashMap::from([
+                    ("src", to_bolt_value(&edge.src.value)),
+                    ("dst", to_bolt_value(&edge.dst.value)),
+                    ("properties", properties_value(&edge.properties)),
+                ])));
+        }
+        for ((tlabel, (src_tlabel, src_key), (dst_tlabel, dst_key)), rows) in groups {
+            info!("Upserting {} {} edges", rows.len(), tlabel);
+            let query = format!(
+                "UNWIND $rows AS row \
+                 MATCH (s:{} {{{}: row.src}}) \
+                 MATCH (d:{} {{{}: row.dst}}) \
+                 MERGE (s)-[r:{}]->(d) SET r += row.properties",
+                quote_identifier(src_tlabel),
+                quote_identifier(src_key),
+                quote_identifier(dst_tlabel),
+                quote_identifier(dst_key),
+                quote_identifier(tlabel)
+            );
+            for batch in rows.chunks(UPSERT_BATCH_SIZE) {
+                self.client
+                    .execute(GraphStatement::new(&query).param("rows", batch.to_vec()))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn scan_vertices(&self, tlabel: &str) -> Result<Vec<GraphNode>, Box<dyn Error>> {
+        let values = self
+            .scan(
+                &format!("MATCH (n:{}) RETURN n", quote_identifier(tlabel)),
+                HashMap::new(),
+            )
+            .await?;
+        Ok(nodes(values))
+    }
+
+    async fn scan_edges(&self, tlabel: &str) -> Result<Vec<GraphRelationship>, Box<dyn Error>> {
+        let values = self
+            .scan(
+                &format!("MATCH ()-[r:{}]->() RETURN r", quote_identifier(tlabel)),
+                HashMap::new(),
+            )
+            .await?;
+        Ok(relationships(values))
+    }
+
+    async fn sample_vertices(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphNode>, Box<dyn Error>> {
+        let values = self
+            .scan(
+                &format!(
+                    "MATCH (n:{}) RETURN n LIMIT $limit",
+                    quote_identifier(tlabel)
+                ),
+                limit_param(limit),
+            )
+            .await?;
+        Ok(nodes(values))
+    }
+
+    async fn sample_edges(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphRelationship>, Box<dyn Error>> {
+        let values = self
+            .scan(
+                &format!(
+                    "MATCH ()-[r:{}]->() RETURN r LIMIT $limit",
+                    quote_identifier(tlabel)
+                ),
+                limit_param(limit),
+            )
+            .await?;
+        Ok(relationships(values))
+    }
+
+    async fn vertex_types(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let values = self
+            .scan("CALL db.labels() YIELD label RETURN label", HashMap::new())
+            .await?;
+        Ok(strings(values))
+    }
+
+    async fn edge_types(&self, sample_size: usize) -> Result<Vec<GraphEdgeType>, Box<dyn Error>> {
+        let tlabels = strings(
+            self.scan(
+                "CALL db.relationshipTypes() YIELD relationshipType RETURN relationshipType",
+                HashMap::new(),
+            )
+            .await?,
+        );
+        let mut res = Vec::new();
+        for tlabel in tlabels {
+            let rows = self
+                .run_query(
+                    &format!(
+                        "MATCH (a)-[:{}]->(b) WITH a, b LIMIT $limit \
+                         UNWIND labels(a) AS src UNWIND labels(b) AS dst \
+                         RETURN DISTINCT src, dst",
+                        quote_identifier(&tlabel)
+                    ),
+                    limit_param(sample_size),
+                )
+                .await?;
+            let endpoints = rows
+                .rows
+                .into_iter()
+                .filter_map(|row| match (&row[0], &row[1]) {
+                    (
+                        GraphValue::Value(FeatureValue::String(src)),
+                        GraphValue::Value(FeatureValue::String(dst)),
+                    ) => Some((src.clone(), dst.clone())),
+                    _ => None,
+                })
+                .collect();
+            res.push(GraphEdgeType { tlabel, endpoints });
+        }
+        Ok(res)
+    }
+
+    async fn schema(&self) -> Result<Vec<GraphSchemaItem>, Box<dyn Error>> {
+        let mut res = Vec::new();
+        let queries = [
+            "SHOW CONSTRAINTS YIELD name, type, entityType, labelsOrTypes, properties \
+             WHERE type = 'UNIQUENESS' \
+             RETURN name, 'constraint' AS kind, entityType, labelsOrTypes[0] AS tlabel, properties",
+            "SHOW INDEXES YIELD name, type, entityType, labelsOrTypes, properties, owningConstraint \
+             WHERE owningConstraint IS NULL AND type <> 'LOOKUP' \
+             RETURN name, 'index' AS kind, entityType, labelsOrTypes[0] AS tlabel, properties",
+        ];
+        for q in queries {
+            let result = self.run_query(q, HashMap::new()).await?;
+            for i in 0..result.rows.len() {
+                let get = |key: &str| match result.get(i, key) {
+                    Some(GraphValue::Value(FeatureValue::String(s))) => s.clone(),
+                    _ => String::new(),
+                };
+                res.push(GraphSchemaItem {
+                    name: get("name"),
+                    kind: match get("kind").as_str() {
+                        "constraint" => GraphSchemaItemKind::UniqueConstraint,
+                        _ => GraphSchemaItemKind::Index,
+                    },
+                    element: match get("entityType").as_str() {
+                        "RELATIONSHIP" => GraphElementKind::Relationship,
+                        _ => GraphElementKind::Node,
+                    },
+                    tlabel: get("tlabel"),
+                    properties: match result.get(i, "properties") {
+                        Some(GraphValue::Value(FeatureValue::Array(properties))) => {
+                            properties.iter().map(|p| p.to_string()).collect()
+                        }
+                        _ => Vec::new(),
+                    },
+                });
+            }
+        }
+        Ok(res)
+    }
+
+    async fn apply_schema(&self, plan: &SchemaPlan) -> Result<(), Box<dyn Error>> {
+        // schema commands cannot share a transaction with each other
+        for item in &plan.to_drop {
+            info!("Dropping {}", item.name);
+            self.client
+                .execute(GraphStatement::new(&item.drop_query()))
+                .await?;
+        }
+        for item in &plan.to_create {
+            info!("Creating {}", item.name);
+            self.client
+                .execute(GraphStatement::new(&item.create_query()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn count_vertices(&self, tlabel: &str) -> Result<usize, Box<dyn Error>> {
+        self.count(
+            &format!("MATCH (n:{}) RETURN count(n)", quote_identifier(tlabel)),
+            HashMap::new(),
+        )
+        .await
+    }
+
+    async fn count_edges(&self, tlabel: &str) -> Result<usize, Box<dyn Error>> {
+        self.count(
+            &format!(
+                "MATCH ()-[r:{}]->() RETURN count(r)",
+                quote_identifier(tlabel)
+            ),
+            HashMap::new(),
+        )
+        .await
+    }
+
+    async fn delete_vertices(
+        &self,
+        tlabel: &str,
+        batch_size: usize,
+    ) -> Result<usize, Box<dyn Error>> {
+        let query = format!(
+            "MATCH (n:{}) WITH n LIMIT $limit DETACH DELETE n RETURN count(*)",
+            quote_identifier(tlabel)
+        );
+        self.delete_in_batches(&query, batch_size).await
+    }
+
+    async fn delete_edges(&self, tlabel: &str, batch_size: usize) -> Result<usize, Box<dyn Error>> {
+        let query = format!(
+            "MATCH ()-[r:{}]->() WITH r LIMIT $limit DELETE r RETURN count(*)",
+            quote_identifier(tlabel)
+        );
+        self.delete_in_batches(&query, batch_size).await
+    }
+
+    async fn clear(&self) -> Result<(), Box<dyn Error>> {
+        self.client
+            .execute_in_transaction(&[GraphStatement::new("MATCH (n) DETACH DELETE n")])
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,595 @@
+use crate::{
+    DataSource, EdgeRecord, FeatureValue, FeatureValueType, GraphMapping, GraphRecords,
+    SourceTable, SourceTransformation, VertexKey, VertexRecord,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+pub type VertexId = usize;
+pub type EdgeId = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vertex {
+    pub tlabel: String,
+    pub properties: HashMap<String, FeatureValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub tlabel: String,
+    pub src: VertexId,
+    pub dst: VertexId,
+    pub properties: HashMap<String, FeatureValue>,
+}
+
+/// The vertices or edges of a tlabel, with the types of their properties
+#[derive(Debug, Clone, Default)]
+pub struct ElementType {
+    /// The property identifying vertices of this type, None for edges
+    pub primary_key: Option<String>,
+    pub schema: BTreeMap<String, FeatureValueType>,
+    pub ids: Vec<usize>,
+}
+
+impl ElementType {
+    /// Casts the value to the type of the property, or records the type of the value if the
+    /// property is new. An Int property becomes Float when a Float value is stored, in which case
+    /// this returns true so that the values stored before are widened too.
+    fn typed_value(
+        &mut self,
+        property: &str,
+        value: &FeatureValue,
+    ) -> Result<(FeatureValue, bool), Box<dyn Error>> {
+        match (self.schema.get(property), value.value_type()) {
+            (_, None) => Ok((FeatureValue::Null, false)),
+            (Some(value_type), Some(t)) if *value_type == t => Ok((value.clone(), false)),
+            (Some(FeatureValueType::Int), Some(FeatureValueType::Float)) => {
+                self.schema
+                    .insert(property.to_string(), FeatureValueType::Float);
+                Ok((value.clone(), true))
+            }
+            (Some(value_type), Some(_)) => match value.cast(value_type) {
+                Ok(cast) => Ok((cast, false)),
+                Err(e) => Err(format!(
+                    "cannot store {} as {:?} in {}: {}",
+                    value, value_type, property, e
+                )
+                .into()),
+            },
+            (None, Some(t)) => {
+                self.schema.insert(property.to_string(), t);
+                Ok((value.clone(), false))
+            }
+        }
+    }
+}
+
+/// The properties of a record cast to the types of its tlabel, with whether each one widens its
+/// property, and the types of the properties once the record is stored
+type TypedProperties<'a> = (
+    Vec<(&'a String, FeatureValue, bool)>,
+    BTreeMap<String, FeatureValueType>,
+);
+
+/// Casts all the properties of a record before any is stored, so that a record with a property
+/// that cannot be cast leaves the graph unchanged
+fn typed_properties<'a>(
+    element_type: Option<&ElementType>,
+    properties: impl Iterator<Item = (&'a String, &'a FeatureValue)>,
+) -> Result<TypedProperties<'a>, Box<dyn Error>> {
+    let mut element_type = ElementType {
+        schema: element_type.map(|t| t.schema.clone()).unwrap_or_default(),
+        ..ElementType::default()
+    };
+    let mut res = Vec::new();
+    for (k, v) in properties {
+        let (value, widened) = element_type.typed_value(k, v)?;
+        res.push((k, value, widened));
+    }
+    Ok((res, element_type.schema))
+}
+
+/// Converts the Int values of the property to Float, after the property type is widened
+fn widen_to_float<'a>(
+    elements: impl Iterator<Item = &'a mut HashMap<String, FeatureValue>>,
+    property: &str,
+) {
+    for properties in elements {
+        if let Some(FeatureValue::Int(v)) = properties.get(property) {
+            let widened = FeatureValue::Float(*v as f64);
+            properties.insert(property.to_string(), widened);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Adjacency {
+    out_edges: HashMap<VertexId, Vec<EdgeId>>,
+    in_edges: HashMap<VertexId, Vec<EdgeId>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Out,
+    In,
+    Both,
+}
+
+/// An in-process property graph. Properties are typed per tlabel, either declared up front or
+/// inferred from the first non-null value with Int widened to Float by later Float values, and
+/// edges are indexed by type in both directions.
+#[derive(Debug, Default)]
+pub struct PropertyGraph {
+    vertices: Vec<Vertex>,
+    edges: Vec<Edge>,
+    vertex_types: BTreeMap<String, ElementType>,
+    edge_types: BTreeMap<String, ElementType>,
+    adjacency: BTreeMap<String, Adjacency>,
+    /// Vertex ids by (tlabel, primary key, value), under every key a vertex was upserted with
+    key_index: HashMap<(String, String, String), VertexId>,
+    /// Edge ids by (tlabel, src, dst)
+    edge_index: HashMap<(String, VertexId, VertexId), EdgeId>,
+}
+
+#[derive(Deserialize)]
+struct JsonGraph {
+    #[serde(default)]
+    vertices: Vec<JsonVertex>,
+    #[serde(default)]
+    edges: Vec<JsonEdge>,
+}
+
+#[derive(Deserialize)]
+struct JsonVertex {
+    tlabel: String,
+    id: serde_json::Value,
+    #[serde(default)]
+    properties: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct JsonEdge {
+    tlabel: String,
+    src: (String, serde_json::Value),
+    dst: (String, serde_json::Value),
+    #[serde(default)]
+    properties: serde_json::Map<String, serde_json::Value>,
+}
+
+fn json_properties(
+    properties: &serde_json::Map<String, serde_json::Value>,
+) -> HashMap<String, FeatureValue> {
+    properties
+        .iter()
+        .map(|(k, v)| (k.clone(), FeatureValue::infer_from_json(v)))
+        .collect()
+}
+
+impl PropertyGraph {
+    pub fn new() -> Self {
+        PropertyGraph::default()
+    }
+
+    /// Loads a graph in the format
+    /// `{"vertices": [{"tlabel": "Person", "id": "p1", "properties": {"age": 30}}],
+    ///   "edges": [{"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"]}]}`,
+    /// where vertices are keyed by their `id` property
+    pub fn from_json(text: &str) -> Result<Self, Box<dyn Error>> {
+        let json: JsonGraph = serde_json::from_str(text)?;
+        let key = |tlabel: &str, id: &serde_json::Value| VertexKey {
+            tlabel: tlabel.to_string(),
+            primary_key: "id".to_string(),
+            value: FeatureValue::infer_from_json(id),
+        };
+        let records = GraphRecords {
+            vertices: json
+                .vertices
+                .iter()
+                .map(|v| VertexRecord {
+                    key: key(&v.tlabel, &v.id),
+                    properties: json_properties(&v.properties),
+                })
+                .collect(),
+            edges: json
+                .edges
+                .iter()
+                .map(|e| EdgeRecord {
+                    tlabel: e.tlabel.clone(),
+                    src: key(&e.src.0, &e.src.1),
+                    dst: key(&e.dst.0, &e.dst.1),
+                    properties: json_properties(&e.properties),
+                })
+                .collect(),
+        };
+        let mut res = PropertyGraph::new();
+        res.load_records(&records)?;
+        Ok(res)
+    }
+
+    /// Loads the vertices and edges mapped from the rows of a CSV text
+    pub fn load_csv(
+        &mut self,
+        text: &str,
+        delimiter: char,
+        mappings: Vec<GraphMapping>,
+    ) -> Result<(), Box<dyn Error>> {
+        let transformation = SourceTransformation {
+            steps: Vec::new(),
+            mappings,
+        };
+        self.load_records(&transformation.apply(SourceTable::from_csv(text, delimiter)?)?)
+    }
+
+    pub fn load_data_source(&mut self, data_source: &DataSource) -> Result<(), Box<dyn Error>> {
+        self.load_records(&data_source.load_graph_records()?)
+    }
+
+    /// Upserts the vertices, then the edges between them. Edges whose endpoints do not exist are
+    /// skipped.
+    pub fn load_records(&mut self, records: &GraphRecords) -> Result<(), Box<dyn Error>> {
+        for vertex in &records.vertices {
+            self.upsert_vertex(vertex)?;
+        }
+        for edge in &records.edges {
+            self.upsert_edge(edge)?;
+        }
+        Ok(())
+    }
+
+    /// Declares the property types of a vertex type. Values stored afterwards are cast to them.
+    pub fn declare_vertex_type(&mut self, tlabel: &str, schema: Vec<(&str, FeatureValueType)>) {
+        let element_type = self.vertex_types.entry(tlabel.to_string()).or_default();
+        for (property, value_type) in schema {
+            element_type.schema.insert(property.to_string(), value_type);
+        }
+    }
+
+    pub fn declare_edge_type(&mut self, tlabel: &str, schema: Vec<(&str, FeatureValueType)>) {
+        let element_type = self.edge_types.entry(tlabel.to_string()).or_default();
+        for (property, value_type) in schema {
+            element_type.schema.insert(property.to_string(), value_type);
+        }
+    }
+
+    /// Finds a vertex by one of the keys it was created or updated with
+    pub fn find_vertex(&self, key: &VertexKey) -> Option<VertexId> {
+        self.key_index
+            .get(&(
+                key.tlabel.clone(),
+                key.primary_key.clone(),
+                key.value.to_string(),
+            ))
+            .copied()
+    }
+
+    pub fn upsert_vertex(&mut self, record: &VertexRecord) -> Result<VertexId, Box<dyn Error>> {
+        let key = &record.key;
+        let (properties, schema) = typed_properties(
+            self.vertex_types.get(&key.tlabel),
+            std::iter::once((&key.primary_key, &key.value)).chain(&record.properties),
+        )?;
+        let id = match self.find_vertex(key) {
+            Some(id) => id,
+            None => {
+                let id = self.vertices.len();
+                self.vertices.push(Vertex {
+                    tlabel: key.tlabel.clone(),
+                    properties: HashMap::new(),
+                });
+                let element_type = self.vertex_types.entry(key.tlabel.clone()).or_default();
+                element_type
+                    .primary_key
+                    .get_or_insert(key.primary_key.clone());
+                element_type.ids.push(id);
+                id
+            }
+        };
+        self.key_index.insert(
+            (
+                key.tlabel.clone(),
+                key.primary_key.clone(),
+                key.value.to_string(),
+            ),
+            id,
+        );
+        self.vertex_types.get_mut(&key.tlabel).unwrap().schema = schema;
+        for (k, value, widened) in properties {
+            if widened {
+                let vertices = self.vertices.iter_mut().filter(|v| v.tlabel == key.tlabel);
+                widen_to_float(vertices.map(|v| &mut v.properties), k);
+            }
+            self.vertices[id].properties.insert(k.clone(), value);
+        }
+        Ok(id)
+    }
+
+    pub fn upsert_edge(&mut self, record: &EdgeRecord) -> Result<Option<EdgeId>, Box<dyn Error>> {
+        let (src, dst) = match (self.find_vertex(&record.src), self.find_vertex(&record.dst)) {
+            (Some(src), Some(dst)) => (src, dst),
+            _ => return Ok(None),
+        };
+        let (properties, schema) = typed_properties(
+            self.edge_types.get(&record.tlabel),
+            record.properties.iter(),
+        )?;
+        let index_key = (record.tlabel.clone(), src, dst);
+        let id = match self.edge_index.get(&index_key) {
+            Some(id) => *id,
+            None => self.push_edge(Edge {
+                tlabel: record.tlabel.clone(),
+                src,
+                dst,
+                properties: HashMap::new(),
+            }),
+        };
+        self.edge_types.get_mut(&record.tlabel).unwrap().schema = schema;
+        for (k, value, widened) in properties {
+            if widened {
+                let edges = self.edges.iter_mut().filter(|e| e.tlabel == record.tlabel);
+                widen_to_float(edges.map(|e| &mut e.properties), k);
+            }
+            self.edges[id].properties.insert(k.clone(), value);
+        }
+        Ok(Some(id))
+    }
+
+    /// Adds an edge between existing vertices and indexes it
+    fn push_edge(&mut self, edge: Edge) -> EdgeId {
+        let id = self.edges.len();
+        self.edge_types
+            .entry(edge.tlabel.clone())
+            .or_default()
+            .ids
+            .push(id);
+        let adjacency = self.adjacency.entry(edge.tlabel.clone()).or_default();
+        adjacency.out_edges.entry(edge.src).or_default().push(id);
+        adjacency.in_edges.entry(edge.dst).or_default().push(id);
+        self.edge_index
+            .insert((edge.tlabel.clone(), edge.src, edge.dst), id);
+        self.edges.push(edge);
+        id
+    }
+
+    /// Removes the vertices of the tlabel with their edges. Returns the number of removed
+    /// vertices.
+    pub fn remove_vertices(&mut self, tlabel: &str) -> usize {
+        let count = self.vertex_types.get(tlabel).map_or(0, |t| t.ids.len());
+        self.retain(|v| v.tlabel != tlabel, |_| true);
+        count
+    }
+
+    /// Removes the edges of the tlabel. Returns the number of removed edges.
+    pub fn remove_edges(&mut self, tlabel: &str) -> usize {
+        let count = self.edge_types.get(tlabel).map_or(0, |t| t.ids.len());
+        self.retain(|_| true, |e| e.tlabel != tlabel);
+        count
+    }
+
+    /// Keeps the vertices and edges for which the predicates hold, and the edges between kept
+    /// vertices. Ids are reassigned.
+    fn retain(&mut self, keep_vertex: impl Fn(&Vertex) -> bool, keep_edge: impl Fn(&Edge) -> bool) {
+        let vertices = std::mem::take(&mut self.vertices);
+        let edges = std::mem::take(&mut self.edges);
+        let key_index = std::mem::take(&mut self.key_index);
+        self.clear();
+        let mut new_ids = HashMap::new();
+        for (old_id, vertex) in vertices.into_iter().enumerate() {
+            if keep_vertex(&vertex) {
+                let id = self.vertices.len();
+                new_ids.insert(old_id, id);
+                self.vertex_types
+                    .entry(vertex.tlabel.clone())
+                    .or_default()
+                    .ids
+                    .push(id);
+                self.vertices.push(vertex);
+            }
+        }
+        for (key, old_id) in key_index {
+            if let Some(id) = new_ids.get(&old_id) {
+                self.key_index.insert(key, *id);
+            }
+        }
+        for edge in edges.into_iter().filter(|e| keep_edge(e)) {
+            if let (Some(src), Some(dst)) = (new_ids.get(&edge.src), new_ids.get(&edge.dst)) {
+                self.push_edge(Edge {
+                    src: *src,
+                    dst: *dst,
+                    ..edge
+                });
+            }
+        }
+    }
+
+    /// Removes all vertices and edges, keeping the declared types
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.edges.clear();
+        self.adjacency.clear();
+        self.key_index.clear();
+        self.edge_index.clear();
+        for element_type in self
+            .vertex_types
+            .values_mut()
+            .chain(self.edge_types.values_mut())
+        {
+            element_type.ids.clear();
+        }
+    }
+
+    pub fn vertex(&self, id: VertexId) -> &Vertex {
+        &self.vertices[id]
+    }
+
+    pub fn edge(&self, id: EdgeId) -> &Edge {
+        &self.edges[id]
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn vertex_types(&self) -> &BTreeMap<String, ElementType> {
+        &self.vertex_types
+    }
+
+    pub fn edge_types(&self) -> &BTreeMap<String, ElementType> {
+        &self.edge_types
+    }
+
+    /// Returns the edges incident to the vertex in the given direction, of the given type or of all
+    /// types if None
+    pub fn incident_edges(
+        &self,
+        vertex: VertexId,
+        edge_type: Option<&str>,
+        direction: Direction,
+    ) -> Vec<EdgeId> {
+        let mut res = Vec::new();
+        let adjacencies: Vec<&Adjacency> = match edge_type {
+            Some(t) => self.adjacency.get(t).into_iter().collect(),
+            None => self.adjacency.values().collect(),
+        };
+        for adjacency in adjacencies {
+            if direction != Direction::In {
+                res.extend(adjacency.out_edges.get(&vertex).into_iter().flatten());
+            }
+            if direction != Direction::Out {
+                res.extend(adjacency.in_edges.get(&vertex).into_iter().flatten());
+            }
+        }
+        res
+    }
+
+    /// Returns the vertices at the other end of the incident edges, once per edge
+    pub fn neighbors(
+        &self,
+        vertex: VertexId,
+        edge_type: Option<&str>,
+        direction: Direction,
+    ) -> Vec<VertexId> {
+        self.incident_edges(vertex, edge_type, direction)
+            .into_iter()
+            .map(|e| {
+                let edge = &self.edges[e];
+                if edge.src == vertex {
+                    edge.dst
+                } else {
+                    edge.src
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn load_typed_graph() {
+    let mut graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41.5}},
+                {"tlabel": "City", "id": "c1"}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"]},
+                {"tlabel": "livesIn", "src": ["Person", "p1"], "dst": ["City", "c1"]},
+                {"tlabel": "livesIn", "src": ["Person", "p3"], "dst": ["City", "c1"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(graph.vertex_count(), 3);
+    assert_eq!(graph.edge_count(), 2);
+    // the type is widened to Float when a Float follows Int values
+    assert_eq!(
+        graph.vertex_types()["Person"].schema["age"],
+        FeatureValueType::Float
+    );
+    assert_eq!(graph.vertex(0).properties["age"], FeatureValue::Float(30.0));
+    assert_eq!(graph.vertex(1).properties["age"], FeatureValue::Float(41.5));
+    assert_eq!(graph.neighbors(0, None, Direction::Out), vec![1, 2]);
+    assert_eq!(graph.neighbors(2, Some("livesIn"), Direction::In), vec![0]);
+
+    graph.declare_vertex_type("City", vec![("population", FeatureValueType::Int)]);
+    graph
+        .load_csv(
+            "city,population\nc1,1000\nc2,20\n",
+            ',',
+            vec![GraphMapping::Vertex {
+                tlabel: "City".to_string(),
+                key: "id".to_string(),
+                properties: vec!["population".to_string()],
+            }],
+        )
+        .unwrap_err();
+    graph
+        .load_csv(
+            "id,population\nc1,1000\nc2,20\n",
+            ',',
+            vec![GraphMapping::Vertex {
+                tlabel: "City".to_string(),
+                key: "id".to_string(),
+                properties: vec!["population".to_string()],
+            }],
+        )
+        .unwrap();
+    assert_eq!(graph.vertex_types()["City"].ids, vec![2, 3]);
+    assert_eq!(
+        graph.vertex(2).properties["population"],
+        FeatureValue::Int(1000)
+    );
+
+    assert_eq!(graph.remove_vertices("Person"), 2);
+    assert_eq!(graph.vertex_count(), 2);
+    assert_eq!(graph.edge_count(), 0);
+    assert_eq!(
+        graph.vertex(0).properties["id"],
+        FeatureValue::String("c1".into())
+    );
+    let key = VertexKey {
+        tlabel: "City".to_string(),
+        primary_key: "id".to_string(),
+        value: FeatureValue::String("c2".to_string()),
+    };
+    assert_eq!(graph.find_vertex(&key), Some(1));
+    // a vertex is found by the keys it was upserted with, not by its other properties
+    let named = VertexKey {
+        primary_key: "name".to_string(),
+        value: FeatureValue::String("Paris".to_string()),
+        ..key.clone()
+    };
+    graph
+        .upsert_vertex(&VertexRecord {
+            key: key.clone(),
+            properties: HashMap::from([("name".to_string(), named.value.clone())]),
+        })
+        .unwrap();
+    assert_eq!(graph.find_vertex(&named), None);
+
+    // a record with a property that cannot be cast is not stored at all
+    let rome = VertexKey {
+        value: FeatureValue::String("c3".to_string()),
+        ..key.clone()
+    };
+    graph
+        .upsert_vertex(&VertexRecord {
+            key: rome.clone(),
+            properties: HashMap::from([
+                ("area".to_string(), FeatureValue::Float(1285.0)),
+                (
+                    "population".to_string(),
+                    FeatureValue::String("many".to_string()),
+                ),
+            ]),
+        })
+        .unwrap_err();
+    assert_eq!(graph.vertex_count(), 2);
+    assert_eq!(graph.find_vertex(&rome), None);
+    assert!(!graph.vertex_types()["City"].schema.contains_key("area"));
+}
@@ -0,0 +1,127 @@
+use super::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+/// A graph database for tests that answers queries with scripted rows. Each query must contain
+/// the text expected by the next answer, and the other operations run on an in-memory graph.
+#[derive(Default)]
+pub struct ScriptedGraphDatabase {
+    graph: MemoryGraphDatabase,
+    answers: RefCell<VecDeque<(String, GraphRows)>>,
+}
+
+impl ScriptedGraphDatabase {
+    pub fn new() -> Self {
+        ScriptedGraphDatabase::default()
+    }
+
+    /// Answers the next query, which must contain `expected`, with the rows
+    pub fn expect(&self, expected: &str, rows: GraphRows) {
+        self.answers
+            .borrow_mut()
+            .push_back((expected.to_string(), rows));
+    }
+
+    /// The number of scripted answers not queried yet
+    pub fn pending(&self) -> usize {
+        self.answers.borrow().len()
+    }
+}
+
+#[async_trait(?Send)]
+impl GraphDatabase for ScriptedGraphDatabase {
+    async fn run_query(
+        &self,
+        query: &str,
+        _params: HashMap<String, FeatureValue>,
+    ) -> Result<GraphRows, Box<dyn Error>> {
+        match self.answers.borrow_mut().pop_front() {
+            Some((expected, rows)) if query.contains(&expected) => Ok(rows),
+            Some((expected, _)) => {
+                Err(format!("expected a query with {} but got {}", expected, query).into())
+            }
+            None => Err(format!("unexpected query {}", query).into()),
+        }
+    }
+
+    async fn stream_rows(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+    ) -> Result<Box<dyn GraphRowStream>, Box<dyn Error>> {
+        let rows = self.run_query(query, params).await?;
+        Ok(Box::new(CollectedRowStream::new(rows)))
+    }
+
+    async fn upsert_vertices(&self, vertices: &[VertexRecord]) -> Result<(), Box<dyn Error>> {
+        self.graph.upsert_vertices(vertices).await
+    }
+
+    async fn upsert_edges(&self, edges: &[EdgeRecord]) -> Result<(), Box<dyn Error>> {
+        self.graph.upsert_edges(edges).await
+    }
+
+    async fn scan_vertices(&self, tlabel: &str) -> Result<Vec<GraphNode>, Box<dyn Error>> {
+        self.graph.scan_vertices(tlabel).await
+    }
+
+    async fn scan_edges(&self, tlabel: &str) -> Result<Vec<GraphRelationship>, Box<dyn Error>> {
+        self.graph.scan_edges(tlabel).await
+    }
+
+    async fn sample_vertices(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphNode>, Box<dyn Error>> {
+        self.graph.sample_vertices(tlabel, limit).await
+    }
+
+    async fn sample_edges(
+        &self,
+        tlabel: &str,
+        limit: usize,
+    ) -> Result<Vec<GraphRelationship>, Box<dyn Error>> {
+        self.graph.sample_edges(tlabel, limit).await
+    }
+
+    async fn vertex_types(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        self.graph.vertex_types().await
+    }
+
+    async fn edge_types(&self, sample_size: usize) -> Result<Vec<GraphEdgeType>, Box<dyn Error>> {
+        self.graph.edge_types(sample_size).await
+    }
+
+    async fn schema(&self) -> Result<Vec<GraphSchemaItem>, Box<dyn Error>> {
+        self.graph.schema().await
+    }
+
+    async fn apply_schema(&self, plan: &SchemaPlan) -> Result<(), Box<dyn Error>> {
+        self.graph.apply_schema(plan).await
+    }
+
+    async fn count_vertices(&self, tlabel: &str) -> Result<usize, Box<dyn Error>> {
+        self.graph.count_vertices(tlabel).await
+    }
+
+    async fn count_edges(&self, tlabel: &str) -> Result<usize, Box<dyn Error>> {
+        self.graph.count_edges(tlabel).await
+    }
+
+    async fn delete_vertices(
+        &self,
+        tlabel: &str,
+        batch_size: usize,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.graph.delete_vertices(tlabel, batch_size).await
+    }
+
+    async fn delete_edges(&self, tlabel: &str, batch_size: usize) -> Result<usize, Box<dyn Error>> {
+        self.graph.delete_edges(tlabel, batch_size).await
+    }
+
+    async fn clear(&self) -> Result<(), Box<dyn Error>> {
+        self.graph.clear().await
+    }
+}
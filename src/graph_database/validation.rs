@@ -0,0 +1,278 @@
+use super::*;
+use crate::{Entity, EntityType, FeatureRegistry, FeatureValueType, Field, ResourceId, ResourceOp};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+/// A difference between the registered entities and fields and the data in the graph database
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriftIssue {
+    /// No vertex or edge of the entity tlabel was found
+    MissingTlabel {
+        entity_id: ResourceId,
+        tlabel: String,
+    },
+    /// The property of the field is absent from all sampled elements
+    MissingProperty { field_id: ResourceId },
+    TypeMismatch {
+        field_id: ResourceId,
+        registered: FeatureValueType,
+        observed: FeatureValueType,
+    },
+    /// The property of the field is null or absent in more sampled elements than allowed
+    HighNullRate {
+        field_id: ResourceId,
+        null_rate: f64,
+    },
+    /// A property found in the sampled elements that no field of the entity registers
+    UnregisteredProperty {
+        entity_id: ResourceId,
+        property: String,
+        observed: FeatureValueType,
+    },
+}
+
+impl Display for DriftIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DriftIssue::MissingTlabel { entity_id, tlabel } => {
+                write!(f, "{}: no elements of {} found", entity_id, tlabel)
+            }
+            DriftIssue::MissingProperty { field_id } => {
+                write!(f, "{}: property not found", field_id)
+            }
+            DriftIssue::TypeMismatch {
+                field_id,
+                registered,
+                observed,
+            } => write!(
+                f,
+                "{}: registered as {:?} but found {:?}",
+                field_id, registered, observed
+            ),
+            DriftIssue::HighNullRate {
+                field_id,
+                null_rate,
+            } => write!(f, "{}: {:.1}% null", field_id, null_rate * 100.0),
+            DriftIssue::UnregisteredProperty {
+                entity_id,
+                property,
+                observed,
+            } => write!(
+                f,
+                "{}: unregistered property {} of type {:?}",
+                entity_id, property, observed
+            ),
+        }
+    }
+}
+
+/// The result of comparing registered entities and fields with sampled data
+#[derive(Debug, Default)]
+pub struct DriftReport {
+    /// Number of elements sampled per entity id
+    pub sample_sizes: BTreeMap<ResourceId, usize>,
+    /// Fraction of sampled elements where the property of a field is null or absent, by field id
+    pub null_rates: BTreeMap<ResourceId, f64>,
+    pub issues: Vec<DriftIssue>,
+}
+
+impl DriftReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Display for DriftReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (entity_id, size) in &self.sample_sizes {
+            writeln!(f, "{}: {} sampled", entity_id, size)?;
+        }
+        for (field_id, null_rate) in &self.null_rates {
+            writeln!(f, "{}: {:.1}% null", field_id, null_rate * 100.0)?;
+        }
+        if self.is_clean() {
+            return writeln!(f, "no schema drift found");
+        }
+        for issue in &self.issues {
+            writeln!(f, "! {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether values of the observed type can be stored in a field of the registered type without
+/// loss
+fn compatible(registered: &FeatureValueType, observed: &FeatureValueType) -> bool {
+    match (registered, observed) {
+        (FeatureValueType::Float, FeatureValueType::Int) => true,
+        (FeatureValueType::Array(r), FeatureValueType::Array(o)) => compatible(r, o),
+        (r, o) => r == o,
+    }
+}
+
+/// Compares the fields of each entity with up to `sample_size` vertices or edges of its tlabel.
+/// Fields computed by transformations are not stored in the graph and are skipped. A field
+/// whose property is null in more than `max_null_rate` of the samples is reported.
+pub async fn validate_schema(
+    database: &dyn GraphDatabase,
+    entities: &[Entity],
+    fields: &[Field],
+    sample_size: usize,
+    max_null_rate: f64,
+) -> Result<DriftReport, Box<dyn Error>> {
+    let mut report = DriftReport::default();
+    for entity in entities {
+        let entity_id = entity.resource_id();
+        let (tlabel, samples) = match &entity.entity_type {
+            EntityType::NodeEntity { tlabel } => (
+                tlabel,
+                database
+                    .sample_vertices(tlabel, sample_size)
+                    .await?
+                    .into_iter()
+                    .map(|n| n.properties)
+                    .collect::<Vec<_>>(),
+            ),
+            EntityType::EdgeEntity { tlabel } => (
+                tlabel,
+                database
+                    .sample_edges(tlabel, sample_size)
+                    .await?
+                    .into_iter()
+                    .map(|e| e.properties)
+                    .collect(),
+            ),
+        };
+        report.sample_sizes.insert(entity_id.clone(), samples.len());
+        if samples.is_empty() {
+            report.issues.push(DriftIssue::MissingTlabel {
+                entity_id,
+                tlabel: tlabel.clone(),
+            });
+            continue;
+        }
+        let observed = observed_types(samples.iter());
+        let entity_fields: Vec<&Field> = fields
+            .iter()
+            .filter(|f| f.entity_id == entity_id && f.transformation_id.is_none())
+            .collect();
+        for field in &entity_fields {
+            let field_id = field.resource_id();
+            let nulls = samples
+                .iter()
+                .filter(|p| matches!(p.get(&field.name), None | Some(FeatureValue::Null)))
+                .count();
+            let null_rate = nulls as f64 / samples.len() as f64;
+            report.null_rates.insert(field_id.clone(), null_rate);
+            match observed.get(&field.name) {
+                None => report.issues.push(DriftIssue::MissingProperty { field_id }),
+                Some(observed) => {
+                    // a property that is always null has no observed type to compare
+                    if nulls < samples.len() && !compatible(&field.value_type, observed) {
+                        report.issues.push(DriftIssue::TypeMismatch {
+                            field_id: field_id.clone(),
+                            registered: field.value_type.clone(),
+                            observed: observed.clone(),
+                        });
+                    }
+                    if null_rate > max_null_rate {
+                        report.issues.push(DriftIssue::HighNullRate {
+                            field_id,
+                            null_rate,
+                        });
+                    }
+                }
+            }
+        }
+        for (property, observed) in observed {
+            if !entity_fields.iter().any(|f| f.name == property) {
+                report.issues.push(DriftIssue::UnregisteredProperty {
+                    entity_id: entity_id.clone(),
+                    property,
+                    observed,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Validates all registered entities and their fields against the graph database
+pub async fn validate_registry(
+    database: &dyn GraphDatabase,
+    registry: &FeatureRegistry,
+    sample_size: usize,
+    max_null_rate: f64,
+) -> Result<DriftReport, Box<dyn Error>> {
+    let entities = registry.get_entities().await?;
+    let mut fields = Vec::new();
+    for entity in &entities {
+        fields.extend(registry.get_entity_fields(&entity.name).await?);
+    }
+    validate_schema(database, &entities, &fields, sample_size, max_null_rate).await
+}
+
+#[tokio::test]
+async fn detect_schema_drift() {
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30, "email": "a@b.c"}},
+                {"tlabel": "Person", "id": "p2", "properties": {"score": 1}},
+                {"tlabel": "Person", "id": "p3", "properties": {"age": 25, "score": 2}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let person = Entity::new_node_entity("person", None, "Person", "id");
+    let city = Entity::new_node_entity("city", None, "City", "id");
+    let mut fields = Field::new_fields(
+        vec![
+            ("id", FeatureValueType::String),
+            ("age", FeatureValueType::Float),
+            ("score", FeatureValueType::String),
+            ("height", FeatureValueType::Float),
+        ],
+        &person,
+        None,
+    );
+    fields.extend(Field::new_fields(
+        vec![("derived", FeatureValueType::Int)],
+        &person,
+        None,
+    ));
+    fields.last_mut().unwrap().transformation_id = Some("Transformation/t/".to_string());
+    let report = validate_schema(
+        &MemoryGraphDatabase::from_graph(graph),
+        &[person, city],
+        &fields,
+        100,
+        0.5,
+    )
+    .await
+    .unwrap();
+    assert_eq!(report.sample_sizes["Entity/person/"], 3);
+    assert!((report.null_rates["Field/person/age/"] - 1.0 / 3.0).abs() < 1e-9);
+    assert_eq!(
+        report.issues,
+        vec![
+            DriftIssue::TypeMismatch {
+                field_id: "Field/person/score/".to_string(),
+                registered: FeatureValueType::String,
+                observed: FeatureValueType::Int,
+            },
+            DriftIssue::MissingProperty {
+                field_id: "Field/person/height/".to_string(),
+            },
+            DriftIssue::UnregisteredProperty {
+                entity_id: "Entity/person/".to_string(),
+                property: "email".to_string(),
+                observed: FeatureValueType::String,
+            },
+            DriftIssue::MissingTlabel {
+                entity_id: "Entity/city/".to_string(),
+                tlabel: "City".to_string(),
+            },
+        ]
+    );
+}
@@ -2,6 +2,8 @@ mod data_source;
 mod feature;
 mod feature_registry;
 mod feature_store;
+mod graph_database;
+mod provider;
 mod storage;
 mod transformation;
 
@@ -9,5 +11,7 @@ pub use data_source::*;
 pub use feature::*;
 pub use feature_registry::*;
 pub use feature_store::*;
+pub use graph_database::*;
+pub use provider::*;
 pub use storage::*;
 pub use transformation::*;
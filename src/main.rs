@@ -3,7 +3,7 @@ mod commands;
 
 use clap::Parser;
 use cli::{Cli, Commands};
-use commands::{apply, clean, materialize};
+use commands::{apply, clean, explain, import_schema, materialize, plan, validate};
 
 #[tokio::main]
 async fn main() {
@@ -18,6 +18,11 @@ async fn main() {
                 println!("Apply: Error: {}", e);
             }
         },
+        Commands::Plan {} => {
+            if let Err(e) = plan().await {
+                println!("Plan: Error: {}", e);
+            }
+        }
         Commands::Materialize { time } => match materialize().await {
             Ok(_) => {
                 println!("Materialize: Success at {}", time);
@@ -29,7 +34,14 @@ async fn main() {
         Commands::Serve {} => {
             println!("Serve");
         }
-        Commands::Clean {} => match clean().await {
+        Commands::Clean {
+            dry_run,
+            yes,
+            graph,
+            online_store,
+            registry,
+            batch_size,
+        } => match clean(dry_run, yes, graph, online_store, registry, batch_size).await {
             Ok(_) => {
                 println!("Clean: Success");
             }
@@ -37,5 +49,35 @@ async fn main() {
                 println!("Clean: Error: {}", e);
             }
         },
+        Commands::ImportSchema {
+            output,
+            sample_size,
+        } => match import_schema(output, sample_size).await {
+            Ok(_) => {
+                println!("ImportSchema: Success");
+            }
+            Err(e) => {
+                println!("ImportSchema: Error: {}", e);
+            }
+        },
+        Commands::Validate {
+            sample_size,
+            max_null_rate,
+        } => match validate(sample_size, max_null_rate).await {
+            Ok(_) => {
+                println!("Validate: Success");
+            }
+            Err(e) => {
+                println!("Validate: Error: {}", e);
+            }
+        },
+        Commands::Explain {
+            transformation_id,
+            json,
+        } => {
+            if let Err(e) = explain(&transformation_id, json).await {
+                println!("Explain: Error: {}", e);
+            }
+        }
     }
 }
@@ -1,36 +1,231 @@
 use crate::*;
+use async_trait::async_trait;
+use log::info;
+use rusqlite::Connection;
+use std::collections::HashMap;
 use std::error::Error;
+use std::rc::Rc;
 
+mod clean;
+mod schema;
+pub use clean::*;
+pub use schema::*;
+
+#[async_trait(?Send)]
 pub trait InfraProvider {
-    fn update_infra(
+    /// Reconciles the infrastructure with the desired feature views. Artifacts of the views to
+    /// delete are removed unless they are still used by a view to keep.
+    async fn update_infra(
         &self,
+        registry: &FeatureRegistry,
         views_to_delete: Vec<FeatureView>,
         views_to_keep: Vec<FeatureView>,
-    ) -> Result<(), Box<dyn Error>> {
-        unimplemented!()
-    }
+    ) -> Result<(), Box<dyn Error>>;
 }
 
 pub struct GDBProvider {
     pub graph_data_handler: GraphDataHandler,
+    pub online_store: OnlineStore,
     // pub data_sources: Vec<DataSource>,
 }
 
 impl GDBProvider {
-    pub fn new(credentials: GraphDatabaseCredentials) -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        graph_data_handler: GraphDataHandler,
+        online_store: OnlineStore,
+    ) -> Result<Self, Box<dyn Error>> {
         Ok(GDBProvider {
-            graph_data_handler: GraphDataHandler { credentials },
+            graph_data_handler,
+            online_store,
         })
     }
 }
 
+#[async_trait(?Send)]
 impl InfraProvider for GDBProvider {
-    fn update_infra(
+    async fn update_infra(
         &self,
+        registry: &FeatureRegistry,
         views_to_delete: Vec<FeatureView>,
         views_to_keep: Vec<FeatureView>,
     ) -> Result<(), Box<dyn Error>> {
-        unimplemented!()
+        let conn = self.online_store.connect()?;
+
+        let plan = self.plan_schema(registry, &views_to_keep).await?;
+        self.graph_data_handler.database.apply_schema(&plan).await?;
+
+        let mut kept = Vec::new();
+        for view in &views_to_keep {
+            let columns = match view.is_online() {
+                true => Some(OnlineStore::columns(registry, view).await?),
+                false => None,
+            };
+            kept.push((view, columns));
+        }
+        self.online_store
+            .update_tables(&conn, &views_to_delete, &kept)
+    }
+}
+
+impl GDBProvider {
+    /// Plans the constraints and indexes for all registered entities and the entities served by
+    /// the given feature views, detecting drift against what exists in the graph database
+    pub async fn plan_schema(
+        &self,
+        registry: &FeatureRegistry,
+        views: &[FeatureView],
+    ) -> Result<SchemaPlan, Box<dyn Error>> {
+        let mut entities = registry.get_entities().await?;
+        for view in views {
+            entities.extend(registry.get_feature_view_entities(view).await?);
+        }
+        let desired: Vec<GraphSchemaItem> = entities
+            .iter()
+            .filter_map(GraphSchemaItem::for_entity)
+            .collect();
+        let existing = self.graph_data_handler.database.schema().await?;
+        Ok(SchemaPlan::diff(&desired, &existing))
+    }
+}
+
+/// The sqlite online store serving online feature views, one table per view
+pub struct OnlineStore {
+    pub path: String,
+}
+
+impl OnlineStore {
+    const VIEWS_TABLE: &'static str = "gfs_feature_views";
+
+    pub fn connect(&self) -> Result<Connection, Box<dyn Error>> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (id TEXT PRIMARY KEY, definition TEXT NOT NULL)",
+                Self::VIEWS_TABLE
+            ),
+            [],
+        )?;
+        Ok(conn)
+    }
+
+    pub fn table_name(view: &FeatureView) -> String {
+        let (name, variant) = match view {
+            FeatureView::TableFeatureView(v) => (&v.name, &v.variant),
+            FeatureView::TopologyFeatureView(v) => (&v.name, &v.variant),
+        };
+        match variant {
+            Some(variant) => format!("{}__{}", name, variant),
+            None => name.clone(),
+        }
+    }
+
+    /// Returns the feature views whose infrastructure has been created
+    pub fn applied_views(&self) -> Result<Vec<FeatureView>, Box<dyn Error>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare(&format!("SELECT definition FROM {}", Self::VIEWS_TABLE))?;
+        let definitions = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut res = Vec::new();
+        for definition in definitions {
+            res.push(serde_json::from_str(&definition)?);
+        }
+        Ok(res)
+    }
+
+    fn record_view(&self, conn: &Connection, view: &FeatureView) -> Result<(), Box<dyn Error>> {
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {} (id, definition) VALUES (?1, ?2)",
+                Self::VIEWS_TABLE
+            ),
+            [view.resource_id(), serde_json::to_string(view)?],
+        )?;
+        Ok(())
+    }
+
+    /// Creates the tables of the kept views served online, with the given column definitions,
+    /// and drops the tables of the deleted views and of the kept views no longer served online
+    fn update_tables(
+        &self,
+        conn: &Connection,
+        views_to_delete: &[FeatureView],
+        views_to_keep: &[(&FeatureView, Option<Vec<String>>)],
+    ) -> Result<(), Box<dyn Error>> {
+        for (view, columns) in views_to_keep {
+            match columns {
+                Some(columns) => self.create_table(conn, view, columns)?,
+                None => self.drop_table(conn, view)?,
+            }
+            self.record_view(conn, view)?;
+        }
+        for view in views_to_delete {
+            self.drop_table(conn, view)?;
+        }
+        Ok(())
+    }
+
+    /// The column definitions of the online table of a view
+    async fn columns(
+        registry: &FeatureRegistry,
+        view: &FeatureView,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(match view {
+            FeatureView::TableFeatureView(v) => {
+                let mut columns = vec!["entity_key TEXT PRIMARY KEY".to_string()];
+                for id in &v.field_ids {
+                    let field = registry.get_field(id).await?;
+                    columns.push(format!(
+                        "\"{}\" {}",
+                        field.name,
+                        sqlite_type(&field.value_type)
+                    ));
+                }
+                columns
+            }
+            FeatureView::TopologyFeatureView(_) => vec![
+                "src_key TEXT NOT NULL".to_string(),
+                "dst_key TEXT NOT NULL".to_string(),
+            ],
+        })
+    }
+
+    fn create_table(
+        &self,
+        conn: &Connection,
+        view: &FeatureView,
+        columns: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        let table = Self::table_name(view);
+        info!("Creating online table {}", table);
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS \"{}\" ({})",
+                table,
+                columns.join(", ")
+            ),
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn drop_table(&self, conn: &Connection, view: &FeatureView) -> Result<(), Box<dyn Error>> {
+        let table = Self::table_name(view);
+        info!("Dropping online table {}", table);
+        conn.execute(&format!("DROP TABLE IF EXISTS \"{}\"", table), [])?;
+        conn.execute(
+            &format!("DELETE FROM {} WHERE id = ?1", Self::VIEWS_TABLE),
+            [view.resource_id()],
+        )?;
+        Ok(())
+    }
+}
+
+fn sqlite_type(value_type: &FeatureValueType) -> &'static str {
+    match value_type {
+        FeatureValueType::Int | FeatureValueType::Boolean | FeatureValueType::Duration => "INTEGER",
+        FeatureValueType::Float => "REAL",
+        _ => "TEXT",
     }
 }
 
@@ -39,45 +234,174 @@ pub struct GraphDatabaseCredentials {
     pub user: String,
     pub pass: String,
 }
+
+/// Gives ingestion, transformation execution and materialization access to the graph database
 pub struct GraphDataHandler {
-    pub credentials: GraphDatabaseCredentials,
+    pub database: Rc<dyn GraphDatabase>,
+}
+
+impl GraphDataHandler {
+    pub fn new(database: Rc<dyn GraphDatabase>) -> Self {
+        GraphDataHandler { database }
+    }
+
+    /// Connects to neo4j through a pooled client
+    pub fn neo4j(
+        credentials: &GraphDatabaseCredentials,
+        config: GraphClientConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(GraphDataHandler::new(Rc::new(Neo4jGraphDatabase::new(
+            credentials,
+            config,
+        )?)))
+    }
 }
 
+#[async_trait(?Send)]
 pub trait DataSourceIngestion {
-    fn ingest_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>>;
-    fn load_offline_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>>;
-    fn subscribe_online_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>>;
+    async fn ingest_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>>;
+    async fn load_offline_data_source(
+        &self,
+        data_source: &DataSource,
+    ) -> Result<(), Box<dyn Error>>;
+    async fn subscribe_online_data_source(
+        &self,
+        data_source: &DataSource,
+    ) -> Result<(), Box<dyn Error>>;
 }
 
+#[async_trait(?Send)]
 impl DataSourceIngestion for GraphDataHandler {
-    fn ingest_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>> {
+    async fn ingest_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>> {
         match data_source.data_source_type {
             DataSourceType::OfflineDataSourceType(_) => {
-                self.load_offline_data_source(data_source)?;
+                self.load_offline_data_source(data_source).await?;
             }
             DataSourceType::OnlineDataSourceType(_) => {
-                self.subscribe_online_data_source(data_source)?;
+                self.subscribe_online_data_source(data_source).await?;
             }
         }
         Ok(())
     }
 
-    fn load_offline_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>> {
+    async fn load_offline_data_source(
+        &self,
+        data_source: &DataSource,
+    ) -> Result<(), Box<dyn Error>> {
         match data_source.data_source_type {
-            DataSourceType::OfflineDataSourceType(OfflineDataSourceType::CsvSource) => {
-                unimplemented!()
+            DataSourceType::OfflineDataSourceType(OfflineDataSourceType::CsvSource { .. }) => {
+                let records = data_source.load_graph_records()?;
+                info!(
+                    "Loaded {} vertices and {} edges from {}",
+                    records.vertices.len(),
+                    records.edges.len(),
+                    data_source.name
+                );
+                self.database.upsert_records(&records).await
             }
             DataSourceType::OfflineDataSourceType(OfflineDataSourceType::CypherSource) => {
-                unimplemented!()
+                let cypher = std::fs::read_to_string(&data_source.path)?;
+                self.database.run_query(&cypher, HashMap::new()).await?;
+                Ok(())
             }
             DataSourceType::OfflineDataSourceType(OfflineDataSourceType::ParquetSource) => {
-                unimplemented!()
+                Err(format!(
+                    "cannot load {}: parquet data sources are not supported yet",
+                    data_source.name
+                )
+                .into())
             }
-            _ => todo!(),
+            DataSourceType::OnlineDataSourceType(_) => Err(format!(
+                "cannot load {}: it is an online data source",
+                data_source.name
+            )
+            .into()),
         }
     }
 
-    fn subscribe_online_data_source(&self, data_source: &DataSource) -> Result<(), Box<dyn Error>> {
-        unimplemented!()
+    async fn subscribe_online_data_source(
+        &self,
+        data_source: &DataSource,
+    ) -> Result<(), Box<dyn Error>> {
+        Err(format!(
+            "cannot subscribe to {}: online data sources are not supported yet",
+            data_source.name
+        )
+        .into())
     }
 }
+
+#[test]
+fn update_online_tables() {
+    let view = |name: &str, online: bool| {
+        FeatureView::TableFeatureView(TableFeatureView {
+            name: name.to_string(),
+            variant: None,
+            entity_id: "Entity/movie/".to_string(),
+            field_ids: vec![],
+            online,
+            description: None,
+            created_at: None,
+            updated_at: None,
+            tags: HashMap::new(),
+            owner: None,
+        })
+    };
+    let path = std::env::temp_dir().join(format!("gfs_online_{}.db", std::process::id()));
+    let store = OnlineStore {
+        path: path.to_str().unwrap().to_string(),
+    };
+    let conn = store.connect().unwrap();
+    let tables = || {
+        let mut stmt = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap();
+        let res = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        res
+    };
+    let columns = Some(vec!["entity_key TEXT PRIMARY KEY".to_string()]);
+    let (kept, offline, deleted) = (
+        view("kept", true),
+        view("offline", true),
+        view("deleted", true),
+    );
+    store
+        .update_tables(
+            &conn,
+            &[],
+            &[
+                (&kept, columns.clone()),
+                (&offline, columns.clone()),
+                (&deleted, columns.clone()),
+            ],
+        )
+        .unwrap();
+    assert_eq!(
+        tables(),
+        vec!["deleted", "gfs_feature_views", "kept", "offline"]
+    );
+
+    // the offline view stays applied without its table
+    let offline = view("offline", false);
+    store
+        .update_tables(
+            &conn,
+            &[deleted],
+            &[(&kept, columns.clone()), (&offline, None)],
+        )
+        .unwrap();
+    assert_eq!(tables(), vec!["gfs_feature_views", "kept"]);
+    let mut applied: Vec<String> = store
+        .applied_views()
+        .unwrap()
+        .iter()
+        .map(|v| v.resource_id())
+        .collect();
+    applied.sort();
+    assert_eq!(applied, vec![kept.resource_id(), offline.resource_id()]);
+    std::fs::remove_file(&path).unwrap();
+}
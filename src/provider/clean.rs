@@ -0,0 +1,343 @@
+use super::*;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Display;
+
+/// What `clean` removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanScope {
+    /// Vertices and edges ingested for the project
+    GraphData,
+    /// Online tables of the applied feature views
+    OnlineStore,
+    /// The resources the project registered
+    Registry,
+}
+
+impl CleanScope {
+    pub const ALL: [CleanScope; 3] = [
+        CleanScope::GraphData,
+        CleanScope::OnlineStore,
+        CleanScope::Registry,
+    ];
+
+    /// Whether the removed data cannot be rebuilt from the other scopes. Online tables can be
+    /// materialized again.
+    pub fn is_destructive(&self) -> bool {
+        !matches!(self, CleanScope::OnlineStore)
+    }
+}
+
+/// The resources registered by all the projects sharing a registry
+#[derive(Debug, Default)]
+pub struct RegisteredResources {
+    pub data_sources: Vec<DataSource>,
+    pub entities: Vec<Entity>,
+    pub fields: Vec<Field>,
+    pub transformations: Vec<Transformation>,
+    pub topologies: Vec<Topology>,
+    pub views: Vec<FeatureView>,
+    pub graphs: Vec<Graph>,
+}
+
+/// What a project created in the registry and in the graph database
+#[derive(Debug, Default, PartialEq)]
+pub struct ProjectResources {
+    pub resource_ids: Vec<ResourceId>,
+    /// The tlabels only the project ingests into or populates
+    pub vertex_tlabels: Vec<String>,
+    pub edge_tlabels: Vec<String>,
+}
+
+async fn get_all<T: DeserializeOwned>(
+    registry: &FeatureRegistry,
+    kind: &str,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let mut res = Vec::new();
+    for jstr in registry.storage.get_all(&format!("{}/", kind)).await? {
+        res.push(serde_json::from_str(&jstr)?);
+    }
+    Ok(res)
+}
+
+impl RegisteredResources {
+    pub async fn load(registry: &FeatureRegistry) -> Result<Self, Box<dyn Error>> {
+        Ok(RegisteredResources {
+            data_sources: registry.get_data_sources().await?,
+            entities: registry.get_entities().await?,
+            fields: get_all(registry, "Field").await?,
+            transformations: get_all(registry, "Transformation").await?,
+            topologies: get_all(registry, "Topology").await?,
+            views: registry.get_feature_views().await?,
+            graphs: get_all(registry, "Graph").await?,
+        })
+    }
+
+    /// Finds the resources of the project with the given data sources, by name: the sources,
+    /// the entities they alone populate, the fields ingested from them or of those entities, and
+    /// the transformations, topologies, feature views and graphs built only on those. The
+    /// tlabels the other data sources and entities write to are not the project's.
+    pub fn project(&self, data_source_names: &[String]) -> ProjectResources {
+        let mut ids: HashSet<ResourceId> = HashSet::new();
+        let mine = |ids: &HashSet<ResourceId>, deps: &[ResourceId]| {
+            !deps.is_empty() && deps.iter().all(|d| ids.contains(d))
+        };
+        let (sources, other_sources): (Vec<_>, Vec<_>) = self
+            .data_sources
+            .iter()
+            .partition(|s| data_source_names.contains(&s.name));
+        ids.extend(sources.iter().map(|s| s.resource_id()));
+        let (entities, other_entities): (Vec<_>, Vec<_>) = self
+            .entities
+            .iter()
+            .filter(|e| !e.data_source_ids.is_empty())
+            .partition(|e| mine(&ids, &e.data_source_ids));
+        ids.extend(entities.iter().map(|e| e.resource_id()));
+        for field in &self.fields {
+            let mine = match &field.data_source_id {
+                Some(id) => ids.contains(id),
+                None => ids.contains(&field.entity_id),
+            };
+            if mine {
+                ids.insert(field.resource_id());
+            }
+        }
+        for transformation in &self.transformations {
+            if mine(&ids, &transformation.source_field_ids) {
+                ids.insert(transformation.resource_id());
+            }
+        }
+        for topology in &self.topologies {
+            if mine(&ids, &topology.edge_entity_ids) {
+                ids.insert(topology.resource_id());
+            }
+        }
+        for view in &self.views {
+            let deps = match view {
+                FeatureView::TableFeatureView(view) => vec![view.entity_id.clone()],
+                FeatureView::TopologyFeatureView(view) => view.topology_ids.clone(),
+            };
+            if mine(&ids, &deps) {
+                ids.insert(view.resource_id());
+            }
+        }
+        for graph in &self.graphs {
+            if mine(&ids, &graph.entity_ids) {
+                ids.insert(graph.resource_id());
+            }
+        }
+
+        let tlabels = |sources: &[&DataSource], entities: &[&Entity]| {
+            let (mut vertices, mut edges) = (BTreeSet::new(), BTreeSet::new());
+            for source in sources {
+                let (v, e) = source.ingested_tlabels();
+                vertices.extend(v);
+                edges.extend(e);
+            }
+            for entity in entities {
+                match &entity.entity_type {
+                    EntityType::NodeEntity { tlabel } => vertices.insert(tlabel.clone()),
+                    EntityType::EdgeEntity { tlabel } => edges.insert(tlabel.clone()),
+                };
+            }
+            (vertices, edges)
+        };
+        let (vertices, edges) = tlabels(&sources, &entities);
+        let (other_vertices, other_edges) = tlabels(&other_sources, &other_entities);
+        let mut resource_ids: Vec<ResourceId> = ids.into_iter().collect();
+        resource_ids.sort();
+        ProjectResources {
+            resource_ids,
+            vertex_tlabels: vertices.difference(&other_vertices).cloned().collect(),
+            edge_tlabels: edges.difference(&other_edges).cloned().collect(),
+        }
+    }
+}
+
+/// The vertices, edges, online tables and registry resources a project created
+#[derive(Debug, Default)]
+pub struct CleanPlan {
+    /// Vertex tlabels with their number of vertices
+    pub vertex_types: Vec<(String, usize)>,
+    /// Edge tlabels with their number of edges
+    pub edge_types: Vec<(String, usize)>,
+    pub views: Vec<FeatureView>,
+    pub resource_ids: Vec<ResourceId>,
+}
+
+impl CleanPlan {
+    pub fn is_empty(&self) -> bool {
+        self.vertex_types.is_empty()
+            && self.edge_types.is_empty()
+            && self.views.is_empty()
+            && self.resource_ids.is_empty()
+    }
+
+    pub fn is_destructive(&self) -> bool {
+        !self.vertex_types.is_empty()
+            || !self.edge_types.is_empty()
+            || !self.resource_ids.is_empty()
+    }
+}
+
+impl Display for CleanPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "nothing to clean");
+        }
+        for (tlabel, count) in &self.edge_types {
+            writeln!(f, "- edges {} ({})", tlabel, count)?;
+        }
+        for (tlabel, count) in &self.vertex_types {
+            writeln!(f, "- vertices {} ({})", tlabel, count)?;
+        }
+        for view in &self.views {
+            writeln!(f, "- online table {}", OnlineStore::table_name(view))?;
+        }
+        for id in &self.resource_ids {
+            writeln!(f, "- resource {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+impl GDBProvider {
+    /// Lists what the project with the given data sources, by name, created in the given scopes:
+    /// the vertices and edges of the tlabels only its data sources are ingested into or only its
+    /// entities are populated from, the online tables of the applied feature views, and its
+    /// registered resources, as found by `RegisteredResources::project`. Other data in the graph
+    /// database and the resources of other projects are left alone.
+    pub async fn plan_clean(
+        &self,
+        registry: &FeatureRegistry,
+        data_source_names: &[String],
+        scopes: &[CleanScope],
+    ) -> Result<CleanPlan, Box<dyn Error>> {
+        let mut plan = CleanPlan::default();
+        let project = RegisteredResources::load(registry)
+            .await?
+            .project(data_source_names);
+        if scopes.contains(&CleanScope::GraphData) {
+            let database = &self.graph_data_handler.database;
+            for tlabel in project.edge_tlabels {
+                let count = database.count_edges(&tlabel).await?;
+                if count > 0 {
+                    plan.edge_types.push((tlabel, count));
+                }
+            }
+            for tlabel in project.vertex_tlabels {
+                let count = database.count_vertices(&tlabel).await?;
+                if count > 0 {
+                    plan.vertex_types.push((tlabel, count));
+                }
+            }
+        }
+        if scopes.contains(&CleanScope::OnlineStore) {
+            plan.views = self.online_store.applied_views()?;
+        }
+        if scopes.contains(&CleanScope::Registry) {
+            plan.resource_ids = project.resource_ids;
+        }
+        Ok(plan)
+    }
+
+    /// Removes what the plan lists, deleting graph elements in transactions of at most
+    /// `batch_size` elements, which must be positive. Edges go first so that vertex deletions
+    /// stay small.
+    pub async fn clean(
+        &self,
+        registry: &FeatureRegistry,
+        plan: &CleanPlan,
+        batch_size: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        if batch_size == 0 {
+            return Err("the batch size must be at least 1".into());
+        }
+        let database = &self.graph_data_handler.database;
+        for (tlabel, _) in &plan.edge_types {
+            let deleted = database.delete_edges(tlabel, batch_size).await?;
+            info!("Deleted {} {} edges", deleted, tlabel);
+        }
+        for (tlabel, _) in &plan.vertex_types {
+            let deleted = database.delete_vertices(tlabel, batch_size).await?;
+            info!("Deleted {} {} vertices", deleted, tlabel);
+        }
+        if !plan.views.is_empty() {
+            let conn = self.online_store.connect()?;
+            for view in &plan.views {
+                self.online_store.drop_table(&conn, view)?;
+            }
+        }
+        for id in &plan.resource_ids {
+            registry.delete_resource(id).await?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn find_project_resources() {
+    let movies = DataSource::new_csv_source("movies", None, "movies.csv", None);
+    let ratings = DataSource::new_csv_source("ratings", None, "ratings.csv", None);
+    let populated_by = |mut entity: Entity, source: &DataSource| {
+        entity.data_source_ids = vec![source.resource_id()];
+        entity
+    };
+    let movie = populated_by(
+        Entity::new_node_entity("movie", None, "Movie", "title"),
+        &movies,
+    );
+    let actor = populated_by(
+        Entity::new_node_entity("actor", None, "Person", "name"),
+        &movies,
+    );
+    // another project populates the same tlabel
+    let user = populated_by(
+        Entity::new_node_entity("user", None, "Person", "name"),
+        &ratings,
+    );
+    let fields = Field::new_fields(vec![("year", FeatureValueType::Int)], &movie, None);
+    let other_fields = Field::new_fields(vec![("age", FeatureValueType::Int)], &user, None);
+    let view = TableFeatureView {
+        name: "movie_view".to_string(),
+        variant: None,
+        entity_id: movie.resource_id(),
+        field_ids: vec![fields[0].resource_id()],
+        online: true,
+        description: None,
+        created_at: None,
+        updated_at: None,
+        tags: HashMap::new(),
+        owner: None,
+    };
+    let cast = Graph::new("cast", None, vec![&movie, &actor]);
+    let audience = Graph::new("audience", None, vec![&movie, &user]);
+    let registered = RegisteredResources {
+        data_sources: vec![movies.clone(), ratings],
+        entities: vec![movie.clone(), actor.clone(), user],
+        fields: fields.iter().chain(&other_fields).cloned().collect(),
+        views: vec![FeatureView::TableFeatureView(view.clone())],
+        graphs: vec![cast.clone(), audience],
+        ..Default::default()
+    };
+
+    let project = registered.project(&["movies".to_string()]);
+    let mut resource_ids = vec![
+        movies.resource_id(),
+        movie.resource_id(),
+        actor.resource_id(),
+        fields[0].resource_id(),
+        view.resource_id(),
+        cast.resource_id(),
+    ];
+    resource_ids.sort();
+    assert_eq!(
+        project,
+        ProjectResources {
+            resource_ids,
+            vertex_tlabels: vec!["Movie".to_string(), "movies".to_string()],
+            edge_tlabels: vec![],
+        }
+    );
+    assert_eq!(registered.project(&[]), ProjectResources::default());
+}
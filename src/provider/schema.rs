@@ -0,0 +1,244 @@
+use crate::transformation::quote_identifier;
+use crate::{Entity, EntityType};
+use std::fmt::Display;
+
+/// Prefix of the names of constraints and indexes managed by the feature store
+pub const MANAGED_SCHEMA_PREFIX: &str = "gfs_";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GraphSchemaItemKind {
+    UniqueConstraint,
+    Index,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GraphElementKind {
+    Node,
+    Relationship,
+}
+
+/// A constraint or index in the graph database
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GraphSchemaItem {
+    pub name: String,
+    pub kind: GraphSchemaItemKind,
+    pub element: GraphElementKind,
+    pub tlabel: String,
+    pub properties: Vec<String>,
+}
+
+impl GraphSchemaItem {
+    pub fn new(
+        kind: GraphSchemaItemKind,
+        element: GraphElementKind,
+        tlabel: &str,
+        properties: Vec<String>,
+    ) -> Self {
+        let name = format!(
+            "{}{}_{}_{}_{}",
+            MANAGED_SCHEMA_PREFIX,
+            match element {
+                GraphElementKind::Node => "node",
+                GraphElementKind::Relationship => "rel",
+            },
+            tlabel,
+            properties.join("_"),
+            match kind {
+                GraphSchemaItemKind::UniqueConstraint => "unique",
+                GraphSchemaItemKind::Index => "index",
+            }
+        );
+        GraphSchemaItem {
+            name,
+            kind,
+            element,
+            tlabel: tlabel.to_string(),
+            properties,
+        }
+    }
+
+    /// Returns the constraint or index needed to look up an entity by its primary key. A node
+    /// primary key of comma-separated properties gives a composite uniqueness constraint. An edge
+    /// primary key of edge properties gives a (composite) relationship index, while edges keyed by
+    /// their endpoints (`{src_entity_id}|{dst_entity_id}`) rely on the endpoint constraints.
+    pub fn for_entity(entity: &Entity) -> Option<Self> {
+        let properties: Vec<String> = entity
+            .primary_key
+            .split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if properties.is_empty() {
+            return None;
+        }
+        match &entity.entity_type {
+            EntityType::NodeEntity { tlabel } => Some(GraphSchemaItem::new(
+                GraphSchemaItemKind::UniqueConstraint,
+                GraphElementKind::Node,
+                tlabel,
+                properties,
+            )),
+            EntityType::EdgeEntity { tlabel } => match entity.edge_endpoint_ids() {
+                Some(_) => None,
+                None => Some(GraphSchemaItem::new(
+                    GraphSchemaItemKind::Index,
+                    GraphElementKind::Relationship,
+                    tlabel,
+                    properties,
+                )),
+            },
+        }
+    }
+
+    pub fn is_managed(&self) -> bool {
+        self.name.starts_with(MANAGED_SCHEMA_PREFIX)
+    }
+
+    /// Whether two items constrain or index the same properties, regardless of their names
+    pub fn same_definition(&self, other: &GraphSchemaItem) -> bool {
+        self.kind == other.kind
+            && self.element == other.element
+            && self.tlabel == other.tlabel
+            && self.properties == other.properties
+    }
+
+    pub fn create_query(&self) -> String {
+        let (pattern, var) = match self.element {
+            GraphElementKind::Node => (format!("(n:{})", quote_identifier(&self.tlabel)), "n"),
+            GraphElementKind::Relationship => {
+                (format!("()-[r:{}]-()", quote_identifier(&self.tlabel)), "r")
+            }
+        };
+        let properties = self
+            .properties
+            .iter()
+            .map(|p| format!("{}.{}", var, quote_identifier(p)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.kind {
+            GraphSchemaItemKind::UniqueConstraint => format!(
+                "CREATE CONSTRAINT {} IF NOT EXISTS FOR {} REQUIRE ({}) IS UNIQUE",
+                quote_identifier(&self.name),
+                pattern,
+                properties
+            ),
+            GraphSchemaItemKind::Index => format!(
+                "CREATE INDEX {} IF NOT EXISTS FOR {} ON ({})",
+                quote_identifier(&self.name),
+                pattern,
+                properties
+            ),
+        }
+    }
+
+    pub fn drop_query(&self) -> String {
+        match self.kind {
+            GraphSchemaItemKind::UniqueConstraint => {
+                format!("DROP CONSTRAINT {} IF EXISTS", quote_identifier(&self.name))
+            }
+            GraphSchemaItemKind::Index => {
+                format!("DROP INDEX {} IF EXISTS", quote_identifier(&self.name))
+            }
+        }
+    }
+}
+
+/// The changes needed to bring the constraints and indexes in the graph database in line with
+/// the registered entities
+#[derive(Debug, Default)]
+pub struct SchemaPlan {
+    pub to_create: Vec<GraphSchemaItem>,
+    pub to_drop: Vec<GraphSchemaItem>,
+}
+
+impl SchemaPlan {
+    /// Desired items already covered by an existing item of the same definition are skipped, even
+    /// if the existing one was created outside the feature store. Only managed items are dropped,
+    /// either because no entity needs them anymore or because their definition drifted.
+    pub fn diff(desired: &[GraphSchemaItem], existing: &[GraphSchemaItem]) -> Self {
+        let mut plan = SchemaPlan::default();
+        for item in existing.iter().filter(|e| e.is_managed()) {
+            if !desired.iter().any(|d| d == item) {
+                plan.to_drop.push(item.clone());
+            }
+        }
+        for item in desired {
+            let satisfied = existing
+                .iter()
+                .filter(|e| !plan.to_drop.contains(e))
+                .any(|e| e.same_definition(item));
+            if !satisfied && !plan.to_create.contains(item) {
+                plan.to_create.push(item.clone());
+            }
+        }
+        plan
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_create.is_empty() && self.to_drop.is_empty()
+    }
+}
+
+impl Display for SchemaPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "graph schema is up to date");
+        }
+        for item in &self.to_drop {
+            writeln!(f, "- {}", item.drop_query())?;
+        }
+        for item in &self.to_create {
+            writeln!(f, "+ {}", item.create_query())?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn plan_schema_drift() {
+    let reviewer = Entity::new_node_entity("reviewer", None, "Reviewer", "reviewerId");
+    let product = Entity::new_node_entity("product", None, "Product", "asin");
+    let rates = Entity::new_edge_entity("rates", None, "rates", &reviewer, &product);
+    let desired: Vec<GraphSchemaItem> = [&reviewer, &product, &rates]
+        .iter()
+        .filter_map(|e| GraphSchemaItem::for_entity(e))
+        .collect();
+    assert_eq!(desired.len(), 2);
+
+    let existing = vec![
+        // created by hand with the same definition
+        GraphSchemaItem {
+            name: "reviewer_id".to_string(),
+            ..desired[0].clone()
+        },
+        // stale managed constraint of a removed entity
+        GraphSchemaItem::new(
+            GraphSchemaItemKind::UniqueConstraint,
+            GraphElementKind::Node,
+            "Brand",
+            vec!["id".to_string()],
+        ),
+    ];
+    let plan = SchemaPlan::diff(&desired, &existing);
+    assert_eq!(plan.to_create, vec![desired[1].clone()]);
+    assert_eq!(plan.to_drop, vec![existing[1].clone()]);
+}
+
+#[test]
+fn quote_schema_names() {
+    let item = GraphSchemaItem::new(
+        GraphSchemaItemKind::Index,
+        GraphElementKind::Relationship,
+        "rates`) DETACH DELETE (n",
+        vec!["at`s".to_string()],
+    );
+    assert_eq!(
+        item.create_query(),
+        "CREATE INDEX `gfs_rel_rates``) DETACH DELETE (n_at``s_index` IF NOT EXISTS \
+         FOR ()-[r:`rates``) DETACH DELETE (n`]-() ON (r.`at``s`)"
+    );
+    assert_eq!(
+        item.drop_query(),
+        "DROP INDEX `gfs_rel_rates``) DETACH DELETE (n_at``s_index` IF EXISTS"
+    );
+}
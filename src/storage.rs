@@ -28,4 +28,14 @@ impl EtcdStorage {
         let resp = self.client.get_by_prefix(key).await?;
         Ok(resp.kvs.iter().map(|e| e.value_str().to_string()).collect())
     }
+
+    pub async fn keys(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let resp = self.client.get_by_prefix(prefix).await?;
+        Ok(resp.kvs.iter().map(|e| e.key_str().to_string()).collect())
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        let _resp = self.client.delete(key).await?;
+        Ok(())
+    }
 }
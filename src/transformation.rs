@@ -4,26 +4,40 @@ mod built_in_fns;
 mod cypher_result;
 #[allow(dead_code, unused)]
 mod dataframe;
+mod executor;
+mod explain;
 #[allow(dead_code, unused)]
 mod graph;
+mod local;
+mod optimizer;
 #[allow(dead_code, unused)]
 mod transformation_context;
 
-use std::{cell::RefCell, error::Error, rc::Rc};
+use std::{any::Any, cell::RefCell, collections::HashMap, error::Error, rc::Rc};
 
-pub use built_in_fns::{Aggregator, RandomWalkPath};
-pub use cypher_result::{CypherResultDataFrame, CypherResultGraph, CypherTransformation};
+pub(crate) use built_in_fns::quote_identifier;
+pub use built_in_fns::{
+    AggregationHop, Aggregator, AggregatorFunc, Expression, FunctionRegistry, RandomWalkPath,
+    SamplingHop, SamplingMethod, SamplingSpec, ScalarFunction,
+};
+pub use cypher_result::{
+    infer_cypher_schema, CypherResultDataFrame, CypherResultGraph, CypherTransformation,
+};
 pub use dataframe::{Column, DataFrame};
-pub use graph::{GraphBase, GraphComputationOps, SingleGraph};
+pub use executor::{TransformationExecutor, TransformationOutput};
+pub use explain::{ColumnExplanation, DataExplanation, Execution, Explanation, OutputSchema};
+pub use graph::{GraphBase, GraphComputationOps, Selector, SingleGraph};
+pub use local::{CypherQuery, LocalColumn, LocalData, LocalExecutor, LocalFrame};
+pub use optimizer::{Optimizer, OptimizerRule, Rewrite};
 pub use transformation_context::TransformationContext;
 
 pub use u32 as DataIdT;
 
-pub(super) use graph::{EdgeSelectGraph, Selector, VertexSelectGraph};
+pub(super) use graph::{EdgeSelectGraph, VertexSelectGraph};
 
 use transformation_context::DataTransformationContext;
 
-use crate::{FeatureStore, ResourceOp};
+use crate::{FeatureStore, GraphRows, ResourceOp};
 
 pub const TRANSFORMATION_NAME_PREFIX: &str = "TRANSFORMATION_";
 
@@ -33,19 +47,86 @@ pub const TRANSFORMATION_NAME_PREFIX: &str = "TRANSFORMATION_";
 pub trait TransformationData {
     // context getter to hide direct member access to enable impl trait function on multiple structs
     fn get_context(&self) -> &DataTransformationContext;
+
+    /// The data as `Any`, so that the optimizer can match the type of data
+    fn as_any(&self) -> &dyn Any;
+
+    /// The operation that creates the data, written like the call that builds it, such as
+    /// `vertices_by_type("Person")`
+    fn operation(&self) -> String {
+        format!("data {}", self.get_context().id)
+    }
+
+    /// The ids of the data this data is derived from
+    fn inputs(&self) -> Vec<DataIdT> {
+        Vec::new()
+    }
+
+    /// Returns a copy of the data with the same id, reading from the given replacements of its
+    /// inputs. Data that cannot be rewritten return None.
+    fn with_inputs(
+        &self,
+        _inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        None
+    }
+
+    /// Picks the part of the result that makes up an exported resource, such as the column of an
+    /// exported field
+    fn exported(
+        &self,
+        _resource_id: &str,
+        result: &LocalData,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        Ok(result.clone())
+    }
+
+    /// Computes the data on the local engine, from the results of the data it is derived from
+    fn execute_local(&self, _executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        Err(format!(
+            "data {} cannot run on the local engine",
+            self.get_context().id
+        )
+        .into())
+    }
+
+    /// Compiles the data into a Cypher query returning its result, given the results of its
+    /// inputs. Data that cannot be pushed down to the graph database fail with the reason.
+    fn compile_cypher(&self, _executor: &LocalExecutor) -> Result<CypherQuery, Box<dyn Error>> {
+        Err(format!("data {} has no Cypher translation", self.get_context().id).into())
+    }
+
+    /// Builds the result of the data from the rows returned by its compiled query
+    fn decode_cypher_rows(
+        &self,
+        _rows: GraphRows,
+        _executor: &LocalExecutor,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        Err(format!("data {} has no Cypher translation", self.get_context().id).into())
+    }
 }
 
 pub trait InnerTransformationData {
     fn get_data_id(&self) -> DataIdT;
 
+    /// The ids of the data derived from this data in its TransformationContext
+    fn outputs(&self) -> Vec<DataIdT>;
+
     // here we can add more functions for all structs that impl TransformationData
-    // TODO(tatiana): handle dependency among data
 }
 
 impl<T: TransformationData> InnerTransformationData for T {
     fn get_data_id(&self) -> DataIdT {
         self.get_context().id
     }
+
+    fn outputs(&self) -> Vec<DataIdT> {
+        match self.get_context().transformation_context.upgrade() {
+            Some(context) => context.as_ref().borrow().outputs(self.get_data_id()),
+            // a deserialized data is not attached to its context
+            None => Vec::new(),
+        }
+    }
 }
 
 impl std::fmt::Debug for dyn InnerTransformationData {
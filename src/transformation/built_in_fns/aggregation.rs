@@ -1,5 +1,311 @@
-struct AggregatorFunc {}
+use crate::{Direction, FeatureValue, FeatureValueType};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 
+/// The function reducing the values of the neighbors of a vertex to one value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregatorFunc {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    Count,
+    /// The sample standard deviation
+    Std,
+    /// The value of the neighbor reached through the edge with the earliest timestamp
+    First {
+        timestamp: String,
+    },
+    /// The value of the neighbor reached through the edge with the latest timestamp
+    Last {
+        timestamp: String,
+    },
+    Collect,
+    /// The number of values in each of `bins` equal-width bins between `min` and `max`
+    Histogram {
+        bins: usize,
+        min: f64,
+        max: f64,
+    },
+}
+
+/// Aggregates a feature of the neighbors of each vertex. Sum, mean, min, max and std apply to
+/// numbers and element-wise to arrays of numbers. Null values are skipped, and aggregates of no
+/// values are Null, except for count, collect and histogram.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Aggregator {
-    top: AggregatorFunc,
+    pub func: AggregatorFunc,
+    /// The vertex feature to aggregate, or None to count the neighbors
+    pub column: Option<String>,
+    /// The edge feature weighting each neighbor, for sum, mean and histogram
+    pub weight: Option<String>,
+    /// Aggregates the neighbors at the source of the incoming edges (In), at the target of the
+    /// outgoing edges (Out), or both
+    pub direction: Direction,
+}
+
+/// One hop of a neighbor aggregation: the aggregator applied to the neighbors reached through
+/// edges of the given type, or of all types if None. The column of the aggregator is only read on
+/// the first hop, later hops aggregate the values of the previous one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AggregationHop {
+    pub edge_type: Option<String>,
+    pub aggregator: Aggregator,
+}
+
+/// The value of a neighbor, with the weight and the timestamp of the edge it is reached through.
+/// The weight is 1 when the aggregator is not weighted.
+pub(crate) struct Message {
+    pub(crate) value: FeatureValue,
+    pub(crate) weight: FeatureValue,
+    pub(crate) timestamp: FeatureValue,
+}
+
+impl Aggregator {
+    /// Aggregates the given feature of the neighbors along incoming edges
+    pub fn new(func: AggregatorFunc, column: &str) -> Self {
+        Aggregator {
+            func,
+            column: Some(column.to_string()),
+            weight: None,
+            direction: Direction::In,
+        }
+    }
+
+    /// Counts the neighbors along incoming edges
+    pub fn count() -> Self {
+        Aggregator {
+            func: AggregatorFunc::Count,
+            column: None,
+            weight: None,
+            direction: Direction::In,
+        }
+    }
+
+    /// Weights each neighbor by the given feature of the edge it is reached through
+    pub fn weighted(self, weight: &str) -> Self {
+        Aggregator {
+            weight: Some(weight.to_string()),
+            ..self
+        }
+    }
+
+    /// Aggregates the neighbors in the given direction
+    pub fn along(self, direction: Direction) -> Self {
+        Aggregator { direction, ..self }
+    }
+
+    /// The edge feature ordering the neighbors for first and last
+    pub(crate) fn timestamp(&self) -> Option<&str> {
+        match &self.func {
+            AggregatorFunc::First { timestamp } | AggregatorFunc::Last { timestamp } => {
+                Some(timestamp)
+            }
+            _ => None,
+        }
+    }
+
+    /// The type of the aggregate of values of the given type. Fails for values the function does
+    /// not apply to, and for weights on functions other than sum, mean and histogram.
+    pub fn output_type(
+        &self,
+        input: &FeatureValueType,
+    ) -> Result<FeatureValueType, Box<dyn Error>> {
+        use FeatureValueType::*;
+        let weighted = self.weight.is_some();
+        if weighted
+            && !matches!(
+                self.func,
+                AggregatorFunc::Sum | AggregatorFunc::Mean | AggregatorFunc::Histogram { .. }
+            )
+        {
+            return Err(format!("{:?} cannot be weighted", self.func).into());
+        }
+        let numeric = |t: &FeatureValueType| matches!(t, Int | Float);
+        let element = match input {
+            Array(t) if numeric(t) => Some(&**t),
+            _ => None,
+        };
+        let res = match &self.func {
+            AggregatorFunc::Count => Some(Int),
+            AggregatorFunc::Collect => Some(Array(Box::new(input.clone()))),
+            AggregatorFunc::First { .. } | AggregatorFunc::Last { .. } => Some(input.clone()),
+            AggregatorFunc::Histogram { bins, min, max } => {
+                if *bins == 0 || min >= max {
+                    return Err("a histogram needs bins over a non-empty range".into());
+                }
+                numeric(input).then(|| Array(Box::new(if weighted { Float } else { Int })))
+            }
+            AggregatorFunc::Sum if *input == Int && !weighted => Some(Int),
+            AggregatorFunc::Sum | AggregatorFunc::Mean | AggregatorFunc::Std => {
+                match (numeric(input), element) {
+                    (true, _) => Some(Float),
+                    (_, Some(_)) => Some(Array(Box::new(Float))),
+                    _ => None,
+                }
+            }
+            AggregatorFunc::Min | AggregatorFunc::Max => {
+                (numeric(input) || element.is_some()).then(|| input.clone())
+            }
+        };
+        res.ok_or_else(|| format!("cannot aggregate {:?} by {:?}", input, self.func).into())
+    }
+
+    /// Aggregates the values of the neighbors into a value of `output_type`. Neighbors with a
+    /// null value, weight or timestamp are skipped.
+    pub(crate) fn aggregate(
+        &self,
+        messages: &[Message],
+        output_type: &FeatureValueType,
+    ) -> Result<FeatureValue, Box<dyn Error>> {
+        let messages: Vec<&Message> = messages
+            .iter()
+            .filter(|m| !m.value.is_null() && !m.weight.is_null())
+            .collect();
+        let weight = |m: &Message| match &m.weight {
+            FeatureValue::Int(w) => Ok(*w as f64),
+            FeatureValue::Float(w) => Ok(*w),
+            w => Err(format!("expected a numeric weight but found {:?}", w)),
+        };
+        match &self.func {
+            AggregatorFunc::Count => return Ok(FeatureValue::Int(messages.len() as i64)),
+            AggregatorFunc::Collect => {
+                return Ok(FeatureValue::Array(
+                    messages.iter().map(|m| m.value.clone()).collect(),
+                ))
+            }
+            AggregatorFunc::First { .. } | AggregatorFunc::Last { .. } => {
+                let wanted = match self.func {
+                    AggregatorFunc::First { .. } => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let mut best: Option<&Message> = None;
+                for m in messages.iter().filter(|m| !m.timestamp.is_null()) {
+                    match best {
+                        Some(b) if m.timestamp.compare(&b.timestamp) != Some(wanted) => {}
+                        _ => best = Some(m),
+                    }
+                }
+                return Ok(best.map_or(FeatureValue::Null, |m| m.value.clone()));
+            }
+            AggregatorFunc::Histogram { bins, min, max } => {
+                let mut counts = vec![0.0; *bins];
+                for m in &messages {
+                    let v = to_f64(&m.value)?;
+                    if v < *min || v > *max {
+                        continue;
+                    }
+                    let bin = (((v - min) / (max - min)) * *bins as f64) as usize;
+                    counts[bin.min(bins - 1)] += weight(m)?;
+                }
+                let weighted = self.weight.is_some();
+                return Ok(FeatureValue::Array(
+                    counts
+                        .into_iter()
+                        .map(|c| match weighted {
+                            true => FeatureValue::Float(c),
+                            false => FeatureValue::Int(c as i64),
+                        })
+                        .collect(),
+                ));
+            }
+            _ => {}
+        }
+        if messages.is_empty() {
+            return Ok(FeatureValue::Null);
+        }
+        if let (AggregatorFunc::Sum, FeatureValueType::Int) = (&self.func, output_type) {
+            return messages
+                .iter()
+                .try_fold(0i64, |acc, m| match m.value {
+                    FeatureValue::Int(v) => acc.checked_add(v).ok_or("sum overflows"),
+                    _ => Err("sum of mixed types"),
+                })
+                .map(FeatureValue::Int)
+                .map_err(|e| e.into());
+        }
+
+        // aggregate numbers as arrays of one element
+        let array = matches!(messages[0].value, FeatureValue::Array(_));
+        let mut rows = Vec::with_capacity(messages.len());
+        for m in &messages {
+            let row = match &m.value {
+                FeatureValue::Array(values) => {
+                    values.iter().map(to_f64).collect::<Result<Vec<_>, _>>()?
+                }
+                v => vec![to_f64(v)?],
+            };
+            if row.len() != rows.first().map_or(row.len(), |r: &Vec<f64>| r.len()) {
+                return Err("cannot aggregate arrays of different lengths".into());
+            }
+            rows.push(row);
+        }
+        let weights = messages
+            .iter()
+            .map(|m| weight(m))
+            .collect::<Result<Vec<_>, _>>()?;
+        let total: f64 = weights.iter().sum();
+        let n = rows.len() as f64;
+        let mut res = Vec::with_capacity(rows[0].len());
+        for i in 0..rows[0].len() {
+            let column = rows.iter().map(|r| r[i]);
+            let value = match self.func {
+                AggregatorFunc::Sum => column.zip(&weights).map(|(v, w)| v * w).sum(),
+                AggregatorFunc::Mean => {
+                    column.zip(&weights).map(|(v, w)| v * w).sum::<f64>() / total
+                }
+                AggregatorFunc::Min => column.fold(f64::INFINITY, f64::min),
+                AggregatorFunc::Max => column.fold(f64::NEG_INFINITY, f64::max),
+                _ => {
+                    if rows.len() < 2 {
+                        return Ok(FeatureValue::Null);
+                    }
+                    let values: Vec<f64> = column.collect();
+                    let mean = values.iter().sum::<f64>() / n;
+                    (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+                }
+            };
+            res.push(value);
+        }
+        // min and max keep the type of the values
+        let ints = *output_type == FeatureValueType::Int
+            || *output_type == FeatureValueType::Array(Box::new(FeatureValueType::Int));
+        let value = |v: f64| match ints {
+            true => FeatureValue::Int(v as i64),
+            false => FeatureValue::Float(v),
+        };
+        Ok(match array {
+            true => FeatureValue::Array(res.into_iter().map(value).collect()),
+            false => value(res[0]),
+        })
+    }
+}
+
+impl Display for Aggregator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let column = self.column.as_deref().unwrap_or("");
+        match &self.func {
+            AggregatorFunc::First { timestamp } => write!(f, "first({}) by {}", column, timestamp)?,
+            AggregatorFunc::Last { timestamp } => write!(f, "last({}) by {}", column, timestamp)?,
+            AggregatorFunc::Histogram { bins, min, max } => {
+                write!(f, "histogram({}, {}, {}, {})", column, bins, min, max)?
+            }
+            func => write!(f, "{}({})", format!("{:?}", func).to_lowercase(), column)?,
+        }
+        if let Some(weight) = &self.weight {
+            write!(f, " weighted by {}", weight)?;
+        }
+        write!(f, " along {:?}", self.direction)
+    }
+}
+
+fn to_f64(value: &FeatureValue) -> Result<f64, Box<dyn Error>> {
+    match value {
+        FeatureValue::Int(v) => Ok(*v as f64),
+        FeatureValue::Float(v) => Ok(*v),
+        v => Err(format!("expected a number but found {:?}", v).into()),
+    }
 }
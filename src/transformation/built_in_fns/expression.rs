@@ -1,52 +1,416 @@
-use crate::FeatureValueType;
+mod cypher;
+mod eval;
+mod functions;
+mod parser;
+mod types;
+
+pub(crate) use cypher::{add_param, quote_identifier};
+pub use functions::{FunctionRegistry, ScalarFunction};
+
+use crate::{FeatureValue, FeatureValueType, LocalFrame};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::Display;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Expression {
     top: ExpressionNode,
 }
 
 impl std::fmt::Debug for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO(tatiana): recurisive expression
-        f.write_fmt(format_args!("Expression"))
+        f.write_fmt(format_args!("Expression({})", self.top))
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.top)
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub(super) enum ExpressionNode {
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ExpressionNode {
     Column(String),
-    Constant(String),
+    Constant(FeatureValue),
+    /// Operators and function calls. Operators are `+ - * / % = <> < <= > >= and or not neg`,
+    /// and function names are lowercase.
     Function(FunctionNode),
+    /// The element of a list at an index, written `col[i]` or `col.str[i]`
+    Index {
+        target: Box<ExpressionNode>,
+        index: Box<ExpressionNode>,
+    },
+    /// Names the result column, written `expr AS name`
+    Alias {
+        child: Box<ExpressionNode>,
+        name: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FunctionNode {
+    pub children: Vec<ExpressionNode>,
+    pub op: String,
+}
+
+impl FunctionNode {
+    pub fn is_operator(&self) -> bool {
+        !self.op.starts_with(|c: char| c.is_alphabetic()) || ["and", "or"].contains(&&*self.op)
+    }
+}
+
+impl Display for ExpressionNode {
+    /// Writes the expression back with every operation parenthesized
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionNode::Column(name) => {
+                if name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+                {
+                    f.write_str(name)
+                } else {
+                    write!(f, "`{}`", name.replace('`', "``"))
+                }
+            }
+            ExpressionNode::Constant(FeatureValue::Null) => f.write_str("null"),
+            ExpressionNode::Constant(FeatureValue::String(s)) => {
+                write!(f, "'{}'", s.replace('\'', "''"))
+            }
+            ExpressionNode::Constant(v) => write!(f, "{}", v),
+            ExpressionNode::Function(func) => match (func.op.as_str(), &func.children[..]) {
+                ("neg", [child]) => write!(f, "(-{})", child),
+                ("not", [child]) => write!(f, "(not {})", child),
+                (op, [left, right]) if func.is_operator() => {
+                    write!(f, "({} {} {})", left, op, right)
+                }
+                (op, children) => {
+                    write!(f, "{}(", op)?;
+                    for (i, child) in children.iter().enumerate() {
+                        if i > 0 {
+                            f.write_str(", ")?;
+                        }
+                        write!(f, "{}", child)?;
+                    }
+                    f.write_str(")")
+                }
+            },
+            ExpressionNode::Index { target, index } => write!(f, "{}[{}]", target, index),
+            ExpressionNode::Alias { child, name } => write!(f, "{} AS {}", child, name),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-pub(super) struct FunctionNode {
-    children: Vec<ExpressionNode>,
-    op: String,
+/// An error in the text of an expression, at a character position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpressionError {
+    pub position: usize,
+    pub message: String,
 }
 
+impl ExpressionError {
+    fn new(position: usize, message: String) -> Self {
+        ExpressionError { position, message }
+    }
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for ExpressionError {}
+
 impl Expression {
+    /// Parses an expression of column references, literals, arithmetic, comparisons, boolean
+    /// logic, function calls such as `avg(col3)`, list indexing such as `dims.str[3]`, and an
+    /// optional `AS` alias
+    pub fn parse(expr: &str) -> Result<Self, ExpressionError> {
+        Ok(Expression {
+            top: parser::parse(expr)?,
+        })
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the expression cannot be parsed
     pub fn new(expr: &str) -> Self {
-        // TODO(tatiana): parse expression, below is an example result
+        Expression::parse(expr).unwrap_or_else(|e| panic!("invalid expression {:?}: {}", expr, e))
+    }
+
+    pub fn top(&self) -> &ExpressionNode {
+        &self.top
+    }
+
+    /// Returns the name of the result column: the alias, the referenced column, or
+    /// `{column}_{index}` for a constant index into a column
+    pub fn get_col_name(&self) -> Option<String> {
+        match &self.top {
+            ExpressionNode::Alias { name, .. } => Some(name.clone()),
+            ExpressionNode::Column(name) => Some(name.clone()),
+            ExpressionNode::Index { target, index } => match (&**target, &**index) {
+                (ExpressionNode::Column(name), ExpressionNode::Constant(FeatureValue::Int(i))) => {
+                    Some(format!("{}_{}", name, i))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Infers the type of the expression over columns of the given types. Fails on unknown
+    /// columns and functions, and on operands of incompatible types. Int and Float operands
+    /// give Float, dates and times can be shifted by and subtracted into Durations, aggregates
+    /// give the type of their result and indexing gives the element type of an Array.
+    pub fn infer_type(
+        &self,
+        columns: &HashMap<String, FeatureValueType>,
+    ) -> Result<FeatureValueType, Box<dyn Error>> {
+        types::infer_type(&self.top, columns)
+    }
+
+    /// Compiles the expression into Cypher, adding its constants to the query parameters. Columns
+    /// are properties of `variable`, or variables of the query when it is None. Fails for
+    /// aggregates and functions that Cypher lacks, which are evaluated locally instead.
+    pub fn to_cypher(
+        &self,
+        variable: Option<&str>,
+        columns: &HashMap<String, FeatureValueType>,
+        params: &mut HashMap<String, FeatureValue>,
+    ) -> Result<String, Box<dyn Error>> {
+        cypher::compile(&self.top, variable, columns, params)
+    }
+
+    /// The names of the columns the expression reads
+    pub fn columns(&self) -> BTreeSet<&str> {
+        fn visit<'a>(node: &'a ExpressionNode, res: &mut BTreeSet<&'a str>) {
+            match node {
+                ExpressionNode::Column(name) => {
+                    res.insert(name);
+                }
+                ExpressionNode::Constant(_) => {}
+                ExpressionNode::Function(func) => func.children.iter().for_each(|c| visit(c, res)),
+                ExpressionNode::Index { target, index } => {
+                    visit(target, res);
+                    visit(index, res);
+                }
+                ExpressionNode::Alias { child, .. } => visit(child, res),
+            }
+        }
+        let mut res = BTreeSet::new();
+        visit(&self.top, &mut res);
+        res
+    }
+
+    /// Splits the expression at its top-level `and`s
+    pub fn conjuncts(&self) -> Vec<Expression> {
+        fn visit(node: &ExpressionNode, res: &mut Vec<Expression>) {
+            match node {
+                ExpressionNode::Function(func) if func.op == "and" => {
+                    func.children.iter().for_each(|c| visit(c, res))
+                }
+                ExpressionNode::Alias { child, .. } => visit(child, res),
+                node => res.push(Expression { top: node.clone() }),
+            }
+        }
+        let mut res = Vec::new();
+        visit(&self.top, &mut res);
+        res
+    }
+
+    /// Whether the expression calls an aggregate, whose value depends on all rows instead of one
+    pub fn contains_aggregate(&self) -> bool {
+        fn visit(node: &ExpressionNode) -> bool {
+            match node {
+                ExpressionNode::Column(_) | ExpressionNode::Constant(_) => false,
+                ExpressionNode::Function(func) => {
+                    eval::AGGREGATES.contains(&func.op.as_str()) || func.children.iter().any(visit)
+                }
+                ExpressionNode::Index { target, index } => visit(target) || visit(index),
+                ExpressionNode::Alias { child, .. } => visit(child),
+            }
+        }
+        visit(&self.top)
+    }
+
+    /// Returns the conjunction of both expressions, without their aliases
+    pub fn and(&self, other: &Expression) -> Expression {
+        fn unaliased(node: &ExpressionNode) -> ExpressionNode {
+            match node {
+                ExpressionNode::Alias { child, .. } => unaliased(child),
+                node => node.clone(),
+            }
+        }
         Expression {
             top: ExpressionNode::Function(FunctionNode {
-                children: vec![
-                    ExpressionNode::Column(expr.to_string()),
-                    ExpressionNode::Constant("1".to_string()),
-                ],
-                op: "=".to_string(),
+                children: vec![unaliased(&self.top), unaliased(&other.top)],
+                op: "and".to_string(),
             }),
         }
     }
 
-    pub fn get_col_name(&self) -> Option<String> {
-        // TODO(tatiana)
-        None
+    /// Evaluates the expression over the rows of a frame with the built-in functions
+    pub fn evaluate(&self, frame: &LocalFrame) -> Result<Vec<FeatureValue>, Box<dyn Error>> {
+        self.evaluate_with(frame, &FunctionRegistry::builtin())
     }
 
-    pub fn get_type(&self) -> FeatureValueType {
-        // TODO(tatiana)
-        FeatureValueType::Int
+    /// Evaluates the expression over the rows of a frame, giving a value of the inferred type or
+    /// Null per row. Nulls propagate through operators and most functions, and `and`/`or`
+    /// follow three-valued logic. Aggregates reduce all rows to one value that is repeated on
+    /// every row.
+    pub fn evaluate_with(
+        &self,
+        frame: &LocalFrame,
+        functions: &FunctionRegistry,
+    ) -> Result<Vec<FeatureValue>, Box<dyn Error>> {
+        let value_type = self.infer_type(&frame.column_types())?;
+        eval::evaluate(&self.top, frame, functions)?
+            .into_iter()
+            .map(|v| match v.value_type() {
+                Some(t) if t != value_type => v.cast(&value_type),
+                _ => Ok(v),
+            })
+            .collect()
     }
 }
+
+#[test]
+fn parse_expressions() {
+    let parsed = |expr: &str| Expression::parse(expr).unwrap().top.to_string();
+    assert_eq!(
+        parsed("col1 + col2 * avg(col3)"),
+        "(col1 + (col2 * avg(col3)))"
+    );
+    assert_eq!(parsed("(col1 + col2) * -2.5e1"), "((col1 + col2) * -25)");
+    assert_eq!(
+        parsed("NOT a >= 1 AND b <> 'x''s' OR c == null"),
+        "(((not (a >= 1)) and (b <> 'x''s')) or (c = null))"
+    );
+    assert_eq!(parsed("dims.str[3]"), "dims[3]");
+    assert_eq!(parsed("u.reviewerID"), "u.reviewerID");
+    assert_eq!(
+        parsed("`total count` / count(*) AS ratio"),
+        "(`total count` / count()) AS ratio"
+    );
+    assert_eq!(
+        parsed("-x % 2 - COALESCE(y, 0)"),
+        "(((-x) % 2) - coalesce(y, 0))"
+    );
+
+    let name = |expr: &str| Expression::new(expr).get_col_name();
+    assert_eq!(name("dims.str[3]"), Some("dims_3".to_string()));
+    assert_eq!(name("user"), Some("user".to_string()));
+    assert_eq!(name("a + b as s"), Some("s".to_string()));
+    assert_eq!(name("a + b"), None);
+
+    let error = |expr: &str| Expression::parse(expr).unwrap_err();
+    assert_eq!(
+        error("a + * b"),
+        ExpressionError::new(4, "expected a value but found '*'".to_string())
+    );
+    assert_eq!(
+        error("avg(a"),
+        ExpressionError::new(5, "expected ')' but the expression ended".to_string())
+    );
+    assert_eq!(error("a b").position, 2);
+    assert_eq!(error("'abc").position, 0);
+    assert_eq!(error("a $ b").position, 2);
+}
+
+#[test]
+fn compile_expressions_to_cypher() {
+    use FeatureValueType::*;
+
+    let columns: HashMap<std::string::String, FeatureValueType> = [
+        ("age", Int),
+        ("name", String),
+        ("born", Date),
+        ("dims", Array(Box::new(Float))),
+    ]
+    .into_iter()
+    .map(|(k, t)| (k.to_string(), t))
+    .collect();
+    let mut params = HashMap::new();
+    let compile = |expr: &str, params: &mut HashMap<_, _>| {
+        Expression::new(expr).to_cypher(Some("n"), &columns, params)
+    };
+    assert_eq!(
+        compile("age >= 18 AND lower(name) <> 'bob'", &mut params).unwrap(),
+        "((n.`age` >= $p0) AND (toLower(n.`name`) <> $p1))"
+    );
+    assert_eq!(
+        params,
+        HashMap::from([
+            ("p0".to_string(), FeatureValue::Int(18)),
+            ("p1".to_string(), FeatureValue::String("bob".to_string())),
+        ])
+    );
+    assert_eq!(
+        compile("date('2020-01-01') - born", &mut params).unwrap(),
+        "duration.inSeconds(n.`born`, date($p2))"
+    );
+    assert_eq!(
+        compile("name.str[-1]", &mut params).unwrap(),
+        "substring(n.`name`, CASE WHEN $p3 < 0 THEN size(n.`name`) + $p3 ELSE $p3 END, 1)"
+    );
+    assert!(compile("age - avg(age)", &mut params).is_err());
+    assert!(compile("variance(age) > 1", &mut params).is_err());
+
+    let conjuncts = Expression::new("a > 1 and (b or c) and not d").conjuncts();
+    let conjuncts: Vec<_> = conjuncts.iter().map(|c| c.top.to_string()).collect();
+    assert_eq!(conjuncts, vec!["(a > 1)", "(b or c)", "(not d)"]);
+    assert_eq!(
+        Expression::new("x + y.z * x")
+            .columns()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec!["x", "y.z"]
+    );
+}
+
+#[test]
+fn infer_expression_types() {
+    use FeatureValueType::*;
+
+    let columns: HashMap<std::string::String, FeatureValueType> = [
+        ("i", Int),
+        ("f", Float),
+        ("s", String),
+        ("d", Date),
+        ("t", DateTime),
+        ("dur", Duration),
+        ("dims", Array(Box::new(Float))),
+    ]
+    .into_iter()
+    .map(|(k, t)| (k.to_string(), t))
+    .collect();
+    let infer = |expr: &str| Expression::new(expr).infer_type(&columns);
+    let ok = |expr: &str| infer(expr).unwrap();
+    assert_eq!(ok("i + i * 2"), Int);
+    assert_eq!(ok("i + f"), Float);
+    assert_eq!(ok("s + 'x'"), String);
+    assert_eq!(ok("dims.str[3]"), Float);
+    assert_eq!(ok("d + dur"), Date);
+    assert_eq!(ok("t - t"), Duration);
+    assert_eq!(ok("avg(i) as m"), Float);
+    assert_eq!(ok("max(s)"), String);
+    assert_eq!(ok("collect(i)"), Array(Box::new(Int)));
+    assert_eq!(ok("count(*)"), Int);
+    assert_eq!(ok("i > 1 and (s = null or not f <= 2)"), Boolean);
+    assert_eq!(ok("coalesce(null, i, f)"), Float);
+
+    let error = |expr: &str| infer(expr).unwrap_err().to_string();
+    assert!(error("i + missing").starts_with("unknown column missing"));
+    assert_eq!(error("s - i"), "- cannot be applied to String, Int");
+    assert_eq!(error("i and true"), "and cannot be applied to Int, Boolean");
+    assert_eq!(error("dims[s]"), "index s is not an Int");
+    assert_eq!(error("i[0]"), "i of type Int cannot be indexed");
+    assert_eq!(error("frobnicate(i)"), "unknown function frobnicate");
+    assert_eq!(
+        error("lower(s, s)"),
+        "lower takes 1 argument(s) but 2 were given"
+    );
+}
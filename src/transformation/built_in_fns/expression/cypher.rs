@@ -0,0 +1,135 @@
+use super::eval::AGGREGATES;
+use super::types::{infer, Columns};
+use super::{ExpressionNode, FunctionNode};
+use crate::{FeatureValue, FeatureValueType};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Quotes a name as a Cypher identifier
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// Adds a value to the query parameters and returns the parameter reference
+pub(crate) fn add_param(params: &mut HashMap<String, FeatureValue>, value: FeatureValue) -> String {
+    let name = format!("p{}", params.len());
+    params.insert(name.clone(), value);
+    format!("${}", name)
+}
+
+struct Compiler<'a> {
+    variable: Option<&'a str>,
+    columns: &'a Columns,
+    params: &'a mut HashMap<String, FeatureValue>,
+}
+
+fn is_temporal(t: &Option<FeatureValueType>) -> bool {
+    matches!(
+        t,
+        Some(FeatureValueType::Date | FeatureValueType::Time | FeatureValueType::DateTime)
+    )
+}
+
+impl Compiler<'_> {
+    fn compile(&mut self, node: &ExpressionNode) -> Result<String, Box<dyn Error>> {
+        match node {
+            ExpressionNode::Column(name) => Ok(match self.variable {
+                Some(variable) => format!("{}.{}", variable, quote_identifier(name)),
+                None => quote_identifier(name),
+            }),
+            ExpressionNode::Constant(FeatureValue::Null) => Ok("null".to_string()),
+            ExpressionNode::Constant(v) => Ok(add_param(self.params, v.clone())),
+            ExpressionNode::Alias { child, .. } => self.compile(child),
+            ExpressionNode::Index { target, index } => {
+                if infer(target, self.columns)? == Some(FeatureValueType::String) {
+                    let (target, index) = (self.compile(target)?, self.compile(index)?);
+                    // negative indexes count from the end, as they do for lists
+                    return Ok(format!(
+                        "substring({0}, CASE WHEN {1} < 0 THEN size({0}) + {1} ELSE {1} END, 1)",
+                        target, index
+                    ));
+                }
+                Ok(format!(
+                    "{}[{}]",
+                    self.compile(target)?,
+                    self.compile(index)?
+                ))
+            }
+            ExpressionNode::Function(func) => self.function(func),
+        }
+    }
+
+    fn function(&mut self, func: &FunctionNode) -> Result<String, Box<dyn Error>> {
+        let op = func.op.as_str();
+        if AGGREGATES.contains(&op) {
+            // Cypher groups by the other returned values instead of aggregating all rows
+            return Err(format!("aggregate {} is evaluated locally", op).into());
+        }
+        let temporal = |c: &ExpressionNode| infer(c, self.columns).is_ok_and(|t| is_temporal(&t));
+        if op == "-" && func.children.iter().all(temporal) {
+            // Cypher has no subtraction of dates, only durations between them
+            let args = self.args(func)?;
+            return Ok(format!("duration.inSeconds({}, {})", args[1], args[0]));
+        }
+        let args = self.args(func)?;
+        let binary = |symbol: &str| format!("({} {} {})", args[0], symbol, args[1]);
+        let call = |name: &str| format!("{}({})", name, args.join(", "));
+        let res = match op {
+            "+" | "-" | "*" | "/" | "%" | "=" | "<>" | "<" | "<=" | ">" | ">=" => binary(op),
+            "and" => binary("AND"),
+            "or" => binary("OR"),
+            "xor" => binary("XOR"),
+            "not" => format!("(NOT {})", args[0]),
+            "neg" => format!("(-{})", args[0]),
+            "pow" | "power" => binary("^"),
+            "startswith" => binary("STARTS WITH"),
+            "endswith" => binary("ENDS WITH"),
+            "contains" => binary("CONTAINS"),
+            "isnull" => format!("({} IS NULL)", args[0]),
+            "isnotnull" => format!("({} IS NOT NULL)", args[0]),
+            "concat" => format!("({})", args.join(" + ")),
+            "coalesce" | "abs" | "sign" | "ceil" | "floor" | "round" | "sqrt" | "exp" | "log10"
+            | "trim" | "ltrim" | "rtrim" | "reverse" | "substring" | "replace" | "split"
+            | "size" | "date" => call(op),
+            "log" | "ln" => call("log"),
+            "lower" => call("toLower"),
+            "upper" => call("toUpper"),
+            "length" => call("size"),
+            "tostring" => call("toString"),
+            "tointeger" | "toint" => call("toInteger"),
+            "tofloat" => call("toFloat"),
+            "toboolean" => call("toBoolean"),
+            "year" | "month" | "day" | "hour" | "minute" | "second" => {
+                format!("{}.{}", args[0], op)
+            }
+            "dayofweek" => format!("{}.dayOfWeek", args[0]),
+            "datetime" => match infer(&func.children[0], self.columns)? {
+                Some(FeatureValueType::Int) => format!("datetime({{epochSeconds: {}}})", args[0]),
+                _ => call("datetime"),
+            },
+            "duration" => format!("duration({{seconds: {}}})", args[0]),
+            _ => return Err(format!("{} cannot be computed in Cypher", op).into()),
+        };
+        Ok(res)
+    }
+
+    fn args(&mut self, func: &FunctionNode) -> Result<Vec<String>, Box<dyn Error>> {
+        func.children.iter().map(|c| self.compile(c)).collect()
+    }
+}
+
+/// Compiles the expression into Cypher, passing constants as parameters. Columns are properties
+/// of the variable, or variables of the query when there is none.
+pub(super) fn compile(
+    node: &ExpressionNode,
+    variable: Option<&str>,
+    columns: &Columns,
+    params: &mut HashMap<String, FeatureValue>,
+) -> Result<String, Box<dyn Error>> {
+    Compiler {
+        variable,
+        columns,
+        params,
+    }
+    .compile(node)
+}
@@ -0,0 +1,397 @@
+use super::{ExpressionNode, FunctionNode, FunctionRegistry};
+use crate::{FeatureValue, LocalFrame};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::error::Error;
+
+use FeatureValue::*;
+
+pub(super) const AGGREGATES: [&str; 11] = [
+    "count", "collect", "sum", "avg", "mean", "min", "max", "std", "stddev", "var", "variance",
+];
+
+/// The values of a subexpression over a batch. Constants and aggregates are kept once instead of
+/// once per row, and columns of the batch are borrowed.
+enum Values<'a> {
+    Constant(FeatureValue),
+    Column(Cow<'a, [FeatureValue]>),
+}
+
+impl Values<'_> {
+    fn get(&self, row: usize) -> &FeatureValue {
+        match self {
+            Values::Constant(v) => v,
+            Values::Column(values) => &values[row],
+        }
+    }
+
+    fn into_vec(self, num_rows: usize) -> Vec<FeatureValue> {
+        match self {
+            Values::Constant(v) => vec![v; num_rows],
+            Values::Column(values) => values.into_owned(),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    frame: &'a LocalFrame,
+    functions: &'a FunctionRegistry,
+}
+
+impl<'a> Evaluator<'a> {
+    /// Applies `f` to the arguments of each row, or once if all arguments are constant
+    fn map(
+        &self,
+        args: &[Values],
+        f: impl Fn(&[FeatureValue]) -> Result<FeatureValue, Box<dyn Error>>,
+    ) -> Result<Values<'a>, Box<dyn Error>> {
+        if args.iter().all(|a| matches!(a, Values::Constant(_))) {
+            let row: Vec<_> = args.iter().map(|a| a.get(0).clone()).collect();
+            return Ok(Values::Constant(f(&row)?));
+        }
+        let mut row = Vec::with_capacity(args.len());
+        let mut res = Vec::with_capacity(self.frame.num_rows());
+        for i in 0..self.frame.num_rows() {
+            row.clear();
+            row.extend(args.iter().map(|a| a.get(i).clone()));
+            res.push(f(&row)?);
+        }
+        Ok(Values::Column(Cow::Owned(res)))
+    }
+
+    fn eval(&self, node: &ExpressionNode) -> Result<Values<'a>, Box<dyn Error>> {
+        match node {
+            ExpressionNode::Column(name) => match self.frame.column(name) {
+                Some(column) => Ok(Values::Column(Cow::Borrowed(&column.values))),
+                None => Err(format!("unknown column {}", name).into()),
+            },
+            ExpressionNode::Constant(v) => Ok(Values::Constant(v.clone())),
+            ExpressionNode::Alias { child, .. } => self.eval(child),
+            ExpressionNode::Index { target, index } => {
+                let args = [self.eval(target)?, self.eval(index)?];
+                self.map(&args, |row| element(&row[0], &row[1]))
+            }
+            ExpressionNode::Function(func) if AGGREGATES.contains(&&*func.op) => {
+                self.aggregate(func)
+            }
+            ExpressionNode::Function(func) => {
+                let args = func
+                    .children
+                    .iter()
+                    .map(|child| self.eval(child))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let op = func.op.as_str();
+                match op {
+                    "and" => self.map(&args, |row| Ok(and(&row[0], &row[1]))),
+                    "or" => self.map(&args, |row| Ok(or(&row[0], &row[1]))),
+                    _ if func.is_operator() || op == "not" || op == "neg" => {
+                        self.map(&args, |row| {
+                            if row.iter().any(|v| v.is_null()) {
+                                return Ok(Null);
+                            }
+                            match row {
+                                [v] => unary(op, v),
+                                [a, b] => binary(op, a, b),
+                                _ => Err(format!("{} takes 1 or 2 arguments", op).into()),
+                            }
+                        })
+                    }
+                    _ => {
+                        let function = self
+                            .functions
+                            .get(op)
+                            .ok_or_else(|| format!("unknown function {}", op))?;
+                        self.map(&args, |row| {
+                            if function.null_propagating && row.iter().any(|v| v.is_null()) {
+                                Ok(Null)
+                            } else {
+                                (function.eval)(row).map_err(|e| format!("{}: {}", op, e).into())
+                            }
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reduces all rows to one value. Nulls are skipped, and aggregates of no values are Null,
+    /// except for `count` and `collect`.
+    fn aggregate(&self, func: &FunctionNode) -> Result<Values<'a>, Box<dyn Error>> {
+        let num_rows = self.frame.num_rows();
+        let child = match &func.children[..] {
+            [] if func.op == "count" => return Ok(Values::Constant(Int(num_rows as i64))),
+            [child] => self.eval(child)?,
+            _ => return Err(format!("{} takes 1 argument", func.op).into()),
+        };
+        let values: Vec<&FeatureValue> = (0..num_rows)
+            .map(|i| child.get(i))
+            .filter(|v| !v.is_null())
+            .collect();
+        match func.op.as_str() {
+            "count" => return Ok(Values::Constant(Int(values.len() as i64))),
+            "collect" => {
+                return Ok(Values::Constant(Array(
+                    values.into_iter().cloned().collect(),
+                )))
+            }
+            _ => {}
+        }
+        if values.is_empty() {
+            return Ok(Values::Constant(Null));
+        }
+        let floats = || {
+            values
+                .iter()
+                .map(|v| to_f64(v))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let n = values.len() as f64;
+        let res = match func.op.as_str() {
+            "sum" => match values[0] {
+                Int(_) | Duration(_) => {
+                    values[1..]
+                        .iter()
+                        .try_fold(values[0].clone(), |acc, v| match (acc, v) {
+                            (Int(a), Int(b)) => a.checked_add(*b).map(Int).ok_or("sum overflows"),
+                            (Duration(a), Duration(b)) => {
+                                a.checked_add(*b).map(Duration).ok_or("sum overflows")
+                            }
+                            _ => Err("sum of mixed types"),
+                        })?
+                }
+                _ => Float(floats()?.iter().sum()),
+            },
+            "avg" | "mean" => match values[0] {
+                Duration(_) => Duration((floats()?.iter().sum::<f64>() / n).round() as i64),
+                _ => Float(floats()?.iter().sum::<f64>() / n),
+            },
+            "min" | "max" => {
+                let wanted = if func.op == "min" {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                let mut best = values[0];
+                for v in &values[1..] {
+                    if v.compare(best) == Some(wanted) {
+                        best = v;
+                    }
+                }
+                best.clone()
+            }
+            _ => {
+                // std, stddev, var and variance are of the sample
+                if values.len() < 2 {
+                    return Ok(Values::Constant(Null));
+                }
+                let floats = floats()?;
+                let mean = floats.iter().sum::<f64>() / n;
+                let var = floats.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+                if func.op.starts_with("var") {
+                    Float(var)
+                } else {
+                    Float(var.sqrt())
+                }
+            }
+        };
+        Ok(Values::Constant(res))
+    }
+}
+
+fn to_f64(value: &FeatureValue) -> Result<f64, Box<dyn Error>> {
+    match value {
+        Int(v) | Duration(v) => Ok(*v as f64),
+        Float(v) => Ok(*v),
+        v => Err(format!("expected a number but found {:?}", v).into()),
+    }
+}
+
+fn and(a: &FeatureValue, b: &FeatureValue) -> FeatureValue {
+    match (a, b) {
+        (Boolean(false), _) | (_, Boolean(false)) => Boolean(false),
+        (Boolean(true), Boolean(true)) => Boolean(true),
+        _ => Null,
+    }
+}
+
+fn or(a: &FeatureValue, b: &FeatureValue) -> FeatureValue {
+    match (a, b) {
+        (Boolean(true), _) | (_, Boolean(true)) => Boolean(true),
+        (Boolean(false), Boolean(false)) => Boolean(false),
+        _ => Null,
+    }
+}
+
+/// The element at an index of an Array or a String, counting from the end for negative indexes.
+/// Indexes out of range give Null.
+fn element(target: &FeatureValue, index: &FeatureValue) -> Result<FeatureValue, Box<dyn Error>> {
+    let at = |len: usize, i: i64| {
+        let i = if i < 0 { i + len as i64 } else { i };
+        (0..len as i64).contains(&i).then_some(i as usize)
+    };
+    match (target, index) {
+        (Null, _) | (_, Null) => Ok(Null),
+        (Array(values), Int(i)) => Ok(at(values.len(), *i).map_or(Null, |i| values[i].clone())),
+        (String(s), Int(i)) => {
+            let chars: Vec<char> = s.chars().collect();
+            Ok(at(chars.len(), *i).map_or(Null, |i| String(chars[i].to_string())))
+        }
+        (t, i) => Err(format!("cannot index {:?} by {:?}", t, i).into()),
+    }
+}
+
+fn unary(op: &str, value: &FeatureValue) -> Result<FeatureValue, Box<dyn Error>> {
+    match (op, value) {
+        ("not", Boolean(v)) => Ok(Boolean(!v)),
+        ("neg", Int(v)) => v.checked_neg().map(Int).ok_or_else(|| "overflow".into()),
+        ("neg", Float(v)) => Ok(Float(-v)),
+        ("neg", Duration(v)) => Ok(Duration(-v)),
+        (op, v) => Err(format!("{} cannot be applied to {:?}", op, v).into()),
+    }
+}
+
+fn binary(op: &str, a: &FeatureValue, b: &FeatureValue) -> Result<FeatureValue, Box<dyn Error>> {
+    let ordering = || a.compare(b);
+    let compared = match op {
+        "=" => Some(ordering() == Some(Ordering::Equal) || a == b),
+        "<>" => Some(ordering() != Some(Ordering::Equal) && a != b),
+        "<" => ordering().map(|o| o.is_lt()),
+        "<=" => ordering().map(|o| o.is_le()),
+        ">" => ordering().map(|o| o.is_gt()),
+        ">=" => ordering().map(|o| o.is_ge()),
+        _ => None,
+    };
+    if ["=", "<>", "<", "<=", ">", ">="].contains(&op) {
+        // values that cannot be ordered compare as Null
+        return Ok(compared.map_or(Null, Boolean));
+    }
+    let seconds = chrono::Duration::seconds;
+    let res = match (op, a, b) {
+        (_, Int(a), Int(b)) => {
+            if matches!(op, "/" | "%") && *b == 0 {
+                return Err("division by zero".into());
+            }
+            match op {
+                "+" => a.checked_add(*b),
+                "-" => a.checked_sub(*b),
+                "*" => a.checked_mul(*b),
+                "/" => a.checked_div(*b),
+                "%" => a.checked_rem(*b),
+                _ => None,
+            }
+            .map(Int)
+        }
+        (_, Int(_) | Float(_), Int(_) | Float(_)) => {
+            let (a, b) = (to_f64(a)?, to_f64(b)?);
+            match op {
+                "+" => Some(a + b),
+                "-" => Some(a - b),
+                "*" => Some(a * b),
+                "/" => Some(a / b),
+                "%" => Some(a % b),
+                _ => None,
+            }
+            .map(Float)
+        }
+        ("+", String(a), String(b)) => Some(String(format!("{}{}", a, b))),
+        ("+", Array(a), Array(b)) => Some(Array(a.iter().chain(b).cloned().collect())),
+        ("+", Date(d), Duration(s)) | ("+", Duration(s), Date(d)) => {
+            d.checked_add_signed(seconds(*s)).map(Date)
+        }
+        ("-", Date(d), Duration(s)) => d.checked_sub_signed(seconds(*s)).map(Date),
+        ("+", DateTime(t), Duration(s)) | ("+", Duration(s), DateTime(t)) => {
+            t.checked_add_signed(seconds(*s)).map(DateTime)
+        }
+        ("-", DateTime(t), Duration(s)) => t.checked_sub_signed(seconds(*s)).map(DateTime),
+        ("+", Time(t), Duration(s)) | ("+", Duration(s), Time(t)) => {
+            Some(Time(t.overflowing_add_signed(seconds(*s)).0))
+        }
+        ("-", Time(t), Duration(s)) => Some(Time(t.overflowing_sub_signed(seconds(*s)).0)),
+        ("+", Duration(a), Duration(b)) => a.checked_add(*b).map(Duration),
+        ("-", Duration(a), Duration(b)) => a.checked_sub(*b).map(Duration),
+        ("-", Date(a), Date(b)) => Some(Duration((*a - *b).num_seconds())),
+        ("-", DateTime(a), DateTime(b)) => Some(Duration((*a - *b).num_seconds())),
+        ("-", Time(a), Time(b)) => Some(Duration((*a - *b).num_seconds())),
+        ("*", Duration(s), Int(_) | Float(_)) | ("*", Int(_) | Float(_), Duration(s)) => {
+            let factor = to_f64(if matches!(a, Duration(_)) { b } else { a })?;
+            Some(Duration((*s as f64 * factor).round() as i64))
+        }
+        ("/", Duration(s), Int(_) | Float(_)) => {
+            Some(Duration((*s as f64 / to_f64(b)?).round() as i64))
+        }
+        _ => return Err(format!("{} cannot be applied to {:?}, {:?}", op, a, b).into()),
+    };
+    res.ok_or_else(|| format!("{} of {:?} and {:?} overflows", op, a, b).into())
+}
+
+/// Evaluates the expression over all rows of the frame
+pub(super) fn evaluate(
+    node: &ExpressionNode,
+    frame: &LocalFrame,
+    functions: &FunctionRegistry,
+) -> Result<Vec<FeatureValue>, Box<dyn Error>> {
+    let evaluator = Evaluator { frame, functions };
+    Ok(evaluator.eval(node)?.into_vec(frame.num_rows()))
+}
+
+#[test]
+fn evaluate_over_frame() {
+    use super::Expression;
+    use crate::{FeatureValueType, LocalColumn};
+
+    let column = |name: &str, value_type, values| LocalColumn {
+        name: name.to_string(),
+        value_type,
+        values,
+    };
+    let frame = LocalFrame {
+        columns: vec![
+            column("i", FeatureValueType::Int, vec![Int(1), Null, Int(3)]),
+            column(
+                "f",
+                FeatureValueType::Float,
+                vec![Float(0.5), Float(2.0), Null],
+            ),
+            column(
+                "s",
+                FeatureValueType::String,
+                vec![String("Ab".into()), String("c".into()), Null],
+            ),
+            column(
+                "dims",
+                FeatureValueType::Array(Box::new(FeatureValueType::Float)),
+                vec![Array(vec![Float(1.0), Float(2.0)]), Array(vec![]), Null],
+            ),
+        ],
+    };
+    let eval = |expr: &str| Expression::new(expr).evaluate(&frame).unwrap();
+    assert_eq!(eval("i + f"), vec![Float(1.5), Null, Null]);
+    assert_eq!(eval("i * 2 - 1"), vec![Int(1), Null, Int(5)]);
+    assert_eq!(
+        eval("i > 1 or f > 1"),
+        vec![Boolean(false), Boolean(true), Boolean(true)]
+    );
+    assert_eq!(eval("i > 1 and f > 1"), vec![Boolean(false), Null, Null]);
+    assert_eq!(eval("dims.str[-1]"), vec![Float(2.0), Null, Null]);
+    assert_eq!(
+        eval("upper(s) + '!'"),
+        vec![String("AB!".into()), String("C!".into()), Null]
+    );
+    assert_eq!(eval("coalesce(i, 0)"), vec![Int(1), Int(0), Int(3)]);
+    assert_eq!(eval("i - avg(i)"), vec![Float(-1.0), Null, Float(1.0)]);
+    assert_eq!(eval("count(*) + count(i)"), vec![Int(5); 3]);
+    assert_eq!(eval("sum(i)"), vec![Int(4); 3]);
+    assert_eq!(
+        eval("collect(s)")[0],
+        Array(vec![String("Ab".into()), String("c".into())])
+    );
+    assert_eq!(
+        eval("date('2023-01-31') + duration(86400)"),
+        vec![Date(chrono::NaiveDate::from_ymd(2023, 2, 1)); 3]
+    );
+    assert!(Expression::new("i / 0").evaluate(&frame).is_err());
+    let err = Expression::new("datetime(9223372036854775807)")
+        .evaluate(&frame)
+        .unwrap_err();
+    assert!(err.to_string().contains("out of range"));
+}
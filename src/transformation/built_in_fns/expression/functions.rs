@@ -0,0 +1,291 @@
+use crate::{FeatureValue, FeatureValueType};
+use chrono::{Datelike, TimeZone, Timelike, Utc};
+use std::collections::HashMap;
+use std::error::Error;
+
+use FeatureValue::*;
+
+pub type ScalarFn = fn(&[FeatureValue]) -> Result<FeatureValue, Box<dyn Error>>;
+
+/// A function applied to the values of each row
+#[derive(Clone, Copy)]
+pub struct ScalarFunction {
+    /// Whether the result is Null when an argument is Null. `eval` then only sees non-null
+    /// arguments.
+    pub null_propagating: bool,
+    pub eval: ScalarFn,
+}
+
+/// The scalar functions that expressions can call, by lowercase name
+#[derive(Clone)]
+pub struct FunctionRegistry {
+    functions: HashMap<std::string::String, ScalarFunction>,
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        FunctionRegistry::builtin()
+    }
+}
+
+impl FunctionRegistry {
+    /// The registry of built-in functions
+    pub fn builtin() -> Self {
+        let mut res = FunctionRegistry {
+            functions: HashMap::new(),
+        };
+        let builtins: [(&str, bool, ScalarFn); 46] = [
+            ("isnull", false, |args| Ok(Boolean(args[0].is_null()))),
+            ("isnotnull", false, |args| Ok(Boolean(!args[0].is_null()))),
+            ("coalesce", false, |args| {
+                Ok(args.iter().find(|v| !v.is_null()).cloned().unwrap_or(Null))
+            }),
+            ("xor", true, |args| {
+                Ok(Boolean(boolean(&args[0])? != boolean(&args[1])?))
+            }),
+            // strings
+            ("lower", true, |args| {
+                Ok(String(string(&args[0])?.to_lowercase()))
+            }),
+            ("upper", true, |args| {
+                Ok(String(string(&args[0])?.to_uppercase()))
+            }),
+            ("trim", true, |args| {
+                Ok(String(string(&args[0])?.trim().into()))
+            }),
+            ("ltrim", true, |args| {
+                Ok(String(string(&args[0])?.trim_start().into()))
+            }),
+            ("rtrim", true, |args| {
+                Ok(String(string(&args[0])?.trim_end().into()))
+            }),
+            ("reverse", true, |args| {
+                Ok(String(string(&args[0])?.chars().rev().collect()))
+            }),
+            ("substring", true, substring),
+            ("replace", true, |args| {
+                Ok(String(
+                    string(&args[0])?.replace(string(&args[1])?, string(&args[2])?),
+                ))
+            }),
+            ("concat", true, |args| {
+                Ok(String(
+                    args.iter()
+                        .map(string)
+                        .collect::<Result<Vec<_>, _>>()?
+                        .concat(),
+                ))
+            }),
+            ("split", true, |args| {
+                Ok(Array(
+                    string(&args[0])?
+                        .split(string(&args[1])?)
+                        .map(|s| String(s.to_string()))
+                        .collect(),
+                ))
+            }),
+            ("startswith", true, |args| {
+                Ok(Boolean(string(&args[0])?.starts_with(string(&args[1])?)))
+            }),
+            ("endswith", true, |args| {
+                Ok(Boolean(string(&args[0])?.ends_with(string(&args[1])?)))
+            }),
+            ("contains", true, |args| {
+                Ok(Boolean(string(&args[0])?.contains(string(&args[1])?)))
+            }),
+            ("length", true, length),
+            ("size", true, length),
+            // conversions
+            ("tostring", true, |args| {
+                Ok(match &args[0] {
+                    String(s) => String(s.clone()),
+                    v => String(v.to_string()),
+                })
+            }),
+            ("tointeger", true, to_integer),
+            ("toint", true, to_integer),
+            ("tofloat", true, |args| {
+                Ok(match &args[0] {
+                    String(s) => s.trim().parse().map(Float).unwrap_or(Null),
+                    v => Float(float(v)?),
+                })
+            }),
+            ("toboolean", true, |args| {
+                Ok(match &args[0] {
+                    Boolean(v) => Boolean(*v),
+                    Int(v) => Boolean(*v != 0),
+                    String(s) => match s.trim().to_lowercase().as_str() {
+                        "true" => Boolean(true),
+                        "false" => Boolean(false),
+                        _ => Null,
+                    },
+                    v => return Err(unsupported("toboolean", v)),
+                })
+            }),
+            // numbers
+            ("abs", true, |args| match &args[0] {
+                Int(v) => Ok(Int(v.abs())),
+                Float(v) => Ok(Float(v.abs())),
+                Duration(v) => Ok(Duration(v.abs())),
+                v => Err(unsupported("abs", v)),
+            }),
+            ("sign", true, |args| {
+                Ok(Int(float(&args[0])?.signum() as i64))
+            }),
+            ("ceil", true, |args| Ok(Float(float(&args[0])?.ceil()))),
+            ("floor", true, |args| Ok(Float(float(&args[0])?.floor()))),
+            ("round", true, |args| Ok(Float(float(&args[0])?.round()))),
+            ("sqrt", true, |args| Ok(Float(float(&args[0])?.sqrt()))),
+            ("exp", true, |args| Ok(Float(float(&args[0])?.exp()))),
+            ("log", true, |args| Ok(Float(float(&args[0])?.ln()))),
+            ("ln", true, |args| Ok(Float(float(&args[0])?.ln()))),
+            ("log10", true, |args| Ok(Float(float(&args[0])?.log10()))),
+            ("pow", true, |args| {
+                Ok(Float(float(&args[0])?.powf(float(&args[1])?)))
+            }),
+            ("power", true, |args| {
+                Ok(Float(float(&args[0])?.powf(float(&args[1])?)))
+            }),
+            // dates and times
+            ("year", true, |args| {
+                date_part(&args[0], |d| d.year() as i64)
+            }),
+            ("month", true, |args| {
+                date_part(&args[0], |d| d.month() as i64)
+            }),
+            ("day", true, |args| date_part(&args[0], |d| d.day() as i64)),
+            ("dayofweek", true, |args| {
+                date_part(&args[0], |d| d.weekday().number_from_monday() as i64)
+            }),
+            ("hour", true, |args| {
+                time_part(&args[0], |t| t.hour() as i64)
+            }),
+            ("minute", true, |args| {
+                time_part(&args[0], |t| t.minute() as i64)
+            }),
+            ("second", true, |args| {
+                time_part(&args[0], |t| t.second() as i64)
+            }),
+            ("date", true, |args| match &args[0] {
+                Date(d) => Ok(Date(*d)),
+                DateTime(t) => Ok(Date(t.naive_utc().date())),
+                String(s) => FeatureValue::parse(s, &FeatureValueType::Date),
+                v => Err(unsupported("date", v)),
+            }),
+            ("datetime", true, |args| match &args[0] {
+                DateTime(t) => Ok(DateTime(*t)),
+                Date(d) => Ok(DateTime(Utc.from_utc_datetime(&d.and_hms(0, 0, 0)))),
+                String(s) => FeatureValue::parse(s, &FeatureValueType::DateTime),
+                Int(secs) => Utc
+                    .timestamp_opt(*secs, 0)
+                    .single()
+                    .map(DateTime)
+                    .ok_or_else(|| format!("timestamp {} is out of range", secs).into()),
+                v => Err(unsupported("datetime", v)),
+            }),
+            ("duration", true, |args| match &args[0] {
+                Int(secs) => Ok(Duration(*secs)),
+                v => Err(unsupported("duration", v)),
+            }),
+        ];
+        for (name, null_propagating, eval) in builtins {
+            res.functions.insert(
+                name.to_string(),
+                ScalarFunction {
+                    null_propagating,
+                    eval,
+                },
+            );
+        }
+        res
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ScalarFunction> {
+        self.functions.get(name)
+    }
+}
+
+fn unsupported(function: &str, value: &FeatureValue) -> Box<dyn Error> {
+    format!("{} does not support {:?}", function, value).into()
+}
+
+fn string(value: &FeatureValue) -> Result<&str, Box<dyn Error>> {
+    match value {
+        String(s) => Ok(s),
+        v => Err(format!("expected a String but found {:?}", v).into()),
+    }
+}
+
+fn boolean(value: &FeatureValue) -> Result<bool, Box<dyn Error>> {
+    match value {
+        Boolean(v) => Ok(*v),
+        v => Err(format!("expected a Boolean but found {:?}", v).into()),
+    }
+}
+
+fn int(value: &FeatureValue) -> Result<i64, Box<dyn Error>> {
+    match value {
+        Int(v) => Ok(*v),
+        v => Err(format!("expected an Int but found {:?}", v).into()),
+    }
+}
+
+fn float(value: &FeatureValue) -> Result<f64, Box<dyn Error>> {
+    match value {
+        Int(v) => Ok(*v as f64),
+        Float(v) => Ok(*v),
+        v => Err(format!("expected a number but found {:?}", v).into()),
+    }
+}
+
+/// `substring(s, start[, length])` with a 0-based start, counted in characters
+fn substring(args: &[FeatureValue]) -> Result<FeatureValue, Box<dyn Error>> {
+    let s = string(&args[0])?;
+    let start = int(&args[1])?.max(0) as usize;
+    let chars = s.chars().skip(start);
+    Ok(String(match args.get(2) {
+        Some(len) => chars.take(int(len)?.max(0) as usize).collect(),
+        None => chars.collect(),
+    }))
+}
+
+fn length(args: &[FeatureValue]) -> Result<FeatureValue, Box<dyn Error>> {
+    match &args[0] {
+        String(s) => Ok(Int(s.chars().count() as i64)),
+        Array(values) => Ok(Int(values.len() as i64)),
+        v => Err(unsupported("length", v)),
+    }
+}
+
+/// Truncates floats, and gives Null for strings that are not integers
+fn to_integer(args: &[FeatureValue]) -> Result<FeatureValue, Box<dyn Error>> {
+    Ok(match &args[0] {
+        Int(v) => Int(*v),
+        Float(v) => Int(*v as i64),
+        Boolean(v) => Int(*v as i64),
+        String(s) => s.trim().parse().map(Int).unwrap_or(Null),
+        v => return Err(unsupported("tointeger", v)),
+    })
+}
+
+fn date_part(
+    value: &FeatureValue,
+    part: fn(&chrono::NaiveDate) -> i64,
+) -> Result<FeatureValue, Box<dyn Error>> {
+    match value {
+        Date(d) => Ok(Int(part(d))),
+        DateTime(t) => Ok(Int(part(&t.naive_utc().date()))),
+        v => Err(format!("expected a Date or DateTime but found {:?}", v).into()),
+    }
+}
+
+fn time_part(
+    value: &FeatureValue,
+    part: fn(&chrono::NaiveTime) -> i64,
+) -> Result<FeatureValue, Box<dyn Error>> {
+    match value {
+        Time(t) => Ok(Int(part(t))),
+        DateTime(t) => Ok(Int(part(&t.naive_utc().time()))),
+        v => Err(format!("expected a Time or DateTime but found {:?}", v).into()),
+    }
+}
@@ -0,0 +1,424 @@
+use super::{ExpressionError, ExpressionNode, FunctionNode};
+use crate::FeatureValue;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A backtick-quoted column name
+    QuotedIdent(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// Operators and punctuation
+    Symbol(&'static str),
+    End,
+}
+
+const SYMBOLS: [&str; 19] = [
+    "==", "!=", "<>", "<=", ">=", "<", ">", "=", "+", "-", "*", "/", "%", "(", ")", "[", "]", ",",
+    ".",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<(Token, usize)>, ExpressionError> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let mut is_float = false;
+            if i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit() {
+                is_float = true;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    is_float = true;
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let token =
+                if is_float {
+                    Token::Float(text.parse().map_err(|_| {
+                        ExpressionError::new(start, format!("invalid number {}", text))
+                    })?)
+                } else {
+                    Token::Int(text.parse().map_err(|_| {
+                        ExpressionError::new(start, format!("integer {} is too large", text))
+                    })?)
+                };
+            res.push((token, start));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            res.push((Token::Ident(chars[start..i].iter().collect()), start));
+        } else if c == '\'' || c == '"' || c == '`' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(ExpressionError::new(
+                            start,
+                            "unterminated quote".to_string(),
+                        ))
+                    }
+                    Some('\\') if c != '`' && i + 1 < chars.len() => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    // a doubled quote stands for the quote itself
+                    Some(q) if *q == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some(q) if *q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(other) => {
+                        text.push(*other);
+                        i += 1;
+                    }
+                }
+            }
+            let token = if c == '`' {
+                Token::QuotedIdent(text)
+            } else {
+                Token::Str(text)
+            };
+            res.push((token, start));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(symbol) => {
+                    i += symbol.chars().count();
+                    res.push((Token::Symbol(symbol), start));
+                }
+                None => {
+                    return Err(ExpressionError::new(
+                        start,
+                        format!("unexpected character '{}'", c),
+                    ))
+                }
+            }
+        }
+    }
+    res.push((Token::End, chars.len()));
+    Ok(res)
+}
+
+/// A recursive descent parser. From the lowest to the highest precedence:
+///
+/// ```text
+/// expr       := or [AS name]
+/// or         := and (OR and)*
+/// and        := not (AND not)*
+/// not        := NOT not | comparison
+/// comparison := additive [(= | == | != | <> | < | <= | > | >=) additive]
+/// additive   := term ((+ | -) term)*
+/// term       := unary ((* | / | %) unary)*
+/// unary      := - unary | postfix
+/// postfix    := primary ([expr] | .str[expr])*
+/// primary    := literal | name(args) | name(.name)* | `quoted name` | (expr)
+/// ```
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        &self.tokens[(self.pos + offset).min(self.tokens.len() - 1)].0
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ExpressionError> {
+        Err(ExpressionError::new(self.position(), message))
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, ExpressionError> {
+        match self.peek() {
+            Token::End => self.error(format!("expected {} but the expression ended", expected)),
+            token => self.error(format!(
+                "expected {} but found {}",
+                expected,
+                describe(token)
+            )),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Token::Symbol(s) if *s == symbol) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), ExpressionError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            self.unexpected(&format!("'{}'", symbol))
+        }
+    }
+
+    fn expression(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let child = self.or()?;
+        if self.is_keyword("as") {
+            self.advance();
+            let name = match self.peek().clone() {
+                Token::Ident(name) | Token::QuotedIdent(name) => name,
+                _ => return self.unexpected("an alias"),
+            };
+            self.advance();
+            return Ok(ExpressionNode::Alias {
+                child: Box::new(child),
+                name,
+            });
+        }
+        Ok(child)
+    }
+
+    fn or(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let mut left = self.and()?;
+        while self.is_keyword("or") {
+            self.advance();
+            left = function("or", vec![left, self.and()?]);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let mut left = self.not()?;
+        while self.is_keyword("and") {
+            self.advance();
+            left = function("and", vec![left, self.not()?]);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        if self.is_keyword("not") {
+            self.advance();
+            return Ok(function("not", vec![self.not()?]));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let left = self.additive()?;
+        let op = match self.peek() {
+            Token::Symbol("=") | Token::Symbol("==") => "=",
+            Token::Symbol("!=") | Token::Symbol("<>") => "<>",
+            Token::Symbol(s @ ("<" | "<=" | ">" | ">=")) => s,
+            _ => return Ok(left),
+        };
+        self.advance();
+        Ok(function(op, vec![left, self.additive()?]))
+    }
+
+    fn additive(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let mut left = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol(s @ ("+" | "-")) => *s,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = function(op, vec![left, self.term()?]);
+        }
+    }
+
+    fn term(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol(s @ ("*" | "/" | "%")) => *s,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = function(op, vec![left, self.unary()?]);
+        }
+    }
+
+    fn unary(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        if self.eat_symbol("-") {
+            return Ok(match self.unary()? {
+                ExpressionNode::Constant(FeatureValue::Int(v)) => {
+                    ExpressionNode::Constant(FeatureValue::Int(-v))
+                }
+                ExpressionNode::Constant(FeatureValue::Float(v)) => {
+                    ExpressionNode::Constant(FeatureValue::Float(-v))
+                }
+                child => function("neg", vec![child]),
+            });
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let mut node = self.primary()?;
+        loop {
+            // `.str[i]` indexes list-like values, as the pandas accessor does
+            let accessor = matches!(
+                (self.peek(), self.peek_at(1), self.peek_at(2)),
+                (Token::Symbol("."), Token::Ident(s), Token::Symbol("[")) if s == "str"
+            );
+            if accessor {
+                self.advance();
+                self.advance();
+            } else if !matches!(self.peek(), Token::Symbol("[")) {
+                return Ok(node);
+            }
+            self.expect_symbol("[")?;
+            let index = self.expression()?;
+            self.expect_symbol("]")?;
+            node = ExpressionNode::Index {
+                target: Box::new(node),
+                index: Box::new(index),
+            };
+        }
+    }
+
+    fn primary(&mut self) -> Result<ExpressionNode, ExpressionError> {
+        let node = match self.peek().clone() {
+            Token::Int(v) => ExpressionNode::Constant(FeatureValue::Int(v)),
+            Token::Float(v) => ExpressionNode::Constant(FeatureValue::Float(v)),
+            Token::Str(v) => ExpressionNode::Constant(FeatureValue::String(v)),
+            Token::QuotedIdent(name) => ExpressionNode::Column(name),
+            Token::Symbol("(") => {
+                self.advance();
+                let node = self.or()?;
+                self.expect_symbol(")")?;
+                return Ok(node);
+            }
+            Token::Ident(name) => return self.name(name),
+            _ => return self.unexpected("a value"),
+        };
+        self.advance();
+        Ok(node)
+    }
+
+    /// Parses a keyword literal, a function call or a column name. Dotted names such as
+    /// `u.name` are kept as one column, as in the columns returned by Cypher queries.
+    fn name(&mut self, name: String) -> Result<ExpressionNode, ExpressionError> {
+        let reserved = ["and", "or", "not", "as"];
+        if reserved.iter().any(|r| name.eq_ignore_ascii_case(r)) {
+            return self.unexpected("a value");
+        }
+        self.advance();
+        match name.to_lowercase().as_str() {
+            "true" => return Ok(ExpressionNode::Constant(FeatureValue::Boolean(true))),
+            "false" => return Ok(ExpressionNode::Constant(FeatureValue::Boolean(false))),
+            "null" => return Ok(ExpressionNode::Constant(FeatureValue::Null)),
+            _ => {}
+        }
+        if self.eat_symbol("(") {
+            let mut children = Vec::new();
+            if matches!(self.peek(), Token::Symbol("*")) && self.peek_at(1) == &Token::Symbol(")") {
+                // count(*)
+                self.advance();
+            } else if !matches!(self.peek(), Token::Symbol(")")) {
+                loop {
+                    children.push(self.or()?);
+                    if !self.eat_symbol(",") {
+                        break;
+                    }
+                }
+            }
+            self.expect_symbol(")")?;
+            return Ok(function(&name.to_lowercase(), children));
+        }
+        let mut column = name;
+        while matches!(self.peek(), Token::Symbol("."))
+            && !matches!(
+                (self.peek_at(1), self.peek_at(2)),
+                (Token::Ident(s), Token::Symbol("[")) if s == "str"
+            )
+        {
+            self.advance();
+            match self.peek().clone() {
+                Token::Ident(part) | Token::QuotedIdent(part) => {
+                    column.push('.');
+                    column.push_str(&part);
+                }
+                _ => return self.unexpected("a name after '.'"),
+            }
+            self.advance();
+        }
+        Ok(ExpressionNode::Column(column))
+    }
+}
+
+fn function(op: &str, children: Vec<ExpressionNode>) -> ExpressionNode {
+    ExpressionNode::Function(FunctionNode {
+        children,
+        op: op.to_string(),
+    })
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(s) => format!("'{}'", s),
+        Token::QuotedIdent(s) => format!("`{}`", s),
+        Token::Int(v) => v.to_string(),
+        Token::Float(v) => v.to_string(),
+        Token::Str(s) => format!("{:?}", s),
+        Token::Symbol(s) => format!("'{}'", s),
+        Token::End => "the end".to_string(),
+    }
+}
+
+pub(super) fn parse(expr: &str) -> Result<ExpressionNode, ExpressionError> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+    };
+    let res = parser.expression()?;
+    if parser.peek() != &Token::End {
+        return parser.unexpected("an operator");
+    }
+    Ok(res)
+}
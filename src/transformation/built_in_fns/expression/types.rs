@@ -0,0 +1,322 @@
+use super::{ExpressionNode, FunctionNode};
+use crate::FeatureValueType;
+use std::collections::HashMap;
+use std::error::Error;
+
+use FeatureValueType::*;
+
+pub(super) type Columns = HashMap<std::string::String, FeatureValueType>;
+
+/// The type of a subexpression. `None` is the type of `null`, which is compatible with all types.
+type Inferred = Option<FeatureValueType>;
+
+fn is_numeric(t: &FeatureValueType) -> bool {
+    matches!(t, Int | Float)
+}
+
+fn is_ordered(t: &FeatureValueType) -> bool {
+    matches!(
+        t,
+        Int | Float | String | Boolean | Date | Time | DateTime | Duration
+    )
+}
+
+/// Returns the type holding values of both types. Int and Float give Float, and arrays unify
+/// their elements.
+fn unify(a: &Inferred, b: &Inferred) -> Option<Inferred> {
+    match (a, b) {
+        (None, t) | (t, None) => Some(t.clone()),
+        (Some(a), Some(b)) if a == b => Some(Some(a.clone())),
+        (Some(Int), Some(Float)) | (Some(Float), Some(Int)) => Some(Some(Float)),
+        (Some(Array(a)), Some(Array(b))) => {
+            unify(&Some(*a.clone()), &Some(*b.clone())).map(|t| t.map(|t| Array(Box::new(t))))
+        }
+        _ => None,
+    }
+}
+
+fn describe(t: &Inferred) -> std::string::String {
+    match t {
+        Some(t) => format!("{:?}", t),
+        None => "Null".to_string(),
+    }
+}
+
+fn mismatch(func: &FunctionNode, types: &[Inferred]) -> Box<dyn Error> {
+    let types: Vec<_> = types.iter().map(describe).collect();
+    format!("{} cannot be applied to {}", func.op, types.join(", ")).into()
+}
+
+/// Result types of the arithmetic operators, including date arithmetic with durations
+fn arithmetic(op: &str, left: &Inferred, right: &Inferred) -> Option<Inferred> {
+    let (l, r) = match (left, right) {
+        (Some(l), Some(r)) => (l, r),
+        // null propagates, keeping the type of the other operand when it is an operand type
+        (Some(t), None) | (None, Some(t)) => {
+            return (is_numeric(t) || matches!(t, Duration) || op == "+").then(|| Some(t.clone()))
+        }
+        (None, None) => return Some(None),
+    };
+    let res = match (op, l, r) {
+        (_, Int, Int) => Int,
+        (_, l, r) if is_numeric(l) && is_numeric(r) => Float,
+        ("+", String, String) => String,
+        ("+", Array(_), Array(_)) => return unify(left, right),
+        ("+", Date | Time | DateTime, Duration) | ("-", Date | Time | DateTime, Duration) => {
+            l.clone()
+        }
+        ("+", Duration, Date | Time | DateTime) => r.clone(),
+        ("+" | "-", Duration, Duration) => Duration,
+        ("-", Date, Date) | ("-", Time, Time) | ("-", DateTime, DateTime) => Duration,
+        ("*", Duration, r) | ("/", Duration, r) if is_numeric(r) => Duration,
+        ("*", l, Duration) if is_numeric(l) => Duration,
+        _ => return None,
+    };
+    Some(Some(res))
+}
+
+fn function(func: &FunctionNode, columns: &Columns) -> Result<Inferred, Box<dyn Error>> {
+    let types = func
+        .children
+        .iter()
+        .map(|child| infer(child, columns))
+        .collect::<Result<Vec<_>, _>>()?;
+    let arity = |n: usize| -> Result<(), Box<dyn Error>> {
+        if types.len() == n {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} takes {} argument(s) but {} were given",
+                func.op,
+                n,
+                types.len()
+            )
+            .into())
+        }
+    };
+    let op = func.op.as_str();
+    let res = match op {
+        "+" | "-" | "*" | "/" | "%" => {
+            arity(2)?;
+            arithmetic(op, &types[0], &types[1])
+        }
+        "neg" => {
+            arity(1)?;
+            match &types[0] {
+                Some(t) if is_numeric(t) || matches!(t, Duration) => Some(types[0].clone()),
+                None => Some(None),
+                _ => None,
+            }
+        }
+        "=" | "<>" => {
+            arity(2)?;
+            unify(&types[0], &types[1]).map(|_| Some(Boolean))
+        }
+        "<" | "<=" | ">" | ">=" => {
+            arity(2)?;
+            unify(&types[0], &types[1])
+                .filter(|t| t.as_ref().is_none_or(is_ordered))
+                .map(|_| Some(Boolean))
+        }
+        "and" | "or" | "not" | "xor" => {
+            arity(if op == "not" { 1 } else { 2 })?;
+            types
+                .iter()
+                .all(|t| matches!(t, None | Some(Boolean)))
+                .then_some(Some(Boolean))
+        }
+        "isnull" | "isnotnull" => {
+            arity(1)?;
+            Some(Some(Boolean))
+        }
+        "coalesce" => types
+            .iter()
+            .try_fold(None, |acc: Inferred, t| unify(&acc, t)),
+        // aggregates
+        "count" => Some(Some(Int)),
+        "sum" => {
+            arity(1)?;
+            match &types[0] {
+                Some(t) if is_numeric(t) || matches!(t, Duration) => Some(types[0].clone()),
+                _ => None,
+            }
+        }
+        "avg" | "mean" => {
+            arity(1)?;
+            match &types[0] {
+                Some(t) if is_numeric(t) => Some(Some(Float)),
+                Some(Duration) => Some(Some(Duration)),
+                _ => None,
+            }
+        }
+        "std" | "stddev" | "var" | "variance" => {
+            arity(1)?;
+            types[0]
+                .as_ref()
+                .filter(|t| is_numeric(t))
+                .map(|_| Some(Float))
+        }
+        "min" | "max" => {
+            arity(1)?;
+            types[0]
+                .as_ref()
+                .filter(|t| is_ordered(t))
+                .map(|t| Some(t.clone()))
+        }
+        "collect" => {
+            arity(1)?;
+            Some(Some(Array(Box::new(types[0].clone().unwrap_or(String)))))
+        }
+        // strings
+        "lower" | "upper" | "trim" | "ltrim" | "rtrim" | "reverse" => {
+            arity(1)?;
+            matches!(types[0], None | Some(String)).then_some(Some(String))
+        }
+        "substring" => {
+            if types.len() != 2 {
+                arity(3)?;
+            }
+            (matches!(types[0], None | Some(String))
+                && types[1..].iter().all(|t| matches!(t, None | Some(Int))))
+            .then_some(Some(String))
+        }
+        "replace" => {
+            arity(3)?;
+            types
+                .iter()
+                .all(|t| matches!(t, None | Some(String)))
+                .then_some(Some(String))
+        }
+        "concat" => types
+            .iter()
+            .all(|t| matches!(t, None | Some(String)))
+            .then_some(Some(String)),
+        "split" => {
+            arity(2)?;
+            types
+                .iter()
+                .all(|t| matches!(t, None | Some(String)))
+                .then_some(Some(Array(Box::new(String))))
+        }
+        "startswith" | "endswith" | "contains" => {
+            arity(2)?;
+            types
+                .iter()
+                .all(|t| matches!(t, None | Some(String)))
+                .then_some(Some(Boolean))
+        }
+        "length" | "size" => {
+            arity(1)?;
+            matches!(types[0], None | Some(String) | Some(Array(_))).then_some(Some(Int))
+        }
+        // conversions
+        "tostring" => {
+            arity(1)?;
+            Some(Some(String))
+        }
+        "tointeger" | "toint" => {
+            arity(1)?;
+            matches!(types[0], None | Some(Int | Float | String | Boolean)).then_some(Some(Int))
+        }
+        "tofloat" => {
+            arity(1)?;
+            matches!(types[0], None | Some(Int | Float | String)).then_some(Some(Float))
+        }
+        "toboolean" => {
+            arity(1)?;
+            matches!(types[0], None | Some(Boolean | String | Int)).then_some(Some(Boolean))
+        }
+        // numbers
+        "abs" => {
+            arity(1)?;
+            match &types[0] {
+                Some(t) if is_numeric(t) || matches!(t, Duration) => Some(types[0].clone()),
+                None => Some(None),
+                _ => None,
+            }
+        }
+        "sign" => {
+            arity(1)?;
+            types[0]
+                .as_ref()
+                .is_none_or(is_numeric)
+                .then_some(Some(Int))
+        }
+        "ceil" | "floor" | "round" | "sqrt" | "exp" | "log" | "ln" | "log10" => {
+            arity(1)?;
+            types[0]
+                .as_ref()
+                .is_none_or(is_numeric)
+                .then_some(Some(Float))
+        }
+        "pow" | "power" => {
+            arity(2)?;
+            types
+                .iter()
+                .all(|t| t.as_ref().is_none_or(is_numeric))
+                .then_some(Some(Float))
+        }
+        // dates and times
+        "year" | "month" | "day" | "dayofweek" => {
+            arity(1)?;
+            matches!(types[0], None | Some(Date | DateTime)).then_some(Some(Int))
+        }
+        "hour" | "minute" | "second" => {
+            arity(1)?;
+            matches!(types[0], None | Some(Time | DateTime)).then_some(Some(Int))
+        }
+        "date" => {
+            arity(1)?;
+            matches!(types[0], None | Some(Date | DateTime | String)).then_some(Some(Date))
+        }
+        "datetime" => {
+            arity(1)?;
+            matches!(types[0], None | Some(DateTime | Date | String | Int))
+                .then_some(Some(DateTime))
+        }
+        "duration" => {
+            arity(1)?;
+            matches!(types[0], None | Some(Int)).then_some(Some(Duration))
+        }
+        _ => return Err(format!("unknown function {}", func.op).into()),
+    };
+    res.ok_or_else(|| mismatch(func, &types))
+}
+
+pub(super) fn infer(node: &ExpressionNode, columns: &Columns) -> Result<Inferred, Box<dyn Error>> {
+    match node {
+        ExpressionNode::Column(name) => match columns.get(name) {
+            Some(t) => Ok(Some(t.clone())),
+            None => {
+                let mut known: Vec<_> = columns.keys().map(|k| k.as_str()).collect();
+                known.sort_unstable();
+                Err(format!("unknown column {} (columns are {})", name, known.join(", ")).into())
+            }
+        },
+        ExpressionNode::Constant(v) => Ok(v.value_type()),
+        ExpressionNode::Function(func) => function(func, columns),
+        ExpressionNode::Index { target, index } => {
+            let target_type = infer(target, columns)?;
+            let index_type = infer(index, columns)?;
+            if !matches!(index_type, None | Some(Int)) {
+                return Err(format!("index {} is not an Int", index).into());
+            }
+            match target_type {
+                Some(Array(element_type)) => Ok(Some(*element_type)),
+                Some(String) => Ok(Some(String)),
+                None => Ok(None),
+                Some(t) => Err(format!("{} of type {:?} cannot be indexed", target, t).into()),
+            }
+        }
+        ExpressionNode::Alias { child, .. } => infer(child, columns),
+    }
+}
+
+/// Infers the type of the expression over columns of the given types. A `null` expression is
+/// typed as String.
+pub(super) fn infer_type(
+    node: &ExpressionNode,
+    columns: &Columns,
+) -> Result<FeatureValueType, Box<dyn Error>> {
+    Ok(infer(node, columns)?.unwrap_or(String))
+}
@@ -1,3 +1,256 @@
+use crate::{Direction, FeatureValue};
 use serde::{Deserialize, Serialize};
-#[derive(Serialize, Deserialize)]
-pub struct SamplingSpec {}
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// How the neighbors of a vertex are picked
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingMethod {
+    /// Every neighbor is equally likely
+    Uniform,
+    /// Neighbors are picked with a probability proportional to a numeric feature of the edge they
+    /// are reached through. Edges with a null, zero or negative weight are never picked.
+    Weighted { weight: String },
+    /// The neighbors reached through the edges with the largest values of a feature, ties going
+    /// to the earliest edges. The picks do not depend on the seed or on replacement.
+    TopK { feature: String },
+    /// Every neighbor is kept with the given probability, from a draw fixed by the seed and the
+    /// neighbor alone, so the picks of a neighbor do not depend on the other candidates. The
+    /// fanout does not bound the picks.
+    Bernoulli { probability: f64 },
+}
+
+/// Describes how to sample neighbors. Samples are drawn from a generator seeded by `seed`, so
+/// the same spec over the same graph samples the same neighbors.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SamplingSpec {
+    pub method: SamplingMethod,
+    /// Whether a neighbor can be picked more than once
+    #[serde(default)]
+    pub replace: bool,
+    #[serde(default)]
+    pub seed: u64,
+    /// Samples the neighbors at the source of the incoming edges (In), at the target of the
+    /// outgoing edges (Out), or both
+    pub direction: Direction,
+}
+
+impl SamplingSpec {
+    /// Samples neighbors uniformly along incoming edges, without replacement
+    pub fn uniform() -> Self {
+        SamplingSpec {
+            method: SamplingMethod::Uniform,
+            replace: false,
+            seed: 0,
+            direction: Direction::In,
+        }
+    }
+
+    /// Samples neighbors in proportion to the given edge feature
+    pub fn weighted(weight: &str) -> Self {
+        SamplingSpec {
+            method: SamplingMethod::Weighted {
+                weight: weight.to_string(),
+            },
+            ..Self::uniform()
+        }
+    }
+
+    /// Picks the neighbors with the largest values of the given edge feature
+    pub fn top_k(feature: &str) -> Self {
+        SamplingSpec {
+            method: SamplingMethod::TopK {
+                feature: feature.to_string(),
+            },
+            ..Self::uniform()
+        }
+    }
+
+    /// Keeps each neighbor with the given probability
+    pub fn bernoulli(probability: f64) -> Self {
+        SamplingSpec {
+            method: SamplingMethod::Bernoulli { probability },
+            ..Self::uniform()
+        }
+    }
+
+    /// Samples with replacement
+    pub fn with_replacement(self) -> Self {
+        SamplingSpec {
+            replace: true,
+            ..self
+        }
+    }
+
+    /// Seeds the generator the samples are drawn from
+    pub fn seeded(self, seed: u64) -> Self {
+        SamplingSpec { seed, ..self }
+    }
+
+    /// Samples the neighbors in the given direction
+    pub fn along(self, direction: Direction) -> Self {
+        SamplingSpec { direction, ..self }
+    }
+
+    /// Whether each element is kept independently of the others, so that filtering before or
+    /// after sampling selects the same elements
+    pub fn commutes_with_filter(&self) -> bool {
+        // the other methods pick a fixed number of elements among the candidates, so filtering
+        // the candidates changes the picks
+        matches!(self.method, SamplingMethod::Bernoulli { .. })
+    }
+
+    /// The edge feature the method reads, if any
+    pub(crate) fn feature(&self) -> Option<&str> {
+        match &self.method {
+            SamplingMethod::Uniform | SamplingMethod::Bernoulli { .. } => None,
+            SamplingMethod::Weighted { weight } => Some(weight),
+            SamplingMethod::TopK { feature } => Some(feature),
+        }
+    }
+
+    /// Picks up to `fanout` of the candidates, given the id of each candidate and the value of the
+    /// feature of the method for it. Returns the indices of the picked candidates, in the order
+    /// they are picked. Without replacement, all the candidates are picked when there are no more
+    /// than `fanout`.
+    pub(crate) fn pick(
+        &self,
+        ids: &[usize],
+        features: &[FeatureValue],
+        fanout: usize,
+        rng: &mut SplitMix64,
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        let n = features.len();
+        match &self.method {
+            SamplingMethod::Bernoulli { probability } => {
+                if !(0.0..=1.0).contains(probability) {
+                    return Err(format!(
+                        "expected a sampling probability between 0 and 1 but found {}",
+                        probability
+                    )
+                    .into());
+                }
+                let seed = self.seed;
+                Ok((0..ids.len())
+                    .filter(|i| {
+                        let key = SplitMix64::new(ids[*i] as u64).next_u64();
+                        SplitMix64::new(seed ^ key).next_f64() < *probability
+                    })
+                    .collect())
+            }
+            SamplingMethod::TopK { .. } => {
+                let mut order: Vec<usize> = (0..n).filter(|i| !features[*i].is_null()).collect();
+                // a stable sort keeps the earliest edges first among ties
+                order.sort_by(|a, b| {
+                    features[*b]
+                        .compare(&features[*a])
+                        .unwrap_or(Ordering::Equal)
+                });
+                order.truncate(fanout);
+                Ok(order)
+            }
+            SamplingMethod::Uniform if self.replace => Ok(match n {
+                0 => Vec::new(),
+                n => (0..fanout).map(|_| rng.below(n)).collect(),
+            }),
+            SamplingMethod::Uniform => {
+                // a partial Fisher-Yates shuffle
+                let mut order: Vec<usize> = (0..n).collect();
+                let picks = fanout.min(n);
+                for i in 0..picks {
+                    order.swap(i, i + rng.below(n - i));
+                }
+                order.truncate(picks);
+                Ok(order)
+            }
+            SamplingMethod::Weighted { .. } => {
+                let mut weights = features
+                    .iter()
+                    .map(|w| match w {
+                        FeatureValue::Null => Ok(0.0),
+                        FeatureValue::Int(w) => Ok((*w as f64).max(0.0)),
+                        FeatureValue::Float(w) => Ok(w.max(0.0)),
+                        w => Err(format!("expected a numeric weight but found {:?}", w)),
+                    })
+                    .collect::<Result<Vec<f64>, _>>()?;
+                let mut res = Vec::new();
+                while res.len() < fanout {
+                    let total: f64 = weights.iter().sum();
+                    if total <= 0.0 {
+                        break;
+                    }
+                    let mut target = rng.next_f64() * total;
+                    // the last candidate with a weight absorbs rounding errors
+                    let mut picked = weights.iter().rposition(|w| *w > 0.0).unwrap_or(0);
+                    for (i, w) in weights.iter().enumerate() {
+                        if *w > 0.0 && target < *w {
+                            picked = i;
+                            break;
+                        }
+                        target -= w;
+                    }
+                    res.push(picked);
+                    if !self.replace {
+                        weights[picked] = 0.0;
+                    }
+                }
+                Ok(res)
+            }
+        }
+    }
+}
+
+impl Display for SamplingSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.method {
+            SamplingMethod::Uniform => write!(f, "uniform")?,
+            SamplingMethod::Weighted { weight } => write!(f, "weighted by {}", weight)?,
+            SamplingMethod::TopK { feature } => write!(f, "top k by {}", feature)?,
+            SamplingMethod::Bernoulli { probability } => {
+                write!(f, "bernoulli with probability {}", probability)?
+            }
+        }
+        if self.replace {
+            write!(f, " with replacement")?;
+        }
+        write!(f, " along {:?} seeded {}", self.direction, self.seed)
+    }
+}
+
+/// One hop of a neighbor sampling: up to `fanout` neighbors of each vertex reached by the
+/// previous hop, through edges of the given type or of all types if None
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SamplingHop {
+    pub fanout: usize,
+    pub edge_type: Option<String>,
+}
+
+/// The SplitMix64 generator, a small generator whose sequence is fixed by its seed, so that
+/// samples stay reproducible across versions of the crate and its dependencies
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number uniformly distributed in [0, 1)
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number uniformly distributed in [0, n), for n > 0
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
@@ -1,19 +1,47 @@
-use crate::{Column, FeatureValueType, ResourceOp, Topology, TopologyType};
+mod query;
+
+use crate::{
+    observed_types, Column, Entity, FeatureValue, FeatureValueType, GraphDatabase, GraphRows,
+    GraphValue, ResourceId, ResourceOp, Topology, TopologyType,
+};
 
 use super::{
-    DataFrame, DataIdT, DataTransformationContext, EdgeSelectGraph, GraphBase,
-    InnerTransformationData, Selector, TransformationData, VertexSelectGraph,
+    dataframe::decode_value,
+    graph::{CypherSource, DataFrameSet},
+    CypherQuery, DataFrame, DataIdT, DataTransformationContext, EdgeSelectGraph, GraphBase,
+    InnerTransformationData, LocalColumn, LocalData, LocalExecutor, LocalFrame, Selector,
+    TransformationData, VertexSelectGraph,
 };
+use log::warn;
 use serde::{Deserialize, Serialize};
+use std::any::Any;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
 use std::rc::Rc;
 
+pub(crate) use query::{graph_schema, return_columns, GraphSchema};
+
 pub trait CypherTransformation {
-    fn cypher_to_graph(&self, query: &str) -> Rc<dyn GraphBase>;
-    fn cypher_to_dataframe(&self, query: &str) -> Rc<DataFrame>;
+    /// Returns the graph of the vertices, edges and paths returned by a query. See
+    /// `CypherResultGraph` for how the rows are read.
+    fn cypher_to_graph(&self, query: &str) -> Rc<CypherResultGraph>;
+
+    /// Returns the rows of a query as a DataFrame with a column per item of its last `RETURN`.
+    /// The query reads its parameters as `$name`, and the columns are typed from the declared
+    /// schema, which `infer_cypher_schema` can discover from a sample of the rows.
+    fn cypher_to_dataframe(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+        schema: &HashMap<String, FeatureValueType>,
+    ) -> Result<Rc<DataFrame>, Box<dyn Error>>;
 }
 
+/// The graph returned by a Cypher query. The vertices are the nodes returned as columns, in lists
+/// or in paths, and the edges are the returned relationships and the relationships of the paths.
+/// A query returning no relationships, whose first column is a node, derives an edge type per
+/// other column of nodes, such as the edges from `u` to each `v` of `RETURN u, collect(v)`.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CypherResultGraph {
     pub(super) context: DataTransformationContext,
@@ -25,7 +53,7 @@ impl<T> CypherTransformation for T
 where
     T: GraphBase + TransformationData,
 {
-    fn cypher_to_graph(&self, query: &str) -> Rc<dyn GraphBase> {
+    fn cypher_to_graph(&self, query: &str) -> Rc<CypherResultGraph> {
         let res = Rc::new(CypherResultGraph {
             context: self.get_context().new_data_context(),
             graph: self.get_data_id(),
@@ -36,26 +64,59 @@ where
         res
     }
 
-    #[allow(unused)]
-    fn cypher_to_dataframe(&self, query: &str) -> Rc<DataFrame> {
-        let new_data_context = self.get_context().new_data_context();
-        let name = format!("cypher_to_dataframe{}", new_data_context.id);
-        // FIXME(tatiana): hard code for now to test transformation flow
+    fn cypher_to_dataframe(
+        &self,
+        query: &str,
+        params: HashMap<String, FeatureValue>,
+        schema: &HashMap<String, FeatureValueType>,
+    ) -> Result<Rc<DataFrame>, Box<dyn Error>> {
+        let columns = return_columns(query).ok_or_else(|| {
+            format!(
+                "cannot find the columns of {:?}: name the items of its last RETURN",
+                query
+            )
+        })?;
+        let mut typed: Vec<(String, FeatureValueType)> = Vec::with_capacity(columns.len());
+        for name in columns {
+            if typed.iter().any(|(c, _)| *c == name) {
+                return Err(format!("the query returns column {} twice", name).into());
+            }
+            let value_type = schema
+                .get(&name)
+                .ok_or_else(|| format!("no type is declared for column {} of the query", name))?;
+            typed.push((name, value_type.clone()));
+        }
+        if let Some(name) = schema.keys().find(|k| typed.iter().all(|(c, _)| c != *k)) {
+            return Err(format!("the query does not return the declared column {}", name).into());
+        }
+        let result = Rc::new(CypherResultDataFrame {
+            query: query.to_string(),
+            context: self.get_context().new_data_context(),
+            graph: self.get_data_id(),
+            params,
+            schema: typed,
+        });
+        self.get_context().register_data(&result);
+
+        // the DataFrame copies the columns of the query result
         let res = Rc::new(DataFrame {
-            context: new_data_context,
-            name,
-            schema: vec![
-                Rc::new(Column::new(self.get_data_id(), FeatureValueType::String)),
-                Rc::new(Column::new(
-                    self.get_data_id(),
-                    FeatureValueType::Array(Box::new(FeatureValueType::Float)),
-                )),
-            ],
-            col_names: vec!["user".to_string(), "dims".to_string()],
-            col_by_names: HashMap::new(),
+            context: self.get_context().new_data_context(),
+            name: format!("cypher_to_dataframe{}", result.get_data_id()),
+            schema: result
+                .schema
+                .iter()
+                .map(|(_, t)| Rc::new(Column::new(result.get_data_id(), t.clone())))
+                .collect(),
+            col_names: result.schema.iter().map(|(name, _)| name.clone()).collect(),
+            col_by_names: result
+                .schema
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.clone(), i))
+                .collect(),
         });
         self.get_context().register_data(&res);
-        res
+        Ok(res)
     }
 }
 
@@ -64,32 +125,231 @@ impl TransformationData for CypherResultGraph {
     fn get_context(&self) -> &DataTransformationContext {
         &self.context
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        format!("cypher_to_graph({:?})", self.query)
+    }
+
+    fn inputs(&self) -> Vec<DataIdT> {
+        vec![self.graph]
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        Some(Rc::new(CypherResultGraph {
+            context: self.context.clone(),
+            graph: inputs.get(&self.graph).copied().unwrap_or(self.graph),
+            query: self.query.clone(),
+        }))
+    }
+
+    fn execute_local(&self, _executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        Err(format!(
+            "data {} runs a Cypher query, which needs a graph database",
+            self.context.id
+        )
+        .into())
+    }
+
+    fn compile_cypher(&self, _executor: &LocalExecutor) -> Result<CypherQuery, Box<dyn Error>> {
+        Ok(CypherQuery {
+            text: self.query.clone(),
+            params: HashMap::new(),
+            complete: true,
+        })
+    }
+
+    /// Collects the types of the returned vertices and edges, and the derived edge types that
+    /// have an edge in the rows
+    fn decode_cypher_rows(
+        &self,
+        rows: GraphRows,
+        _executor: &LocalExecutor,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        fn collect_types(
+            value: &GraphValue,
+            vertex_types: &mut BTreeSet<String>,
+            edge_types: &mut BTreeSet<String>,
+        ) {
+            match value {
+                GraphValue::Node(node) => vertex_types.extend(node.labels.iter().take(1).cloned()),
+                GraphValue::Relationship(rel) => {
+                    edge_types.insert(rel.tlabel.clone());
+                }
+                GraphValue::Path(path) => {
+                    vertex_types
+                        .extend(path.nodes.iter().filter_map(|n| n.labels.first().cloned()));
+                    edge_types.extend(path.relationships.iter().map(|r| r.tlabel.clone()));
+                }
+                GraphValue::List(values) => values
+                    .iter()
+                    .for_each(|v| collect_types(v, vertex_types, edge_types)),
+                GraphValue::Value(_) => {}
+            }
+        }
+        let has_node = |value: Option<&GraphValue>| match value {
+            Some(GraphValue::Node(_)) => true,
+            Some(GraphValue::List(values)) => {
+                values.iter().any(|v| matches!(v, GraphValue::Node(_)))
+            }
+            _ => false,
+        };
+        let schema = self.schema();
+        let mut vertex_types = BTreeSet::new();
+        let mut edge_types = BTreeSet::new();
+        for (i, row) in rows.rows.iter().enumerate() {
+            row.iter()
+                .for_each(|v| collect_types(v, &mut vertex_types, &mut edge_types));
+            for derived in &schema.derived_edges {
+                if has_node(rows.get(i, &derived.source)) && has_node(rows.get(i, &derived.target))
+                {
+                    edge_types.insert(derived.tlabel.clone());
+                }
+            }
+        }
+        Ok(LocalData::Graph {
+            vertex_types: vertex_types.into_iter().collect(),
+            edge_types: edge_types.into_iter().collect(),
+        })
+    }
+}
+
+impl CypherResultGraph {
+    pub(super) fn schema(&self) -> GraphSchema {
+        graph_schema(&self.query)
+    }
+
+    /// The edge entities of the derived edge types whose endpoint labels are given by the
+    /// patterns of the query, to be registered with the exported topology
+    pub fn derived_edge_entities(&self) -> Vec<Entity> {
+        self.schema()
+            .derived_edges
+            .iter()
+            .filter_map(|derived| {
+                let endpoint = |t: &Option<String>| {
+                    t.as_ref()
+                        .map(|t| Entity::new_node_entity(t, None, t, "id"))
+                };
+                match (endpoint(&derived.src_type), endpoint(&derived.dst_type)) {
+                    (Some(src), Some(dst)) => Some(Entity::new_edge_entity(
+                        &derived.tlabel,
+                        None,
+                        &derived.tlabel,
+                        &src,
+                        &dst,
+                    )),
+                    _ => {
+                        warn!(
+                            "no edge entity is derived for {}, whose endpoints have no label",
+                            derived.tlabel
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Selects the returned vertices or edges in the query of the graph. Derived edges are not
+    /// in the graph database, so edge selections only read the returned relationships.
+    fn select(&self, vertices: bool, ltype: Option<&str>) -> Rc<dyn GraphBase> {
+        let schema = self.schema();
+        let context = self.context.new_data_context();
+        let df = DataFrameSet::Homo((
+            format!(
+                "cypher_to_graph{}_{}",
+                self.context.id,
+                if vertices { "vertices" } else { "edges" }
+            ),
+            Vec::new(),
+        ));
+        let selector = Selector::DirectAccess {
+            ltype: ltype.map(|t| t.to_string()),
+        };
+        let source = Some(CypherSource {
+            query: self.query.clone(),
+            columns: if vertices {
+                schema.vertex_sources
+            } else {
+                schema.edge_sources
+            },
+        });
+        if vertices {
+            let res = Rc::new(VertexSelectGraph {
+                context,
+                graph: self.context.id,
+                selector,
+                df,
+                source,
+            });
+            self.context.register_data(&res);
+            res
+        } else {
+            let res = Rc::new(EdgeSelectGraph {
+                context,
+                graph: self.context.id,
+                selector,
+                df,
+                source,
+            });
+            self.context.register_data(&res);
+            res
+        }
+    }
 }
 
 impl GraphBase for CypherResultGraph {
     fn vertices(&self) -> Rc<dyn GraphBase> {
-        todo!()
+        self.select(true, None)
     }
 
     fn edges(&self) -> Rc<dyn GraphBase> {
-        todo!()
+        self.select(false, None)
     }
 
+    /// Returns None when the patterns label all returned vertices, none with the given type
     fn vertices_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
-        todo!()
+        match self.schema().vertex_types {
+            Some(types) if !types.contains(t) => None,
+            _ => Some(self.select(true, Some(t))),
+        }
     }
 
     fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
-        todo!()
+        match self.schema().edge_types {
+            Some(types) if !types.contains(t) => None,
+            _ => Some(self.select(false, Some(t))),
+        }
     }
 
+    /// Exports the graph as an adjacency list when its edges are derived from rows of vertices.
+    /// Otherwise its edges are in the graph database and the topology has no type.
     fn export_topology(&self, name: &str) -> Topology {
+        let schema = self.schema();
+        let mut edge_entity_ids: Vec<ResourceId> = schema
+            .edge_types
+            .iter()
+            .flatten()
+            .filter(|t| schema.derived_edges.iter().all(|d| d.tlabel != **t))
+            .map(|t| format!("Entity/{}/", t))
+            .collect();
+        edge_entity_ids.extend(
+            self.derived_edge_entities()
+                .iter()
+                .map(|entity| entity.resource_id()),
+        );
         let res = Topology {
             name: name.to_string(),
             transformation_id: Some(self.get_context().get_transformation_id()),
-            // FIXME(tatiana): hard code for now
-            topology_type: Some(TopologyType::AdjacencyList),
-            edge_entity_ids: Vec::new(), // may be existing edges or a new edge type
+            topology_type: (!schema.derived_edges.is_empty())
+                .then_some(TopologyType::AdjacencyList),
+            edge_entity_ids,
             variant: None,
             description: None,
             created_at: None,
@@ -102,10 +362,55 @@ impl GraphBase for CypherResultGraph {
     }
 }
 
+/// Runs up to `sample_size` rows of a query and returns the type of each returned column, for
+/// `cypher_to_dataframe`. Columns that are always null are typed as String.
+pub async fn infer_cypher_schema(
+    database: &dyn GraphDatabase,
+    query: &str,
+    params: HashMap<String, FeatureValue>,
+    sample_size: usize,
+) -> Result<HashMap<String, FeatureValueType>, Box<dyn Error>> {
+    let rows = database
+        .run_query(
+            &format!(
+                "CALL {{ {} }} RETURN * LIMIT {}",
+                query.trim().trim_end_matches(';'),
+                sample_size
+            ),
+            params,
+        )
+        .await?;
+    let samples: Vec<HashMap<String, FeatureValue>> = rows
+        .rows
+        .iter()
+        .map(|row| {
+            rows.columns
+                .iter()
+                .cloned()
+                .zip(row.iter().map(|v| match v {
+                    GraphValue::Value(v) => v.clone(),
+                    _ => FeatureValue::Null,
+                }))
+                .collect()
+        })
+        .collect();
+    let mut res: HashMap<String, FeatureValueType> =
+        observed_types(samples.iter()).into_iter().collect();
+    for name in rows.columns {
+        res.entry(name).or_insert(FeatureValueType::String);
+    }
+    Ok(res)
+}
+
+/// The rows of a Cypher query run on the graph database
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CypherResultDataFrame {
     pub query: String,
     context: DataTransformationContext,
+    pub(super) graph: DataIdT, // graph data id
+    pub(super) params: HashMap<String, FeatureValue>,
+    /// The returned columns and their types
+    pub(super) schema: Vec<(String, FeatureValueType)>,
 }
 
 #[typetag::serde]
@@ -113,4 +418,184 @@ impl TransformationData for CypherResultDataFrame {
     fn get_context(&self) -> &DataTransformationContext {
         &self.context
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        format!("cypher_to_dataframe({:?})", self.query)
+    }
+
+    fn inputs(&self) -> Vec<DataIdT> {
+        vec![self.graph]
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        Some(Rc::new(CypherResultDataFrame {
+            query: self.query.clone(),
+            context: self.context.clone(),
+            graph: inputs.get(&self.graph).copied().unwrap_or(self.graph),
+            params: self.params.clone(),
+            schema: self.schema.clone(),
+        }))
+    }
+
+    fn execute_local(&self, _executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        Err(format!(
+            "data {} runs a Cypher query, which needs a graph database",
+            self.context.id
+        )
+        .into())
+    }
+
+    fn compile_cypher(&self, _executor: &LocalExecutor) -> Result<CypherQuery, Box<dyn Error>> {
+        Ok(CypherQuery {
+            text: self.query.clone(),
+            params: self.params.clone(),
+            complete: true,
+        })
+    }
+
+    /// Takes the declared columns from the rows by name, casting their values to the declared
+    /// types
+    fn decode_cypher_rows(
+        &self,
+        rows: GraphRows,
+        _executor: &LocalExecutor,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        let mut columns = Vec::with_capacity(self.schema.len());
+        for (name, value_type) in &self.schema {
+            let values = match rows.columns.iter().position(|c| c == name) {
+                Some(i) => rows
+                    .rows
+                    .iter()
+                    .map(|row| decode_value(row[i].clone(), value_type))
+                    .collect::<Result<_, _>>()?,
+                // a query without rows may not report its columns
+                None if rows.rows.is_empty() => Vec::new(),
+                None => {
+                    return Err(
+                        format!("data {} returned no column {}", self.context.id, name).into(),
+                    )
+                }
+            };
+            columns.push(LocalColumn {
+                name: name.clone(),
+                value_type: value_type.clone(),
+                values,
+            });
+        }
+        Ok(LocalData::Frame {
+            frame: LocalFrame { columns },
+        })
+    }
+}
+
+#[test]
+fn run_cypher_to_dataframe() {
+    use crate::{PropertyGraph, SingleGraph, TransformationContext};
+
+    let context = TransformationContext::new();
+    let graph = SingleGraph::from_property_graph(&context, &PropertyGraph::new());
+    let query = "MATCH (u:Reviewer) WHERE u.age > $min_age RETURN u.name AS user, u.age AS age";
+    let params = HashMap::from([("min_age".to_string(), FeatureValue::Int(20))]);
+    let mut schema = HashMap::from([("user".to_string(), FeatureValueType::String)]);
+    assert!(graph
+        .cypher_to_dataframe(query, params.clone(), &schema)
+        .is_err());
+    schema.insert("age".to_string(), FeatureValueType::Float);
+    let df = graph.cypher_to_dataframe(query, params, &schema).unwrap();
+    let next = df.select(vec!["age + 1 AS next_age".to_string()]).unwrap();
+    assert_eq!(df.inputs(), vec![1]);
+
+    let empty = PropertyGraph::new();
+    let mut executor = LocalExecutor::new(&empty);
+    let data = context.borrow().topological_order().unwrap();
+    assert_eq!(
+        data[1].operation(),
+        format!("cypher_to_dataframe({:?})", query)
+    );
+    executor.insert(0, data[0].execute_local(&executor).unwrap());
+    let compiled = data[1].compile_cypher(&executor).unwrap();
+    assert_eq!(compiled.text, query);
+    assert_eq!(compiled.params["min_age"], FeatureValue::Int(20));
+    let rows = GraphRows {
+        columns: vec!["user".to_string(), "age".to_string()],
+        rows: vec![
+            vec![
+                GraphValue::Value(FeatureValue::String("ann".to_string())),
+                GraphValue::Value(FeatureValue::Int(30)),
+            ],
+            vec![
+                GraphValue::Value(FeatureValue::String("bob".to_string())),
+                GraphValue::Value(FeatureValue::Null),
+            ],
+        ],
+    };
+    executor.insert(1, data[1].decode_cypher_rows(rows, &executor).unwrap());
+    executor.execute(&context.borrow()).unwrap();
+    let frame = executor
+        .result(next.get_data_id())
+        .unwrap()
+        .frame()
+        .unwrap();
+    assert_eq!(
+        frame.column("next_age").unwrap().values,
+        vec![FeatureValue::Float(31.0), FeatureValue::Null]
+    );
+}
+
+#[test]
+fn run_cypher_to_graph() {
+    use crate::{GraphNode, PropertyGraph, SingleGraph, TransformationContext};
+
+    let context = TransformationContext::new();
+    let graph = SingleGraph::from_property_graph(&context, &PropertyGraph::new());
+    let upu = graph.cypher_to_graph(
+        "MATCH (r1:Review)-[:isWrittenBy]->(u1:Reviewer) MATCH (r2:Review)-[:isWrittenBy]->(u2:Reviewer) \
+         RETURN u1, collect(u2)",
+    );
+    let topology = upu.export_topology("upu");
+    assert!(matches!(
+        topology.topology_type,
+        Some(TopologyType::AdjacencyList)
+    ));
+    let entities = upu.derived_edge_entities();
+    assert_eq!(entities[0].tlabel(), "u1_u2");
+    assert_eq!(topology.edge_entity_ids, vec![entities[0].resource_id()]);
+    assert!(upu.vertices_by_type("Review").is_none());
+    upu.vertices_by_type("Reviewer").unwrap();
+
+    let node = |id: i64| {
+        GraphValue::Node(GraphNode {
+            id,
+            labels: vec!["Reviewer".to_string()],
+            properties: HashMap::new(),
+        })
+    };
+    let rows = GraphRows {
+        columns: vec!["u1".to_string(), "collect(u2)".to_string()],
+        rows: vec![vec![node(1), GraphValue::List(vec![node(2), node(3)])]],
+    };
+    let empty = PropertyGraph::new();
+    let mut executor = LocalExecutor::new(&empty);
+    let result = upu.decode_cypher_rows(rows, &executor).unwrap();
+    assert_eq!(
+        result,
+        LocalData::Graph {
+            vertex_types: vec!["Reviewer".to_string()],
+            edge_types: vec!["u1_u2".to_string()],
+        }
+    );
+    executor.insert(upu.get_data_id(), result);
+    let data = context.borrow().get_data(2).unwrap().clone();
+    let query = data.compile_cypher(&executor).unwrap();
+    assert!(query
+        .text
+        .contains("RETURN u1, collect(u2) } UNWIND [`u1`, `collect(u2)`] AS __value"));
+    assert!(data.execute_local(&executor).is_err());
 }
@@ -0,0 +1,368 @@
+use crate::transformation::built_in_fns::quote_identifier;
+use std::collections::{BTreeSet, HashMap};
+
+/// Returns the names of the columns returned by a query: the aliases of the items of its last
+/// `RETURN`, or the items themselves. Returns None for `RETURN *` and queries without `RETURN`.
+pub(crate) fn return_columns(query: &str) -> Option<Vec<String>> {
+    Some(
+        return_items(query)?
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect(),
+    )
+}
+
+/// Returns the expression and the column name of each item of the last `RETURN` of a query
+fn return_items(query: &str) -> Option<Vec<(String, String)>> {
+    // the words and commas outside brackets and quotes, by offset
+    let mut tokens: Vec<(usize, &str)> = Vec::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut word: Option<usize> = None;
+    for (i, c) in query.char_indices() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        let in_word = c.is_alphanumeric() || c == '_';
+        if let (Some(start), false) = (word, in_word) {
+            if depth == 0 {
+                tokens.push((start, &query[start..i]));
+            }
+            word = None;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => tokens.push((i, ",")),
+            _ if in_word && word.is_none() => word = Some(i),
+            _ => {}
+        }
+    }
+    if let (Some(start), 0) = (word, depth) {
+        tokens.push((start, &query[start..]));
+    }
+    let is = |token: &str, keyword: &str| token.eq_ignore_ascii_case(keyword);
+    let ret = tokens.iter().rposition(|(_, t)| is(t, "RETURN"))?;
+    let clause = &tokens[ret + 1..];
+    let len = clause
+        .iter()
+        .position(|(_, t)| ["ORDER", "SKIP", "LIMIT", "UNION"].iter().any(|k| is(t, k)))
+        .unwrap_or(clause.len());
+    let end = clause.get(len).map_or(query.len(), |(offset, _)| *offset);
+    let clause = &clause[..len];
+    let mut start = tokens[ret].0 + "RETURN".len();
+    if let Some((offset, t)) = clause.first().filter(|(_, t)| is(t, "DISTINCT")) {
+        start = offset + t.len();
+    }
+    let mut items = Vec::new();
+    for (offset, t) in clause.iter().chain([(end, ",")].iter()) {
+        if *t != "," {
+            continue;
+        }
+        let alias = clause
+            .iter()
+            .rfind(|(o, t)| (start..*offset).contains(o) && is(t, "AS"));
+        let (expr, name) = match alias {
+            Some((o, _)) => (&query[start..*o], &query[o + 2..*offset]),
+            None => (&query[start..*offset], &query[start..*offset]),
+        };
+        let expr = expr.trim().trim_end_matches(';').trim();
+        match name.trim().trim_end_matches(';').trim() {
+            "*" | "" => return None,
+            name => items.push((expr.to_string(), name.trim_matches('`').to_string())),
+        }
+        start = offset + 1;
+    }
+    Some(items)
+}
+
+/// A variable bound in the patterns of a query
+#[derive(Debug, Clone, PartialEq)]
+enum Binding {
+    /// A vertex, with its first label if a pattern gives one
+    Node(Option<String>),
+    /// An edge or a variable-length list of edges, with its first type if a pattern gives one
+    Relationship(Option<String>),
+    Path,
+}
+
+/// Returns the variables bound by the node, relationship and path patterns of a query
+fn bindings(query: &str) -> HashMap<String, Binding> {
+    let chars: Vec<char> = query.chars().collect();
+    let is_ident = |c: &char| c.is_alphanumeric() || *c == '_';
+    let ident = |i: usize| -> (String, usize) {
+        let end = (i..chars.len())
+            .find(|j| !is_ident(&chars[*j]))
+            .unwrap_or(chars.len());
+        (chars[i..end].iter().collect(), end)
+    };
+    let skip_whitespace = |i: usize| {
+        (i..chars.len())
+            .find(|j| !chars[*j].is_whitespace())
+            .unwrap_or(chars.len())
+    };
+    // the variable and the label or type after `(` or `[`, if it starts a pattern
+    let pattern = |i: usize, ends: &[char]| -> Option<(String, Option<String>)> {
+        let (var, end) = ident(skip_whitespace(i + 1));
+        let next = skip_whitespace(end);
+        match chars.get(next) {
+            Some(':') => {
+                let (label, _) = ident(skip_whitespace(next + 1));
+                Some((var, Some(label).filter(|l| !l.is_empty())))
+            }
+            Some(c) if ends.contains(c) => Some((var, None)),
+            _ => None,
+        }
+    };
+    let mut res: HashMap<String, Binding> = HashMap::new();
+    let mut bind = |var: String, binding: Binding| {
+        if var.is_empty() {
+            return;
+        }
+        match (res.get(&var), &binding) {
+            (None, _)
+            | (Some(Binding::Node(None)), Binding::Node(Some(_)))
+            | (Some(Binding::Relationship(None)), Binding::Relationship(Some(_))) => {
+                res.insert(var, binding);
+            }
+            _ => {}
+        }
+    };
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev: Option<char> = None;
+    let mut prev_word = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            // a `(` right after a name calls a function, unless the name is a clause keyword
+            '(' if i == 0
+                || !is_ident(&chars[i - 1])
+                || ["MATCH", "MERGE", "CREATE"]
+                    .iter()
+                    .any(|k| prev_word.eq_ignore_ascii_case(k)) =>
+            {
+                if let Some((var, label)) = pattern(i, &[')', '{']) {
+                    bind(var, Binding::Node(label));
+                }
+            }
+            '[' if matches!(prev, Some('-') | Some('<')) => {
+                if let Some((var, label)) = pattern(i, &[']', '{', '*']) {
+                    bind(var, Binding::Relationship(label));
+                }
+            }
+            c if is_ident(&c) => {
+                let (word, end) = ident(i);
+                // a path is bound as `MATCH p = (...)` or `MATCH p = shortestPath(...)`
+                let next = skip_whitespace(end);
+                if chars.get(next) == Some(&'=')
+                    && (prev_word.eq_ignore_ascii_case("MATCH") || prev == Some(','))
+                {
+                    let (call, after) = ident(skip_whitespace(next + 1));
+                    let call = call.to_ascii_lowercase();
+                    if chars.get(skip_whitespace(after)) == Some(&'(')
+                        && (call.is_empty() || call == "shortestpath" || call == "allshortestpaths")
+                    {
+                        bind(word.clone(), Binding::Path);
+                    }
+                }
+                prev = Some(chars[end - 1]);
+                prev_word = word;
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        if !c.is_whitespace() {
+            prev = Some(c);
+        }
+        i += 1;
+    }
+    res
+}
+
+/// Edges derived from rows of vertices, from the vertex of one column to the vertices of another
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DerivedEdges {
+    pub(crate) tlabel: String,
+    pub(crate) source: String,
+    pub(crate) target: String,
+    /// The labels of the endpoints, if the patterns give them
+    pub(crate) src_type: Option<String>,
+    pub(crate) dst_type: Option<String>,
+}
+
+/// The vertices and edges returned by a `cypher_to_graph` query
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct GraphSchema {
+    /// Cypher expressions over the returned columns holding vertices, or lists of them
+    pub(crate) vertex_sources: Vec<String>,
+    pub(crate) edge_sources: Vec<String>,
+    /// The labels of the returned vertices, or None if the patterns do not label all of them
+    pub(crate) vertex_types: Option<BTreeSet<String>>,
+    pub(crate) edge_types: Option<BTreeSet<String>>,
+    pub(crate) derived_edges: Vec<DerivedEdges>,
+}
+
+/// Reads the vertices, edges and paths returned by a query from the variables its patterns bind.
+/// When the query returns no edges and its first column is a vertex, the edges are derived from
+/// that vertex to the vertices, or lists of vertices, of the other columns, as in
+/// `RETURN u, collect(v)`. `RETURN *` returns all bound variables.
+pub(crate) fn graph_schema(query: &str) -> GraphSchema {
+    let bindings = bindings(query);
+    let items = return_items(query).unwrap_or_else(|| {
+        let mut vars: Vec<String> = bindings.keys().cloned().collect();
+        vars.sort();
+        vars.into_iter().map(|v| (v.clone(), v)).collect()
+    });
+    let mut res = GraphSchema {
+        vertex_types: Some(BTreeSet::new()),
+        edge_types: Some(BTreeSet::new()),
+        ..Default::default()
+    };
+    let add_type = |types: &mut Option<BTreeSet<String>>, t: &Option<String>| match t {
+        Some(t) => {
+            if let Some(types) = types {
+                types.insert(t.clone());
+            }
+        }
+        None => *types = None,
+    };
+    // (column, variable, label) of the vertex columns, and whether the column is a list
+    let mut vertex_columns: Vec<(&str, &str, Option<String>, bool)> = Vec::new();
+    for (expr, name) in &items {
+        let column = quote_identifier(name);
+        let call = |function: &str| {
+            let (f, rest) = expr.split_once('(')?;
+            let rest = rest.trim().strip_suffix(')')?;
+            f.trim()
+                .eq_ignore_ascii_case(function)
+                .then_some(rest.trim())
+        };
+        let collected = call("collect").map(|inner| match inner.split_once(char::is_whitespace) {
+            Some((distinct, var)) if distinct.eq_ignore_ascii_case("DISTINCT") => var.trim(),
+            _ => inner,
+        });
+        if let Some(p) = call("nodes") {
+            if bindings.get(p) == Some(&Binding::Path) {
+                res.vertex_sources.push(column);
+                res.vertex_types = None;
+            }
+            continue;
+        }
+        if let Some(p) = call("relationships") {
+            if bindings.get(p) == Some(&Binding::Path) {
+                res.edge_sources.push(column);
+                res.edge_types = None;
+            }
+            continue;
+        }
+        let var = collected.unwrap_or(expr).trim_matches('`');
+        match bindings.get(var) {
+            Some(Binding::Node(label)) => {
+                add_type(&mut res.vertex_types, label);
+                res.vertex_sources.push(column);
+                vertex_columns.push((name, var, label.clone(), collected.is_some()));
+            }
+            Some(Binding::Relationship(t)) => {
+                add_type(&mut res.edge_types, t);
+                res.edge_sources.push(column);
+            }
+            Some(Binding::Path) if collected.is_none() => {
+                res.vertex_sources.push(format!("nodes({})", column));
+                res.edge_sources.push(format!("relationships({})", column));
+                res.vertex_types = None;
+                res.edge_types = None;
+            }
+            _ => {}
+        }
+    }
+    if let (true, Some(((source, src_var, src_type, false), targets))) =
+        (res.edge_sources.is_empty(), vertex_columns.split_first())
+    {
+        let is_name = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let tlabel_part = |name: &str, var: &str| {
+            if is_name(name) {
+                name.to_string()
+            } else {
+                var.to_string()
+            }
+        };
+        for (target, dst_var, dst_type, _) in targets {
+            res.derived_edges.push(DerivedEdges {
+                tlabel: format!(
+                    "{}_{}",
+                    tlabel_part(source, src_var),
+                    tlabel_part(target, dst_var)
+                ),
+                source: source.to_string(),
+                target: target.to_string(),
+                src_type: src_type.clone(),
+                dst_type: dst_type.clone(),
+            });
+        }
+        if let Some(types) = &mut res.edge_types {
+            types.extend(res.derived_edges.iter().map(|e| e.tlabel.clone()));
+        }
+    }
+    res
+}
+
+#[test]
+fn read_graph_schema() {
+    let schema = graph_schema(
+        "MATCH (p: Product)-[:belongsTo]->(cat: Category {name: \" Books\"}) \
+         MATCH (r1: Review)-[:rates]->(p)<-[:rates]-(r2: Review) \
+         MATCH (r1: Review)-[:isWrittenBy]->(u1: Reviewer) \
+         MATCH (r2: Review)-[:isWrittenBy]->(u2: Reviewer) RETURN u1, collect(u2)",
+    );
+    assert_eq!(schema.vertex_sources, vec!["`u1`", "`collect(u2)`"]);
+    assert!(schema.edge_sources.is_empty());
+    assert_eq!(
+        schema.vertex_types,
+        Some(BTreeSet::from(["Reviewer".to_string()]))
+    );
+    assert_eq!(
+        schema.derived_edges,
+        vec![DerivedEdges {
+            tlabel: "u1_u2".to_string(),
+            source: "u1".to_string(),
+            target: "collect(u2)".to_string(),
+            src_type: Some("Reviewer".to_string()),
+            dst_type: Some("Reviewer".to_string()),
+        }]
+    );
+
+    let schema = graph_schema(
+        "MATCH p = (a:Person)-[:knows*1..2]->(b) MATCH (a)-[l:livesIn]->(c:City) \
+         RETURN p, l AS lives, count(c) AS n",
+    );
+    assert_eq!(schema.vertex_sources, vec!["nodes(`p`)"]);
+    assert_eq!(schema.edge_sources, vec!["relationships(`p`)", "`lives`"]);
+    assert_eq!(schema.vertex_types, None);
+    assert!(schema.derived_edges.is_empty());
+    assert_eq!(
+        graph_schema("MATCH (a:Person)-[r:knows]->(b:Person) RETURN *").edge_types,
+        Some(BTreeSet::from(["knows".to_string()]))
+    );
+}
@@ -1,11 +1,16 @@
 use super::{
-    built_in_fns::Expression, DataIdT, DataTransformationContext, InnerTransformationData,
-    TransformationContext, TransformationData,
+    built_in_fns::{quote_identifier, Expression},
+    CypherQuery, DataIdT, DataTransformationContext, InnerTransformationData, LocalColumn,
+    LocalData, LocalExecutor, LocalFrame, TransformationContext, TransformationData,
+};
+use crate::{
+    FeatureValue, FeatureValueType, Field, GraphRows, GraphValue, ResourceOp, TableFeatureView,
 };
-use crate::{FeatureValueType, Field, ResourceOp, TableFeatureView};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
 use std::rc::{Rc, Weak};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,6 +43,7 @@ impl Column {
             // FIXME(tatiana): how should we determine the entity? trace back input column? or let user assign by function parameter?
             entity_id: "Entity/entity_name/".to_string(),
             transformation_id: Some(transformation_id),
+            data_source_id: None,
             description: None,
             tags: HashMap::new(),
             owners: Vec::new(),
@@ -114,7 +120,19 @@ impl DataFrame {
         res
     }
 
-    pub fn select(&self, expr: Vec<String>) -> Rc<Self> {
+    /// The names and types of the columns
+    pub fn column_types(&self) -> HashMap<String, FeatureValueType> {
+        self.col_names
+            .iter()
+            .cloned()
+            .zip(self.schema.iter().map(|col| col.value_type.clone()))
+            .collect()
+    }
+
+    /// Computes a column per expression. The expressions are type checked against the columns of
+    /// this DataFrame.
+    pub fn select(&self, expr: Vec<String>) -> Result<Rc<Self>, Box<dyn Error>> {
+        let columns = self.column_types();
         let mut res = Self {
             context: self.context.new_data_context(),
             name: self.name.clone(),
@@ -124,18 +142,19 @@ impl DataFrame {
         };
         res.schema = expr
             .iter()
-            .map(|e| {
-                let encoder = Expression::new(e); // TODO(tatiana): need to parse the expression
-                let mut col = Column {
+            .map(|e| -> Result<_, Box<dyn Error>> {
+                let encoder = Expression::parse(e)?;
+                let value_type = encoder
+                    .infer_type(&columns)
+                    .map_err(|err| format!("{}: {}", e, err))?;
+                Ok(Rc::new(Column {
                     origin: self.context.id,
                     expr: Some(e.to_string()),
-                    encoder: None,
-                    value_type: encoder.get_type(),
-                };
-                col.encoder = Some(encoder);
-                Rc::new(col)
+                    encoder: Some(encoder),
+                    value_type,
+                }))
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
         res.col_names = res
             .schema
             .iter()
@@ -156,7 +175,7 @@ impl DataFrame {
             .collect();
         let res = Rc::new(res);
         self.context.register_data(&res);
-        res
+        Ok(res)
     }
 
     pub fn with_column(&self, colname: &str, col: Rc<Column>) -> Rc<Self> {
@@ -178,6 +197,23 @@ impl DataFrame {
         res
     }
 
+    /// The names of the columns of the origin that the columns computed from it read
+    pub(super) fn columns_read_from(&self, origin: DataIdT) -> BTreeSet<&str> {
+        let mut res = BTreeSet::new();
+        for (name, col) in self.col_names.iter().zip(&self.schema) {
+            if col.origin != origin {
+                continue;
+            }
+            match &col.encoder {
+                Some(encoder) => res.extend(encoder.columns()),
+                None => {
+                    res.insert(col.expr.as_deref().unwrap_or(name));
+                }
+            }
+        }
+        res
+    }
+
     pub fn col(&self, colname: &str) -> Option<Rc<Column>> {
         self.col_by_names
             .get(colname)
@@ -190,6 +226,210 @@ impl TransformationData for DataFrame {
     fn get_context(&self) -> &DataTransformationContext {
         &self.context
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    /// `dataframe(name)` for a DataFrame read from elsewhere, and `select` with the expression
+    /// of each column otherwise
+    fn operation(&self) -> String {
+        if self.inputs().is_empty() {
+            return format!("dataframe({:?})", self.name);
+        }
+        let columns: Vec<String> = self
+            .col_names
+            .iter()
+            .zip(&self.schema)
+            .map(|(name, col)| match &col.expr {
+                Some(expr)
+                    if expr == name
+                        || col.encoder.as_ref().and_then(|e| e.get_col_name()).as_ref()
+                            == Some(name) =>
+                {
+                    expr.clone()
+                }
+                Some(expr) => format!("{} AS {}", expr, name),
+                None => name.clone(),
+            })
+            .collect();
+        format!("select({})", columns.join(", "))
+    }
+
+    /// The origins of the columns other than the DataFrame itself
+    fn inputs(&self) -> Vec<DataIdT> {
+        let mut res: Vec<DataIdT> = self
+            .schema
+            .iter()
+            .map(|col| col.origin)
+            .filter(|origin| *origin != self.context.id)
+            .collect();
+        res.sort_unstable();
+        res.dedup();
+        res
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        let schema = self
+            .schema
+            .iter()
+            .map(|col| match inputs.get(&col.origin) {
+                Some(origin) => Rc::new(Column {
+                    origin: *origin,
+                    expr: col.expr.clone(),
+                    encoder: col.encoder.clone(),
+                    value_type: col.value_type.clone(),
+                }),
+                None => col.clone(),
+            })
+            .collect();
+        Some(Rc::new(DataFrame {
+            context: self.context.clone(),
+            name: self.name.clone(),
+            schema,
+            col_names: self.col_names.clone(),
+            col_by_names: self.col_by_names.clone(),
+        }))
+    }
+
+    /// The column of the exported field
+    fn exported(&self, resource_id: &str, result: &LocalData) -> Result<LocalData, Box<dyn Error>> {
+        let frame = result
+            .frame()
+            .ok_or_else(|| format!("DataFrame {} has no feature values", self.name))?;
+        let name = self
+            .col_names
+            .iter()
+            .zip(&self.schema)
+            .find(|(name, col)| col.to_field(name, String::new()).resource_id() == resource_id)
+            .map(|(name, _)| name)
+            .ok_or_else(|| format!("DataFrame {} does not export {}", self.name, resource_id))?;
+        let column = frame
+            .column(name)
+            .ok_or_else(|| format!("data {} has no column {}", self.context.id, name))?;
+        Ok(LocalData::Frame {
+            frame: LocalFrame {
+                columns: vec![column.clone()],
+            },
+        })
+    }
+
+    /// Computes each column from the frame of its origin: columns with an expression are
+    /// evaluated over it and the others are copied from it. A DataFrame read from elsewhere
+    /// must have its frame inserted into the executor.
+    fn execute_local(&self, executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        let mut columns = Vec::with_capacity(self.schema.len());
+        for (name, col) in self.col_names.iter().zip(&self.schema) {
+            if col.origin == self.context.id {
+                return Err(
+                    format!("no local data was provided for DataFrame {}", self.name).into(),
+                );
+            }
+            let origin = executor.frame(col.origin)?;
+            let values = match &col.encoder {
+                Some(encoder) => encoder.evaluate_with(origin, executor.functions())?,
+                None => {
+                    let source = col.expr.as_deref().unwrap_or(name);
+                    origin
+                        .column(source)
+                        .ok_or_else(|| format!("data {} has no column {}", col.origin, source))?
+                        .values
+                        .clone()
+                }
+            };
+            if let Some(first) = columns.first().map(|c: &LocalColumn| c.values.len()) {
+                if first != values.len() {
+                    return Err(format!(
+                        "column {} has {} rows but DataFrame {} has {}",
+                        name,
+                        values.len(),
+                        self.name,
+                        first
+                    )
+                    .into());
+                }
+            }
+            columns.push(LocalColumn {
+                name: name.clone(),
+                value_type: col.value_type.clone(),
+                values,
+            });
+        }
+        Ok(LocalData::Frame {
+            frame: LocalFrame { columns },
+        })
+    }
+
+    /// Wraps the query of the origin in a subquery and computes the columns in its `RETURN`,
+    /// when all columns come from one origin that ran entirely on the graph database
+    fn compile_cypher(&self, executor: &LocalExecutor) -> Result<CypherQuery, Box<dyn Error>> {
+        let origin = match self.schema.first() {
+            Some(col) if self.schema.iter().all(|c| c.origin == col.origin) => col.origin,
+            _ => return Err(format!("DataFrame {} has no single origin", self.name).into()),
+        };
+        let source = executor
+            .query(origin)
+            .filter(|q| q.complete)
+            .ok_or_else(|| format!("data {} did not run entirely on the graph database", origin))?;
+        let columns = executor.frame(origin)?.column_types();
+        let mut params = source.params.clone();
+        let mut items = Vec::with_capacity(self.schema.len());
+        for (name, col) in self.col_names.iter().zip(&self.schema) {
+            let item = match &col.encoder {
+                Some(encoder) => encoder.to_cypher(None, &columns, &mut params)?,
+                None => quote_identifier(col.expr.as_deref().unwrap_or(name)),
+            };
+            items.push(format!("{} AS {}", item, quote_identifier(name)));
+        }
+        Ok(CypherQuery {
+            text: format!("CALL {{ {} }} RETURN {}", source.text, items.join(", ")),
+            params,
+            complete: true,
+        })
+    }
+
+    fn decode_cypher_rows(
+        &self,
+        rows: GraphRows,
+        _executor: &LocalExecutor,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        let mut columns: Vec<LocalColumn> = self
+            .col_names
+            .iter()
+            .zip(&self.schema)
+            .map(|(name, col)| LocalColumn {
+                name: name.clone(),
+                value_type: col.value_type.clone(),
+                values: Vec::with_capacity(rows.rows.len()),
+            })
+            .collect();
+        for row in rows.rows {
+            for (column, value) in columns.iter_mut().zip(row) {
+                column.values.push(decode_value(value, &column.value_type)?);
+            }
+        }
+        Ok(LocalData::Frame {
+            frame: LocalFrame { columns },
+        })
+    }
+}
+
+/// Casts a value returned by the graph database to the type of its column. Nodes and
+/// relationships are Null.
+pub(super) fn decode_value(
+    value: GraphValue,
+    value_type: &FeatureValueType,
+) -> Result<FeatureValue, Box<dyn Error>> {
+    Ok(match value {
+        GraphValue::Value(v) => match v.value_type() {
+            Some(t) if t != *value_type => v.cast(value_type)?,
+            _ => v,
+        },
+        _ => FeatureValue::Null,
+    })
 }
 
 #[test]
@@ -208,6 +448,7 @@ fn demo_use_dataframe() {
         last_updated_timestamp: None,
         tags: HashMap::new(),
         owners: Vec::new(),
+        data_source_ids: Vec::new(),
     };
 
     let col1 = Field {
@@ -216,6 +457,7 @@ fn demo_use_dataframe() {
         value_type: FeatureValueType::Int,
         entity_id: entity.resource_id(),
         transformation_id: Some("t_1".to_string()),
+        data_source_id: None,
         description: None,
         tags: HashMap::new(),
         owners: Vec::new(),
@@ -227,6 +469,7 @@ fn demo_use_dataframe() {
         value_type: FeatureValueType::Int,
         entity_id: entity.resource_id(),
         transformation_id: Some("t_1".to_string()),
+        data_source_id: None,
         description: None,
         tags: HashMap::new(),
         owners: Vec::new(),
@@ -238,6 +481,7 @@ fn demo_use_dataframe() {
         value_type: FeatureValueType::Int,
         entity_id: entity.resource_id(),
         transformation_id: Some("t_2".to_string()),
+        data_source_id: None,
         description: None,
         tags: HashMap::new(),
         owners: Vec::new(),
@@ -245,10 +489,14 @@ fn demo_use_dataframe() {
 
     let context = TransformationContext::new();
     let df = DataFrame::new(&context, "test", vec![&col1, &col2, &col3]);
-    let df2 = df.select(vec![
-        "col1 + col2 * avg(col3)".to_string(),
-        "col1".to_string(),
-    ]);
+    let df2 = df
+        .select(vec![
+            "feature_1 + feature_2 * avg(feature_3)".to_string(),
+            "feature_1".to_string(),
+        ])
+        .unwrap();
+    assert_eq!(df2.schema[0].value_type, FeatureValueType::Float);
+    assert!(df.select(vec!["col1".to_string()]).is_err());
     let df3 = df.with_column("computed_col", df2.col("col0").unwrap());
 
     println!("{:#?}", df3);
@@ -260,3 +508,60 @@ fn demo_use_dataframe() {
     let events: Rc<RefCell<TransformationContext>> = serde_json::from_str(&serialized).unwrap();
     println!("Deserialized TransformationContext: {:?}", events);
 }
+
+#[test]
+fn select_dataframe_locally() {
+    use crate::{Entity, FeatureValue, PropertyGraph};
+
+    let person = Entity::new_node_entity("person", None, "Person", "name");
+    let fields = Field::new_fields(
+        vec![
+            ("name", FeatureValueType::String),
+            ("age", FeatureValueType::Int),
+        ],
+        &person,
+        None,
+    );
+    let context = TransformationContext::new();
+    let df = DataFrame::new(&context, "person", fields.iter().collect());
+    let df2 = df
+        .select(vec![
+            "age / 2.0 AS half".to_string(),
+            "upper(name)".to_string(),
+        ])
+        .unwrap();
+    let df3 = df.with_column("half", df2.col("half").unwrap());
+
+    let graph = PropertyGraph::new();
+    let mut executor = LocalExecutor::new(&graph);
+    executor.insert(
+        df.get_data_id(),
+        LocalData::Frame {
+            frame: LocalFrame {
+                columns: vec![
+                    LocalColumn {
+                        name: "name".to_string(),
+                        value_type: FeatureValueType::String,
+                        values: vec![FeatureValue::String("ann".to_string()), FeatureValue::Null],
+                    },
+                    LocalColumn {
+                        name: "age".to_string(),
+                        value_type: FeatureValueType::Int,
+                        values: vec![FeatureValue::Int(30), FeatureValue::Int(41)],
+                    },
+                ],
+            },
+        },
+    );
+    executor.execute(&context.borrow()).unwrap();
+    let frame = executor.result(df3.get_data_id()).unwrap().frame().unwrap();
+    assert_eq!(
+        frame.column("half").unwrap().values,
+        vec![FeatureValue::Float(15.0), FeatureValue::Float(20.5)]
+    );
+    let frame = executor.result(df2.get_data_id()).unwrap().frame().unwrap();
+    assert_eq!(
+        frame.column("col1").unwrap().values,
+        vec![FeatureValue::String("ANN".to_string()), FeatureValue::Null]
+    );
+}
@@ -0,0 +1,170 @@
+use super::{
+    DataIdT, LocalData, LocalExecutor, Optimizer, Rewrite, TransformationContext,
+    TransformationData,
+};
+use crate::{GraphDatabase, ResourceId, ResourceOp, Transformation};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::rc::Rc;
+
+/// The results of the resources exported by a transformation, by resource id
+pub type TransformationOutput = BTreeMap<ResourceId, LocalData>;
+
+/// Runs the data flow saved in the body of a transformation and collects the fields and
+/// topologies it exports
+pub struct TransformationExecutor {
+    name: String,
+    context: TransformationContext,
+    rewrites: Vec<Rewrite>,
+}
+
+impl TransformationExecutor {
+    /// Restores the data flow of the transformation, checking that its data can be ordered and
+    /// that its exports refer to its data, and optimizes it
+    pub fn new(transformation: &Transformation) -> Result<Self, Box<dyn Error>> {
+        let context = TransformationContext::from_transformation(transformation)?;
+        context.topological_order()?;
+        let mut res = TransformationExecutor {
+            name: transformation.resource_id(),
+            context,
+            rewrites: Vec::new(),
+        };
+        for (id, resource) in res.context.export_resources() {
+            res.data(*id)
+                .map_err(|e| format!("cannot export {}: {}", resource, e))?;
+        }
+        res.rewrites = Optimizer::default().optimize(&mut res.context)?;
+        Ok(res)
+    }
+
+    pub fn context(&self) -> &TransformationContext {
+        &self.context
+    }
+
+    /// The rewrites of the optimizer on the data flow
+    pub fn rewrites(&self) -> &[Rewrite] {
+        &self.rewrites
+    }
+
+    /// The ids of the data in the order they run
+    pub fn plan(&self) -> Result<Vec<DataIdT>, Box<dyn Error>> {
+        Ok(self
+            .context
+            .topological_order()?
+            .iter()
+            .map(|data| data.get_context().id)
+            .collect())
+    }
+
+    /// Runs all data on the local engine
+    pub fn execute_local(
+        &self,
+        executor: &mut LocalExecutor,
+    ) -> Result<TransformationOutput, Box<dyn Error>> {
+        executor.execute(&self.context)?;
+        self.collect_exports(executor)
+    }
+
+    /// Runs the data that compile to Cypher on the graph database and the others on the local
+    /// engine
+    pub async fn execute_on(
+        &self,
+        database: &dyn GraphDatabase,
+        executor: &mut LocalExecutor<'_>,
+    ) -> Result<TransformationOutput, Box<dyn Error>> {
+        executor.execute_on(database, &self.context).await?;
+        self.collect_exports(executor)
+    }
+
+    fn data(&self, id: DataIdT) -> Result<&Rc<dyn TransformationData>, Box<dyn Error>> {
+        self.context
+            .get_data(id)
+            .ok_or_else(|| format!("{} has no data {}", self.name, id).into())
+    }
+
+    fn collect_exports(
+        &self,
+        executor: &LocalExecutor,
+    ) -> Result<TransformationOutput, Box<dyn Error>> {
+        let mut res = TransformationOutput::new();
+        for (id, resource) in self.context.export_resources() {
+            let result = executor
+                .result(*id)
+                .ok_or_else(|| format!("data {} has not been executed", id))?;
+            res.insert(
+                resource.clone(),
+                self.data(*id)?.exported(resource, result)?,
+            );
+        }
+        Ok(res)
+    }
+}
+
+#[test]
+fn execute_transformation() {
+    use super::{
+        DataFrame, GraphBase, InnerTransformationData, LocalColumn, LocalFrame, SingleGraph,
+    };
+    use crate::{Entity, FeatureValue, FeatureValueType, Field, PropertyGraph};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41}}
+            ],
+            "edges": []
+        }"#,
+    )
+    .unwrap();
+    let person = Entity::new_node_entity("person", None, "Person", "age");
+    let fields = Field::new_fields(vec![("age", FeatureValueType::Int)], &person, None);
+
+    let context = TransformationContext::new();
+    let df = DataFrame::new(&context, "person", fields.iter().collect());
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let topology = single_graph.export_topology("people");
+    let df2 = df.select(vec!["age + 1 AS next_age".to_string()]).unwrap();
+    let exported = df2.export();
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("next_age".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+    assert_eq!(executor.plan().unwrap(), vec![0, 1, 2]);
+    let mut local = LocalExecutor::new(&graph);
+    local.insert(
+        df.get_data_id(),
+        LocalData::Frame {
+            frame: LocalFrame {
+                columns: vec![LocalColumn {
+                    name: "age".to_string(),
+                    value_type: FeatureValueType::Int,
+                    values: vec![FeatureValue::Int(30), FeatureValue::Int(41)],
+                }],
+            },
+        },
+    );
+    let output = executor.execute_local(&mut local).unwrap();
+    assert_eq!(
+        output[&topology.resource_id()],
+        LocalData::Graph {
+            vertex_types: vec!["Person".to_string()],
+            edge_types: Vec::new(),
+        }
+    );
+    let frame = output[&exported[0].resource_id()].frame().unwrap();
+    assert_eq!(
+        frame.column("next_age").unwrap().values,
+        vec![FeatureValue::Int(31), FeatureValue::Int(42)]
+    );
+
+    let mut invalid = transformation.clone();
+    invalid
+        .export_resources
+        .push((7, "Field/person/x/".to_string()));
+    assert!(TransformationExecutor::new(&invalid).is_err());
+}
@@ -0,0 +1,369 @@
+use super::{
+    DataFrame, DataIdT, LocalColumn, LocalData, LocalExecutor, LocalFrame, Rewrite,
+    TransformationContext, TransformationData, TransformationExecutor,
+};
+use crate::{FeatureValue, FeatureValueType, GraphRows, PropertyGraph, ResourceId, ResourceOp};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Display;
+
+/// A column of the result of a data
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnExplanation {
+    pub name: String,
+    pub value_type: FeatureValueType,
+}
+
+/// The shape of the result of a data
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutputSchema {
+    Graph {
+        vertex_types: Vec<String>,
+        edge_types: Vec<String>,
+    },
+    Vertices {
+        columns: Vec<ColumnExplanation>,
+    },
+    Edges {
+        columns: Vec<ColumnExplanation>,
+    },
+    Frame {
+        columns: Vec<ColumnExplanation>,
+    },
+    Subgraph {
+        vertex_columns: Vec<ColumnExplanation>,
+        edge_columns: Vec<ColumnExplanation>,
+    },
+}
+
+impl OutputSchema {
+    fn of(data: &LocalData) -> Self {
+        let columns = |frame: &LocalFrame| {
+            frame
+                .columns
+                .iter()
+                .map(|col| ColumnExplanation {
+                    name: col.name.clone(),
+                    value_type: col.value_type.clone(),
+                })
+                .collect()
+        };
+        match data {
+            LocalData::Graph {
+                vertex_types,
+                edge_types,
+            } => OutputSchema::Graph {
+                vertex_types: vertex_types.clone(),
+                edge_types: edge_types.clone(),
+            },
+            LocalData::Vertices { frame, .. } => OutputSchema::Vertices {
+                columns: columns(frame),
+            },
+            LocalData::Edges { frame, .. } => OutputSchema::Edges {
+                columns: columns(frame),
+            },
+            LocalData::Frame { frame } => OutputSchema::Frame {
+                columns: columns(frame),
+            },
+            LocalData::Subgraph {
+                vertex_frame,
+                edge_frame,
+                ..
+            } => OutputSchema::Subgraph {
+                vertex_columns: columns(vertex_frame),
+                edge_columns: columns(edge_frame),
+            },
+        }
+    }
+}
+
+impl Display for OutputSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |columns: &Vec<ColumnExplanation>| {
+            let columns: Vec<String> = columns
+                .iter()
+                .map(|col| format!("{}: {:?}", col.name, col.value_type))
+                .collect();
+            columns.join(", ")
+        };
+        let (kind, columns) = match self {
+            OutputSchema::Graph {
+                vertex_types,
+                edge_types,
+            } => {
+                return write!(
+                    f,
+                    "graph of vertices [{}] and edges [{}]",
+                    vertex_types.join(", "),
+                    edge_types.join(", ")
+                )
+            }
+            OutputSchema::Vertices { columns } => ("vertices", columns),
+            OutputSchema::Edges { columns } => ("edges", columns),
+            OutputSchema::Frame { columns } => ("frame", columns),
+            OutputSchema::Subgraph {
+                vertex_columns,
+                edge_columns,
+            } => {
+                return write!(
+                    f,
+                    "subgraph of vertices ({}) and edges ({})",
+                    describe(vertex_columns),
+                    describe(edge_columns)
+                )
+            }
+        };
+        write!(f, "{} of ({})", kind, describe(columns))
+    }
+}
+
+/// Where a data runs when the transformation runs with a graph database
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "engine", rename_all = "snake_case")]
+pub enum Execution {
+    /// The data is pushed down to the graph database as a Cypher query
+    GraphDatabase {
+        query: String,
+        params: BTreeMap<String, FeatureValue>,
+        /// Whether the rows of the query are the whole result, with nothing evaluated locally
+        complete: bool,
+    },
+    /// The data runs on the local engine, with the reason it is not pushed down
+    Local { reason: String },
+    /// The result is read from the online store or inserted into the executor
+    Provided,
+}
+
+impl Display for Execution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Execution::GraphDatabase {
+                query,
+                params,
+                complete,
+            } => {
+                write!(f, "graph database")?;
+                if !complete {
+                    write!(f, ", then filtered locally")?;
+                }
+                write!(f, "\n    cypher: {}", query)?;
+                for (name, value) in params {
+                    write!(f, "\n    ${} = {}", name, value)?;
+                }
+                Ok(())
+            }
+            Execution::Local { reason } => write!(f, "local ({})", reason),
+            Execution::Provided => write!(f, "provided"),
+        }
+    }
+}
+
+/// How a data of the data flow is computed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DataExplanation {
+    pub id: DataIdT,
+    pub operation: String,
+    pub inputs: Vec<DataIdT>,
+    /// None when the shape is only known once the data runs
+    pub schema: Option<OutputSchema>,
+    pub execution: Execution,
+    pub exports: Vec<ResourceId>,
+}
+
+/// The plan of a data flow: its data in the order they run, and the rewrites that produced it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Explanation {
+    pub transformation: Option<ResourceId>,
+    pub rewrites: Vec<Rewrite>,
+    pub data: Vec<DataExplanation>,
+}
+
+impl Explanation {
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(transformation) = &self.transformation {
+            writeln!(f, "{}", transformation)?;
+        }
+        for rewrite in &self.rewrites {
+            writeln!(f, "rewrite: {:?} on data {}", rewrite.rule, rewrite.data)?;
+        }
+        for data in &self.data {
+            write!(f, "[{}] {}", data.id, data.operation)?;
+            if !data.inputs.is_empty() {
+                let inputs: Vec<String> = data.inputs.iter().map(|id| id.to_string()).collect();
+                write!(f, " <- {}", inputs.join(", "))?;
+            }
+            writeln!(f)?;
+            match &data.schema {
+                Some(schema) => writeln!(f, "  output: {}", schema)?,
+                None => writeln!(f, "  output: unknown")?,
+            }
+            writeln!(f, "  runs on: {}", data.execution)?;
+            for resource in &data.exports {
+                writeln!(f, "  exports: {}", resource)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TransformationContext {
+    /// Explains how each data runs with a graph database. The data are planned over an empty
+    /// graph, so a data that fails to compile to Cypher is explained as running locally, and the
+    /// output of data that cannot be planned without their results is unknown.
+    pub fn explain(&self) -> Result<Explanation, Box<dyn Error>> {
+        let graph = PropertyGraph::new();
+        let mut executor = LocalExecutor::new(&graph);
+        executor.place(self)?;
+        let mut data = Vec::new();
+        for d in self.topological_order()? {
+            let id = d.get_context().id;
+            let (execution, result) = plan(d.as_ref(), &mut executor);
+            if let Some(result) = &result {
+                executor.insert(id, result.clone());
+            }
+            data.push(DataExplanation {
+                id,
+                operation: d.operation(),
+                inputs: d.inputs(),
+                schema: result.as_ref().map(OutputSchema::of),
+                execution,
+                exports: self
+                    .export_resources()
+                    .iter()
+                    .filter(|(exported, _)| *exported == id)
+                    .map(|(_, resource)| resource.clone())
+                    .collect(),
+            });
+        }
+        Ok(Explanation {
+            transformation: self.transformation().map(|t| t.resource_id()),
+            rewrites: Vec::new(),
+            data,
+        })
+    }
+}
+
+impl TransformationExecutor {
+    /// Explains the optimized data flow, with the rewrites of the optimizer
+    pub fn explain(&self) -> Result<Explanation, Box<dyn Error>> {
+        let mut res = self.context().explain()?;
+        res.rewrites = self.rewrites().to_vec();
+        Ok(res)
+    }
+}
+
+/// Decides where the data runs and computes its result over the empty graph of the executor
+fn plan(
+    data: &dyn TransformationData,
+    executor: &mut LocalExecutor,
+) -> (Execution, Option<LocalData>) {
+    // a DataFrame without inputs is read from elsewhere, so only its columns are known
+    if let Some(df) = data.as_any().downcast_ref::<DataFrame>() {
+        if data.inputs().is_empty() {
+            let types = df.column_types();
+            let columns = df
+                .col_names
+                .iter()
+                .map(|name| LocalColumn {
+                    name: name.clone(),
+                    value_type: types[name].clone(),
+                    values: Vec::new(),
+                })
+                .collect();
+            let frame = LocalData::Frame {
+                frame: LocalFrame { columns },
+            };
+            return (Execution::Provided, Some(frame));
+        }
+    }
+    match executor.compile(data) {
+        Ok(query) => {
+            let result = data.decode_cypher_rows(GraphRows::default(), executor).ok();
+            let execution = Execution::GraphDatabase {
+                query: query.text.clone(),
+                params: query.params.clone().into_iter().collect(),
+                complete: query.complete,
+            };
+            executor.insert_query(data.get_context().id, query);
+            (execution, result)
+        }
+        Err(reason) => (
+            Execution::Local {
+                reason: reason.to_string(),
+            },
+            executor.execute_fallback(data).ok(),
+        ),
+    }
+}
+
+#[test]
+fn explain_data_flow() {
+    use super::{GraphBase, SingleGraph};
+    use crate::{Entity, Field};
+
+    let person = Entity::new_node_entity("person", None, "Person", "name");
+    let fields = Field::new_fields(vec![("age", FeatureValueType::Int)], &person, None);
+    let context = TransformationContext::new();
+    let graph = SingleGraph::from_property_graph(
+        &context,
+        &PropertyGraph::from_json(
+            r#"{"vertices": [{"tlabel": "Person", "id": "p1", "properties": {"age": 30}}], "edges": []}"#,
+        )
+        .unwrap(),
+    );
+    graph.vertices_by_type("Person").unwrap();
+    let df = DataFrame::new(&context, "person", fields.iter().collect());
+    let df2 = df.select(vec!["age + 1 AS next_age".to_string()]).unwrap();
+    df2.export();
+
+    let explanation = context.borrow().explain().unwrap();
+    let operations: Vec<&str> = explanation
+        .data
+        .iter()
+        .map(|d| d.operation.as_str())
+        .collect();
+    assert_eq!(
+        operations,
+        vec![
+            "single_graph",
+            "vertices_by_type(\"Person\")",
+            "dataframe(\"person\")",
+            "select(age + 1 AS next_age)"
+        ]
+    );
+    assert_eq!(explanation.data[1].inputs, vec![0]);
+    match &explanation.data[1].execution {
+        Execution::GraphDatabase {
+            query, complete, ..
+        } => {
+            assert!(query.starts_with("MATCH (n) WHERE"));
+            assert!(complete);
+        }
+        execution => panic!("unexpected execution {:?}", execution),
+    }
+    assert_eq!(explanation.data[2].execution, Execution::Provided);
+    assert_eq!(
+        explanation.data[3].schema,
+        Some(OutputSchema::Frame {
+            columns: vec![ColumnExplanation {
+                name: "next_age".to_string(),
+                value_type: FeatureValueType::Int,
+            }]
+        })
+    );
+    assert_eq!(explanation.data[3].exports.len(), 1);
+
+    let text = explanation.to_string();
+    assert!(text.contains("[3] select(age + 1 AS next_age) <- 2\n"));
+    let json: serde_json::Value = serde_json::from_str(&explanation.to_json().unwrap()).unwrap();
+    assert_eq!(json["data"][1]["execution"]["engine"], "graph_database");
+    assert_eq!(json["data"][2]["schema"]["kind"], "frame");
+}
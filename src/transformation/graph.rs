@@ -1,22 +1,80 @@
+mod aggregate;
 #[allow(dead_code, unused)]
 mod multiple_graphs;
+mod sample;
 #[allow(dead_code, unused)]
 mod select;
 #[allow(dead_code, unused)]
 mod single_graph;
+mod subgraph;
 
-pub use select::{DataFrameSet, EdgeSelectGraph, Selector, VertexSelectGraph};
+pub use aggregate::NeighborAggregation;
+pub use sample::NeighborSampling;
+pub use select::{CypherSource, DataFrameSet, EdgeSelectGraph, Selector, VertexSelectGraph};
 pub use single_graph::SingleGraph;
+pub use subgraph::SubGraph;
 
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::HashMap, error::Error, rc::Rc};
 
-use crate::Topology;
+use crate::{Direction, Field, ResourceOp, Topology, TopologyType};
 
 use super::{
-    Aggregator, CypherResultDataFrame, CypherResultGraph, CypherTransformation, DataFrame,
-    RandomWalkPath,
+    AggregationHop, Aggregator, CypherResultDataFrame, CypherResultGraph, CypherTransformation,
+    DataFrame, DataIdT, DataTransformationContext, Expression, RandomWalkPath, SamplingHop,
+    SamplingSpec, TransformationData,
 };
 
+/// Calls `f` on the data with the given id in the transformation context of `context`
+///
+/// # Panics
+///
+/// Panics if the context has no such data of type `S`
+fn with_data<S: 'static, R>(
+    context: &DataTransformationContext,
+    id: DataIdT,
+    f: impl FnOnce(&S) -> R,
+) -> R {
+    // the context is not borrowed while `f` registers new data
+    let data = context
+        .transformation_context
+        .upgrade()
+        .and_then(|tc| tc.as_ref().borrow().get_data(id).cloned())
+        .unwrap_or_else(|| panic!("data {} is not in the transformation context", id));
+    f(data
+        .as_any()
+        .downcast_ref::<S>()
+        .unwrap_or_else(|| panic!("data {} is not a {}", id, std::any::type_name::<S>())))
+}
+
+/// Exports a subgraph of `graph` computed by `data` as an adjacency list over the edge entities
+/// of the graph
+fn export_adjacency_list(data: &dyn TransformationData, graph: DataIdT, name: &str) -> Topology {
+    let context = data.get_context();
+    let edge_entity_ids = context
+        .transformation_context
+        .upgrade()
+        .and_then(|tc| tc.as_ref().borrow().get_data(graph).cloned())
+        .and_then(|data| {
+            data.as_any()
+                .downcast_ref::<SingleGraph>()
+                .map(|g| g.get_edge_entity_ids())
+        })
+        .unwrap_or_default();
+    let res = Topology {
+        name: name.to_string(),
+        transformation_id: Some(context.get_transformation_id()),
+        topology_type: Some(TopologyType::AdjacencyList),
+        edge_entity_ids,
+        variant: None,
+        description: None,
+        created_at: None,
+        tags: HashMap::new(),
+        owners: Vec::new(),
+    };
+    context.export_resource(context.id, res.resource_id());
+    res
+}
+
 /// A graph interface
 pub trait GraphBase {
     /// Returns a vertex data frame containing all vertices in the graph
@@ -37,6 +95,63 @@ pub trait GraphBase {
     /// * `t` - The type of edges to return. If the type does not exist, an error is raised
     fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>>;
 
+    /// Returns the vertices or edges picked by the selector among the selected ones
+    fn select(&self, selector: Selector) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        Err(format!("select vertices or edges before selecting ({})", selector).into())
+    }
+
+    /// Returns the selected vertices or edges for which the condition is true
+    fn filter(&self, condition: &str) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        self.select(Selector::Expression(Expression::parse(condition)?))
+    }
+
+    /// Returns up to `size` of the selected vertices or edges, sampled by the spec
+    fn sample(&self, size: usize, spec: SamplingSpec) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        self.select(Selector::Sampling { spec, size })
+    }
+
+    /// Returns the vertices adjacent to the selected vertices
+    ///
+    /// # Arguments
+    /// * `edge_type` - The type of edges to follow. If None, edges of all types are followed
+    /// * `direction` - Follows the edges leaving (Out), entering (In) or touching (Both) the
+    ///   selected vertices
+    fn neighbors(
+        &self,
+        edge_type: Option<&str>,
+        direction: Direction,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        Err("only the neighbors of selected vertices can be selected".into())
+    }
+
+    /// Returns the subgraph induced by the selected vertices or edges
+    fn induced_subgraph(&self) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        Err("only selected vertices or edges induce a subgraph".into())
+    }
+
+    /// Aggregates the neighbors of the selected vertices hop after hop into a new column
+    fn aggregate_hops(
+        &self,
+        hops: Vec<AggregationHop>,
+        output_col_name: &str,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        Err("only the neighbors of selected vertices can be aggregated".into())
+    }
+
+    /// Samples the neighbors of the selected vertices hop after hop into a graph
+    fn sample_hops(
+        &self,
+        hops: Vec<SamplingHop>,
+        spec: SamplingSpec,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        Err("only the neighbors of selected vertices can be sampled".into())
+    }
+
+    /// Exports the features of the selected vertices or edges as fields on their entities
+    fn export_fields(&self) -> Result<Vec<Field>, Box<dyn Error>> {
+        Err("only the features of selected vertices or edges can be exported".into())
+    }
+
     // FIXME(tatiana): a dummy implementation to avoid repeated implementation of immature function
     fn export_topology(&self, name: &str) -> Topology {
         Topology {
@@ -58,12 +173,19 @@ pub trait MultipleGraphsBase {
 }
 
 pub trait GraphComputationOps {
-    /// Returns an induced subgraph of the graph containing only the given vertices
+    /// Returns an induced subgraph of the graph containing only the given vertices, and the edges
+    /// between them
     ///
     /// # Arguments
-    /// * `vertices` - The vertex set from which the induced subgraph is computed
-    // FIXME(tatiana): interface for subgraph extraction?
-    // fn subgraph(&self, vertices: ???) -> Rc<Self>;
+    /// * `vertices` - The selection of the vertices from which the induced subgraph is computed
+    fn subgraph(&self, vertices: Selector) -> Result<Rc<dyn GraphBase>, Box<dyn Error>>;
+
+    /// Returns an edge-induced subgraph of the graph containing only the given edges, and the
+    /// vertices they touch
+    ///
+    /// # Arguments
+    /// * `edges` - The selection of the edges from which the subgraph is computed
+    fn edge_subgraph(&self, edges: Selector) -> Result<Rc<dyn GraphBase>, Box<dyn Error>>;
 
     /// Returns a vertex data frame of vertices visited by the random walks. TODO(tatiana) need to consider the return type
     ///
@@ -86,7 +208,8 @@ pub trait GraphComputationOps {
         restart_prob: Option<&Vec<f32>>,
     ) -> Rc<dyn GraphBase>;
 
-    /// Returns a vertex data frame with the same set of vertices but new vertex features computed from neighbor aggregation
+    /// Returns a vertex data frame with the same set of vertices but new vertex features computed from neighbor aggregation.
+    /// The new column exports as a field of the vertex entity when the vertices are of one type
     ///
     /// # Arguments
     ///
@@ -98,7 +221,7 @@ pub trait GraphComputationOps {
         edge_type: Option<String>,
         aggregator: Aggregator,
         output_col_name: String,
-    ) -> Rc<dyn GraphBase>;
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>>;
 
     /// Returns a vertex data frame with the same set of vertices but new vertex features computed from k-hop neighbor
     /// aggregation
@@ -111,7 +234,7 @@ pub trait GraphComputationOps {
     ///  given, the number of edge types must be equal to the number of hops
     /// * `aggregator` - The aggregator to use for aggregating the neighbor features for each hop. If only one aggregator
     ///  is given, it is used for all hops. If multiple aggregators are given, the number of aggregators must be equal to
-    ///  the number of hops
+    ///  the number of hops. Only the column of the first aggregator is read, later hops aggregate the previous hop
     /// * `output_col_name` - The name of the output column
     fn aggregate_k_hop_neighbors(
         &self,
@@ -119,25 +242,25 @@ pub trait GraphComputationOps {
         edge_types: Vec<String>,
         aggregator: Vec<Aggregator>,
         output_col_name: String,
-    ) -> Rc<dyn GraphBase>;
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>>;
 
     /// Samples a fixed number of neighbors for each vertex in the data frame, and returns the vertices with their sampled
-    /// neighbors as a graph
+    /// neighbors as a graph. The graph exports as a topology made of the sampled edges
     ///
     /// # Arguments
     ///
     /// * `fanout` - The number of neighbors to sample for each vertex
     /// * `edge_type` - The type of edges to traverse. If None, all edge types are traversed as if in a homogeneous graph
-    /// * `replace` - Whether to sample with replacement
+    /// * `spec` - How to sample the neighbors, with or without replacement, and the seed of the samples
     fn sample_neighbors(
         &self,
         fanout: u32,
         edge_type: Option<String>,
-        replace: bool,
-    ) -> Rc<dyn GraphBase>;
+        spec: SamplingSpec,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>>;
 
     /// Samples a fixed number of k-hop neighbors for each vertex in the data frame, and returns the vertices with their sampled
-    /// neighbors as a graph
+    /// neighbors as a graph. Each hop samples the neighbors of the vertices first reached by the previous hop
     ///
     /// # Arguments
     ///
@@ -147,17 +270,25 @@ pub trait GraphComputationOps {
     /// * `edge_types` - The type of edges to traverse for each hop. If None, all edge types are traversed as if in a homogeneous
     /// graph. If only one edge type is given, it is used for all hops. If multiple edge types are given, the number of edge
     /// types must be equal to the number of hops
-    /// * `replace` - Whether to sample with replacement
+    /// * `spec` - How to sample the neighbors, with or without replacement, and the seed of the samples
     fn sample_k_hop_neighbors(
         &self,
         k: u32,
         fanouts: Vec<u32>,
         edge_types: Option<Vec<String>>,
-        replace: bool,
-    ) -> Rc<dyn GraphBase>;
+        spec: SamplingSpec,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>>;
 }
 
-impl<T: GraphBase> GraphComputationOps for T {
+impl<T: GraphBase + ?Sized> GraphComputationOps for T {
+    fn subgraph(&self, vertices: Selector) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        self.vertices().select(vertices)?.induced_subgraph()
+    }
+
+    fn edge_subgraph(&self, edges: Selector) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        self.edges().select(edges)?.induced_subgraph()
+    }
+
     fn random_walk(
         &self,
         path: super::RandomWalkPath,
@@ -181,8 +312,12 @@ impl<T: GraphBase> GraphComputationOps for T {
         edge_type: Option<String>,
         aggregator: super::Aggregator,
         output_col_name: String,
-    ) -> Rc<dyn GraphBase> {
-        todo!()
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        let hops = vec![AggregationHop {
+            edge_type,
+            aggregator,
+        }];
+        self.vertices().aggregate_hops(hops, &output_col_name)
     }
 
     fn aggregate_k_hop_neighbors(
@@ -191,17 +326,37 @@ impl<T: GraphBase> GraphComputationOps for T {
         edge_types: Vec<String>,
         aggregator: Vec<super::Aggregator>,
         output_col_name: String,
-    ) -> Rc<dyn GraphBase> {
-        todo!()
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        let k = k as usize;
+        if k == 0 {
+            return Err("a k-hop aggregation needs at least one hop".into());
+        }
+        if !matches!(edge_types.len(), 0 | 1) && edge_types.len() != k {
+            return Err(format!("expected 1 or {} edge types", k).into());
+        }
+        if aggregator.len() != 1 && aggregator.len() != k {
+            return Err(format!("expected 1 or {} aggregators", k).into());
+        }
+        let hops = (0..k)
+            .map(|i| AggregationHop {
+                edge_type: edge_types.get(i).or_else(|| edge_types.first()).cloned(),
+                aggregator: aggregator.get(i).unwrap_or(&aggregator[0]).clone(),
+            })
+            .collect();
+        self.vertices().aggregate_hops(hops, &output_col_name)
     }
 
     fn sample_neighbors(
         &self,
         fanout: u32,
         edge_type: Option<String>,
-        replace: bool,
-    ) -> Rc<dyn GraphBase> {
-        todo!()
+        spec: SamplingSpec,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        let hops = vec![SamplingHop {
+            fanout: fanout as usize,
+            edge_type,
+        }];
+        self.vertices().sample_hops(hops, spec)
     }
 
     fn sample_k_hop_neighbors(
@@ -209,8 +364,25 @@ impl<T: GraphBase> GraphComputationOps for T {
         k: u32,
         fanouts: Vec<u32>,
         edge_types: Option<Vec<String>>,
-        replace: bool,
-    ) -> Rc<dyn GraphBase> {
-        todo!()
+        spec: SamplingSpec,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        let k = k as usize;
+        if k == 0 {
+            return Err("a k-hop sampling needs at least one hop".into());
+        }
+        if fanouts.len() != 1 && fanouts.len() != k {
+            return Err(format!("expected 1 or {} fanouts", k).into());
+        }
+        let edge_types = edge_types.unwrap_or_default();
+        if !matches!(edge_types.len(), 0 | 1) && edge_types.len() != k {
+            return Err(format!("expected 1 or {} edge types", k).into());
+        }
+        let hops = (0..k)
+            .map(|i| SamplingHop {
+                fanout: *fanouts.get(i).unwrap_or(&fanouts[0]) as usize,
+                edge_type: edge_types.get(i).or_else(|| edge_types.first()).cloned(),
+            })
+            .collect();
+        self.vertices().sample_hops(hops, spec)
     }
 }
@@ -0,0 +1,474 @@
+use super::select::{graph_fields, GraphScope};
+use super::{with_data, DataFrameSet, VertexSelectGraph};
+use crate::transformation::built_in_fns::Message;
+use crate::transformation::{
+    AggregationHop, DataIdT, DataTransformationContext, GraphBase, InnerTransformationData,
+    LocalColumn, LocalData, LocalExecutor, LocalFrame, TransformationData,
+};
+use crate::{FeatureValue, FeatureValueType, Field, ResourceOp};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::error::Error;
+use std::rc::Rc;
+
+/// The selected vertices with a new column aggregated from their neighbors, hop after hop. The
+/// first hop aggregates a feature of the neighbors, and each later hop aggregates the values of
+/// the previous hop, so that k hops reach the vertices k edges away.
+#[derive(Serialize, Deserialize)]
+pub struct NeighborAggregation {
+    pub(crate) context: DataTransformationContext,
+    pub(crate) vertices: DataIdT, // vertex selection data id
+    pub(crate) graph: DataIdT,    // graph data id
+    pub(crate) hops: Vec<AggregationHop>,
+    pub(crate) output: String,
+    pub(crate) input_type: FeatureValueType, // type of the feature read on the first hop
+    pub(crate) value_type: FeatureValueType, // type of the output column
+}
+
+impl NeighborAggregation {
+    /// Registers the aggregation over the given vertices, typing its output from the fields of
+    /// the graph
+    pub(crate) fn new(
+        vertices: &VertexSelectGraph,
+        hops: Vec<AggregationHop>,
+        output: &str,
+    ) -> Result<Rc<NeighborAggregation>, Box<dyn Error>> {
+        let first = hops.first().ok_or("a neighbor aggregation needs a hop")?;
+        if vertices.df.field_names().contains(output) {
+            return Err(format!("the vertices already have a column {}", output).into());
+        }
+        let input_type = match &first.aggregator.column {
+            // the neighbors are counted as ones
+            None => FeatureValueType::Int,
+            Some(column) => match graph_fields(&vertices.context, vertices.graph, true) {
+                DataFrameSet::Homo((_, fields)) => fields
+                    .into_iter()
+                    .find(|f| &f.name == column)
+                    .map(|f| f.value_type),
+                DataFrameSet::Hetero(dfs) => dfs
+                    .into_values()
+                    .flat_map(|(_, fields)| fields)
+                    .find(|f| &f.name == column)
+                    .map(|f| f.value_type),
+            }
+            .ok_or_else(|| format!("the vertices have no feature {}", column))?,
+        };
+        let value_type = hops
+            .iter()
+            .try_fold(input_type.clone(), |t, hop| hop.aggregator.output_type(&t))?;
+        let res = Rc::new(NeighborAggregation {
+            context: vertices.context.new_data_context(),
+            vertices: vertices.get_data_id(),
+            graph: vertices.graph,
+            hops,
+            output: output.to_string(),
+            input_type,
+            value_type,
+        });
+        res.context.register_data(&res);
+        Ok(res)
+    }
+
+    /// Calls `f` on the aggregated vertex selection
+    fn with_selection<R>(&self, f: impl FnOnce(&VertexSelectGraph) -> R) -> R {
+        with_data(&self.context, self.vertices, f)
+    }
+}
+
+#[typetag::serde]
+impl TransformationData for NeighborAggregation {
+    fn get_context(&self) -> &DataTransformationContext {
+        &self.context
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        let hops: Vec<String> = self
+            .hops
+            .iter()
+            .map(|hop| match &hop.edge_type {
+                Some(t) => format!("{} through {:?}", hop.aggregator, t),
+                None => hop.aggregator.to_string(),
+            })
+            .collect();
+        format!(
+            "aggregate_neighbors({}) AS {}",
+            hops.join(", "),
+            self.output
+        )
+    }
+
+    fn inputs(&self) -> Vec<DataIdT> {
+        vec![self.vertices, self.graph]
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        Some(Rc::new(NeighborAggregation {
+            context: self.context.clone(),
+            vertices: inputs.get(&self.vertices).copied().unwrap_or(self.vertices),
+            graph: inputs.get(&self.graph).copied().unwrap_or(self.graph),
+            hops: self.hops.clone(),
+            output: self.output.clone(),
+            input_type: self.input_type.clone(),
+            value_type: self.value_type.clone(),
+        }))
+    }
+
+    /// The aggregated vertices with the output column
+    fn exported(
+        &self,
+        _resource_id: &str,
+        result: &LocalData,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        match result {
+            LocalData::Vertices { ids, frame } => Ok(LocalData::Vertices {
+                ids: ids.clone(),
+                frame: LocalFrame {
+                    columns: frame.column(&self.output).into_iter().cloned().collect(),
+                },
+            }),
+            _ => Err(format!("data {} has no aggregated vertices", self.context.id).into()),
+        }
+    }
+
+    /// Aggregates over the local graph. The executor selects the vertices on the local engine too,
+    /// as the graph database identifies them by other ids.
+    fn execute_local(&self, executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        let (ids, mut frame) = match executor.result(self.vertices) {
+            Some(LocalData::Vertices { ids, frame }) => (ids.clone(), frame.clone()),
+            _ => return Err(format!("data {} has no selected vertices", self.vertices).into()),
+        };
+        let graph = executor.graph();
+        let scope = GraphScope::of(executor, self.graph)?;
+        let in_scope = |types: &[String], t: &str| types.iter().any(|s| s == t);
+
+        // the values of all the vertices of the graph, as neighbors of the next hop
+        let column = self.hops[0].aggregator.column.as_deref();
+        let mut values: HashMap<usize, FeatureValue> = HashMap::new();
+        for v in 0..graph.vertex_count() {
+            let vertex = graph.vertex(v);
+            if !in_scope(scope.vertex_types, &vertex.tlabel) {
+                continue;
+            }
+            let value = match column.map(|c| vertex.properties.get(c)) {
+                None => FeatureValue::Int(1),
+                Some(Some(value)) if value.value_type().as_ref() != Some(&self.input_type) => {
+                    value.cast(&self.input_type)?
+                }
+                Some(value) => value.cloned().unwrap_or(FeatureValue::Null),
+            };
+            values.insert(v, value);
+        }
+        let mut value_type = self.input_type.clone();
+        for hop in &self.hops {
+            let aggregator = &hop.aggregator;
+            let output_type = aggregator.output_type(&value_type)?;
+            let edge_value = |e: &crate::Edge, name: Option<&str>, default: FeatureValue| {
+                name.map_or(default, |name| {
+                    e.properties
+                        .get(name)
+                        .cloned()
+                        .unwrap_or(FeatureValue::Null)
+                })
+            };
+            let mut next = HashMap::with_capacity(values.len());
+            for &v in values.keys() {
+                let mut messages = Vec::new();
+                for e in graph.incident_edges(v, hop.edge_type.as_deref(), aggregator.direction) {
+                    let edge = graph.edge(e);
+                    if !in_scope(scope.edge_types, &edge.tlabel) {
+                        continue;
+                    }
+                    let neighbor = if edge.src == v { edge.dst } else { edge.src };
+                    if let Some(value) = values.get(&neighbor) {
+                        messages.push(Message {
+                            value: value.clone(),
+                            weight: edge_value(
+                                edge,
+                                aggregator.weight.as_deref(),
+                                FeatureValue::Int(1),
+                            ),
+                            timestamp: edge_value(edge, aggregator.timestamp(), FeatureValue::Null),
+                        });
+                    }
+                }
+                next.insert(v, aggregator.aggregate(&messages, &output_type)?);
+            }
+            values = next;
+            value_type = output_type;
+        }
+        frame.columns.push(LocalColumn {
+            name: self.output.clone(),
+            value_type: self.value_type.clone(),
+            values: ids
+                .iter()
+                .map(|id| values.remove(id).unwrap_or(FeatureValue::Null))
+                .collect(),
+        });
+        Ok(LocalData::Vertices { ids, frame })
+    }
+}
+
+impl GraphBase for NeighborAggregation {
+    fn vertices(&self) -> Rc<dyn GraphBase> {
+        self.with_selection(|v| v.vertices())
+    }
+
+    fn edges(&self) -> Rc<dyn GraphBase> {
+        self.with_selection(|v| v.edges())
+    }
+
+    fn vertices_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        self.with_selection(|v| v.vertices_by_type(t))
+    }
+
+    fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        self.with_selection(|v| v.edges_by_type(t))
+    }
+
+    /// Exports the output column as a field of the vertex entity of the aggregated vertices,
+    /// which must be of one type
+    fn export_fields(&self) -> Result<Vec<Field>, Box<dyn Error>> {
+        let entity_id = self.with_selection(|v| match &v.df {
+            DataFrameSet::Homo((_, fields)) => fields.first().map(|f| f.entity_id.clone()),
+            DataFrameSet::Hetero(_) => None,
+        });
+        let entity_id = entity_id.ok_or(
+            "cannot export an aggregation over vertices of several or unknown types, \
+             select one type first",
+        )?;
+        let res = Field {
+            name: self.output.clone(),
+            variant: None,
+            value_type: self.value_type.clone(),
+            entity_id,
+            transformation_id: Some(self.context.get_transformation_id()),
+            data_source_id: None,
+            description: Some(self.operation()),
+            tags: HashMap::new(),
+            owners: Vec::new(),
+        };
+        self.get_context()
+            .export_resource(self.get_data_id(), res.resource_id());
+        Ok(vec![res])
+    }
+}
+
+#[test]
+fn aggregate_neighbor_features() {
+    use crate::transformation::{
+        Aggregator, AggregatorFunc, GraphComputationOps, SingleGraph, TransformationExecutor,
+    };
+    use crate::{Direction, PropertyGraph, TransformationContext};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30, "emb": [1.0, 2.0]}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 40, "emb": [3.0, 4.0]}},
+                {"tlabel": "Person", "id": "p3", "properties": {"age": 50, "emb": [5.0, 0.0]}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p3"],
+                 "properties": {"since": 2010, "strength": 1.0}},
+                {"tlabel": "knows", "src": ["Person", "p2"], "dst": ["Person", "p3"],
+                 "properties": {"since": 2015, "strength": 3.0}},
+                {"tlabel": "knows", "src": ["Person", "p3"], "dst": ["Person", "p1"],
+                 "properties": {"since": 2012, "strength": 2.0}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let people = single_graph.vertices_by_type("Person").unwrap();
+    let aggregate = |aggregator: Aggregator, name: &str| {
+        people
+            .aggregate_neighbors(Some("knows".to_string()), aggregator, name.to_string())
+            .unwrap()
+    };
+    let aggregations = [
+        aggregate(Aggregator::new(AggregatorFunc::Sum, "age"), "sum_age"),
+        aggregate(
+            Aggregator::new(AggregatorFunc::Mean, "age").weighted("strength"),
+            "weighted_age",
+        ),
+        aggregate(Aggregator::count().along(Direction::Both), "degree"),
+        aggregate(Aggregator::new(AggregatorFunc::Mean, "emb"), "mean_emb"),
+        aggregate(
+            Aggregator::new(
+                AggregatorFunc::Last {
+                    timestamp: "since".to_string(),
+                },
+                "age",
+            ),
+            "latest_age",
+        ),
+        aggregate(
+            Aggregator::new(
+                AggregatorFunc::Histogram {
+                    bins: 2,
+                    min: 0.0,
+                    max: 100.0,
+                },
+                "age",
+            ),
+            "age_histogram",
+        ),
+        people
+            .aggregate_k_hop_neighbors(
+                2,
+                Vec::new(),
+                vec![Aggregator::new(AggregatorFunc::Collect, "age")],
+                "two_hops".to_string(),
+            )
+            .unwrap(),
+    ];
+    let fields: Vec<Field> = aggregations
+        .iter()
+        .map(|a| a.export_fields().unwrap().remove(0))
+        .collect();
+    assert_eq!(fields[0].resource_id(), "Field/Person/sum_age/");
+    assert_eq!(fields[0].value_type, FeatureValueType::Int);
+    assert_eq!(
+        fields[6].value_type,
+        FeatureValueType::Array(Box::new(FeatureValueType::Array(Box::new(
+            FeatureValueType::Int
+        ))))
+    );
+    assert!(people
+        .aggregate_neighbors(None, Aggregator::count(), "id".to_string())
+        .is_err());
+    assert!(people
+        .aggregate_neighbors(
+            None,
+            Aggregator::new(AggregatorFunc::Min, "age").weighted("strength"),
+            "min_age".to_string()
+        )
+        .is_err());
+    assert!(people
+        .aggregate_k_hop_neighbors(2, vec!["knows".to_string()], Vec::new(), "x".to_string())
+        .is_err());
+    assert!(single_graph
+        .aggregate_neighbors(None, Aggregator::count(), "in_degree".to_string())
+        .unwrap()
+        .export_fields()
+        .is_ok());
+
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("aggregations".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+    let mut local = LocalExecutor::new(&graph);
+    let output = executor.execute_local(&mut local).unwrap();
+    let values = |field: &Field| match &output[&field.resource_id()] {
+        LocalData::Vertices { ids, frame } => {
+            assert_eq!(ids, &vec![0, 1, 2]);
+            assert_eq!(frame.columns.len(), 1);
+            frame.columns[0].values.clone()
+        }
+        res => panic!("unexpected result {:?}", res),
+    };
+    use FeatureValue::{Array, Float, Int, Null};
+    assert_eq!(values(&fields[0]), vec![Int(50), Null, Int(70)]);
+    assert_eq!(
+        values(&fields[1]),
+        vec![Float(50.0), Null, Float((30.0 + 40.0 * 3.0) / 4.0)]
+    );
+    assert_eq!(values(&fields[2]), vec![Int(2), Int(1), Int(3)]);
+    assert_eq!(
+        values(&fields[3]),
+        vec![
+            Array(vec![Float(5.0), Float(0.0)]),
+            Null,
+            Array(vec![Float(2.0), Float(3.0)])
+        ]
+    );
+    assert_eq!(values(&fields[4]), vec![Int(50), Null, Int(40)]);
+    assert_eq!(
+        values(&fields[5]),
+        vec![
+            Array(vec![Int(0), Int(1)]),
+            Array(vec![Int(0), Int(0)]),
+            Array(vec![Int(2), Int(0)])
+        ]
+    );
+    // p1 hears from p3, which heard from p1 and p2
+    assert_eq!(
+        values(&fields[6]),
+        vec![
+            Array(vec![Array(vec![Int(30), Int(40)])]),
+            Array(Vec::new()),
+            Array(vec![Array(vec![Int(50)]), Array(Vec::new())])
+        ]
+    );
+
+    let explanation = executor.explain().unwrap();
+    assert!(explanation.data.iter().any(|d| d.operation
+        == "aggregate_neighbors(mean(age) weighted by strength along In through \"knows\") \
+            AS weighted_age"));
+}
+
+#[tokio::test]
+async fn aggregate_neighbors_with_graph_database() {
+    use crate::transformation::{
+        Aggregator, AggregatorFunc, GraphComputationOps, SingleGraph, TransformationExecutor,
+    };
+    use crate::{PropertyGraph, ScriptedGraphDatabase, TransformationContext};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 40}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    single_graph
+        .vertices_by_type("Person")
+        .unwrap()
+        .aggregate_neighbors(
+            None,
+            Aggregator::new(AggregatorFunc::Sum, "age"),
+            "sum_age".to_string(),
+        )
+        .unwrap()
+        .export_fields()
+        .unwrap();
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("aggregations".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+
+    // the vertices would be selected on the graph database, but the aggregation reads them with
+    // the local graph, so nothing is queried
+    let database = ScriptedGraphDatabase::new();
+    let mut local = LocalExecutor::new(&graph);
+    let output = executor.execute_on(&database, &mut local).await.unwrap();
+    let mut all_local = LocalExecutor::new(&graph);
+    assert_eq!(output, executor.execute_local(&mut all_local).unwrap());
+    let explanation = executor.explain().unwrap();
+    assert!(explanation
+        .data
+        .iter()
+        .all(|d| matches!(d.execution, crate::Execution::Local { .. })));
+}
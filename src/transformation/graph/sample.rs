@@ -0,0 +1,397 @@
+use super::select::{graph_fields, GraphScope};
+use super::{export_adjacency_list, with_data, DataFrameSet, VertexSelectGraph};
+use crate::transformation::built_in_fns::SplitMix64;
+use crate::transformation::local::build_frame;
+use crate::transformation::{
+    DataIdT, DataTransformationContext, GraphBase, InnerTransformationData, LocalData,
+    LocalExecutor, SamplingHop, SamplingSpec, TransformationData,
+};
+use crate::{FeatureValue, Topology};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::rc::Rc;
+
+/// The selected vertices with neighbors sampled hop after hop, such as the mini-batches a GNN is
+/// trained on. Each hop samples the neighbors of the vertices first reached by the previous
+/// hop, and the result is the subgraph of the seed vertices, the sampled neighbors and the
+/// edges they are sampled through.
+#[derive(Serialize, Deserialize)]
+pub struct NeighborSampling {
+    pub(crate) context: DataTransformationContext,
+    pub(crate) vertices: DataIdT, // seed vertex selection data id
+    pub(crate) graph: DataIdT,    // graph data id
+    pub(crate) hops: Vec<SamplingHop>,
+    pub(crate) spec: SamplingSpec,
+    pub(crate) vertex_df: DataFrameSet,
+    pub(crate) edge_df: DataFrameSet,
+}
+
+impl NeighborSampling {
+    /// Registers the sampling of the neighbors of the given vertices
+    pub(crate) fn new(
+        vertices: &VertexSelectGraph,
+        hops: Vec<SamplingHop>,
+        spec: SamplingSpec,
+    ) -> Result<Rc<NeighborSampling>, Box<dyn Error>> {
+        if hops.is_empty() {
+            return Err("a neighbor sampling needs a hop".into());
+        }
+        let res = Rc::new(NeighborSampling {
+            context: vertices.context.new_data_context(),
+            vertices: vertices.get_data_id(),
+            graph: vertices.graph,
+            hops,
+            spec,
+            vertex_df: graph_fields(&vertices.context, vertices.graph, true),
+            edge_df: graph_fields(&vertices.context, vertices.graph, false),
+        });
+        res.context.register_data(&res);
+        Ok(res)
+    }
+
+    /// Calls `f` on the seed vertex selection
+    fn with_selection<R>(&self, f: impl FnOnce(&VertexSelectGraph) -> R) -> R {
+        with_data(&self.context, self.vertices, f)
+    }
+}
+
+#[typetag::serde]
+impl TransformationData for NeighborSampling {
+    fn get_context(&self) -> &DataTransformationContext {
+        &self.context
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        let hops: Vec<String> = self
+            .hops
+            .iter()
+            .map(|hop| match &hop.edge_type {
+                Some(t) => format!("{} through {:?}", hop.fanout, t),
+                None => hop.fanout.to_string(),
+            })
+            .collect();
+        format!("sample_neighbors({}) {}", hops.join(", "), self.spec)
+    }
+
+    fn inputs(&self) -> Vec<DataIdT> {
+        vec![self.vertices, self.graph]
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        Some(Rc::new(NeighborSampling {
+            context: self.context.clone(),
+            vertices: inputs.get(&self.vertices).copied().unwrap_or(self.vertices),
+            graph: inputs.get(&self.graph).copied().unwrap_or(self.graph),
+            hops: self.hops.clone(),
+            spec: self.spec.clone(),
+            vertex_df: self.vertex_df.clone(),
+            edge_df: self.edge_df.clone(),
+        }))
+    }
+
+    /// Samples the local graph. The executor selects the seed vertices on the local engine too, as
+    /// the graph database identifies them by other ids.
+    fn execute_local(&self, executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        let seeds = match executor.result(self.vertices) {
+            Some(LocalData::Vertices { ids, .. }) => ids,
+            _ => return Err(format!("data {} has no selected vertices", self.vertices).into()),
+        };
+        let graph = executor.graph();
+        let scope = GraphScope::of(executor, self.graph)?;
+        let in_scope = |types: &[String], t: &str| types.iter().any(|s| s == t);
+        let mut rng = SplitMix64::new(self.spec.seed);
+
+        let mut reached = HashSet::new();
+        let mut vertex_ids: Vec<usize> = seeds
+            .iter()
+            .filter(|v| reached.insert(**v))
+            .copied()
+            .collect();
+        let mut edge_ids = Vec::new();
+        let mut frontier = vertex_ids.clone();
+        for hop in &self.hops {
+            let mut next = Vec::new();
+            for &v in &frontier {
+                let mut candidates = Vec::new();
+                let mut features = Vec::new();
+                for e in graph.incident_edges(v, hop.edge_type.as_deref(), self.spec.direction) {
+                    let edge = graph.edge(e);
+                    let neighbor = if edge.src == v { edge.dst } else { edge.src };
+                    if !in_scope(scope.edge_types, &edge.tlabel)
+                        || !in_scope(scope.vertex_types, &graph.vertex(neighbor).tlabel)
+                    {
+                        continue;
+                    }
+                    candidates.push((e, neighbor));
+                    features.push(
+                        self.spec
+                            .feature()
+                            .and_then(|f| edge.properties.get(f).cloned())
+                            .unwrap_or(FeatureValue::Null),
+                    );
+                }
+                let ids: Vec<usize> = candidates.iter().map(|(e, _)| *e).collect();
+                for i in self.spec.pick(&ids, &features, hop.fanout, &mut rng)? {
+                    let (e, neighbor) = candidates[i];
+                    edge_ids.push(e);
+                    if reached.insert(neighbor) {
+                        vertex_ids.push(neighbor);
+                        next.push(neighbor);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        let vertex_frame = build_frame(
+            &self.vertex_df.fields_by_type(scope.vertex_types),
+            vertex_ids.iter().map(|id| {
+                let vertex = graph.vertex(*id);
+                (vertex.tlabel.as_str(), &vertex.properties)
+            }),
+        )?;
+        let edge_frame = build_frame(
+            &self.edge_df.fields_by_type(scope.edge_types),
+            edge_ids.iter().map(|id| {
+                let edge = graph.edge(*id);
+                (edge.tlabel.as_str(), &edge.properties)
+            }),
+        )?;
+        Ok(LocalData::Subgraph {
+            vertex_ids,
+            vertex_frame,
+            edge_ids,
+            edge_frame,
+        })
+    }
+}
+
+/// Selections over a sampled graph read the seed vertices and the graph they are sampled from
+impl GraphBase for NeighborSampling {
+    fn vertices(&self) -> Rc<dyn GraphBase> {
+        self.with_selection(|v| v.vertices())
+    }
+
+    fn edges(&self) -> Rc<dyn GraphBase> {
+        self.with_selection(|v| v.edges())
+    }
+
+    fn vertices_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        self.with_selection(|v| v.vertices_by_type(t))
+    }
+
+    fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        self.with_selection(|v| v.edges_by_type(t))
+    }
+
+    /// Exports the sampled edges as an adjacency list over the edge entities of the graph
+    fn export_topology(&self, name: &str) -> Topology {
+        export_adjacency_list(self, self.graph, name)
+    }
+}
+
+#[test]
+fn sample_neighborhoods() {
+    use crate::transformation::{GraphComputationOps, SingleGraph, TransformationExecutor};
+    use crate::{PropertyGraph, ResourceOp, TopologyType, TransformationContext};
+
+    // p1 to p5 know p0, and p6 and p7 know p1
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p0", "properties": {"rank": 0}},
+                {"tlabel": "Person", "id": "p1", "properties": {"rank": 1}},
+                {"tlabel": "Person", "id": "p2", "properties": {"rank": 1}},
+                {"tlabel": "Person", "id": "p3", "properties": {"rank": 1}},
+                {"tlabel": "Person", "id": "p4", "properties": {"rank": 1}},
+                {"tlabel": "Person", "id": "p5", "properties": {"rank": 1}},
+                {"tlabel": "Person", "id": "p6", "properties": {"rank": 2}},
+                {"tlabel": "Person", "id": "p7", "properties": {"rank": 2}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p0"],
+                 "properties": {"weight": 0.0}},
+                {"tlabel": "knows", "src": ["Person", "p2"], "dst": ["Person", "p0"],
+                 "properties": {"weight": 3.0}},
+                {"tlabel": "knows", "src": ["Person", "p3"], "dst": ["Person", "p0"],
+                 "properties": {"weight": 0.0}},
+                {"tlabel": "knows", "src": ["Person", "p4"], "dst": ["Person", "p0"],
+                 "properties": {"weight": 1.0}},
+                {"tlabel": "knows", "src": ["Person", "p5"], "dst": ["Person", "p0"],
+                 "properties": {}},
+                {"tlabel": "knows", "src": ["Person", "p6"], "dst": ["Person", "p1"],
+                 "properties": {"weight": 1.0}},
+                {"tlabel": "knows", "src": ["Person", "p7"], "dst": ["Person", "p1"],
+                 "properties": {"weight": 1.0}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let seeds = single_graph
+        .vertices_by_type("Person")
+        .unwrap()
+        .filter("rank = 0")
+        .unwrap();
+    let one_hop = |fanout, spec| seeds.sample_neighbors(fanout, None, spec).unwrap();
+    let samples = [
+        one_hop(2, SamplingSpec::uniform().seeded(7)),
+        one_hop(2, SamplingSpec::uniform().seeded(7)),
+        one_hop(8, SamplingSpec::uniform().with_replacement()),
+        one_hop(3, SamplingSpec::weighted("weight").seeded(11)),
+        one_hop(2, SamplingSpec::top_k("weight")),
+        seeds
+            .sample_k_hop_neighbors(
+                2,
+                vec![5, 1],
+                Some(vec!["knows".to_string()]),
+                SamplingSpec::uniform(),
+            )
+            .unwrap(),
+    ];
+    let topologies: Vec<Topology> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s.export_topology(&format!("sample{}", i)))
+        .collect();
+    assert!(matches!(
+        topologies[0].topology_type,
+        Some(TopologyType::AdjacencyList)
+    ));
+    assert_eq!(
+        topologies[0].edge_entity_ids,
+        vec!["Entity/knows/".to_string()]
+    );
+    assert!(seeds
+        .sample_k_hop_neighbors(0, vec![1], None, SamplingSpec::uniform())
+        .is_err());
+    assert!(seeds
+        .sample_k_hop_neighbors(3, vec![1, 2], None, SamplingSpec::uniform())
+        .is_err());
+
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("samples".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+    let mut local = LocalExecutor::new(&graph);
+    let output = executor.execute_local(&mut local).unwrap();
+    let sampled = |i: usize| match &output[&topologies[i].resource_id()] {
+        LocalData::Subgraph {
+            vertex_ids,
+            edge_ids,
+            edge_frame,
+            ..
+        } => {
+            assert_eq!(vertex_ids[0], 0);
+            assert_eq!(edge_ids.len(), edge_frame.columns[0].values.len());
+            (vertex_ids.clone(), edge_ids.clone())
+        }
+        res => panic!("unexpected result {:?}", res),
+    };
+    let (vertices, edges) = sampled(0);
+    assert_eq!((vertices.len(), edges.len()), (3, 2));
+    assert_ne!(edges[0], edges[1]);
+    assert!(edges.iter().all(|e| *e < 5));
+    // the same seed samples the same neighbors
+    assert_eq!(sampled(1), (vertices, edges));
+    let (_, edges) = sampled(2);
+    assert_eq!(edges.len(), 8);
+    assert!(edges.iter().all(|e| *e < 5));
+    // only the edges with a positive weight are sampled
+    let (_, mut edges) = sampled(3);
+    edges.sort();
+    assert_eq!(edges, vec![1, 3]);
+    assert_eq!(sampled(4), (vec![0, 2, 4], vec![1, 3]));
+    let (mut vertices, edges) = sampled(5);
+    vertices.sort();
+    assert_eq!(vertices.len(), 7);
+    assert_eq!(edges.len(), 6);
+    assert!(edges[5] == 5 || edges[5] == 6);
+
+    let explanation = executor.explain().unwrap();
+    let sampling = explanation
+        .data
+        .iter()
+        .find(|d| d.operation == "sample_neighbors(5 through \"knows\", 1 through \"knows\") uniform along In seeded 0")
+        .unwrap();
+    // the seeds are selected locally, with the sampling
+    assert!(matches!(sampling.execution, crate::Execution::Local { .. }));
+}
+
+#[tokio::test]
+async fn sample_neighbors_with_graph_database() {
+    use crate::transformation::{GraphComputationOps, SingleGraph, TransformationExecutor};
+    use crate::{
+        GraphRows, GraphValue, PropertyGraph, ScriptedGraphDatabase, TransformationContext,
+    };
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p0", "properties": {"rank": 0}},
+                {"tlabel": "Person", "id": "p1", "properties": {"rank": 1}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p0"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let seeds = single_graph
+        .vertices_by_type("Person")
+        .unwrap()
+        .filter("rank = 0")
+        .unwrap();
+    seeds
+        .sample_neighbors(1, None, SamplingSpec::uniform())
+        .unwrap()
+        .export_topology("sample");
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("samples".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+
+    // the seeds would be selected on the graph database, but the sampling reads them with the
+    // local graph, so only the selection of the type, which is not read, is queried
+    let database = ScriptedGraphDatabase::new();
+    let value = |v: FeatureValue| GraphValue::Value(v);
+    let rows = GraphRows {
+        columns: ["__id", "__tlabel", "id", "rank"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+        rows: vec![vec![
+            value(FeatureValue::Int(1000)),
+            value(FeatureValue::String("Person".to_string())),
+            value(FeatureValue::String("p0".to_string())),
+            value(FeatureValue::Int(0)),
+        ]],
+    };
+    database.expect("n:`Person` RETURN", rows);
+    let mut local = LocalExecutor::new(&graph);
+    let output = executor.execute_on(&database, &mut local).await.unwrap();
+    assert_eq!(database.pending(), 0);
+    let mut all_local = LocalExecutor::new(&graph);
+    assert_eq!(output, executor.execute_local(&mut all_local).unwrap());
+    match output.values().next().unwrap() {
+        LocalData::Subgraph { vertex_ids, .. } => assert_eq!(vertex_ids, &vec![0, 1]),
+        res => panic!("unexpected result {:?}", res),
+    }
+}
@@ -1,15 +1,27 @@
+use super::{NeighborAggregation, NeighborSampling, SingleGraph, SubGraph};
 use crate::{
+    merge_value_types, observed_types,
     transformation::{
-        built_in_fns::{Expression, SamplingSpec},
-        DataIdT, DataTransformationContext, GraphBase, TransformationData,
+        built_in_fns::{add_param, quote_identifier, Expression, SamplingSpec, SplitMix64},
+        local::build_frame,
+        AggregationHop, CypherQuery, DataIdT, DataTransformationContext, GraphBase,
+        InnerTransformationData, LocalColumn, LocalData, LocalExecutor, LocalFrame, SamplingHop,
+        TransformationData,
     },
-    Field,
+    Direction, FeatureValue, FeatureValueType, Field, FunctionRegistry, GraphRows, GraphValue,
+    ResourceOp,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    any::Any,
+    collections::{BTreeSet, HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    rc::Rc,
+};
 
 /// The representation of vertex/edge selection, recursively defined as a union of direct access, filtering, and sampling results
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Selector {
     /// Direct access to the set of vertices/edges in a graph
     DirectAccess {
@@ -17,34 +29,859 @@ pub enum Selector {
     },
     /// A set of vertices/edges in a graph whose expression is evaluated to true
     Expression(Expression),
-    /// A set of sampled vertices/edges in a graph
-    Sampling(SamplingSpec),
+    /// Up to `size` vertices/edges sampled among the selected ones, reading the feature of the
+    /// sampling method from their properties
+    Sampling {
+        spec: SamplingSpec,
+        size: usize,
+    },
     Union(Box<Selector>, Box<Selector>),
+    /// The vertices/edges selected by the second selector among the ones selected by the first
+    Intersect(Box<Selector>, Box<Selector>),
+    /// The vertices/edges selected by the first selector and not by the second
+    Difference(Box<Selector>, Box<Selector>),
+    /// The vertices/edges not selected by the selector
+    Not(Box<Selector>),
+    /// The edges leaving (Out), entering (In) or touching (Both) the vertices of the selector
+    Incident {
+        vertices: Box<Selector>,
+        direction: Direction,
+    },
+    /// The vertices that the edges of the selector leave (Out), enter (In) or touch (Both)
+    Endpoints {
+        edges: Box<Selector>,
+        direction: Direction,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
+impl Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Selector::DirectAccess { ltype: None } => write!(f, "all"),
+            Selector::DirectAccess { ltype: Some(t) } => write!(f, "type = {:?}", t),
+            Selector::Expression(expr) => write!(f, "{}", expr),
+            Selector::Sampling { spec, size } => write!(f, "sample {} {}", size, spec),
+            Selector::Union(a, b) => write!(f, "({}) or ({})", a, b),
+            Selector::Intersect(a, b) => write!(f, "({}) intersect ({})", a, b),
+            Selector::Difference(a, b) => write!(f, "({}) except ({})", a, b),
+            Selector::Not(s) => write!(f, "not ({})", s),
+            Selector::Incident {
+                vertices,
+                direction,
+            } => match direction {
+                Direction::Out => write!(f, "edges leaving ({})", vertices),
+                Direction::In => write!(f, "edges entering ({})", vertices),
+                Direction::Both => write!(f, "edges touching ({})", vertices),
+            },
+            Selector::Endpoints { edges, direction } => match direction {
+                Direction::Out => write!(f, "vertices left by ({})", edges),
+                Direction::In => write!(f, "vertices entered by ({})", edges),
+                Direction::Both => write!(f, "vertices touched by ({})", edges),
+            },
+        }
+    }
+}
+
+/// A selection compiled into a Cypher predicate, and the conjuncts of its expression that are
+/// evaluated locally on the elements matching the predicate
+struct CypherSelection {
+    predicate: String,
+    residual: Vec<Expression>,
+}
+
+/// The vertex and edge types of the graph a selection reads from
+#[derive(Clone, Copy)]
+pub(crate) struct GraphScope<'a> {
+    pub(crate) vertex_types: &'a [String],
+    pub(crate) edge_types: &'a [String],
+}
+
+impl<'a> GraphScope<'a> {
+    /// The types of the graph computed by the executor
+    pub(crate) fn of(
+        executor: &'a LocalExecutor,
+        graph: DataIdT,
+    ) -> Result<GraphScope<'a>, Box<dyn Error>> {
+        Ok(GraphScope {
+            vertex_types: executor.graph_types(graph, true)?,
+            edge_types: executor.graph_types(graph, false)?,
+        })
+    }
+
+    fn types(&self, vertices: bool) -> &'a [String] {
+        if vertices {
+            self.vertex_types
+        } else {
+            self.edge_types
+        }
+    }
+}
+
+impl Selector {
+    /// Selects the elements of the given type, or all elements if None
+    pub fn by_type(ltype: Option<&str>) -> Selector {
+        Selector::DirectAccess {
+            ltype: ltype.map(|t| t.to_string()),
+        }
+    }
+
+    /// Selects the vertices reached from the vertices of the selector through edges of the given
+    /// type, or of all types if None, followed in the given direction
+    pub fn adjacent(vertices: Selector, edge_type: Option<&str>, direction: Direction) -> Selector {
+        let edges = Selector::Incident {
+            vertices: Box::new(vertices),
+            direction,
+        };
+        let edges = match edge_type {
+            Some(_) => Selector::Intersect(Box::new(edges), Box::new(Selector::by_type(edge_type))),
+            None => edges,
+        };
+        Selector::Endpoints {
+            edges: Box::new(edges),
+            direction: match direction {
+                Direction::Out => Direction::In,
+                Direction::In => Direction::Out,
+                Direction::Both => Direction::Both,
+            },
+        }
+    }
+
+    /// Returns an equivalent selector without selections of all elements in intersections and
+    /// unions and without double negations, with consecutive filters merged into one expression
+    /// unless they aggregate
+    pub fn simplify(self) -> Selector {
+        let all = |s: &Selector| matches!(s, Selector::DirectAccess { ltype: None });
+        match self {
+            Selector::Intersect(first, second) => match (first.simplify(), second.simplify()) {
+                (s, other) | (other, s) if all(&other) => s,
+                (Selector::Expression(a), Selector::Expression(b))
+                    if !a.contains_aggregate() && !b.contains_aggregate() =>
+                {
+                    Selector::Expression(a.and(&b))
+                }
+                (a, b) => Selector::Intersect(Box::new(a), Box::new(b)),
+            },
+            Selector::Union(left, right) => match (left.simplify(), right.simplify()) {
+                (s, _) | (_, s) if all(&s) => s,
+                (a, b) => Selector::Union(Box::new(a), Box::new(b)),
+            },
+            Selector::Difference(first, second) => match (first.simplify(), second.simplify()) {
+                (s, other) if all(&s) => Selector::Not(Box::new(other)),
+                (a, b) => Selector::Difference(Box::new(a), Box::new(b)),
+            },
+            Selector::Not(s) => match s.simplify() {
+                Selector::Not(s) => *s,
+                s => Selector::Not(Box::new(s)),
+            },
+            Selector::Incident {
+                vertices,
+                direction,
+            } => Selector::Incident {
+                vertices: Box::new(vertices.simplify()),
+                direction,
+            },
+            Selector::Endpoints { edges, direction } => Selector::Endpoints {
+                edges: Box::new(edges.simplify()),
+                direction,
+            },
+            s => s,
+        }
+    }
+
+    /// Whether the selector reads the edges of vertices or the endpoints of edges
+    fn crosses_kinds(&self) -> bool {
+        match self {
+            Selector::Incident { .. } | Selector::Endpoints { .. } => true,
+            Selector::Union(a, b) | Selector::Intersect(a, b) | Selector::Difference(a, b) => {
+                a.crosses_kinds() || b.crosses_kinds()
+            }
+            Selector::Not(s) => s.crosses_kinds(),
+            _ => false,
+        }
+    }
+
+    /// Returns the ids of the selected vertices or edges among the ones of the types of the
+    /// graph, in the order of the types and then of the ids
+    pub(crate) fn select_local(
+        &self,
+        executor: &LocalExecutor,
+        scope: GraphScope,
+        vertices: bool,
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        let graph = executor.graph();
+        let element_types = if vertices {
+            graph.vertex_types()
+        } else {
+            graph.edge_types()
+        };
+        let candidates = scope
+            .types(vertices)
+            .iter()
+            .filter_map(|t| element_types.get(t))
+            .flat_map(|t| t.ids.iter().copied())
+            .collect();
+        self.select_among(executor, scope, vertices, candidates)
+    }
+
+    /// Returns the selected vertices or edges among the candidates, keeping their order. The
+    /// second selector of an intersection only sees the elements selected by the first, so its
+    /// aggregates are computed over those elements only.
+    fn select_among(
+        &self,
+        executor: &LocalExecutor,
+        scope: GraphScope,
+        vertices: bool,
+        candidates: Vec<usize>,
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        let graph = executor.graph();
+        let element = |id: &usize| match vertices {
+            true => (&graph.vertex(*id).tlabel, &graph.vertex(*id).properties),
+            false => (&graph.edge(*id).tlabel, &graph.edge(*id).properties),
+        };
+        match self {
+            Selector::DirectAccess { ltype: None } => Ok(candidates),
+            Selector::DirectAccess { ltype: Some(t) } => Ok(candidates
+                .into_iter()
+                .filter(|id| element(id).0 == t)
+                .collect()),
+            Selector::Expression(expr) => {
+                let element_types = if vertices {
+                    graph.vertex_types()
+                } else {
+                    graph.edge_types()
+                };
+                let mut columns: HashMap<String, FeatureValueType> = HashMap::new();
+                for t in scope
+                    .types(vertices)
+                    .iter()
+                    .filter_map(|t| element_types.get(t))
+                {
+                    for (name, value_type) in &t.schema {
+                        let merged = match columns.get(name) {
+                            Some(other) => merge_value_types(other, value_type),
+                            None => value_type.clone(),
+                        };
+                        columns.insert(name.clone(), merged);
+                    }
+                }
+                let properties: Vec<_> = candidates.iter().map(|id| element(id).1).collect();
+                let keep = filter_elements(
+                    &expr.conjuncts(),
+                    &columns,
+                    &properties,
+                    executor.functions(),
+                )?;
+                Ok(candidates
+                    .into_iter()
+                    .zip(keep)
+                    .filter(|(_, k)| *k)
+                    .map(|(id, _)| id)
+                    .collect())
+            }
+            Selector::Union(left, right) => {
+                let mut res = left.select_among(executor, scope, vertices, candidates.clone())?;
+                for id in right.select_among(executor, scope, vertices, candidates)? {
+                    if !res.contains(&id) {
+                        res.push(id);
+                    }
+                }
+                Ok(res)
+            }
+            Selector::Intersect(first, second) => {
+                let candidates = first.select_among(executor, scope, vertices, candidates)?;
+                second.select_among(executor, scope, vertices, candidates)
+            }
+            Selector::Difference(first, second) => {
+                let excluded: HashSet<usize> = second
+                    .select_among(executor, scope, vertices, candidates.clone())?
+                    .into_iter()
+                    .collect();
+                Ok(first
+                    .select_among(executor, scope, vertices, candidates)?
+                    .into_iter()
+                    .filter(|id| !excluded.contains(id))
+                    .collect())
+            }
+            Selector::Not(s) => {
+                let excluded: HashSet<usize> = s
+                    .select_among(executor, scope, vertices, candidates.clone())?
+                    .into_iter()
+                    .collect();
+                Ok(candidates
+                    .into_iter()
+                    .filter(|id| !excluded.contains(id))
+                    .collect())
+            }
+            Selector::Incident {
+                vertices: selected,
+                direction,
+            } => {
+                if vertices {
+                    return Err("incident edges cannot be selected among vertices".into());
+                }
+                let selected: HashSet<usize> = selected
+                    .select_local(executor, scope, true)?
+                    .into_iter()
+                    .collect();
+                Ok(candidates
+                    .into_iter()
+                    .filter(|id| {
+                        let edge = graph.edge(*id);
+                        (*direction != Direction::In && selected.contains(&edge.src))
+                            || (*direction != Direction::Out && selected.contains(&edge.dst))
+                    })
+                    .collect())
+            }
+            Selector::Endpoints { edges, direction } => {
+                if !vertices {
+                    return Err("endpoint vertices cannot be selected among edges".into());
+                }
+                let selected: HashSet<usize> = edges
+                    .select_local(executor, scope, false)?
+                    .into_iter()
+                    .collect();
+                Ok(candidates
+                    .into_iter()
+                    .filter(|id| {
+                        graph
+                            .incident_edges(*id, None, *direction)
+                            .iter()
+                            .any(|e| selected.contains(e))
+                    })
+                    .collect())
+            }
+            Selector::Sampling { spec, size } => {
+                let features: Vec<FeatureValue> = candidates
+                    .iter()
+                    .map(|id| {
+                        spec.feature()
+                            .and_then(|f| element(id).1.get(f).cloned())
+                            .unwrap_or(FeatureValue::Null)
+                    })
+                    .collect();
+                // an element picked more than once is selected once
+                let picked: HashSet<usize> = spec
+                    .pick(
+                        &candidates,
+                        &features,
+                        *size,
+                        &mut SplitMix64::new(spec.seed),
+                    )?
+                    .into_iter()
+                    .map(|i| candidates[i])
+                    .collect();
+                Ok(candidates
+                    .into_iter()
+                    .filter(|id| picked.contains(id))
+                    .collect())
+            }
+        }
+    }
+
+    /// Compiles the selection among elements of the given types into a Cypher predicate on
+    /// `variable`. Conjuncts of expressions that Cypher cannot compute are left to evaluate
+    /// locally. Fails for sampling and for unions, differences, negations and neighborhoods with
+    /// such conjuncts.
+    fn to_cypher(
+        &self,
+        variable: &str,
+        vertices: bool,
+        scope: GraphScope,
+        columns: &HashMap<String, FeatureValueType>,
+        params: &mut HashMap<String, FeatureValue>,
+    ) -> Result<CypherSelection, Box<dyn Error>> {
+        let types = scope.types(vertices);
+        // the selectors evaluated on all their candidates before the remaining conjuncts
+        let exact = |s: &Selector,
+                     variable: &str,
+                     vertices: bool,
+                     columns: &HashMap<String, FeatureValueType>,
+                     params: &mut HashMap<String, FeatureValue>|
+         -> Result<String, Box<dyn Error>> {
+            let selection = s.to_cypher(variable, vertices, scope, columns, params)?;
+            if !selection.residual.is_empty() {
+                return Err(format!(
+                    "selections of ({}) with expressions evaluated locally are not pushed down",
+                    self
+                )
+                .into());
+            }
+            Ok(selection.predicate)
+        };
+        let predicate = match self {
+            Selector::DirectAccess { ltype: None } => "true".to_string(),
+            Selector::DirectAccess { ltype: Some(t) } if !types.contains(t) => "false".to_string(),
+            Selector::DirectAccess { ltype: Some(t) } if vertices => {
+                format!("{}:{}", variable, quote_identifier(t))
+            }
+            Selector::DirectAccess { ltype: Some(t) } => format!(
+                "type({}) = {}",
+                variable,
+                add_param(params, FeatureValue::String(t.clone()))
+            ),
+            Selector::Expression(expr) => {
+                let mut predicates = Vec::new();
+                let mut residual = Vec::new();
+                for conjunct in expr.conjuncts() {
+                    let mut compiled_params = params.clone();
+                    match conjunct.to_cypher(Some(variable), columns, &mut compiled_params) {
+                        Ok(predicate) => {
+                            *params = compiled_params;
+                            predicates.push(predicate);
+                        }
+                        Err(_) => residual.push(conjunct),
+                    }
+                }
+                let predicate = match predicates.is_empty() {
+                    true => "true".to_string(),
+                    false => predicates.join(" AND "),
+                };
+                return Ok(CypherSelection {
+                    predicate,
+                    residual,
+                });
+            }
+            Selector::Union(left, right) => {
+                let left = exact(left, variable, vertices, columns, params)?;
+                let right = exact(right, variable, vertices, columns, params)?;
+                format!("({} OR {})", left, right)
+            }
+            Selector::Intersect(first, second) => {
+                let first = first.to_cypher(variable, vertices, scope, columns, params)?;
+                let second = second.to_cypher(variable, vertices, scope, columns, params)?;
+                let mut residual = first.residual;
+                residual.extend(second.residual);
+                if residual.iter().any(|c| c.contains_aggregate()) {
+                    return Err(
+                        "intersections with aggregates evaluated locally are not pushed down"
+                            .into(),
+                    );
+                }
+                return Ok(CypherSelection {
+                    predicate: format!("({} AND {})", first.predicate, second.predicate),
+                    residual,
+                });
+            }
+            // a predicate on a null property is null and does not select the element, so the
+            // complement of the selection must select it
+            Selector::Difference(first, second) => {
+                let first = exact(first, variable, vertices, columns, params)?;
+                let second = exact(second, variable, vertices, columns, params)?;
+                format!("({} AND NOT coalesce({}, false))", first, second)
+            }
+            Selector::Not(s) => format!(
+                "(NOT coalesce({}, false))",
+                exact(s, variable, vertices, columns, params)?
+            ),
+            Selector::Incident {
+                vertices: selected,
+                direction,
+            } => {
+                if vertices {
+                    return Err("incident edges cannot be selected among vertices".into());
+                }
+                // the properties of the other element type are not known to type the expressions
+                let inner = format!("{}_n", variable);
+                let predicate = exact(selected, &inner, true, &HashMap::new(), params)?;
+                let types_param = add_param(
+                    params,
+                    FeatureValue::Array(
+                        scope
+                            .vertex_types
+                            .iter()
+                            .cloned()
+                            .map(FeatureValue::String)
+                            .collect(),
+                    ),
+                );
+                let pattern = match direction {
+                    Direction::Out => format!("({})-[{}]->()", inner, variable),
+                    Direction::In => format!("()-[{}]->({})", variable, inner),
+                    Direction::Both => format!("({})-[{}]-()", inner, variable),
+                };
+                format!(
+                    "EXISTS {{ MATCH {} WHERE any(l IN labels({}) WHERE l IN {}) AND {} }}",
+                    pattern, inner, types_param, predicate
+                )
+            }
+            Selector::Endpoints { edges, direction } => {
+                if !vertices {
+                    return Err("endpoint vertices cannot be selected among edges".into());
+                }
+                let inner = format!("{}_e", variable);
+                let predicate = exact(edges, &inner, false, &HashMap::new(), params)?;
+                let types_param = add_param(
+                    params,
+                    FeatureValue::Array(
+                        scope
+                            .edge_types
+                            .iter()
+                            .cloned()
+                            .map(FeatureValue::String)
+                            .collect(),
+                    ),
+                );
+                let pattern = match direction {
+                    Direction::Out => format!("({})-[{}]->()", variable, inner),
+                    Direction::In => format!("()-[{}]->({})", inner, variable),
+                    Direction::Both => format!("({})-[{}]-()", variable, inner),
+                };
+                format!(
+                    "EXISTS {{ MATCH {} WHERE type({}) IN {} AND {} }}",
+                    pattern, inner, types_param, predicate
+                )
+            }
+            Selector::Sampling { .. } => return Err("sampling is not pushed down".into()),
+        };
+        Ok(CypherSelection {
+            predicate,
+            residual: Vec::new(),
+        })
+    }
+}
+
+/// Evaluates the conjuncts on the properties of each element, keeping the elements for which
+/// they are all true. Properties are read as the given types.
+fn filter_elements(
+    conjuncts: &[Expression],
+    columns: &HashMap<String, FeatureValueType>,
+    elements: &[&HashMap<String, FeatureValue>],
+    functions: &FunctionRegistry,
+) -> Result<Vec<bool>, Box<dyn Error>> {
+    let mut keep = vec![true; elements.len()];
+    if conjuncts.is_empty() {
+        return Ok(keep);
+    }
+    let mut frame = LocalFrame::default();
+    let names: BTreeSet<&str> = conjuncts.iter().flat_map(|c| c.columns()).collect();
+    for name in names {
+        let value_type = match columns.get(name) {
+            Some(value_type) => value_type,
+            // evaluation reports the unknown column
+            None => continue,
+        };
+        let values = elements
+            .iter()
+            .map(|properties| match properties.get(name) {
+                Some(v) if v.value_type().as_ref().is_some_and(|t| t != value_type) => {
+                    v.cast(value_type)
+                }
+                Some(v) => Ok(v.clone()),
+                None => Ok(FeatureValue::Null),
+            })
+            .collect::<Result<_, _>>()?;
+        frame.columns.push(LocalColumn {
+            name: name.to_string(),
+            value_type: value_type.clone(),
+            values,
+        });
+    }
+    if frame.columns.is_empty() {
+        // constant conditions are evaluated once per element
+        frame.columns.push(LocalColumn {
+            name: String::new(),
+            value_type: FeatureValueType::Int,
+            values: vec![FeatureValue::Null; elements.len()],
+        });
+    }
+    for conjunct in conjuncts {
+        for (keep, value) in keep
+            .iter_mut()
+            .zip(conjunct.evaluate_with(&frame, functions)?)
+        {
+            *keep &= value == FeatureValue::Boolean(true);
+        }
+    }
+    Ok(keep)
+}
+
+/// The aliases of the element id and tlabel in the queries of selections, which cannot clash
+/// with property names
+const ID_COLUMN: &str = "__id";
+const TLABEL_COLUMN: &str = "__tlabel";
+
+const SOURCE_ON_DATABASE: &str = "selections over a Cypher query run on the graph database";
+const NEIGHBORHOOD_OF_SOURCE: &str =
+    "the edges and endpoints of elements selected over a Cypher query are not supported";
+
+/// Compiles the selection of vertices or edges into a query returning the id, the tlabel, the
+/// fields and the properties read by the conjuncts evaluated locally of each selected element.
+/// The candidates are the elements of the graph, or the ones returned by the source query.
+fn compile_select(
+    selector: &Selector,
+    df: &DataFrameSet,
+    vertices: bool,
+    scope: GraphScope,
+    source: Option<&CypherSource>,
+) -> Result<(CypherQuery, Vec<Expression>), Box<dyn Error>> {
+    if source.is_some() && selector.crosses_kinds() {
+        return Err(NEIGHBORHOOD_OF_SOURCE.into());
+    }
+    let types = scope.types(vertices);
+    let columns: HashMap<String, FeatureValueType> = df
+        .fields_by_type(types)
+        .values()
+        .flat_map(|fields| fields.iter())
+        .map(|f| (f.name.clone(), f.value_type.clone()))
+        .collect();
+    let mut params = HashMap::new();
+    let types_param = add_param(
+        &mut params,
+        FeatureValue::Array(types.iter().cloned().map(FeatureValue::String).collect()),
+    );
+    let (variable, pattern, type_filter, tlabel) = if vertices {
+        (
+            "n",
+            "(n)",
+            format!("any(l IN labels(n) WHERE l IN {})", types_param),
+            format!("[l IN labels(n) WHERE l IN {}][0]", types_param),
+        )
+    } else {
+        (
+            "e",
+            "()-[e]->()",
+            format!("type(e) IN {}", types_param),
+            "type(e)".to_string(),
+        )
+    };
+    let selection = selector.to_cypher(variable, vertices, scope, &columns, &mut params)?;
+    let mut returned: BTreeSet<&str> = columns.keys().map(|k| k.as_str()).collect();
+    for conjunct in &selection.residual {
+        returned.extend(conjunct.columns());
+    }
+    let candidates = match source {
+        // unwinding a value that is not a list gives the value itself
+        Some(source) => format!(
+            "CALL {{ {} }} UNWIND [{}] AS __value UNWIND __value AS {2} WITH DISTINCT {2}",
+            source.query,
+            source.columns.join(", "),
+            variable
+        ),
+        None => format!("MATCH {}", pattern),
+    };
+    let mut text = format!(
+        "{} WHERE {} AND {} RETURN id({}) AS {}, {} AS {}",
+        candidates, type_filter, selection.predicate, variable, ID_COLUMN, tlabel, TLABEL_COLUMN
+    );
+    for name in returned {
+        let name = quote_identifier(name);
+        text.push_str(&format!(", {}.{} AS {}", variable, name, name));
+    }
+    text.push_str(&format!(" ORDER BY {}, {}", TLABEL_COLUMN, ID_COLUMN));
+    let query = CypherQuery {
+        text,
+        params,
+        complete: selection.residual.is_empty(),
+    };
+    Ok((query, selection.residual))
+}
+
+/// Builds the selected ids and frame from the rows of a query compiled by `compile_select`,
+/// keeping the rows for which the residual conjuncts are true
+fn decode_select(
+    residual: &[Expression],
+    df: &DataFrameSet,
+    types: &[String],
+    rows: GraphRows,
+    functions: &FunctionRegistry,
+) -> Result<(Vec<usize>, LocalFrame), Box<dyn Error>> {
+    let mut elements = Vec::with_capacity(rows.rows.len());
+    for row in rows.rows {
+        let mut values = row.into_iter().map(|v| match v {
+            GraphValue::Value(v) => v,
+            _ => FeatureValue::Null,
+        });
+        let (id, tlabel) = match (values.next(), values.next()) {
+            (Some(FeatureValue::Int(id)), Some(FeatureValue::String(tlabel))) => (id, tlabel),
+            (id, tlabel) => return Err(format!("unexpected element {:?} {:?}", id, tlabel).into()),
+        };
+        let properties: HashMap<String, FeatureValue> =
+            rows.columns[2..].iter().cloned().zip(values).collect();
+        elements.push((id as usize, tlabel, properties));
+    }
+    let fields_by_type = df.fields_by_type(types);
+    let mut columns = observed_types(elements.iter().map(|(_, _, p)| p));
+    for fields in fields_by_type.values() {
+        for field in fields.iter() {
+            columns.insert(field.name.clone(), field.value_type.clone());
+        }
+    }
+    let properties: Vec<_> = elements.iter().map(|(_, _, p)| p).collect();
+    let keep = filter_elements(
+        residual,
+        &columns.into_iter().collect(),
+        &properties,
+        functions,
+    )?;
+    let selected: Vec<_> = elements
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(e, _)| e)
+        .collect();
+    let frame = build_frame(
+        &fields_by_type,
+        selected.iter().map(|(_, tlabel, p)| (tlabel.as_str(), p)),
+    )?;
+    Ok((selected.iter().map(|(id, _, _)| *id).collect(), frame))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub enum DataFrameSet {
     Homo((String, Vec<Field>)),                    // name, fields
     Hetero(HashMap<String, (String, Vec<Field>)>), // {type, {name, fields}}
 }
 
+impl DataFrameSet {
+    /// The names of the fields of all types
+    pub(crate) fn field_names(&self) -> BTreeSet<&str> {
+        match self {
+            DataFrameSet::Homo((_, fields)) => fields.iter().map(|f| f.name.as_str()).collect(),
+            DataFrameSet::Hetero(dfs) => dfs
+                .values()
+                .flat_map(|(_, fields)| fields.iter().map(|f| f.name.as_str()))
+                .collect(),
+        }
+    }
+
+    /// Keeps the fields with the given names
+    pub(crate) fn retain_fields(&self, names: &BTreeSet<&str>) -> DataFrameSet {
+        let retain = |fields: &Vec<Field>| -> Vec<Field> {
+            fields
+                .iter()
+                .filter(|f| names.contains(f.name.as_str()))
+                .cloned()
+                .collect()
+        };
+        match self {
+            DataFrameSet::Homo((name, fields)) => {
+                DataFrameSet::Homo((name.clone(), retain(fields)))
+            }
+            DataFrameSet::Hetero(dfs) => DataFrameSet::Hetero(
+                dfs.iter()
+                    .map(|(t, (name, fields))| (t.clone(), (name.clone(), retain(fields))))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The fields of a Homo set as fields computed by the given transformation, on the entities
+    /// of the original fields. The fields of a Hetero set belong to several element types, so
+    /// one type has to be selected first.
+    fn export_fields(&self, transformation_id: String) -> Result<Vec<Field>, Box<dyn Error>> {
+        match self {
+            DataFrameSet::Homo((_, fields)) => Ok(fields
+                .iter()
+                .map(|f| Field {
+                    transformation_id: Some(transformation_id.clone()),
+                    ..f.clone()
+                })
+                .collect()),
+            DataFrameSet::Hetero(_) => {
+                Err("cannot export the fields of several types, select one type first".into())
+            }
+        }
+    }
+
+    /// Picks the selected elements and the column of an exported field from the result of a
+    /// selection
+    fn exported(
+        &self,
+        data: DataIdT,
+        resource_id: &str,
+        result: &LocalData,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        let field = self
+            .export_fields(String::new())?
+            .into_iter()
+            .find(|f| f.resource_id() == resource_id)
+            .ok_or_else(|| format!("data {} does not export {}", data, resource_id))?;
+        let pick = |frame: &LocalFrame| -> Result<LocalFrame, Box<dyn Error>> {
+            let column = frame
+                .column(&field.name)
+                .ok_or_else(|| format!("data {} has no column {}", data, field.name))?;
+            Ok(LocalFrame {
+                columns: vec![column.clone()],
+            })
+        };
+        match result {
+            LocalData::Vertices { ids, frame } => Ok(LocalData::Vertices {
+                ids: ids.clone(),
+                frame: pick(frame)?,
+            }),
+            LocalData::Edges { ids, frame } => Ok(LocalData::Edges {
+                ids: ids.clone(),
+                frame: pick(frame)?,
+            }),
+            _ => Err(format!("data {} has no selected vertices or edges", data).into()),
+        }
+    }
+
+    /// Returns the fields of each type, where the fields of a Homo set apply to the given types
+    pub(super) fn fields_by_type<'a>(
+        &'a self,
+        types: &'a [String],
+    ) -> HashMap<&'a str, &'a [Field]> {
+        match self {
+            DataFrameSet::Homo((_, fields)) => types
+                .iter()
+                .map(|t| (t.as_str(), fields.as_slice()))
+                .collect(),
+            DataFrameSet::Hetero(dfs) => dfs
+                .iter()
+                .map(|(t, (_, fields))| (t.as_str(), fields.as_slice()))
+                .collect(),
+        }
+    }
+}
+
+/// Writes a selection like the call that builds it, such as `vertices_by_type("Person")`
+fn selection_operation(
+    element: &str,
+    selector: &Selector,
+    source: &Option<CypherSource>,
+) -> String {
+    let res = match selector {
+        Selector::DirectAccess { ltype: None } => format!("{}()", element),
+        Selector::DirectAccess { ltype: Some(t) } => format!("{}_by_type({:?})", element, t),
+        selector => format!("select_{}({})", element, selector),
+    };
+    match source {
+        Some(source) => format!("{} over cypher_to_graph({:?})", res, source.query),
+        None => res,
+    }
+}
+
+/// The query of a `cypher_to_graph` fused into a selection over its result. The columns are
+/// Cypher expressions over the returned columns giving the candidate vertices or edges, or lists
+/// of them.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct CypherSource {
+    pub(crate) query: String,
+    pub(crate) columns: Vec<String>,
+}
+
 /// Represents a filter-projection vertex relation from the original graph, such as
 /// select vertices {feat1, feat3, feat2 / feat2.avg()} from graph where vertices.type = "Person"
 #[derive(Serialize, Deserialize)]
 pub struct VertexSelectGraph {
-    pub(super) context: DataTransformationContext,
-    pub(super) graph: DataIdT,     // graph data id
-    pub(super) selector: Selector, // vertex selection
-    pub(super) df: DataFrameSet,
+    pub(crate) context: DataTransformationContext,
+    pub(crate) graph: DataIdT,     // graph data id
+    pub(crate) selector: Selector, // vertex selection
+    pub(crate) df: DataFrameSet,
+    #[serde(default)]
+    pub(crate) source: Option<CypherSource>,
 }
 
 /// Represents a filter-projection edge relation from the original graph
 #[derive(Serialize, Deserialize)]
 pub struct EdgeSelectGraph {
-    pub(super) context: DataTransformationContext,
-    pub(super) graph: DataIdT,     // graph data id
-    pub(super) selector: Selector, // edge selection
-    pub(super) df: DataFrameSet,
+    pub(crate) context: DataTransformationContext,
+    pub(crate) graph: DataIdT,     // graph data id
+    pub(crate) selector: Selector, // edge selection
+    pub(crate) df: DataFrameSet,
+    #[serde(default)]
+    pub(crate) source: Option<CypherSource>,
 }
 
 #[typetag::serde]
@@ -52,22 +889,760 @@ impl TransformationData for VertexSelectGraph {
     fn get_context(&self) -> &DataTransformationContext {
         &self.context
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        selection_operation("vertices", &self.selector, &self.source)
+    }
+
+    /// The selected vertices with the column of the exported field
+    fn exported(&self, resource_id: &str, result: &LocalData) -> Result<LocalData, Box<dyn Error>> {
+        self.df.exported(self.context.id, resource_id, result)
+    }
+
+    fn inputs(&self) -> Vec<DataIdT> {
+        vec![self.graph]
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        Some(Rc::new(VertexSelectGraph {
+            context: self.context.clone(),
+            graph: inputs.get(&self.graph).copied().unwrap_or(self.graph),
+            selector: self.selector.clone(),
+            df: self.df.clone(),
+            source: self.source.clone(),
+        }))
+    }
+
+    fn execute_local(&self, executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        if self.source.is_some() {
+            return Err(SOURCE_ON_DATABASE.into());
+        }
+        let graph = executor.graph();
+        let scope = GraphScope::of(executor, self.graph)?;
+        let ids = self.selector.select_local(executor, scope, true)?;
+        let frame = build_frame(
+            &self.df.fields_by_type(scope.vertex_types),
+            ids.iter().map(|id| {
+                let vertex = graph.vertex(*id);
+                (vertex.tlabel.as_str(), &vertex.properties)
+            }),
+        )?;
+        Ok(LocalData::Vertices { ids, frame })
+    }
+
+    fn compile_cypher(&self, executor: &LocalExecutor) -> Result<CypherQuery, Box<dyn Error>> {
+        let scope = GraphScope::of(executor, self.graph)?;
+        Ok(compile_select(&self.selector, &self.df, true, scope, self.source.as_ref())?.0)
+    }
+
+    fn decode_cypher_rows(
+        &self,
+        rows: GraphRows,
+        executor: &LocalExecutor,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        let scope = GraphScope::of(executor, self.graph)?;
+        let (_, residual) =
+            compile_select(&self.selector, &self.df, true, scope, self.source.as_ref())?;
+        let (ids, frame) = decode_select(
+            &residual,
+            &self.df,
+            scope.vertex_types,
+            rows,
+            executor.functions(),
+        )?;
+        Ok(LocalData::Vertices { ids, frame })
+    }
+}
+
+#[typetag::serde]
+impl TransformationData for EdgeSelectGraph {
+    fn get_context(&self) -> &DataTransformationContext {
+        &self.context
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        selection_operation("edges", &self.selector, &self.source)
+    }
+
+    fn inputs(&self) -> Vec<DataIdT> {
+        vec![self.graph]
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        Some(Rc::new(EdgeSelectGraph {
+            context: self.context.clone(),
+            graph: inputs.get(&self.graph).copied().unwrap_or(self.graph),
+            selector: self.selector.clone(),
+            df: self.df.clone(),
+            source: self.source.clone(),
+        }))
+    }
+
+    /// The selected edges with the column of the exported field
+    fn exported(&self, resource_id: &str, result: &LocalData) -> Result<LocalData, Box<dyn Error>> {
+        self.df.exported(self.context.id, resource_id, result)
+    }
+
+    fn execute_local(&self, executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        if self.source.is_some() {
+            return Err(SOURCE_ON_DATABASE.into());
+        }
+        let graph = executor.graph();
+        let scope = GraphScope::of(executor, self.graph)?;
+        let ids = self.selector.select_local(executor, scope, false)?;
+        let frame = build_frame(
+            &self.df.fields_by_type(scope.edge_types),
+            ids.iter().map(|id| {
+                let edge = graph.edge(*id);
+                (edge.tlabel.as_str(), &edge.properties)
+            }),
+        )?;
+        Ok(LocalData::Edges { ids, frame })
+    }
+
+    fn compile_cypher(&self, executor: &LocalExecutor) -> Result<CypherQuery, Box<dyn Error>> {
+        let scope = GraphScope::of(executor, self.graph)?;
+        Ok(compile_select(&self.selector, &self.df, false, scope, self.source.as_ref())?.0)
+    }
+
+    fn decode_cypher_rows(
+        &self,
+        rows: GraphRows,
+        executor: &LocalExecutor,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        let scope = GraphScope::of(executor, self.graph)?;
+        let (_, residual) =
+            compile_select(&self.selector, &self.df, false, scope, self.source.as_ref())?;
+        let (ids, frame) = decode_select(
+            &residual,
+            &self.df,
+            scope.edge_types,
+            rows,
+            executor.functions(),
+        )?;
+        Ok(LocalData::Edges { ids, frame })
+    }
+}
+
+/// The fields of the vertices or edges of a graph of the transformation context
+pub(super) fn graph_fields(
+    context: &DataTransformationContext,
+    graph: DataIdT,
+    vertices: bool,
+) -> DataFrameSet {
+    let data = context
+        .transformation_context
+        .upgrade()
+        .and_then(|tc| tc.as_ref().borrow().get_data(graph).cloned());
+    match data
+        .as_ref()
+        .and_then(|data| data.as_any().downcast_ref::<SingleGraph>())
+    {
+        Some(single_graph) => single_graph.element_fields(vertices),
+        // a graph computed by a query has no known fields
+        None => DataFrameSet::Homo((
+            format!(
+                "data{}_{}",
+                graph,
+                if vertices { "vertices" } else { "edges" }
+            ),
+            Vec::new(),
+        )),
+    }
+}
+
+/// Narrows the fields to the given type, or returns None if the set has no such type
+fn type_fields(df: &DataFrameSet, t: &str) -> Option<DataFrameSet> {
+    match df {
+        DataFrameSet::Homo(df) => Some(DataFrameSet::Homo(df.clone())),
+        DataFrameSet::Hetero(dfs) => Some(DataFrameSet::Homo(dfs.get(t)?.clone())),
+    }
+}
+
+impl VertexSelectGraph {
+    /// Registers a selection of vertices over the same graph as this one
+    fn reselect(&self, selector: Selector, df: DataFrameSet) -> Rc<VertexSelectGraph> {
+        let res = Rc::new(VertexSelectGraph {
+            context: self.context.new_data_context(),
+            graph: self.graph,
+            selector,
+            df,
+            source: self.source.clone(),
+        });
+        self.context.register_data(&res);
+        res
+    }
+
+    /// Registers the selection of the edges touching the selected vertices. Over a Cypher query,
+    /// the selection fails to compile and run.
+    fn incident_edges(&self, ltype: Option<&str>) -> Option<Rc<EdgeSelectGraph>> {
+        let mut df = graph_fields(&self.context, self.graph, false);
+        let mut selector = Selector::Incident {
+            vertices: Box::new(self.selector.clone()),
+            direction: Direction::Both,
+        };
+        if let Some(t) = ltype {
+            df = type_fields(&df, t)?;
+            selector = Selector::Intersect(Box::new(selector), Box::new(Selector::by_type(ltype)));
+        }
+        let res = Rc::new(EdgeSelectGraph {
+            context: self.context.new_data_context(),
+            graph: self.graph,
+            selector,
+            df,
+            source: self.source.clone(),
+        });
+        self.context.register_data(&res);
+        Some(res)
+    }
 }
 
 impl GraphBase for VertexSelectGraph {
     fn vertices(&self) -> Rc<dyn GraphBase> {
-        todo!()
+        self.reselect(self.selector.clone(), self.df.clone())
     }
 
+    /// Returns the edges touching the selected vertices
     fn edges(&self) -> Rc<dyn GraphBase> {
-        todo!()
+        self.incident_edges(None).unwrap()
     }
 
     fn vertices_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
-        todo!()
+        let selector = Selector::Intersect(
+            Box::new(self.selector.clone()),
+            Box::new(Selector::by_type(Some(t))),
+        );
+        Some(self.reselect(selector, type_fields(&self.df, t)?))
     }
 
+    /// Returns the edges of the given type touching the selected vertices
     fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
-        todo!()
+        Some(self.incident_edges(Some(t))?)
+    }
+
+    fn select(&self, selector: Selector) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        let selector = Selector::Intersect(Box::new(self.selector.clone()), Box::new(selector));
+        Ok(self.reselect(selector.simplify(), self.df.clone()))
+    }
+
+    fn neighbors(
+        &self,
+        edge_type: Option<&str>,
+        direction: Direction,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        if self.source.is_some() {
+            return Err(
+                "the neighbors of vertices selected over a Cypher query are not supported".into(),
+            );
+        }
+        let res = Rc::new(VertexSelectGraph {
+            context: self.context.new_data_context(),
+            graph: self.graph,
+            selector: Selector::adjacent(self.selector.clone(), edge_type, direction),
+            df: graph_fields(&self.context, self.graph, true),
+            source: None,
+        });
+        self.context.register_data(&res);
+        Ok(res)
+    }
+
+    /// Returns the selected vertices with the edges whose both ends are selected
+    fn induced_subgraph(&self) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        if self.source.is_some() {
+            return Err(
+                "subgraphs of vertices selected over a Cypher query are not supported".into(),
+            );
+        }
+        let incident = |direction| {
+            Box::new(Selector::Incident {
+                vertices: Box::new(self.selector.clone()),
+                direction,
+            })
+        };
+        let edges = Rc::new(EdgeSelectGraph {
+            context: self.context.new_data_context(),
+            graph: self.graph,
+            selector: Selector::Intersect(incident(Direction::Out), incident(Direction::In)),
+            df: graph_fields(&self.context, self.graph, false),
+            source: None,
+        });
+        self.context.register_data(&edges);
+        Ok(SubGraph::new(self, &edges))
+    }
+
+    /// Aggregates the neighbors of the selected vertices in the graph into a new column
+    fn aggregate_hops(
+        &self,
+        hops: Vec<AggregationHop>,
+        output_col_name: &str,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        if self.source.is_some() {
+            return Err(
+                "aggregations of vertices selected over a Cypher query are not supported".into(),
+            );
+        }
+        Ok(NeighborAggregation::new(self, hops, output_col_name)?)
+    }
+
+    /// Samples the neighbors of the selected vertices in the graph
+    fn sample_hops(
+        &self,
+        hops: Vec<SamplingHop>,
+        spec: SamplingSpec,
+    ) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        if self.source.is_some() {
+            return Err(
+                "samplings of vertices selected over a Cypher query are not supported".into(),
+            );
+        }
+        Ok(NeighborSampling::new(self, hops, spec)?)
+    }
+
+    /// Exports the fields of the selected vertices, each on the vertex entity it is read from.
+    /// The vertices must be of one type, such as the ones returned by `vertices_by_type`.
+    fn export_fields(&self) -> Result<Vec<Field>, Box<dyn Error>> {
+        let res = self
+            .df
+            .export_fields(self.context.get_transformation_id())?;
+        res.iter().for_each(|e| {
+            self.get_context()
+                .export_resource(self.get_data_id(), e.resource_id());
+        });
+        Ok(res)
+    }
+}
+
+impl EdgeSelectGraph {
+    /// Registers a selection of edges over the same graph as this one
+    fn reselect(&self, selector: Selector, df: DataFrameSet) -> Rc<EdgeSelectGraph> {
+        let res = Rc::new(EdgeSelectGraph {
+            context: self.context.new_data_context(),
+            graph: self.graph,
+            selector,
+            df,
+            source: self.source.clone(),
+        });
+        self.context.register_data(&res);
+        res
+    }
+
+    /// Registers the selection of the vertices at the ends of the selected edges. Over a Cypher
+    /// query, the selection fails to compile and run.
+    fn endpoints(&self, ltype: Option<&str>) -> Option<Rc<VertexSelectGraph>> {
+        let mut df = graph_fields(&self.context, self.graph, true);
+        let mut selector = Selector::Endpoints {
+            edges: Box::new(self.selector.clone()),
+            direction: Direction::Both,
+        };
+        if let Some(t) = ltype {
+            df = type_fields(&df, t)?;
+            selector = Selector::Intersect(Box::new(selector), Box::new(Selector::by_type(ltype)));
+        }
+        let res = Rc::new(VertexSelectGraph {
+            context: self.context.new_data_context(),
+            graph: self.graph,
+            selector,
+            df,
+            source: self.source.clone(),
+        });
+        self.context.register_data(&res);
+        Some(res)
+    }
+}
+
+impl GraphBase for EdgeSelectGraph {
+    /// Returns the vertices at the ends of the selected edges
+    fn vertices(&self) -> Rc<dyn GraphBase> {
+        self.endpoints(None).unwrap()
+    }
+
+    fn edges(&self) -> Rc<dyn GraphBase> {
+        self.reselect(self.selector.clone(), self.df.clone())
+    }
+
+    /// Returns the vertices of the given type at the ends of the selected edges
+    fn vertices_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        Some(self.endpoints(Some(t))?)
+    }
+
+    fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        let selector = Selector::Intersect(
+            Box::new(self.selector.clone()),
+            Box::new(Selector::by_type(Some(t))),
+        );
+        Some(self.reselect(selector, type_fields(&self.df, t)?))
+    }
+
+    fn select(&self, selector: Selector) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        let selector = Selector::Intersect(Box::new(self.selector.clone()), Box::new(selector));
+        Ok(self.reselect(selector.simplify(), self.df.clone()))
+    }
+
+    /// Returns the selected edges with the vertices they touch
+    fn induced_subgraph(&self) -> Result<Rc<dyn GraphBase>, Box<dyn Error>> {
+        if self.source.is_some() {
+            return Err("subgraphs of edges selected over a Cypher query are not supported".into());
+        }
+        let vertices = self
+            .endpoints(None)
+            .ok_or("the selected edges have no endpoints")?;
+        Ok(SubGraph::new(&vertices, self))
+    }
+
+    /// Exports the fields of the selected edges, each on the edge entity it is read from. The
+    /// edges must be of one type, such as the ones returned by `edges_by_type`.
+    fn export_fields(&self) -> Result<Vec<Field>, Box<dyn Error>> {
+        let res = self
+            .df
+            .export_fields(self.context.get_transformation_id())?;
+        res.iter().for_each(|e| {
+            self.get_context()
+                .export_resource(self.get_data_id(), e.resource_id());
+        });
+        Ok(res)
+    }
+}
+
+#[test]
+fn push_down_selection() {
+    use crate::{Entity, PropertyGraph, SingleGraph, TransformationContext};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30, "name": "ann"}},
+                {"tlabel": "City", "id": "c1", "properties": {"population": 1000}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41, "name": "bob"}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let person = Entity::new_node_entity("Person", None, "Person", "id");
+    let selected = Rc::new(VertexSelectGraph {
+        context: single_graph.get_context().new_data_context(),
+        graph: single_graph.get_data_id(),
+        // the average is computed over all selected rows, so it is evaluated locally
+        selector: Selector::Expression(Expression::new("name <> 'cat' AND age >= avg(age)")),
+        df: DataFrameSet::Homo((
+            "Person".to_string(),
+            Field::new_fields(vec![("age", FeatureValueType::Int)], &person, None),
+        )),
+        source: None,
+    });
+    single_graph.get_context().register_data(&selected);
+
+    let mut executor = LocalExecutor::new(&graph);
+    executor.execute(&context.borrow()).unwrap();
+    match executor.result(selected.get_data_id()).unwrap() {
+        LocalData::Vertices { ids, .. } => assert_eq!(ids, &vec![2]),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let query = selected.compile_cypher(&executor).unwrap();
+    assert_eq!(
+        query.text,
+        "MATCH (n) WHERE any(l IN labels(n) WHERE l IN $p0) AND (n.`name` <> $p1) \
+         RETURN id(n) AS __id, [l IN labels(n) WHERE l IN $p0][0] AS __tlabel, n.`age` AS `age` \
+         ORDER BY __tlabel, __id"
+    );
+    assert_eq!(query.params["p1"], FeatureValue::String("cat".to_string()));
+    assert!(!query.complete);
+
+    let rows = GraphRows {
+        columns: vec!["__id".into(), "__tlabel".into(), "age".into()],
+        rows: [(5, 30), (7, 41)]
+            .into_iter()
+            .map(|(id, age)| {
+                vec![
+                    GraphValue::Value(FeatureValue::Int(id)),
+                    GraphValue::Value(FeatureValue::String("Person".to_string())),
+                    GraphValue::Value(FeatureValue::Int(age)),
+                ]
+            })
+            .collect(),
+    };
+    match selected.decode_cypher_rows(rows, &executor).unwrap() {
+        LocalData::Vertices { ids, frame } => {
+            assert_eq!(ids, vec![7]);
+            assert_eq!(
+                frame.column("age").unwrap().values,
+                vec![FeatureValue::Int(41)]
+            );
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn compose_selections() {
+    use crate::{PropertyGraph, SingleGraph, TransformationContext};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41}},
+                {"tlabel": "Person", "id": "p3", "properties": {"age": 50}},
+                {"tlabel": "City", "id": "c1", "properties": {"population": 1000}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"]},
+                {"tlabel": "lives", "src": ["Person", "p2"], "dst": ["City", "c1"]},
+                {"tlabel": "lives", "src": ["Person", "p3"], "dst": ["City", "c1"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let people = single_graph.vertices_by_type("Person").unwrap();
+    let young = people.filter("age < 35").unwrap();
+    let known = young.neighbors(Some("knows"), Direction::Out).unwrap();
+    let old = people
+        .select(Selector::Not(Box::new(Selector::Expression(
+            Expression::new("age < 45"),
+        ))))
+        .unwrap();
+    let young_edges = young.edges();
+    let cities = single_graph
+        .edges_by_type("lives")
+        .unwrap()
+        .vertices_by_type("City")
+        .unwrap();
+    assert!(people.filter("age >").is_err());
+    assert!(single_graph.filter("age > 30").is_err());
+
+    let mut executor = LocalExecutor::new(&graph);
+    executor.execute(&context.borrow()).unwrap();
+    let selected = |id: DataIdT| match executor.result(id).unwrap() {
+        LocalData::Vertices { ids, .. } | LocalData::Edges { ids, .. } => ids.clone(),
+        res => panic!("unexpected result {:?}", res),
+    };
+    // the data are registered in the order they are built
+    assert_eq!(selected(2), vec![0]);
+    assert_eq!(selected(3), vec![1]);
+    assert_eq!(selected(4), vec![2]);
+    assert_eq!(selected(5), vec![0]);
+    assert_eq!(selected(7), vec![3]);
+
+    let data = context.borrow().get_data(3).unwrap().clone();
+    assert_eq!(
+        data.operation(),
+        "select_vertices(vertices entered by ((edges leaving ((type = \"Person\") intersect \
+         ((age < 35)))) intersect (type = \"knows\")))"
+    );
+    let query = data.compile_cypher(&executor).unwrap();
+    assert!(query.text.starts_with(
+        "MATCH (n) WHERE any(l IN labels(n) WHERE l IN $p0) AND EXISTS { MATCH ()-[n_e]->(n) \
+         WHERE type(n_e) IN $p4 AND (EXISTS { MATCH (n_e_n)-[n_e]->() WHERE any(l IN \
+         labels(n_e_n) WHERE l IN $p2) AND (n_e_n:`Person` AND (n_e_n.`age` < $p1)) } AND \
+         type(n_e) = $p3) }"
+    ));
+    assert!(query.complete);
+}
+
+#[test]
+fn complement_null_properties() {
+    use crate::{PropertyGraph, SingleGraph, TransformationContext};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "Person", "id": "p2"},
+                {"tlabel": "Person", "id": "p3", "properties": {"age": 50}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let people = single_graph.vertices_by_type("Person").unwrap();
+    people
+        .select(Selector::Not(Box::new(Selector::Expression(
+            Expression::new("age < 45"),
+        ))))
+        .unwrap();
+
+    // p2 has no age, so it is not younger than 45 and its complement selects it, locally and in
+    // Cypher
+    let mut executor = LocalExecutor::new(&graph);
+    executor.execute(&context.borrow()).unwrap();
+    match executor.result(2).unwrap() {
+        LocalData::Vertices { ids, .. } => assert_eq!(ids, &vec![1, 2]),
+        res => panic!("unexpected result {:?}", res),
+    }
+    let data = context.borrow().get_data(2).unwrap().clone();
+    let query = data.compile_cypher(&executor).unwrap();
+    assert!(query
+        .text
+        .contains("(n:`Person` AND (NOT coalesce((n.`age` < $p1), false)))"));
+}
+
+#[test]
+fn sample_selections() {
+    use crate::{PropertyGraph, SingleGraph, TransformationContext};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 20}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 35}},
+                {"tlabel": "Person", "id": "p3", "properties": {"age": 50}},
+                {"tlabel": "Person", "id": "p4", "properties": {"age": 40}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let older = single_graph
+        .vertices_by_type("Person")
+        .unwrap()
+        .filter("age > 30")
+        .unwrap();
+    older.sample(2, SamplingSpec::uniform().seeded(7)).unwrap();
+    older.sample(2, SamplingSpec::top_k("age")).unwrap();
+    older.sample(5, SamplingSpec::uniform()).unwrap();
+
+    let mut executor = LocalExecutor::new(&graph);
+    executor.execute(&context.borrow()).unwrap();
+    let selected = |id: DataIdT| match executor.result(id).unwrap() {
+        LocalData::Vertices { ids, .. } => ids.clone(),
+        res => panic!("unexpected result {:?}", res),
+    };
+    // the samples are drawn among the filtered vertices, in their order
+    let uniform = selected(3);
+    assert_eq!(uniform.len(), 2);
+    assert!(uniform.iter().all(|id| [1, 2, 3].contains(id)));
+    assert_eq!(selected(4), vec![2, 3]);
+    assert_eq!(selected(5), vec![1, 2, 3]);
+    let data = context.borrow().get_data(4).unwrap().clone();
+    assert!(data.compile_cypher(&executor).is_err());
+}
+
+#[test]
+fn simplify_selectors() {
+    let all = || Selector::by_type(None);
+    let filter = |expr: &str| Selector::Expression(Expression::new(expr));
+    let boxed = Box::new;
+
+    assert_eq!(
+        Selector::Intersect(boxed(all()), boxed(filter("age > 30"))).simplify(),
+        filter("age > 30")
+    );
+    assert_eq!(
+        Selector::Intersect(boxed(filter("age > 30")), boxed(filter("age < 50"))).simplify(),
+        Selector::Expression(Expression::new("age > 30").and(&Expression::new("age < 50")))
+    );
+    let averaged = Selector::Intersect(boxed(filter("age > 30")), boxed(filter("age > avg(age)")));
+    assert_eq!(averaged.clone().simplify(), averaged);
+    assert_eq!(
+        Selector::Union(boxed(filter("age > 30")), boxed(all())).simplify(),
+        all()
+    );
+    assert_eq!(
+        Selector::Difference(boxed(all()), boxed(filter("age > 30"))).simplify(),
+        Selector::Not(boxed(filter("age > 30")))
+    );
+    assert_eq!(
+        Selector::Not(boxed(Selector::Not(boxed(Selector::by_type(Some("City")))))).simplify(),
+        Selector::by_type(Some("City"))
+    );
+    assert_eq!(
+        Selector::Endpoints {
+            edges: boxed(Selector::Intersect(boxed(all()), boxed(all()))),
+            direction: Direction::Both,
+        }
+        .simplify(),
+        Selector::Endpoints {
+            edges: boxed(all()),
+            direction: Direction::Both,
+        }
+    );
+
+    let selector = Selector::adjacent(
+        Selector::Difference(
+            boxed(Selector::by_type(Some("Person"))),
+            boxed(Selector::Union(
+                boxed(filter("age > 30")),
+                boxed(Selector::Sampling {
+                    spec: SamplingSpec::uniform(),
+                    size: 2,
+                }),
+            )),
+        ),
+        Some("knows"),
+        Direction::Out,
+    );
+    let serialized = serde_json::to_string(&selector).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Selector>(&serialized).unwrap(),
+        selector
+    );
+}
+
+#[test]
+fn neighborhoods_over_cypher_source() {
+    use crate::{
+        CypherTransformation, GraphNode, PropertyGraph, SingleGraph, TransformationContext,
+    };
+
+    let context = TransformationContext::new();
+    let graph = SingleGraph::from_property_graph(&context, &PropertyGraph::new());
+    let upu = graph.cypher_to_graph(
+        "MATCH (r1:Review)-[:isWrittenBy]->(u1:Reviewer) MATCH (r2:Review)-[:isWrittenBy]->(u2:Reviewer) \
+         RETURN u1, collect(u2)",
+    );
+    // the edges of selected vertices and the endpoints of selected edges are built but fail to
+    // run, and subgraphs fail to build
+    let reviewers = upu.vertices_by_type("Reviewer").unwrap();
+    reviewers.edges();
+    let edges = upu.edges();
+    edges.vertices();
+    assert!(reviewers.induced_subgraph().is_err());
+    assert!(edges.induced_subgraph().is_err());
+    assert!(reviewers.neighbors(None, Direction::Both).is_err());
+
+    let node = GraphValue::Node(GraphNode {
+        id: 1,
+        labels: vec!["Reviewer".to_string()],
+        properties: HashMap::new(),
+    });
+    let rows = GraphRows {
+        columns: vec!["u1".to_string(), "collect(u2)".to_string()],
+        rows: vec![vec![node.clone(), GraphValue::List(vec![node])]],
+    };
+    let empty = PropertyGraph::new();
+    let mut executor = LocalExecutor::new(&empty);
+    let upu_id = 1;
+    let result = context
+        .borrow()
+        .get_data(upu_id)
+        .unwrap()
+        .decode_cypher_rows(rows, &executor)
+        .unwrap();
+    executor.insert(upu_id, result);
+    for id in [3, 5] {
+        let data = context.borrow().get_data(id).unwrap().clone();
+        let err = data.compile_cypher(&executor).unwrap_err();
+        assert_eq!(err.to_string(), NEIGHBORHOOD_OF_SOURCE);
+        assert!(data.execute_local(&executor).is_err());
     }
 }
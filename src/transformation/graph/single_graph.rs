@@ -1,14 +1,16 @@
 use super::{DataFrameSet, EdgeSelectGraph, Selector, VertexSelectGraph};
 use crate::transformation::{
     CypherResultDataFrame, CypherResultGraph, CypherTransformation, DataFrame, DataIdT,
-    DataTransformationContext, GraphBase, GraphComputationOps, InnerTransformationData,
-    TransformationContext, TransformationData,
+    DataTransformationContext, GraphBase, GraphComputationOps, InnerTransformationData, LocalData,
+    LocalExecutor, TransformationContext, TransformationData,
 };
 use crate::{
-    EntityType, FeatureRegistry, FeatureView, Field, Graph, GraphDataset, ResourceId, ResourceOp,
-    Topology, TopologyFeatureView, TopologyType,
+    ElementType, EntityType, FeatureRegistry, FeatureView, Field, Graph, GraphDataset,
+    PropertyGraph, ResourceId, ResourceOp, Topology, TopologyFeatureView, TopologyType,
 };
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::rc::Rc;
 use std::{cell::RefCell, collections::HashMap};
@@ -87,8 +89,71 @@ impl SingleGraph {
         }
     } // pub fn from
 
+    /// Describes all vertex and edge types of a property graph, with a field per property. The
+    /// entity of each type is named after its tlabel.
+    pub fn from_property_graph(
+        context: &Rc<RefCell<TransformationContext>>,
+        graph: &PropertyGraph,
+    ) -> Rc<SingleGraph> {
+        let views = |types: &BTreeMap<String, ElementType>| {
+            let mut fvs = HashMap::new();
+            let mut entities = HashMap::new();
+            for (tlabel, element_type) in types {
+                let entity_id = format!("Entity/{}/", tlabel);
+                let fields = element_type
+                    .schema
+                    .iter()
+                    .map(|(name, value_type)| Field {
+                        name: name.clone(),
+                        variant: None,
+                        value_type: value_type.clone(),
+                        entity_id: entity_id.clone(),
+                        transformation_id: None,
+                        data_source_id: None,
+                        description: None,
+                        tags: HashMap::new(),
+                        owners: Vec::new(),
+                    })
+                    .collect();
+                fvs.insert(tlabel.clone(), (format!("{}_ALL_FIELDS", tlabel), fields));
+                entities.insert(tlabel.clone(), entity_id);
+            }
+            (fvs, entities)
+        };
+        let (vertex_fvs, vertex_entities) = views(graph.vertex_types());
+        let (edge_fvs, edge_entities) = views(graph.edge_types());
+        let id = context.as_ref().borrow_mut().new_data_id();
+        let res = Rc::new(SingleGraph {
+            context: DataTransformationContext {
+                id,
+                transformation_context: Rc::downgrade(context),
+            },
+            vertex_fvs,
+            edge_fvs,
+            vertex_entities,
+            edge_entities,
+            topology_type: None,
+        });
+        context.as_ref().borrow_mut().add_data(&res);
+        res
+    }
+
+    /// The fields of all vertex or edge types, as one set when there is a single type
+    pub(crate) fn element_fields(&self, vertices: bool) -> DataFrameSet {
+        let fvs = if vertices {
+            &self.vertex_fvs
+        } else {
+            &self.edge_fvs
+        };
+        if fvs.len() == 1 {
+            DataFrameSet::Homo(fvs.values().next().unwrap().clone())
+        } else {
+            DataFrameSet::Hetero(fvs.clone())
+        }
+    }
+
     // TODO(tatiana): make this function part of GraphBase trait?
-    fn get_edge_entity_ids(&self) -> Vec<ResourceId> {
+    pub(crate) fn get_edge_entity_ids(&self) -> Vec<ResourceId> {
         self.edge_entities.iter().map(|e| e.1.clone()).collect()
     }
 }
@@ -143,6 +208,25 @@ impl TransformationData for SingleGraph {
     fn get_context(&self) -> &DataTransformationContext {
         &self.context
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        "single_graph".to_string()
+    }
+
+    fn execute_local(&self, _executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        let mut vertex_types: Vec<String> = self.vertex_fvs.keys().cloned().collect();
+        let mut edge_types: Vec<String> = self.edge_fvs.keys().cloned().collect();
+        vertex_types.sort();
+        edge_types.sort();
+        Ok(LocalData::Graph {
+            vertex_types,
+            edge_types,
+        })
+    }
 }
 
 impl GraphBase for SingleGraph {
@@ -151,18 +235,23 @@ impl GraphBase for SingleGraph {
             context: self.context.new_data_context(),
             graph: self.context.id,
             selector: Selector::DirectAccess { ltype: None },
-            df: if self.vertex_fvs.len() == 1 {
-                DataFrameSet::Homo(self.vertex_fvs.values().next().unwrap().clone())
-            } else {
-                DataFrameSet::Hetero(self.vertex_fvs.clone())
-            },
+            df: self.element_fields(true),
+            source: None,
         });
         self.context.register_data(&res);
         res
     }
 
     fn edges(&self) -> Rc<dyn GraphBase> {
-        todo!()
+        let res = Rc::new(EdgeSelectGraph {
+            context: self.context.new_data_context(),
+            graph: self.context.id,
+            selector: Selector::DirectAccess { ltype: None },
+            df: self.element_fields(false),
+            source: None,
+        });
+        self.context.register_data(&res);
+        res
     }
 
     fn vertices_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
@@ -174,6 +263,7 @@ impl GraphBase for SingleGraph {
                     ltype: Some(t.to_string()),
                 },
                 df: DataFrameSet::Homo(df.clone()),
+                source: None,
             });
             self.context.register_data(&res);
             Some(res)
@@ -183,7 +273,21 @@ impl GraphBase for SingleGraph {
     }
 
     fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
-        todo!()
+        if let Some(df) = self.edge_fvs.get(t) {
+            let res = Rc::new(EdgeSelectGraph {
+                context: self.context.new_data_context(),
+                graph: self.context.id,
+                selector: Selector::DirectAccess {
+                    ltype: Some(t.to_string()),
+                },
+                df: DataFrameSet::Homo(df.clone()),
+                source: None,
+            });
+            self.context.register_data(&res);
+            Some(res)
+        } else {
+            None
+        }
     }
 
     fn export_topology(&self, name: &str) -> Topology {
@@ -240,3 +344,61 @@ async fn demo() -> Result<(), Box<dyn Error>> {
     println!("Deserialized TransformationContext: {:?}", tc_deser);
     Ok(())
 }
+
+#[test]
+fn export_edge_fields() {
+    use crate::transformation::TransformationExecutor;
+    use crate::{FeatureValue, LocalData};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {}},
+                {"tlabel": "Person", "id": "p2", "properties": {}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"],
+                 "properties": {"since": 2010}},
+                {"tlabel": "likes", "src": ["Person", "p1"], "dst": ["Person", "p2"],
+                 "properties": {"weight": 0.5}},
+                {"tlabel": "knows", "src": ["Person", "p2"], "dst": ["Person", "p1"],
+                 "properties": {"since": 2015}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    assert!(single_graph.edges_by_type("follows").is_none());
+    let edges = single_graph.edges();
+    let knows = edges
+        .edges_by_type("knows")
+        .unwrap()
+        .export_fields()
+        .unwrap();
+    assert_eq!(knows.len(), 1);
+    assert_eq!(knows[0].entity_id, "Entity/knows/");
+    // the edges of several types have to be narrowed to one type first
+    assert!(edges.export_fields().is_err());
+    assert!(single_graph.export_fields().is_err());
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("knows_since".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+    let mut local = LocalExecutor::new(&graph);
+    let output = executor.execute_local(&mut local).unwrap();
+    match &output[&knows[0].resource_id()] {
+        LocalData::Edges { ids, frame } => {
+            assert_eq!(ids, &vec![0, 2]);
+            assert_eq!(
+                frame.column("since").unwrap().values,
+                vec![FeatureValue::Int(2010), FeatureValue::Int(2015)]
+            );
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
@@ -0,0 +1,257 @@
+use super::{export_adjacency_list, with_data, EdgeSelectGraph, VertexSelectGraph};
+use crate::transformation::{
+    DataIdT, DataTransformationContext, GraphBase, InnerTransformationData, LocalData,
+    LocalExecutor, TransformationData,
+};
+use crate::{Field, ResourceOp, Topology};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::error::Error;
+use std::rc::Rc;
+
+/// A subgraph made of a selection of vertices and a selection of the edges between them, such as
+/// the subgraph induced by a vertex selection. The selections run as data of their own, on the
+/// graph database or on the local engine, and the subgraph gathers their results. It has no
+/// Cypher translation of its own, so it runs on the local engine, and the executor keeps both
+/// selections on the local engine when one of them cannot be pushed down.
+#[derive(Serialize, Deserialize)]
+pub struct SubGraph {
+    pub(crate) context: DataTransformationContext,
+    pub(crate) vertices: DataIdT, // vertex selection data id
+    pub(crate) edges: DataIdT,    // edge selection data id
+}
+
+impl SubGraph {
+    pub(crate) fn new(vertices: &VertexSelectGraph, edges: &EdgeSelectGraph) -> Rc<SubGraph> {
+        let res = Rc::new(SubGraph {
+            context: vertices.get_context().new_data_context(),
+            vertices: vertices.get_data_id(),
+            edges: edges.get_data_id(),
+        });
+        res.context.register_data(&res);
+        res
+    }
+
+    /// Calls `f` on the selection with the given id
+    fn with_selection<S: 'static, R>(&self, id: DataIdT, f: impl FnOnce(&S) -> R) -> R {
+        with_data(&self.context, id, f)
+    }
+
+    /// The data id of the graph the selections read from
+    fn graph(&self) -> DataIdT {
+        self.with_selection(self.edges, |edges: &EdgeSelectGraph| edges.graph)
+    }
+}
+
+#[typetag::serde]
+impl TransformationData for SubGraph {
+    fn get_context(&self) -> &DataTransformationContext {
+        &self.context
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn operation(&self) -> String {
+        "subgraph".to_string()
+    }
+
+    fn inputs(&self) -> Vec<DataIdT> {
+        vec![self.vertices, self.edges]
+    }
+
+    fn with_inputs(
+        &self,
+        inputs: &HashMap<DataIdT, DataIdT>,
+    ) -> Option<Rc<dyn TransformationData>> {
+        Some(Rc::new(SubGraph {
+            context: self.context.clone(),
+            vertices: inputs.get(&self.vertices).copied().unwrap_or(self.vertices),
+            edges: inputs.get(&self.edges).copied().unwrap_or(self.edges),
+        }))
+    }
+
+    fn execute_local(&self, executor: &LocalExecutor) -> Result<LocalData, Box<dyn Error>> {
+        let (vertex_ids, vertex_frame) = match executor.result(self.vertices) {
+            Some(LocalData::Vertices { ids, frame }) => (ids.clone(), frame.clone()),
+            _ => return Err(format!("data {} has no selected vertices", self.vertices).into()),
+        };
+        let (edge_ids, edge_frame) = match executor.result(self.edges) {
+            Some(LocalData::Edges { ids, frame }) => (ids.clone(), frame.clone()),
+            _ => return Err(format!("data {} has no selected edges", self.edges).into()),
+        };
+        Ok(LocalData::Subgraph {
+            vertex_ids,
+            vertex_frame,
+            edge_ids,
+            edge_frame,
+        })
+    }
+}
+
+impl GraphBase for SubGraph {
+    fn vertices(&self) -> Rc<dyn GraphBase> {
+        self.with_selection(self.vertices, |v: &VertexSelectGraph| v.vertices())
+    }
+
+    fn edges(&self) -> Rc<dyn GraphBase> {
+        self.with_selection(self.edges, |e: &EdgeSelectGraph| e.edges())
+    }
+
+    fn vertices_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        self.with_selection(self.vertices, |v: &VertexSelectGraph| v.vertices_by_type(t))
+    }
+
+    fn edges_by_type(&self, t: &str) -> Option<Rc<dyn GraphBase>> {
+        self.with_selection(self.edges, |e: &EdgeSelectGraph| e.edges_by_type(t))
+    }
+
+    /// Exports the fields of the vertices and of the edges of the subgraph, which must each be
+    /// of one type
+    fn export_fields(&self) -> Result<Vec<Field>, Box<dyn Error>> {
+        let mut res =
+            self.with_selection(self.vertices, |v: &VertexSelectGraph| v.export_fields())?;
+        res.extend(self.with_selection(self.edges, |e: &EdgeSelectGraph| e.export_fields())?);
+        Ok(res)
+    }
+
+    /// Exports the subgraph as an adjacency list over the edge entities of its graph
+    fn export_topology(&self, name: &str) -> Topology {
+        export_adjacency_list(self, self.graph(), name)
+    }
+}
+
+#[test]
+fn extract_subgraphs() {
+    use crate::transformation::{
+        GraphComputationOps, Selector, SingleGraph, TransformationExecutor,
+    };
+    use crate::{Expression, FeatureValue, PropertyGraph, TransformationContext};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41}},
+                {"tlabel": "Person", "id": "p3", "properties": {"age": 50}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"],
+                 "properties": {"since": 2010}},
+                {"tlabel": "knows", "src": ["Person", "p2"], "dst": ["Person", "p3"],
+                 "properties": {"since": 2012}},
+                {"tlabel": "knows", "src": ["Person", "p3"], "dst": ["Person", "p1"],
+                 "properties": {"since": 2015}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let young = single_graph
+        .subgraph(Selector::Expression(Expression::new("age < 45")))
+        .unwrap();
+    let topology = young.export_topology("young");
+    let fields = young.export_fields().unwrap();
+    let recent = single_graph
+        .edge_subgraph(Selector::Expression(Expression::new("since >= 2015")))
+        .unwrap();
+    let recent_topology = recent.export_topology("recent");
+    let older = single_graph
+        .subgraph(Selector::Expression(Expression::new("age >= avg(age)")))
+        .unwrap();
+    let older_topology = older.export_topology("older");
+    assert_eq!(topology.edge_entity_ids, vec!["Entity/knows/".to_string()]);
+    assert_eq!(
+        fields.iter().map(|f| f.resource_id()).collect::<Vec<_>>(),
+        vec![
+            "Field/Person/age/",
+            "Field/Person/id/",
+            "Field/knows/since/"
+        ]
+    );
+
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("subgraphs".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+    let mut local = LocalExecutor::new(&graph);
+    let output = executor.execute_local(&mut local).unwrap();
+    match &output[&topology.resource_id()] {
+        LocalData::Subgraph {
+            vertex_ids,
+            edge_ids,
+            ..
+        } => {
+            assert_eq!(vertex_ids, &vec![0, 1]);
+            assert_eq!(edge_ids, &vec![0]);
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    match &output[&recent_topology.resource_id()] {
+        LocalData::Subgraph {
+            vertex_ids,
+            edge_ids,
+            ..
+        } => {
+            assert_eq!(vertex_ids, &vec![0, 2]);
+            assert_eq!(edge_ids, &vec![2]);
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    match &output[&older_topology.resource_id()] {
+        LocalData::Subgraph {
+            vertex_ids,
+            edge_ids,
+            ..
+        } => {
+            assert_eq!(vertex_ids, &vec![1, 2]);
+            assert_eq!(edge_ids, &vec![1]);
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(
+        output[&fields[2].resource_id()].frame().unwrap().columns[0].values,
+        vec![FeatureValue::Int(2010)]
+    );
+
+    // the selections of the subgraph are pushed down to the graph database
+    let explanation = executor.explain().unwrap();
+    let edges = explanation
+        .data
+        .iter()
+        .find(|d| d.operation.starts_with("select_edges((edges leaving"))
+        .unwrap();
+    match &edges.execution {
+        crate::Execution::GraphDatabase { query, .. } => {
+            assert!(query.contains("EXISTS { MATCH (e_n)-[e]->()"));
+            assert!(query.contains("EXISTS { MATCH ()-[e]->(e_n)"));
+        }
+        execution => panic!("unexpected execution {:?}", execution),
+    }
+    // the vertices of the older subgraph are selected locally, so its edges are too
+    let older = explanation.data.last().unwrap();
+    assert_eq!(older.operation, "subgraph");
+    for input in older.inputs.iter() {
+        let selection = explanation.data.iter().find(|d| d.id == *input).unwrap();
+        assert!(matches!(
+            selection.execution,
+            crate::Execution::Local { .. }
+        ));
+    }
+    let subgraph = explanation
+        .data
+        .iter()
+        .find(|d| d.operation == "subgraph")
+        .unwrap();
+    assert!(matches!(subgraph.execution, crate::Execution::Local { .. }));
+    assert!(matches!(
+        subgraph.schema,
+        Some(crate::OutputSchema::Subgraph { .. })
+    ));
+}
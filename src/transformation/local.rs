@@ -0,0 +1,459 @@
+use super::{DataIdT, FunctionRegistry, TransformationContext, TransformationData};
+use crate::{FeatureValue, FeatureValueType, Field, GraphDatabase, GraphRows, PropertyGraph};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+/// A column of values, all of the column type or Null
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalColumn {
+    pub name: String,
+    pub value_type: FeatureValueType,
+    pub values: Vec<FeatureValue>,
+}
+
+/// The feature values of a set of vertices or edges, one row per element
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalFrame {
+    pub columns: Vec<LocalColumn>,
+}
+
+impl LocalFrame {
+    pub fn column(&self, name: &str) -> Option<&LocalColumn> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.columns.first().map_or(0, |c| c.values.len())
+    }
+
+    pub fn column_types(&self) -> HashMap<String, FeatureValueType> {
+        self.columns
+            .iter()
+            .map(|c| (c.name.clone(), c.value_type.clone()))
+            .collect()
+    }
+}
+
+/// The result of running a data node on the local engine
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocalData {
+    /// The vertex and edge types of a graph
+    Graph {
+        vertex_types: Vec<String>,
+        edge_types: Vec<String>,
+    },
+    /// Selected vertices, by their id in the property graph
+    Vertices { ids: Vec<usize>, frame: LocalFrame },
+    /// Selected edges, by their id in the property graph
+    Edges { ids: Vec<usize>, frame: LocalFrame },
+    /// The rows of a DataFrame
+    Frame { frame: LocalFrame },
+    /// Selected vertices and the selected edges between them, by their ids in the property graph
+    Subgraph {
+        vertex_ids: Vec<usize>,
+        vertex_frame: LocalFrame,
+        edge_ids: Vec<usize>,
+        edge_frame: LocalFrame,
+    },
+}
+
+impl LocalData {
+    /// The feature values of the result, if it has any
+    pub fn frame(&self) -> Option<&LocalFrame> {
+        match self {
+            LocalData::Graph { .. } | LocalData::Subgraph { .. } => None,
+            LocalData::Vertices { frame, .. }
+            | LocalData::Edges { frame, .. }
+            | LocalData::Frame { frame } => Some(frame),
+        }
+    }
+}
+
+/// A Cypher query and its parameters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CypherQuery {
+    pub text: String,
+    pub params: HashMap<String, FeatureValue>,
+    /// Whether the rows of the query are the whole result of the data, with nothing evaluated
+    /// locally
+    pub complete: bool,
+}
+
+/// Runs the data of a TransformationContext on a `PropertyGraph`, or on a graph database for the
+/// data that compile to Cypher
+pub struct LocalExecutor<'a> {
+    graph: &'a PropertyGraph,
+    results: HashMap<DataIdT, LocalData>,
+    queries: HashMap<DataIdT, CypherQuery>,
+    /// The data whose elements are identified by their id in the graph database rather than in
+    /// the property graph
+    database_elements: HashSet<DataIdT>,
+    /// The data kept on the local engine because local data read their elements along with
+    /// elements of the local graph
+    local_only: HashSet<DataIdT>,
+    functions: FunctionRegistry,
+}
+
+impl<'a> LocalExecutor<'a> {
+    pub fn new(graph: &'a PropertyGraph) -> Self {
+        LocalExecutor {
+            graph,
+            results: HashMap::new(),
+            queries: HashMap::new(),
+            database_elements: HashSet::new(),
+            local_only: HashSet::new(),
+            functions: FunctionRegistry::builtin(),
+        }
+    }
+
+    pub fn graph(&self) -> &PropertyGraph {
+        self.graph
+    }
+
+    pub fn result(&self, id: DataIdT) -> Option<&LocalData> {
+        self.results.get(&id)
+    }
+
+    /// The query a data ran on the graph database, if it was pushed down
+    pub fn query(&self, id: DataIdT) -> Option<&CypherQuery> {
+        self.queries.get(&id)
+    }
+
+    /// Provides the result of a data computed elsewhere, such as a DataFrame read from the online
+    /// store or returned by the graph database. The data is not run again.
+    pub fn insert(&mut self, id: DataIdT, data: LocalData) {
+        self.results.insert(id, data);
+    }
+
+    /// Records the query a data runs on the graph database, so that the data derived from it can
+    /// compile against it
+    pub(super) fn insert_query(&mut self, id: DataIdT, query: CypherQuery) {
+        self.database_elements.insert(id);
+        self.queries.insert(id, query);
+    }
+
+    /// The functions that expressions can call
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+
+    /// Runs all data in topological order, so that inputs run before the data derived from
+    /// them. Data with a result already inserted are skipped.
+    pub fn execute(&mut self, context: &TransformationContext) -> Result<(), Box<dyn Error>> {
+        for data in context.topological_order()? {
+            if self.results.contains_key(&data.get_context().id) {
+                continue;
+            }
+            let res = data.execute_local(self)?;
+            self.results.insert(data.get_context().id, res);
+        }
+        Ok(())
+    }
+
+    /// Runs all data like `execute`, but runs the ones that compile to Cypher on the graph
+    /// database. The others, such as expressions calling functions that Cypher lacks, run on the
+    /// local engine from the results of their inputs. The data whose elements are read by local
+    /// data along with elements of the local graph run on the local engine too.
+    pub async fn execute_on(
+        &mut self,
+        database: &dyn GraphDatabase,
+        context: &TransformationContext,
+    ) -> Result<(), Box<dyn Error>> {
+        self.place(context)?;
+        for data in context.topological_order()? {
+            let id = data.get_context().id;
+            if self.results.contains_key(&id) {
+                continue;
+            }
+            let res = match self.compile(data.as_ref()) {
+                Ok(query) => {
+                    debug!("Running data {} on the graph database: {}", id, query.text);
+                    let rows = database
+                        .run_query(&query.text, query.params.clone())
+                        .await?;
+                    let res = data.decode_cypher_rows(rows, self)?;
+                    self.insert_query(id, query);
+                    res
+                }
+                Err(reason) => {
+                    debug!("Running data {} locally: {}", id, reason);
+                    self.execute_fallback(data.as_ref())?
+                }
+            };
+            self.results.insert(id, res);
+        }
+        Ok(())
+    }
+
+    /// Compiles the data to Cypher, unless it is kept on the local engine
+    pub(super) fn compile(
+        &self,
+        data: &dyn TransformationData,
+    ) -> Result<CypherQuery, Box<dyn Error>> {
+        let id = data.get_context().id;
+        if self.local_only.contains(&id) {
+            return Err(format!(
+                "data {} is read by local data along with elements of the local graph",
+                id
+            )
+            .into());
+        }
+        data.compile_cypher(self)
+    }
+
+    /// Finds the data to keep on the local engine. The graph database and the property graph
+    /// identify elements by different ids, so the graphs and elements read by a local data must
+    /// all come from one of them. The data are planned over an empty graph, and the elements
+    /// from the graph database that a local data reads along with local ones are moved to the
+    /// local engine, until no data moves.
+    pub(super) fn place(&mut self, context: &TransformationContext) -> Result<(), Box<dyn Error>> {
+        let order = context.topological_order()?;
+        let graph = PropertyGraph::new();
+        loop {
+            let mut planner = LocalExecutor::new(&graph);
+            planner.local_only = self.local_only.clone();
+            let mut moved = false;
+            for data in &order {
+                let id = data.get_context().id;
+                let result = match planner.compile(data.as_ref()) {
+                    Ok(query) => {
+                        planner.insert_query(id, query);
+                        data.decode_cypher_rows(GraphRows::default(), &planner).ok()
+                    }
+                    Err(_) => {
+                        let (database, local) = planner.element_inputs(data.as_ref());
+                        if local.is_empty() && !database.is_empty() {
+                            planner.database_elements.insert(id);
+                        }
+                        if !local.is_empty() {
+                            for input in database {
+                                moved |= self.local_only.insert(input);
+                            }
+                        }
+                        data.execute_local(&planner).ok()
+                    }
+                };
+                if let Some(result) = result {
+                    planner.results.insert(id, result);
+                }
+            }
+            if !moved {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs a data that does not compile to Cypher on the local engine, from inputs that `place`
+    /// put on the same engine
+    pub(super) fn execute_fallback(
+        &mut self,
+        data: &dyn TransformationData,
+    ) -> Result<LocalData, Box<dyn Error>> {
+        let id = data.get_context().id;
+        let (database, local) = self.element_inputs(data);
+        if let (Some(database), Some(local)) = (database.first(), local.first()) {
+            return Err(format!(
+                "data {} cannot run on the local engine over the elements of data {}, returned by \
+                 the graph database, and the elements of data {}, from the local graph",
+                id, database, local
+            )
+            .into());
+        }
+        let res = data.execute_local(self)?;
+        if !database.is_empty() {
+            self.database_elements.insert(id);
+        }
+        Ok(res)
+    }
+
+    /// The inputs of the data holding graphs or elements, from the graph database and from the
+    /// local graph. Inputs without a result, which failed to run while planning, are taken to be
+    /// from the local graph.
+    fn element_inputs(&self, data: &dyn TransformationData) -> (Vec<DataIdT>, Vec<DataIdT>) {
+        data.inputs()
+            .into_iter()
+            // frames have no element ids
+            .filter(|input| !matches!(self.results.get(input), Some(LocalData::Frame { .. })))
+            .partition(|input| self.database_elements.contains(input))
+    }
+
+    /// Returns the vertex or edge types of the graph computed for the given data
+    pub(super) fn graph_types(
+        &self,
+        id: DataIdT,
+        vertices: bool,
+    ) -> Result<&[String], Box<dyn Error>> {
+        match self.results.get(&id) {
+            Some(LocalData::Graph {
+                vertex_types,
+                edge_types,
+            }) => Ok(if vertices { vertex_types } else { edge_types }),
+            Some(_) => Err(format!("data {} is not a graph", id).into()),
+            None => Err(format!("data {} has not been executed", id).into()),
+        }
+    }
+
+    /// Returns the feature values computed for the given data
+    pub(super) fn frame(&self, id: DataIdT) -> Result<&LocalFrame, Box<dyn Error>> {
+        match self.results.get(&id) {
+            Some(data) => data
+                .frame()
+                .ok_or_else(|| format!("data {} has no feature values", id).into()),
+            None => Err(format!("data {} has not been executed", id).into()),
+        }
+    }
+}
+
+/// Builds a frame with a column per field, taking the fields of each element from its tlabel.
+/// Properties of fields not defined for the tlabel of an element are Null.
+pub(super) fn build_frame<'a>(
+    fields_by_type: &HashMap<&str, &[Field]>,
+    elements: impl Iterator<Item = (&'a str, &'a HashMap<String, FeatureValue>)>,
+) -> Result<LocalFrame, Box<dyn Error>> {
+    let mut columns: Vec<LocalColumn> = Vec::new();
+    let mut types: Vec<&&str> = fields_by_type.keys().collect();
+    types.sort();
+    for t in types {
+        for field in fields_by_type[*t] {
+            if !columns.iter().any(|c| c.name == field.name) {
+                columns.push(LocalColumn {
+                    name: field.name.clone(),
+                    value_type: field.value_type.clone(),
+                    values: Vec::new(),
+                });
+            }
+        }
+    }
+    for (tlabel, properties) in elements {
+        let fields = fields_by_type.get(tlabel).copied().unwrap_or_default();
+        for column in &mut columns {
+            let value = match properties.get(&column.name) {
+                Some(v) if fields.iter().any(|f| f.name == column.name) => {
+                    if v.value_type().as_ref() == Some(&column.value_type) {
+                        v.clone()
+                    } else {
+                        v.cast(&column.value_type)?
+                    }
+                }
+                _ => FeatureValue::Null,
+            };
+            column.values.push(value);
+        }
+    }
+    Ok(LocalFrame { columns })
+}
+
+#[test]
+fn select_vertices_locally() {
+    use super::{GraphBase, SingleGraph};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "City", "id": "c1", "properties": {"population": 1000}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41}}
+            ],
+            "edges": [
+                {"tlabel": "livesIn", "src": ["Person", "p1"], "dst": ["City", "c1"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    single_graph.vertices_by_type("Person").unwrap();
+    single_graph.vertices();
+    assert!(single_graph.vertices_by_type("Product").is_none());
+
+    let mut executor = LocalExecutor::new(&graph);
+    executor.execute(&context.borrow()).unwrap();
+    match executor.result(1).unwrap() {
+        LocalData::Vertices { ids, frame } => {
+            assert_eq!(ids, &vec![0, 2]);
+            assert_eq!(
+                frame.column("age").unwrap().values,
+                vec![FeatureValue::Int(30), FeatureValue::Int(41)]
+            );
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+    match executor.result(2).unwrap() {
+        LocalData::Vertices { ids, frame } => {
+            // vertices are ordered by type
+            assert_eq!(ids, &vec![1, 0, 2]);
+            assert_eq!(frame.num_rows(), 3);
+            assert_eq!(
+                frame.column("population").unwrap().values,
+                vec![
+                    FeatureValue::Int(1000),
+                    FeatureValue::Null,
+                    FeatureValue::Null
+                ]
+            );
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn run_selections_read_together_on_one_engine() {
+    use super::{GraphComputationOps, Selector, SingleGraph, TransformationExecutor};
+    use crate::{Expression, GraphRows, GraphValue, ScriptedGraphDatabase};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41}}
+            ],
+            "edges": [
+                {"tlabel": "knows", "src": ["Person", "p1"], "dst": ["Person", "p2"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    // the vertices could be selected on the graph database, with the average evaluated on their
+    // rows, but the edges between them are selected locally
+    single_graph
+        .subgraph(Selector::Expression(Expression::new("age >= avg(age)")))
+        .unwrap()
+        .export_topology("older");
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(Some("older".to_string()), None)
+        .unwrap()
+        .unwrap()
+        .clone();
+    let executor = TransformationExecutor::new(&transformation).unwrap();
+
+    let database = ScriptedGraphDatabase::new();
+    let value = |v: FeatureValue| GraphValue::Value(v);
+    let rows = GraphRows {
+        columns: ["__id", "__tlabel", "age"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+        rows: vec![vec![
+            value(FeatureValue::Int(1000)),
+            value(FeatureValue::String("Person".to_string())),
+            value(FeatureValue::Int(41)),
+        ]],
+    };
+    // only the selection of all vertices, which the subgraph does not read, is pushed down
+    database.expect("AND true RETURN", rows);
+    let mut local = LocalExecutor::new(&graph);
+    let output = executor.execute_on(&database, &mut local).await.unwrap();
+    assert_eq!(database.pending(), 0);
+    let mut all_local = LocalExecutor::new(&graph);
+    assert_eq!(output, executor.execute_local(&mut all_local).unwrap());
+
+    let explanation = executor.explain().unwrap();
+    assert!(matches!(
+        explanation.data[2].execution,
+        super::Execution::Local { .. }
+    ));
+}
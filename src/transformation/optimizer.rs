@@ -0,0 +1,613 @@
+use super::cypher_result::graph_schema;
+use super::graph::{CypherSource, DataFrameSet, EdgeSelectGraph, Selector, VertexSelectGraph};
+use super::{
+    CypherResultGraph, DataFrame, DataIdT, DataTransformationContext, TransformationContext,
+    TransformationData,
+};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::rc::Rc;
+
+/// A rewrite rule of the optimizer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptimizerRule {
+    /// Replaces data computed the same way from the same inputs as earlier data by the earlier
+    /// data
+    DeduplicateSubplans,
+    /// Merges a selection over another selection into one selection over the graph of the other
+    MergeSelectors,
+    /// Selects the vertices or edges returned by a `cypher_to_graph` query in the same query
+    FuseCypherSelection,
+    /// Evaluates filters before the sampling they follow, when sampling keeps each element
+    /// independently of the others
+    PushFiltersBelowSampling,
+    /// Drops the fields of a selection that no data reads
+    EliminateUnusedColumns,
+}
+
+impl OptimizerRule {
+    /// All rules, in the order they are applied
+    pub const ALL: [OptimizerRule; 5] = [
+        OptimizerRule::DeduplicateSubplans,
+        OptimizerRule::MergeSelectors,
+        OptimizerRule::FuseCypherSelection,
+        OptimizerRule::PushFiltersBelowSampling,
+        OptimizerRule::EliminateUnusedColumns,
+    ];
+}
+
+/// A rule applied to a data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rewrite {
+    pub rule: OptimizerRule,
+    pub data: DataIdT,
+}
+
+/// Rewrites the data flow of a TransformationContext to reduce the work and the round-trips to
+/// the graph database. The results of exported data and of data without outputs are unchanged.
+pub struct Optimizer {
+    rules: Vec<OptimizerRule>,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Optimizer::new(OptimizerRule::ALL.to_vec())
+    }
+}
+
+impl Optimizer {
+    pub fn new(rules: Vec<OptimizerRule>) -> Self {
+        Optimizer { rules }
+    }
+
+    /// Applies the rules until none applies. Data that only fed rewritten data are removed.
+    pub fn optimize(
+        &self,
+        context: &mut TransformationContext,
+    ) -> Result<Vec<Rewrite>, Box<dyn Error>> {
+        let leaves: HashSet<DataIdT> = context
+            .data()
+            .iter()
+            .map(|data| data.get_context().id)
+            .filter(|id| context.outputs(*id).is_empty())
+            .collect();
+        let mut res = Vec::new();
+        loop {
+            let applied = res.len();
+            for rule in &self.rules {
+                let ids: Vec<DataIdT> = context
+                    .topological_order()?
+                    .iter()
+                    .map(|data| data.get_context().id)
+                    .collect();
+                for id in ids {
+                    // earlier rewrites may have replaced or removed the data
+                    let data = match context.get_data(id) {
+                        Some(data) => data.clone(),
+                        None => continue,
+                    };
+                    if apply(*rule, data.as_ref(), context, &leaves)? {
+                        res.push(Rewrite {
+                            rule: *rule,
+                            data: id,
+                        });
+                    }
+                }
+            }
+            remove_unused(context, &leaves);
+            if res.len() == applied {
+                return Ok(res);
+            }
+        }
+    }
+}
+
+/// Removes the data without outputs that are neither exported nor leaves
+fn remove_unused(context: &mut TransformationContext, leaves: &HashSet<DataIdT>) {
+    loop {
+        let exported = context.exported_data();
+        let unused: Vec<DataIdT> = context
+            .data()
+            .iter()
+            .map(|data| data.get_context().id)
+            .filter(|id| !leaves.contains(id) && !exported.contains(id))
+            .filter(|id| context.outputs(*id).is_empty())
+            .collect();
+        if unused.is_empty() {
+            return;
+        }
+        unused.into_iter().for_each(|id| context.remove_data(id));
+    }
+}
+
+fn apply(
+    rule: OptimizerRule,
+    data: &dyn TransformationData,
+    context: &mut TransformationContext,
+    leaves: &HashSet<DataIdT>,
+) -> Result<bool, Box<dyn Error>> {
+    let id = data.get_context().id;
+    if rule == OptimizerRule::DeduplicateSubplans {
+        return deduplicate(data, context, leaves);
+    }
+    let selection = match Selection::of(data) {
+        Some(selection) => selection,
+        None => return Ok(false),
+    };
+    let rewritten = match rule {
+        OptimizerRule::MergeSelectors => context
+            .get_data(selection.graph)
+            .and_then(|input| Selection::of(input.as_ref()))
+            .filter(|input| input.vertices == selection.vertices && selection.source.is_none())
+            .map(|input| Selection {
+                graph: input.graph,
+                selector: intersect(input.selector, selection.selector.clone()),
+                source: input.source,
+                ..selection
+            }),
+        OptimizerRule::FuseCypherSelection => context.get_data(selection.graph).and_then(|input| {
+            let input = input.as_any().downcast_ref::<CypherResultGraph>()?;
+            if selection.source.is_some() {
+                return None;
+            }
+            let schema = graph_schema(&input.query);
+            let columns = if selection.vertices {
+                schema.vertex_sources
+            } else {
+                schema.edge_sources
+            };
+            Some(Selection {
+                graph: input.graph,
+                source: Some(CypherSource {
+                    query: input.query.clone(),
+                    columns,
+                }),
+                ..selection
+            })
+        }),
+        OptimizerRule::PushFiltersBelowSampling => {
+            push_filters(&selection.selector).map(|selector| Selection {
+                selector,
+                ..selection
+            })
+        }
+        OptimizerRule::EliminateUnusedColumns => {
+            if context.exported_data().contains(&id) {
+                return Ok(false);
+            }
+            let outputs = context.outputs(id);
+            let mut used = BTreeSet::new();
+            for output in &outputs {
+                match context
+                    .get_data(*output)
+                    .and_then(|data| data.as_any().downcast_ref::<DataFrame>())
+                {
+                    Some(df) => used.extend(df.columns_read_from(id)),
+                    // other data may read any field
+                    None => return Ok(false),
+                }
+            }
+            let df = selection.df.retain_fields(&used);
+            (!outputs.is_empty() && df.field_names() != selection.df.field_names())
+                .then_some(Selection { df, ..selection })
+        }
+        OptimizerRule::DeduplicateSubplans => unreachable!(),
+    };
+    Ok(match rewritten {
+        Some(selection) => {
+            context.replace_data(selection.build());
+            true
+        }
+        None => false,
+    })
+}
+
+/// Makes the outputs of the data read from an earlier data computed the same way instead
+fn deduplicate(
+    data: &dyn TransformationData,
+    context: &mut TransformationContext,
+    leaves: &HashSet<DataIdT>,
+) -> Result<bool, Box<dyn Error>> {
+    let id = data.get_context().id;
+    if leaves.contains(&id) && !context.exported_data().contains(&id) {
+        return Ok(false);
+    }
+    let key = plan_key(data)?;
+    let mut original = None;
+    for other in context.topological_order()? {
+        let other_id = other.get_context().id;
+        if other_id == id {
+            break;
+        }
+        if plan_key(other.as_ref())? == key {
+            original = Some(other_id);
+            break;
+        }
+    }
+    let original = match original {
+        Some(original) => original,
+        None => return Ok(false),
+    };
+    let inputs = HashMap::from([(id, original)]);
+    let mut outputs = Vec::new();
+    for output in context.outputs(id) {
+        match context
+            .get_data(output)
+            .and_then(|data| data.with_inputs(&inputs))
+        {
+            Some(data) => outputs.push(data),
+            None => return Ok(false),
+        }
+    }
+    outputs
+        .into_iter()
+        .for_each(|data| context.replace_data(data));
+    context.redirect_exports(id, original);
+    context.remove_data(id);
+    Ok(true)
+}
+
+/// The serialized data without its id, equal for data computed the same way from the same inputs
+fn plan_key(data: &dyn TransformationData) -> Result<serde_json::Value, Box<dyn Error>> {
+    let mut res = serde_json::to_value(data)?;
+    if let Some(object) = res.as_object_mut() {
+        for value in object.values_mut() {
+            if let Some(fields) = value.as_object_mut() {
+                fields.remove("context");
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Selects the elements selected by `second` among the ones selected by `first`, merging filters
+/// into one expression
+fn intersect(first: Selector, second: Selector) -> Selector {
+    Selector::Intersect(Box::new(first), Box::new(second)).simplify()
+}
+
+/// Moves the filters that follow a sampling before it, when they select the same elements either
+/// way
+fn push_filters(selector: &Selector) -> Option<Selector> {
+    let commutes =
+        |s: &Selector| matches!(s, Selector::Sampling { spec, .. } if spec.commutes_with_filter());
+    match selector {
+        Selector::Intersect(first, second) => {
+            let is_filter = match &**second {
+                Selector::Expression(expr) => !expr.contains_aggregate(),
+                Selector::DirectAccess { .. } => true,
+                _ => false,
+            };
+            if is_filter {
+                match &**first {
+                    sampling if commutes(sampling) => {
+                        return Some(Selector::Intersect(second.clone(), first.clone()))
+                    }
+                    Selector::Intersect(before, sampling) if commutes(sampling) => {
+                        return Some(Selector::Intersect(
+                            Box::new(Selector::Intersect(before.clone(), second.clone())),
+                            sampling.clone(),
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            let (a, b) = (push_filters(first), push_filters(second));
+            (a.is_some() || b.is_some()).then(|| {
+                Selector::Intersect(
+                    Box::new(a.unwrap_or_else(|| (**first).clone())),
+                    Box::new(b.unwrap_or_else(|| (**second).clone())),
+                )
+            })
+        }
+        Selector::Union(left, right) => {
+            let (a, b) = (push_filters(left), push_filters(right));
+            (a.is_some() || b.is_some()).then(|| {
+                Selector::Union(
+                    Box::new(a.unwrap_or_else(|| (**left).clone())),
+                    Box::new(b.unwrap_or_else(|| (**right).clone())),
+                )
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The parts of a vertex or edge selection
+struct Selection {
+    vertices: bool,
+    context: DataTransformationContext,
+    graph: DataIdT,
+    selector: Selector,
+    df: DataFrameSet,
+    source: Option<CypherSource>,
+}
+
+impl Selection {
+    fn of(data: &dyn TransformationData) -> Option<Selection> {
+        if let Some(s) = data.as_any().downcast_ref::<VertexSelectGraph>() {
+            return Some(Selection {
+                vertices: true,
+                context: s.context.clone(),
+                graph: s.graph,
+                selector: s.selector.clone(),
+                df: s.df.clone(),
+                source: s.source.clone(),
+            });
+        }
+        let s = data.as_any().downcast_ref::<EdgeSelectGraph>()?;
+        Some(Selection {
+            vertices: false,
+            context: s.context.clone(),
+            graph: s.graph,
+            selector: s.selector.clone(),
+            df: s.df.clone(),
+            source: s.source.clone(),
+        })
+    }
+
+    fn build(self) -> Rc<dyn TransformationData> {
+        if self.vertices {
+            Rc::new(VertexSelectGraph {
+                context: self.context,
+                graph: self.graph,
+                selector: self.selector,
+                df: self.df,
+                source: self.source,
+            })
+        } else {
+            Rc::new(EdgeSelectGraph {
+                context: self.context,
+                graph: self.graph,
+                selector: self.selector,
+                df: self.df,
+                source: self.source,
+            })
+        }
+    }
+}
+
+#[test]
+fn optimize_selections() {
+    use super::built_in_fns::Expression;
+    use super::{
+        Column, CypherTransformation, InnerTransformationData, LocalData, LocalExecutor,
+        SingleGraph,
+    };
+    use crate::{Entity, FeatureValue, FeatureValueType, Field, PropertyGraph};
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 30, "name": "ann"}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 41, "name": "bob"}},
+                {"tlabel": "City", "id": "c1", "properties": {"population": 1000}}
+            ],
+            "edges": [
+                {"tlabel": "livesIn", "src": ["Person", "p1"], "dst": ["City", "c1"]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let person = Entity::new_node_entity("person", None, "Person", "name");
+    let fields = Field::new_fields(
+        vec![
+            ("age", FeatureValueType::Int),
+            ("name", FeatureValueType::String),
+        ],
+        &person,
+        None,
+    );
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let select = |graph: DataIdT, selector: Selector, df: DataFrameSet| {
+        let res = Rc::new(VertexSelectGraph {
+            context: single_graph.get_context().new_data_context(),
+            graph,
+            selector,
+            df,
+            source: None,
+        });
+        single_graph.get_context().register_data(&res);
+        res.get_data_id()
+    };
+    let read = |origin: DataIdT, name: &str, value_type: FeatureValueType| {
+        let res = Rc::new(DataFrame {
+            context: single_graph.get_context().new_data_context(),
+            name: name.to_string(),
+            schema: vec![Rc::new(Column::new(origin, value_type))],
+            col_names: vec![name.to_string()],
+            col_by_names: HashMap::from([(name.to_string(), 0)]),
+        });
+        single_graph.get_context().register_data(&res);
+        res
+    };
+    let people = || Selector::DirectAccess {
+        ltype: Some("Person".to_string()),
+    };
+    let people_df = || DataFrameSet::Homo(("Person".to_string(), fields.clone()));
+    let all = select(0, people(), people_df());
+    let older = select(
+        all,
+        Selector::Expression(Expression::new("age > 35")),
+        people_df(),
+    );
+    let same = select(0, people(), people_df());
+    read(older, "age", FeatureValueType::Int).export();
+    read(same, "name", FeatureValueType::String).export();
+    let transformation = context
+        .borrow_mut()
+        .build_transformation(None, None)
+        .unwrap()
+        .unwrap()
+        .clone();
+
+    let mut optimized = TransformationContext::from_transformation(&transformation).unwrap();
+    let rewrites = Optimizer::default().optimize(&mut optimized).unwrap();
+    let rewrite = |rule, data| Rewrite { rule, data };
+    assert_eq!(
+        rewrites,
+        vec![
+            rewrite(OptimizerRule::DeduplicateSubplans, same),
+            rewrite(OptimizerRule::MergeSelectors, older),
+            rewrite(OptimizerRule::EliminateUnusedColumns, all),
+            rewrite(OptimizerRule::EliminateUnusedColumns, older),
+        ]
+    );
+    assert!(optimized.get_data(same).is_none());
+    assert_eq!(optimized.outputs(0), vec![all, older]);
+    let mut executor = LocalExecutor::new(&graph);
+    executor.execute(&optimized).unwrap();
+    let column = |id: DataIdT, name: &str| {
+        executor
+            .result(id)
+            .unwrap()
+            .frame()
+            .unwrap()
+            .column(name)
+            .unwrap()
+            .values
+            .clone()
+    };
+    assert_eq!(column(4, "age"), vec![FeatureValue::Int(41)]);
+    assert_eq!(
+        column(5, "name"),
+        vec![
+            FeatureValue::String("ann".to_string()),
+            FeatureValue::String("bob".to_string())
+        ]
+    );
+    assert!(executor
+        .result(all)
+        .unwrap()
+        .frame()
+        .unwrap()
+        .column("age")
+        .is_none());
+
+    // a selection over the result of a query runs in the query
+    let city = Entity::new_node_entity("city", None, "City", "population");
+    let city_df = DataFrameSet::Homo((
+        "City".to_string(),
+        Field::new_fields(vec![("population", FeatureValueType::Int)], &city, None),
+    ));
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    single_graph.cypher_to_graph(
+        "MATCH (u:Person)-[:livesIn]->(c) RETURN DISTINCT u, collect(c) AS cities ORDER BY u.age",
+    );
+    let lived_in = Rc::new(VertexSelectGraph {
+        context: single_graph.get_context().new_data_context(),
+        graph: 1,
+        selector: Selector::Expression(Expression::new("population > 100")),
+        df: city_df,
+        source: None,
+    });
+    single_graph.get_context().register_data(&lived_in);
+    let mut context = context.borrow_mut();
+    let rewrites = Optimizer::new(vec![OptimizerRule::FuseCypherSelection])
+        .optimize(&mut context)
+        .unwrap();
+    assert_eq!(
+        rewrites,
+        vec![rewrite(OptimizerRule::FuseCypherSelection, 2)]
+    );
+    assert!(context.get_data(1).is_none());
+    let mut executor = LocalExecutor::new(&graph);
+    executor.insert(
+        0,
+        LocalData::Graph {
+            vertex_types: vec!["City".to_string(), "Person".to_string()],
+            edge_types: vec!["livesIn".to_string()],
+        },
+    );
+    let query = context
+        .get_data(2)
+        .unwrap()
+        .compile_cypher(&executor)
+        .unwrap();
+    assert_eq!(
+        query.text,
+        "CALL { MATCH (u:Person)-[:livesIn]->(c) RETURN DISTINCT u, collect(c) AS cities \
+         ORDER BY u.age } UNWIND [`u`, `cities`] AS __value UNWIND __value AS n \
+         WITH DISTINCT n WHERE any(l IN labels(n) WHERE l IN $p0) AND (n.`population` > $p1) \
+         RETURN id(n) AS __id, [l IN labels(n) WHERE l IN $p0][0] AS __tlabel, \
+         n.`population` AS `population` ORDER BY __tlabel, __id"
+    );
+    assert!(super::cypher_result::return_columns("MATCH (n) RETURN *").is_none());
+}
+
+#[test]
+fn push_filters_below_sampling() {
+    use super::{GraphBase, LocalData, LocalExecutor, SamplingSpec, SingleGraph};
+    use crate::PropertyGraph;
+
+    let graph = PropertyGraph::from_json(
+        r#"{
+            "vertices": [
+                {"tlabel": "Person", "id": "p1", "properties": {"age": 20}},
+                {"tlabel": "Person", "id": "p2", "properties": {"age": 35}},
+                {"tlabel": "Person", "id": "p3", "properties": {"age": 50}},
+                {"tlabel": "Person", "id": "p4", "properties": {"age": 40}},
+                {"tlabel": "Person", "id": "p5", "properties": {"age": 60}},
+                {"tlabel": "Person", "id": "p6", "properties": {"age": 25}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let context = TransformationContext::new();
+    let single_graph = SingleGraph::from_property_graph(&context, &graph);
+    let people = single_graph.vertices_by_type("Person").unwrap();
+    people
+        .sample(2, SamplingSpec::bernoulli(0.5).seeded(3))
+        .unwrap()
+        .filter("age > 30")
+        .unwrap();
+    people
+        .sample(2, SamplingSpec::uniform().seeded(3))
+        .unwrap()
+        .filter("age > 30")
+        .unwrap();
+    let (older, uniform) = (3, 5);
+    let selected = |context: &TransformationContext, id: DataIdT| {
+        let mut executor = LocalExecutor::new(&graph);
+        executor.execute(context).unwrap();
+        match executor.result(id).unwrap() {
+            LocalData::Vertices { ids, .. } => ids.clone(),
+            res => panic!("unexpected result {:?}", res),
+        }
+    };
+    let original = selected(&context.borrow(), older);
+
+    // only the bernoulli sampling keeps each vertex independently of the others
+    let mut context = context.borrow_mut();
+    let rewrites = Optimizer::default().optimize(&mut context).unwrap();
+    assert_eq!(
+        rewrites,
+        vec![Rewrite {
+            rule: OptimizerRule::PushFiltersBelowSampling,
+            data: older
+        }]
+    );
+    let selector = |id: DataIdT| {
+        context
+            .get_data(id)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<VertexSelectGraph>()
+            .unwrap()
+            .selector
+            .clone()
+    };
+    assert!(matches!(
+        selector(older),
+        Selector::Intersect(_, last) if matches!(*last, Selector::Sampling { .. })
+    ));
+    assert!(matches!(
+        selector(uniform),
+        Selector::Intersect(_, last) if matches!(*last, Selector::Expression(_))
+    ));
+    assert_eq!(selected(&context, older), original);
+}
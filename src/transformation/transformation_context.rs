@@ -1,11 +1,11 @@
-use crate::{FeatureRegistry, ResourceOp, Transformation};
+use crate::{FeatureRegistry, ResourceId, ResourceOp, Transformation};
 
 use super::{DataIdT, InnerTransformationData, TransformationData, TRANSFORMATION_NAME_PREFIX};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
     error::Error,
     rc::{Rc, Weak},
 };
@@ -22,7 +22,7 @@ pub struct TransformationContext {
 
 // non-pub struct makes it difficult to be used in pub trait TransformationData. I will make it pub for now until I find any better solution.
 /// Used by the tranformation operations on TransformationData to create and register new data.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataTransformationContext {
     pub id: DataIdT,
     #[serde(skip_serializing, skip_deserializing)]
@@ -88,11 +88,159 @@ impl TransformationContext {
         }))
     }
 
+    /// Restores the data flow saved in the body of a transformation
+    pub fn from_transformation(transformation: &Transformation) -> Result<Self, Box<dyn Error>> {
+        let mut res: TransformationContext = serde_json::from_str(&transformation.body)
+            .map_err(|e| format!("invalid body of {}: {}", transformation.resource_id(), e))?;
+        res.transformation = Some(transformation.clone());
+        Ok(res)
+    }
+
     pub fn new_data_id(&mut self) -> DataIdT {
         self.next_data_id += 1;
         self.next_data_id - 1
     }
 
+    /// Returns the data in the order they were added
+    pub fn data(&self) -> &[Rc<dyn TransformationData>] {
+        &self.data_vec
+    }
+
+    /// Returns the data ordered so that each data comes after its inputs, keeping the order they
+    /// were added otherwise
+    pub fn topological_order(&self) -> Result<Vec<Rc<dyn TransformationData>>, Box<dyn Error>> {
+        let index: HashMap<DataIdT, usize> = self
+            .data_vec
+            .iter()
+            .enumerate()
+            .map(|(i, data)| (data.get_context().id, i))
+            .collect();
+        let mut pending = vec![0; self.data_vec.len()];
+        let mut dependents = vec![Vec::new(); self.data_vec.len()];
+        for (i, data) in self.data_vec.iter().enumerate() {
+            for input in data.inputs() {
+                let j = *index.get(&input).ok_or_else(|| {
+                    format!(
+                        "data {} depends on unknown data {}",
+                        data.get_context().id,
+                        input
+                    )
+                })?;
+                pending[i] += 1;
+                dependents[j].push(i);
+            }
+        }
+        let mut ready: BTreeSet<usize> = (0..pending.len()).filter(|i| pending[*i] == 0).collect();
+        let mut res = Vec::with_capacity(self.data_vec.len());
+        while let Some(i) = ready.pop_first() {
+            res.push(self.data_vec[i].clone());
+            for j in &dependents[i] {
+                pending[*j] -= 1;
+                if pending[*j] == 0 {
+                    ready.insert(*j);
+                }
+            }
+        }
+        if res.len() < self.data_vec.len() {
+            let cycle: Vec<String> = (0..pending.len())
+                .filter(|i| pending[*i] > 0)
+                .map(|i| self.data_vec[i].get_context().id.to_string())
+                .collect();
+            return Err(format!("data {} depend on each other", cycle.join(", ")).into());
+        }
+        Ok(res)
+    }
+
+    /// Returns the ids of the data derived from the given data
+    pub fn outputs(&self, id: DataIdT) -> Vec<DataIdT> {
+        self.data_vec
+            .iter()
+            .filter(|data| data.inputs().contains(&id))
+            .map(|data| data.get_context().id)
+            .collect()
+    }
+
+    /// Returns the data with the given id
+    pub fn get_data(&self, id: DataIdT) -> Option<&Rc<dyn TransformationData>> {
+        self.data_vec
+            .iter()
+            .find(|data| data.get_context().id == id)
+    }
+
+    /// Replaces the data with the same id
+    pub(super) fn replace_data(&mut self, data: Rc<dyn TransformationData>) {
+        let id = data.get_context().id;
+        if let Some(old) = self
+            .data_vec
+            .iter_mut()
+            .find(|old| old.get_context().id == id)
+        {
+            *old = data;
+        }
+    }
+
+    pub(super) fn remove_data(&mut self, id: DataIdT) {
+        self.data_vec.retain(|data| data.get_context().id != id);
+    }
+
+    /// The transformation the data flow is saved in, if one was created
+    pub fn transformation(&self) -> Option<&Transformation> {
+        self.transformation.as_ref()
+    }
+
+    /// The data ids and resource ids of the exported resources
+    pub fn export_resources(&self) -> &[(DataIdT, ResourceId)] {
+        match &self.transformation {
+            Some(transformation) => &transformation.export_resources,
+            None => &[],
+        }
+    }
+
+    /// The ids of the data that resources are exported from
+    pub(super) fn exported_data(&self) -> BTreeSet<DataIdT> {
+        self.export_resources().iter().map(|(id, _)| *id).collect()
+    }
+
+    /// Exports the resources of one data from another
+    pub(super) fn redirect_exports(&mut self, from: DataIdT, to: DataIdT) {
+        if let Some(transformation) = &mut self.transformation {
+            for (id, _) in &mut transformation.export_resources {
+                if *id == from {
+                    *id = to;
+                }
+            }
+        }
+    }
+
+    /// Removes the data that no exported resource is computed from, and returns their ids
+    pub fn prune(&mut self) -> Result<Vec<DataIdT>, Box<dyn Error>> {
+        let exported: Vec<DataIdT> = self.exported_data().into_iter().collect();
+        if exported.is_empty() {
+            return Err("no resources are exported, so no data would be kept".into());
+        }
+        let inputs: HashMap<DataIdT, Vec<DataIdT>> = self
+            .data_vec
+            .iter()
+            .map(|data| (data.get_context().id, data.inputs()))
+            .collect();
+        let mut needed = HashSet::new();
+        let mut stack = exported;
+        while let Some(id) = stack.pop() {
+            if needed.insert(id) {
+                stack.extend(inputs.get(&id).into_iter().flatten());
+            }
+        }
+        let mut removed = Vec::new();
+        self.data_vec.retain(|data| {
+            let id = data.get_context().id;
+            if !needed.contains(&id) {
+                removed.push(id);
+            }
+            needed.contains(&id)
+        });
+        Ok(removed)
+    }
+
     pub fn add_data(&mut self, data: &Rc<impl TransformationData + 'static>) {
         self.data_vec.push(data.clone());
     }
@@ -120,6 +268,8 @@ impl TransformationContext {
         name: Option<String>,
         variant: Option<String>,
     ) -> Result<Option<&Transformation>, Box<dyn Error>> {
+        // an invalid data flow cannot be executed later
+        self.topological_order()?;
         let body = serde_json::to_string(&self)?;
         if let Some(transformation) = &mut self.transformation {
             if let Some(name_str) = name {
@@ -131,3 +281,34 @@ impl TransformationContext {
         Ok(self.transformation.as_ref())
     }
 }
+
+#[test]
+fn prune_unexported_data() {
+    use crate::transformation::{DataFrame, GraphBase, SingleGraph};
+    use crate::{Entity, FeatureValueType, Field, PropertyGraph};
+
+    let person = Entity::new_node_entity("person", None, "Person", "age");
+    let fields = Field::new_fields(vec![("age", FeatureValueType::Int)], &person, None);
+    let context = TransformationContext::new();
+    let df = DataFrame::new(&context, "person", fields.iter().collect());
+    let graph = SingleGraph::from_property_graph(&context, &PropertyGraph::new());
+    graph.vertices();
+    let df2 = df.select(vec!["age + 1 AS next_age".to_string()]).unwrap();
+    df.select(vec!["age * 2 AS double_age".to_string()])
+        .unwrap();
+    assert_eq!(df.outputs(), vec![3, 4]);
+    assert!(context.borrow_mut().prune().is_err());
+
+    df2.export();
+    let removed = context.borrow_mut().prune().unwrap();
+    assert_eq!(removed, vec![1, 2, 4]);
+    let kept: Vec<DataIdT> = context
+        .borrow()
+        .topological_order()
+        .unwrap()
+        .iter()
+        .map(|data| data.get_context().id)
+        .collect();
+    assert_eq!(kept, vec![0, 3]);
+    assert_eq!(df.outputs(), vec![3]);
+}
@@ -39,3 +39,15 @@ fn test_cli_serve() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_cli_clean_rejects_empty_batches() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("gfs")?;
+
+    cmd.arg("clean").arg("--batch-size").arg("0");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "the batch size must be at least 1",
+    ));
+
+    Ok(())
+}